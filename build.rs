@@ -0,0 +1,41 @@
+use std::{path::Path, process::Command};
+
+// stamps the git commit and a build date into the binary, see build_info.rs. The date is
+// SOURCE_DATE_EPOCH or the commit's time, never the time of the build, so building the same
+// commit twice gives the same stamp
+fn main() {
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    // HEAD only names the branch, its ref moves with every commit
+    if let Ok(head) = std::fs::read_to_string(".git/HEAD") {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        if let Some(reference) = head.trim().strip_prefix("ref: ") {
+            let reference = Path::new(".git").join(reference);
+            if reference.exists() {
+                println!("cargo:rerun-if-changed={}", reference.display());
+            }
+        }
+    }
+
+    let version = std::env::var("CARGO_PKG_VERSION").unwrap();
+    match git(&["rev-parse", "--short=12", "HEAD"]) {
+        Some(hash) => {
+            println!("cargo:rustc-env=MOD_LAUNCHER_GIT_HASH={}", hash);
+            println!("cargo:rustc-env=MOD_LAUNCHER_VERSION={}+{}", version, hash);
+        }
+        None => println!("cargo:rustc-env=MOD_LAUNCHER_VERSION={}", version),
+    }
+    let epoch = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .or_else(|| git(&["log", "-1", "--format=%ct"]));
+    if let Some(epoch) = epoch.filter(|epoch| epoch.parse::<i64>().is_ok()) {
+        println!("cargo:rustc-env=MOD_LAUNCHER_BUILD_EPOCH={}", epoch);
+    }
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
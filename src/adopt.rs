@@ -0,0 +1,293 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tracing::{debug, info};
+
+use crate::{
+    curseforge, instance, modrinth, mods,
+    packs::{self, PackKind},
+};
+
+// where a file in mods/ or resourcepacks/ was published
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "platform", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ContentSource {
+    Modrinth {
+        project_id: String,
+        version_id: String,
+        version_number: String,
+    },
+    CurseForge {
+        project_id: u32,
+        file_id: u32,
+        display_name: String,
+    },
+}
+
+impl ContentSource {
+    pub fn version(&self) -> &str {
+        match self {
+            ContentSource::Modrinth { version_number, .. } => version_number,
+            ContentSource::CurseForge { display_name, .. } => display_name,
+        }
+    }
+}
+
+// kept in the lockfile by path relative to the game dir, e.g. mods/sodium.jar
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AdoptedFile {
+    pub sha1: String,
+    // None when neither platform knows the file
+    #[serde(default)]
+    pub source: Option<ContentSource>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AdoptedContent {
+    pub path: String,
+    pub source: Option<ContentSource>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct AdoptReport {
+    // files that weren't in the lockfile or changed since
+    pub adopted: Vec<AdoptedContent>,
+    // files in the lockfile that are gone
+    pub removed: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ContentUpdate {
+    pub path: String,
+    pub current: ContentSource,
+    pub latest: ContentSource,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ContentKind {
+    Mod,
+    ResourcePack,
+}
+
+impl ContentKind {
+    fn of(path: &str) -> ContentKind {
+        match path.starts_with(PackKind::Resource.dir_name()) {
+            true => ContentKind::ResourcePack,
+            false => ContentKind::Mod,
+        }
+    }
+}
+
+// jars in mods/, disabled ones included, and zipped resource packs, keyed like the lockfile
+fn scan(game_dir: &Path) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    for info in mods::list_mods(game_dir)? {
+        files.push((format!("mods/{}", info.file_name), info.path));
+    }
+    for pack in packs::list_packs(game_dir, PackKind::Resource, None)? {
+        if pack.path.is_file() {
+            let path = format!("{}/{}", PackKind::Resource.dir_name(), pack.file_name);
+            files.push((path, pack.path));
+        }
+    }
+    Ok(files)
+}
+
+// identifies the mods and resource packs put into the instance by hand, by their sha1 on
+// Modrinth and their fingerprint on CurseForge when there is an API key, and records where they
+// were published in the lockfile so their updates can be checked like everything else's. Files
+// the lockfile already knows are only hashed again
+pub async fn adopt(
+    client: &reqwest::Client,
+    curseforge_api: Option<&curseforge::Api>,
+    instances_dir: &Path,
+    name: &str,
+) -> anyhow::Result<AdoptReport> {
+    let instance = instance::load(instances_dir, name)?;
+    let lockfile = instance::lockfile(instances_dir, name)?;
+    let known = lockfile
+        .content
+        .values()
+        .map(|file| (file.sha1.clone(), file.source.clone()))
+        .collect::<HashMap<_, _>>();
+
+    let mut content = BTreeMap::new();
+    // (path, sha1, fingerprint) of the files to look up
+    let mut pending = Vec::new();
+    let mut report = AdoptReport::default();
+    for (path, file) in scan(&instance.game_dir(instances_dir))? {
+        let bytes =
+            std::fs::read(&file).with_context(|| format!("Could not read {}", file.display()))?;
+        let sha1 = format!("{:x}", Sha1::digest(&bytes));
+        match lockfile.content.get(&path) {
+            Some(adopted) if adopted.sha1 == sha1 => {
+                content.insert(path, adopted.clone());
+            }
+            // renamed or disabled, the same file under another name
+            _ if known.contains_key(&sha1) => {
+                let source = known[&sha1].clone();
+                report.adopted.push(AdoptedContent {
+                    path: path.clone(),
+                    source: source.clone(),
+                });
+                content.insert(path, AdoptedFile { sha1, source });
+            }
+            _ => pending.push((path, sha1, curseforge::fingerprint(&bytes))),
+        }
+    }
+    report.removed = lockfile
+        .content
+        .keys()
+        .filter(|path| !content.contains_key(*path) && !pending.iter().any(|p| &p.0 == *path))
+        .cloned()
+        .collect();
+
+    if !pending.is_empty() {
+        let hashes = pending
+            .iter()
+            .map(|(_, sha1, _)| sha1.clone())
+            .collect::<Vec<_>>();
+        let published = modrinth::versions_by_hash(client, &hashes)
+            .await
+            .context("Could not look up the instance's files on Modrinth")?;
+        let fingerprints = pending
+            .iter()
+            .filter(|(_, sha1, _)| !published.contains_key(sha1))
+            .map(|(_, _, fingerprint)| *fingerprint)
+            .collect::<Vec<_>>();
+        let curseforge = match (curseforge_api, fingerprints.is_empty()) {
+            (Some(api), false) => curseforge::files_by_fingerprint(client, api, &fingerprints)
+                .await
+                .context("Could not look up the instance's files on CurseForge")?,
+            _ => HashMap::new(),
+        };
+
+        for (path, sha1, fingerprint) in pending {
+            let source = match (published.get(&sha1), curseforge.get(&fingerprint)) {
+                (Some(version), _) => Some(ContentSource::Modrinth {
+                    project_id: version.project_id.clone(),
+                    version_id: version.id.clone(),
+                    version_number: version.version_number.clone(),
+                }),
+                (None, Some(file)) => Some(ContentSource::CurseForge {
+                    project_id: file.project_id,
+                    file_id: file.file_id,
+                    display_name: file.display_name.clone(),
+                }),
+                (None, None) => None,
+            };
+            // without an API key CurseForge wasn't asked, so unknown files are tried again
+            // next time rather than recorded as unknown
+            if source.is_none() && curseforge_api.is_none() {
+                debug!("{} is not on Modrinth", path);
+                continue;
+            }
+            report.adopted.push(AdoptedContent {
+                path: path.clone(),
+                source: source.clone(),
+            });
+            content.insert(path, AdoptedFile { sha1, source });
+        }
+    }
+
+    if content != lockfile.content {
+        instance::update_locked(instances_dir, name, |_, lockfile| {
+            lockfile.content = content;
+        })?;
+    }
+    let identified = report
+        .adopted
+        .iter()
+        .filter(|adopted| adopted.source.is_some())
+        .count();
+    if identified > 0 {
+        info!("Identified {} files added to {} by hand", identified, name);
+    }
+    Ok(report)
+}
+
+// the newer versions of adopted files for the instance's game version and loader. CurseForge
+// files are only checked with an API key
+pub async fn check_updates(
+    client: &reqwest::Client,
+    curseforge_api: Option<&curseforge::Api>,
+    instances_dir: &Path,
+    name: &str,
+) -> anyhow::Result<Vec<ContentUpdate>> {
+    let instance = instance::load(instances_dir, name)?;
+    let lockfile = instance::lockfile(instances_dir, name)?;
+    let mut updates = Vec::new();
+
+    for kind in [ContentKind::Mod, ContentKind::ResourcePack] {
+        let loader = match kind {
+            ContentKind::Mod => instance.loader,
+            ContentKind::ResourcePack => None,
+        };
+        let adopted = lockfile
+            .content
+            .iter()
+            .filter(|(path, _)| ContentKind::of(path) == kind)
+            .filter_map(|(path, file)| Some((path, &file.sha1, file.source.as_ref()?)))
+            .collect::<Vec<_>>();
+
+        let hashes = adopted
+            .iter()
+            .filter(|(_, _, source)| matches!(source, ContentSource::Modrinth { .. }))
+            .map(|(_, sha1, _)| sha1.to_string())
+            .collect::<Vec<_>>();
+        let latest = match hashes.is_empty() {
+            true => HashMap::new(),
+            false => {
+                let loaders = [loader.map_or("minecraft", |loader| loader.id())];
+                modrinth::latest_versions_by_hash(client, &hashes, &loaders, &[&instance.version])
+                    .await
+                    .context("Could not check Modrinth for updates")?
+            }
+        };
+
+        for (path, sha1, source) in adopted {
+            let newer = match source {
+                ContentSource::Modrinth { version_id, .. } => latest
+                    .get(sha1)
+                    .filter(|version| &version.id != version_id)
+                    .map(|version| ContentSource::Modrinth {
+                        project_id: version.project_id.clone(),
+                        version_id: version.id.clone(),
+                        version_number: version.version_number.clone(),
+                    }),
+                ContentSource::CurseForge {
+                    project_id,
+                    file_id,
+                    ..
+                } => {
+                    let Some(api) = curseforge_api else {
+                        continue;
+                    };
+                    curseforge::latest_file(client, api, *project_id, &instance.version, loader)
+                        .await
+                        .context("Could not check CurseForge for updates")?
+                        .filter(|file| file.file_id > *file_id)
+                        .map(|file| ContentSource::CurseForge {
+                            project_id: *project_id,
+                            file_id: file.file_id,
+                            display_name: file.display_name,
+                        })
+                }
+            };
+            if let Some(latest) = newer {
+                updates.push(ContentUpdate {
+                    path: path.clone(),
+                    current: source.clone(),
+                    latest,
+                });
+            }
+        }
+    }
+    Ok(updates)
+}
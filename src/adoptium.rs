@@ -0,0 +1,282 @@
+use std::{
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info};
+
+use crate::{cache, net::Downloader, staging::Staging, write_atomic};
+
+const API_URL: &str = "https://api.adoptium.net/v3/assets/latest";
+// written once a runtime is unpacked, a directory without it is a cut off install
+const RECORD_FILE: &str = "release.json";
+
+#[derive(Deserialize, Debug)]
+struct Asset {
+    binary: Binary,
+    release_name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Binary {
+    package: Package,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Package {
+    name: String,
+    link: String,
+    // sha256
+    checksum: String,
+    size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RuntimeRecord {
+    release_name: String,
+    package: Package,
+}
+
+// Adoptium's names for the architectures it builds Linux runtimes for
+fn api_arch(os_arch: &str) -> Option<&'static str> {
+    match os_arch {
+        "x86_64" => Some("x64"),
+        "aarch64" => Some("aarch64"),
+        "arm" => Some("arm"),
+        _ => None,
+    }
+}
+
+pub fn runtime_dir(cache_dir: &Path, major: u8, os_arch: &str) -> PathBuf {
+    cache::runtimes_dir(cache_dir).join(format!("temurin-{}-{}", major, os_arch))
+}
+
+pub fn java_binary(runtime_dir: &Path) -> PathBuf {
+    runtime_dir.join("bin").join("java")
+}
+
+// a Temurin JRE for `major` on Linux, downloaded and unpacked into the cache the first time.
+// Returns its java binary
+pub async fn ensure_runtime(
+    downloader: &dyn Downloader,
+    cache_dir: &Path,
+    major: u8,
+    os_arch: &str,
+) -> anyhow::Result<PathBuf> {
+    let dir = runtime_dir(cache_dir, major, os_arch);
+    let java = java_binary(&dir);
+    // another launcher process may be unpacking the same runtime
+    let (_lock, _) = cache::lock_file(cache_dir, &dir).await?;
+    if dir.join(RECORD_FILE).is_file() && java.is_file() {
+        return Ok(java);
+    }
+
+    let arch =
+        api_arch(os_arch).ok_or_else(|| anyhow!("Adoptium has no Java builds for {}", os_arch))?;
+    let url = format!(
+        "{}/{}/hotspot?architecture={}&image_type=jre&os=linux&vendor=eclipse",
+        API_URL, major, arch
+    );
+    let assets: Vec<Asset> = serde_json::from_slice(&downloader.fetch(&url).await?)
+        .context("Unexpected Adoptium release metadata")?;
+    let asset = assets
+        .into_iter()
+        .find(|asset| asset.binary.package.name.ends_with(".tar.gz"))
+        .ok_or_else(|| anyhow!("Adoptium has no Java {} runtime for {}", major, os_arch))?;
+    let package = asset.binary.package;
+    info!(
+        "Downloading Java {} ({}, {} MiB) from Adoptium",
+        major,
+        asset.release_name,
+        package.size / (1024 * 1024)
+    );
+
+    // resumes after an interrupted download
+    let archive = dir.with_extension("tar.gz.part");
+    std::fs::create_dir_all(cache::runtimes_dir(cache_dir))?;
+    downloader.fetch_to(&package.link, &archive).await?;
+    let unpacked = tokio::task::spawn_blocking({
+        let (archive, dir, package) = (archive.clone(), dir.clone(), package.clone());
+        move || -> anyhow::Result<()> {
+            if !sha256_file(&archive)?.eq_ignore_ascii_case(&package.checksum) {
+                return Err(anyhow!("Incorrect hash for {}", package.name));
+            }
+            let _ = std::fs::remove_dir_all(&dir);
+            let staging = Staging::new(&dir)?;
+            unpack(std::fs::File::open(&archive)?, staging.dir())
+                .with_context(|| format!("Could not unpack {}", package.name))?;
+            staging.commit()
+        }
+    })
+    .await?;
+    let _ = std::fs::remove_file(&archive);
+    unpacked?;
+
+    let record = RuntimeRecord {
+        release_name: asset.release_name,
+        package,
+    };
+    write_atomic(
+        &dir.join(RECORD_FILE),
+        serde_json::to_string_pretty(&record)?,
+    )
+    .await?;
+    debug!(java = %java.display(), "unpacked adoptium runtime");
+    Ok(java)
+}
+
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// just enough tar for Temurin's archives: files, directories and symlinks, with GNU and pax long
+// names. The release directory everything sits in is dropped
+fn unpack(archive: impl Read, dest: &Path) -> anyhow::Result<()> {
+    let mut archive = GzDecoder::new(archive);
+    let mut header = [0u8; 512];
+    let (mut long_name, mut long_link) = (None, None);
+    // made last, so no file is written through one
+    let mut symlinks = Vec::new();
+    loop {
+        archive.read_exact(&mut header)?;
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        let size = octal(&header[124..136])?;
+        let padding = (512 - size % 512) % 512;
+        let entry_type = header[156];
+
+        match entry_type {
+            b'L' => long_name = Some(read_string(&mut archive, size)?),
+            b'K' => long_link = Some(read_string(&mut archive, size)?),
+            b'x' => {
+                let pax = read_string(&mut archive, size)?;
+                long_name = pax_value(&pax, "path").or(long_name);
+                long_link = pax_value(&pax, "linkpath").or(long_link);
+            }
+            _ => {
+                let name = long_name.take().unwrap_or_else(|| header_name(&header));
+                let link = long_link.take().unwrap_or_else(|| field(&header[157..257]));
+                let mut remaining = size;
+                match (relative(&name), entry_type) {
+                    (Some(path), b'5') => std::fs::create_dir_all(dest.join(path))?,
+                    (Some(path), b'0' | 0) => {
+                        let path = dest.join(path);
+                        if let Some(parent) = path.parent() {
+                            std::fs::create_dir_all(parent)?;
+                        }
+                        let mut file = std::fs::File::create(&path)?;
+                        std::io::copy(&mut (&mut archive).take(size), &mut file)?;
+                        remaining = 0;
+                        set_mode(&path, octal(&header[100..108])?)?;
+                    }
+                    (Some(path), b'2') if !Path::new(&link).is_absolute() => {
+                        symlinks.push((dest.join(path), link))
+                    }
+                    // hard links and devices, Temurin ships none
+                    _ => debug!(name, entry_type, "skipped tar entry"),
+                }
+                skip(&mut archive, remaining)?;
+            }
+        }
+        skip(&mut archive, padding)?;
+    }
+
+    for (path, target) in symlinks {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        symlink(&target, &path)?;
+    }
+    Ok(())
+}
+
+fn skip(archive: &mut impl Read, bytes: u64) -> std::io::Result<()> {
+    std::io::copy(&mut archive.take(bytes), &mut std::io::sink())?;
+    Ok(())
+}
+
+fn read_string(archive: &mut impl Read, size: u64) -> std::io::Result<String> {
+    let mut bytes = Vec::new();
+    archive.take(size).read_to_end(&mut bytes)?;
+    Ok(field(&bytes))
+}
+
+// up to the first NUL
+fn field(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn octal(bytes: &[u8]) -> anyhow::Result<u64> {
+    let text = field(bytes);
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| anyhow!("Invalid number {:?} in tar header", text))
+}
+
+// ustar splits long names into a prefix and the name
+fn header_name(header: &[u8; 512]) -> String {
+    let name = field(&header[..100]);
+    match &header[257..262] == b"ustar" {
+        true => match field(&header[345..500]) {
+            prefix if prefix.is_empty() => name,
+            prefix => format!("{}/{}", prefix, name),
+        },
+        false => name,
+    }
+}
+
+// records are "<length> <key>=<value>\n"
+fn pax_value(pax: &str, key: &str) -> Option<String> {
+    pax.lines().find_map(|record| {
+        let (_, pair) = record.split_once(' ')?;
+        let (record_key, value) = pair.split_once('=')?;
+        (record_key == key).then(|| value.to_string())
+    })
+}
+
+// below the release directory, None for the directory itself and anything escaping it
+fn relative(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name.trim_start_matches("./"));
+    let mut components = path.components();
+    components.next()?;
+    let rest = components.as_path();
+    let is_inside = rest
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    (is_inside && !rest.as_os_str().is_empty()).then(|| rest.to_path_buf())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u64) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode as u32 & 0o777))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u64) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &str, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+// the runtimes are Linux only
+#[cfg(not(unix))]
+fn symlink(_target: &str, _link: &Path) -> std::io::Result<()> {
+    Ok(())
+}
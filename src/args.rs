@@ -0,0 +1,461 @@
+use std::borrow::Cow;
+
+use anyhow::anyhow;
+use regex::Regex;
+
+use crate::{build_info, legacy};
+
+// long enough for any real path or token, short enough to catch garbage
+const MAX_VALUE_LEN: usize = 8192;
+const MAX_WORLD_NAME_LEN: usize = 255;
+// DNS names are at most 253 characters, plus room for a port
+const MAX_ADDRESS_LEN: usize = 260;
+
+// what the game gets told to do right after starting
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum QuickPlay {
+    // a world folder under saves/
+    Singleplayer(String),
+    // host[:port]
+    Multiplayer(String),
+}
+
+impl QuickPlay {
+    // the feature that enables the matching arguments in the version JSON
+    pub fn feature(&self) -> &'static str {
+        match self {
+            QuickPlay::Singleplayer(_) => "is_quick_play_singleplayer",
+            QuickPlay::Multiplayer(_) => "is_quick_play_multiplayer",
+        }
+    }
+
+    // the placeholder those arguments use
+    pub fn key(&self) -> &'static str {
+        match self {
+            QuickPlay::Singleplayer(_) => "quickPlaySingleplayer",
+            QuickPlay::Multiplayer(_) => "quickPlayMultiplayer",
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        match self {
+            QuickPlay::Singleplayer(world) | QuickPlay::Multiplayer(world) => world,
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            QuickPlay::Singleplayer(world) => validate_world_name(world),
+            QuickPlay::Multiplayer(address) => validate_server_address(address),
+        }
+    }
+}
+
+// the window size the game opens with, `has_custom_resolution` arguments in the version JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Resolution {
+    // the feature that enables the --width and --height arguments
+    pub const FEATURE: &'static str = "has_custom_resolution";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserType {
+    Msa,
+    // Yggdrasil tokens from third-party auth servers
+    Mojang,
+}
+
+// who the game plays as. The offline player has a made up uuid and "0" for a token
+#[derive(Debug, Clone)]
+pub struct AuthValues {
+    pub player_name: String,
+    pub uuid: String,
+    pub access_token: String,
+    // empty when the account has none
+    pub xuid: String,
+    pub user_type: UserType,
+    // the account's profile properties as JSON, "{}" without any
+    pub user_properties: String,
+}
+
+// absolute, the game resolves relative paths against its working directory
+#[derive(Debug, Clone)]
+pub struct GameDirs {
+    pub game_directory: String,
+    pub assets_root: String,
+    // the assets root, or a legacy version's virtual copy of it, see legacy.rs
+    pub game_assets: String,
+    pub natives_directory: String,
+}
+
+// a required part of a LaunchContext that wasn't given yet
+#[derive(Debug, Clone, Copy)]
+pub struct Missing;
+
+// what the ${...} placeholders in a version's arguments stand for. The builder only builds once
+// the login, the directories and the classpath are set, so none of them can end up empty
+#[derive(Debug, Clone)]
+pub struct LaunchContext {
+    version_name: String,
+    assets_index_name: String,
+    auth: AuthValues,
+    dirs: GameDirs,
+    classpath: String,
+    resolution: Option<Resolution>,
+    quick_play: Option<QuickPlay>,
+}
+
+pub struct LaunchContextBuilder<A, D, C> {
+    version_name: String,
+    assets_index_name: String,
+    auth: A,
+    dirs: D,
+    classpath: C,
+    resolution: Option<Resolution>,
+    quick_play: Option<QuickPlay>,
+}
+
+impl LaunchContext {
+    pub fn builder(
+        version_name: &str,
+        assets_index_name: &str,
+    ) -> LaunchContextBuilder<Missing, Missing, Missing> {
+        LaunchContextBuilder {
+            version_name: version_name.to_string(),
+            assets_index_name: assets_index_name.to_string(),
+            auth: Missing,
+            dirs: Missing,
+            classpath: Missing,
+            resolution: None,
+            quick_play: None,
+        }
+    }
+
+    // None for placeholders the launcher doesn't know
+    pub fn value(&self, key: &str) -> Option<Cow<'_, str>> {
+        let auth = &self.auth;
+        let dirs = &self.dirs;
+        let value = match key {
+            "auth_player_name" => Cow::Borrowed(auth.player_name.as_str()),
+            "auth_uuid" => Cow::Borrowed(auth.uuid.as_str()),
+            "auth_access_token" => Cow::Borrowed(auth.access_token.as_str()),
+            "auth_session" => Cow::Owned(legacy::auth_session(&auth.access_token, &auth.uuid)),
+            "auth_xuid" => Cow::Borrowed(auth.xuid.as_str()),
+            "user_type" => Cow::Borrowed(match auth.user_type {
+                UserType::Msa => "msa",
+                UserType::Mojang => "mojang",
+            }),
+            "user_properties" => Cow::Borrowed(auth.user_properties.as_str()),
+            "clientid" => Cow::Borrowed(""),
+            "version_name" => Cow::Borrowed(self.version_name.as_str()),
+            "version_type" | "launcher_name" => Cow::Borrowed("ModLauncher"),
+            "launcher_version" => Cow::Borrowed(build_info::VERSION),
+            "assets_index_name" => Cow::Borrowed(self.assets_index_name.as_str()),
+            "game_directory" => Cow::Borrowed(dirs.game_directory.as_str()),
+            "assets_root" => Cow::Borrowed(dirs.assets_root.as_str()),
+            "game_assets" => Cow::Borrowed(dirs.game_assets.as_str()),
+            "natives_directory" => Cow::Borrowed(dirs.natives_directory.as_str()),
+            "classpath" => Cow::Borrowed(self.classpath.as_str()),
+            "resolution_width" => Cow::Owned(self.resolution?.width.to_string()),
+            "resolution_height" => Cow::Owned(self.resolution?.height.to_string()),
+            key => {
+                let quick_play = self.quick_play.as_ref().filter(|play| play.key() == key)?;
+                Cow::Borrowed(quick_play.value())
+            }
+        };
+        Some(value)
+    }
+
+    // the values that are credentials, kept out of logs and printed plans
+    pub fn secrets(&self) -> [String; 2] {
+        [
+            self.auth.access_token.clone(),
+            legacy::auth_session(&self.auth.access_token, &self.auth.uuid),
+        ]
+    }
+}
+
+impl<D, C> LaunchContextBuilder<Missing, D, C> {
+    pub fn auth(self, auth: AuthValues) -> LaunchContextBuilder<AuthValues, D, C> {
+        LaunchContextBuilder {
+            version_name: self.version_name,
+            assets_index_name: self.assets_index_name,
+            auth,
+            dirs: self.dirs,
+            classpath: self.classpath,
+            resolution: self.resolution,
+            quick_play: self.quick_play,
+        }
+    }
+}
+
+impl<A, C> LaunchContextBuilder<A, Missing, C> {
+    pub fn dirs(self, dirs: GameDirs) -> LaunchContextBuilder<A, GameDirs, C> {
+        LaunchContextBuilder {
+            version_name: self.version_name,
+            assets_index_name: self.assets_index_name,
+            auth: self.auth,
+            dirs,
+            classpath: self.classpath,
+            resolution: self.resolution,
+            quick_play: self.quick_play,
+        }
+    }
+}
+
+impl<A, D> LaunchContextBuilder<A, D, Missing> {
+    // joined with the platform's separator
+    pub fn classpath(self, classpath: String) -> LaunchContextBuilder<A, D, String> {
+        LaunchContextBuilder {
+            version_name: self.version_name,
+            assets_index_name: self.assets_index_name,
+            auth: self.auth,
+            dirs: self.dirs,
+            classpath,
+            resolution: self.resolution,
+            quick_play: self.quick_play,
+        }
+    }
+}
+
+impl<A, D, C> LaunchContextBuilder<A, D, C> {
+    pub fn resolution(mut self, resolution: Option<Resolution>) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    pub fn quick_play(mut self, quick_play: Option<QuickPlay>) -> Self {
+        self.quick_play = quick_play;
+        self
+    }
+}
+
+impl LaunchContextBuilder<AuthValues, GameDirs, String> {
+    pub fn build(self) -> LaunchContext {
+        LaunchContext {
+            version_name: self.version_name,
+            assets_index_name: self.assets_index_name,
+            auth: self.auth,
+            dirs: self.dirs,
+            classpath: self.classpath,
+            resolution: self.resolution,
+            quick_play: self.quick_play,
+        }
+    }
+}
+
+// Mojang's rules for profile names
+pub fn validate_username(name: &str) -> anyhow::Result<()> {
+    if !Regex::new(r"^[A-Za-z0-9_]{3,16}$").unwrap().is_match(name) {
+        return Err(anyhow!(
+            "Invalid username {:?}, expected 3 to 16 letters, digits or underscores",
+            name
+        ));
+    }
+    Ok(())
+}
+
+// host[:port] where host is a DNS name, an IPv4 address or a bracketed IPv6 address
+pub fn validate_server_address(address: &str) -> anyhow::Result<()> {
+    let address_regex = Regex::new(
+        r"^(?:[A-Za-z0-9_](?:[A-Za-z0-9_.-]*[A-Za-z0-9_])?|\[[0-9A-Fa-f:.]+\])(?::(?<port>\d{1,5}))?$",
+    )
+    .unwrap();
+    let valid = address.len() <= MAX_ADDRESS_LEN
+        && address_regex.captures(address).is_some_and(|caps| {
+            caps.name("port")
+                .is_none_or(|port| port.as_str().parse::<u16>().is_ok_and(|port| port > 0))
+        });
+    if !valid {
+        return Err(anyhow!("Invalid server address {:?}", address));
+    }
+    Ok(())
+}
+
+// names the OS accepts for environment variables, checked before they reach the game process
+pub fn validate_env_var(name: &str) -> anyhow::Result<()> {
+    let valid = !name.is_empty() && !name.contains(['=', '\0']);
+    if !valid {
+        return Err(anyhow!("Invalid environment variable name {:?}", name));
+    }
+    Ok(())
+}
+
+// world folder names, which end up both in paths and in the argument vector
+pub fn validate_world_name(world: &str) -> anyhow::Result<()> {
+    let valid = !world.is_empty()
+        && world.len() <= MAX_WORLD_NAME_LEN
+        && !world.starts_with('-')
+        && world != "."
+        && world != ".."
+        && !world.contains(['/', '\\'])
+        && !world.chars().any(char::is_control);
+    if !valid {
+        return Err(anyhow!("Invalid world name {:?}", world));
+    }
+    Ok(())
+}
+
+// checks a value before it's substituted into an argument. `whole_argument` is set when the
+// value makes up the entire argument, where a leading dash would be read as another flag
+pub fn check_value(key: &str, value: &str, whole_argument: bool) -> anyhow::Result<()> {
+    if value.len() > MAX_VALUE_LEN {
+        return Err(anyhow!("Value for ${{{}}} is too long", key));
+    }
+    if value.chars().any(char::is_control) {
+        return Err(anyhow!(
+            "Value for ${{{}}} contains control characters",
+            key
+        ));
+    }
+    if whole_argument && value.starts_with('-') {
+        return Err(anyhow!(
+            "Value for ${{{}}} starts with a dash and would be read as a flag: {:?}",
+            key,
+            value
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rules::Environment, LaunchArgument};
+
+    fn context(player_name: &str, game_directory: &str) -> LaunchContext {
+        LaunchContext::builder("fixture-1.0", "fixture")
+            .auth(AuthValues {
+                player_name: player_name.to_string(),
+                uuid: String::from("fa7dae1b-e8ca-4540-9195-356e364db0af"),
+                access_token: String::from("token"),
+                xuid: String::new(),
+                user_type: UserType::Msa,
+                user_properties: String::from("{}"),
+            })
+            .dirs(GameDirs {
+                game_directory: game_directory.to_string(),
+                assets_root: String::from("/assets"),
+                game_assets: String::from("/assets"),
+                natives_directory: String::from("/natives"),
+            })
+            .classpath(String::from("/a.jar"))
+            .build()
+    }
+
+    fn resolve(arguments: &[&str], context: &LaunchContext) -> anyhow::Result<Vec<String>> {
+        let arguments = arguments
+            .iter()
+            .map(|arg| LaunchArgument::String(arg.to_string()))
+            .collect();
+        crate::resolve_arguments(arguments, context, &Environment::current())
+    }
+
+    #[test]
+    fn values_are_checked_before_substitution() {
+        // a dash is only a flag when the value is the whole argument
+        let dashed = context("-Dlog4j.configurationFile=evil.xml", "/game");
+        let err = resolve(&["--username", "${auth_player_name}"], &dashed).unwrap_err();
+        assert!(err.to_string().contains("starts with a dash"), "{}", err);
+        assert_eq!(
+            resolve(&["-Dfoo=${auth_player_name}"], &dashed).unwrap(),
+            vec!["-Dfoo=-Dlog4j.configurationFile=evil.xml"]
+        );
+
+        let newline = context("Steve\n--demo", "/game");
+        let err = resolve(&["-Dfoo=${auth_player_name}"], &newline).unwrap_err();
+        assert!(err.to_string().contains("control characters"), "{}", err);
+        let err = check_value("auth_player_name", "Steve\u{7}", false).unwrap_err();
+        assert!(err.to_string().contains("control characters"), "{}", err);
+
+        let long = "a".repeat(MAX_VALUE_LEN + 1);
+        let err = resolve(&["${auth_player_name}"], &context(&long, "/game")).unwrap_err();
+        assert!(err.to_string().contains("too long"), "{}", err);
+        check_value("auth_player_name", &long[1..], true).unwrap();
+    }
+
+    #[test]
+    fn legacy_values_with_spaces_stay_one_argument() {
+        let legacy =
+            legacy::arguments("--username ${auth_player_name} --gameDir ${game_directory}");
+        let context = context("Steve", "/home/steve/My Games/minecraft");
+        let args =
+            crate::resolve_arguments(legacy.game, &context, &Environment::current()).unwrap();
+        assert_eq!(
+            args,
+            vec![
+                "--username",
+                "Steve",
+                "--gameDir",
+                "/home/steve/My Games/minecraft"
+            ]
+        );
+    }
+
+    #[test]
+    fn launch_context_only_has_values_for_what_was_set() {
+        let context = LaunchContext::builder("fixture-1.0", "fixture")
+            .auth(AuthValues {
+                player_name: String::from("Steve"),
+                uuid: String::from("fa7dae1b-e8ca-4540-9195-356e364db0af"),
+                access_token: String::from("token"),
+                xuid: String::new(),
+                user_type: UserType::Mojang,
+                user_properties: String::from("{}"),
+            })
+            .dirs(GameDirs {
+                game_directory: String::from("/game"),
+                assets_root: String::from("/assets"),
+                game_assets: String::from("/assets"),
+                natives_directory: String::from("/natives"),
+            })
+            .classpath(String::from("/a.jar:/b.jar"))
+            .quick_play(Some(QuickPlay::Multiplayer(String::from("example.com"))))
+            .build();
+        let value = |key| context.value(key).map(|value| value.into_owned());
+
+        assert_eq!(value("auth_player_name").as_deref(), Some("Steve"));
+        assert_eq!(value("user_type").as_deref(), Some("mojang"));
+        assert_eq!(value("classpath").as_deref(), Some("/a.jar:/b.jar"));
+        assert_eq!(
+            value("quickPlayMultiplayer").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(value("quickPlaySingleplayer"), None);
+        assert_eq!(value("resolution_width"), None);
+        assert_eq!(value("no_such_placeholder"), None);
+        let session = value("auth_session").unwrap();
+        assert!(context.secrets().contains(&session));
+        assert!(context.secrets().contains(&String::from("token")));
+
+        let context = LaunchContext::builder("fixture-1.0", "fixture")
+            .classpath(String::new())
+            .dirs(GameDirs {
+                game_directory: String::from("/game"),
+                assets_root: String::from("/assets"),
+                game_assets: String::from("/assets"),
+                natives_directory: String::from("/natives"),
+            })
+            .auth(AuthValues {
+                player_name: String::from("Steve"),
+                uuid: String::from("fa7dae1b-e8ca-4540-9195-356e364db0af"),
+                access_token: String::from("0"),
+                xuid: String::new(),
+                user_type: UserType::Msa,
+                user_properties: String::from("{}"),
+            })
+            .resolution(Some(Resolution {
+                width: 1280,
+                height: 720,
+            }))
+            .build();
+        assert_eq!(context.value("resolution_width").as_deref(), Some("1280"));
+        assert_eq!(context.value("resolution_height").as_deref(), Some("720"));
+    }
+}
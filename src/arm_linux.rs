@@ -0,0 +1,56 @@
+use std::{collections::BTreeMap, path::Path};
+
+use tracing::debug;
+
+use crate::{rules::Environment, write_atomic};
+
+// Raspberry Pi class boards. Mojang ships neither natives nor a Java runtime for ARM Linux, and
+// the GPUs advertise less OpenGL than newer versions ask for. The profile covers all of it:
+// upstream LWJGL natives (natives::OVERRIDES), a Temurin JRE when no installed Java fits (see
+// adoptium.rs), Mesa overrides and lighter settings for new game dirs
+
+// V3D on the Pi 4 and 5 advertises 3.1, 1.17 and newer ask for a 3.2 core profile
+const MESA_ENV: &[(&str, &str)] = &[
+    ("MESA_GL_VERSION_OVERRIDE", "3.3"),
+    ("MESA_GLSL_VERSION_OVERRIDE", "330"),
+];
+// the first options.txt of a game dir. Keys older or newer versions don't know are ignored
+const DEFAULT_OPTIONS: &[(&str, &str)] = &[
+    ("renderDistance", "6"),
+    ("simulationDistance", "5"),
+    // fast graphics, `fancyGraphics` before 1.16
+    ("graphicsMode", "0"),
+    ("fancyGraphics", "false"),
+    // minimal
+    ("particles", "2"),
+    ("entityShadows", "false"),
+    ("biomeBlendRadius", "0"),
+    ("mipmapLevels", "0"),
+];
+
+pub fn detect(env: &Environment) -> bool {
+    env.os_name == "linux" && matches!(env.os_arch.as_str(), "aarch64" | "arm")
+}
+
+// leaves what the launcher's config or the user's shell already set alone
+pub fn apply_env(env: &mut BTreeMap<String, String>) {
+    for (key, value) in MESA_ENV {
+        if !env.contains_key(*key) && std::env::var_os(key).is_none() {
+            env.insert(key.to_string(), value.to_string());
+        }
+    }
+}
+
+// only for game dirs without an options.txt, settings the player changed stay
+pub async fn write_default_options(game_dir: &Path) -> anyhow::Result<()> {
+    let path = game_dir.join("options.txt");
+    if path.exists() {
+        return Ok(());
+    }
+    debug!(path = %path.display(), "writing arm linux default options");
+    let options = DEFAULT_OPTIONS
+        .iter()
+        .map(|(key, value)| format!("{}:{}\n", key, value))
+        .collect::<String>();
+    write_atomic(&path, options).await
+}
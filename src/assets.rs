@@ -0,0 +1,159 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::{sha1_file, write_atomic};
+
+// when the last full hash pass over the objects finished
+const DEEP_VERIFY_FILE: &str = "last_deep_verify";
+
+// how much a file already on disk is trusted, cheapest first
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetCheck {
+    // objects are written atomically, so one that exists was complete when written
+    #[default]
+    Exists,
+    // also catches truncation and most corruption for the cost of a stat
+    Size,
+    // reads every object, slow on a cold disk
+    Hash,
+}
+
+impl AssetCheck {
+    // files without a known size or hash, like some maven libraries, only have to exist
+    pub fn is_valid(&self, path: &Path, size: u64, sha1: &String) -> bool {
+        match self {
+            AssetCheck::Size if size > 0 => {
+                std::fs::metadata(path).is_ok_and(|metadata| metadata.len() == size)
+            }
+            AssetCheck::Hash if !sha1.is_empty() => sha1_file(path).is_ok_and(|hash| hash == *sha1),
+            _ => path.exists(),
+        }
+    }
+
+    // checks (path, size, sha1) entries on the blocking pool, split across the cores so warm
+    // installs don't hash thousands of files one after another on the runtime. Returns the
+    // indices of the ones that failed
+    pub async fn find_invalid(
+        &self,
+        files: Vec<(PathBuf, u64, String)>,
+    ) -> anyhow::Result<Vec<usize>> {
+        let workers = std::thread::available_parallelism().map_or(4, |workers| workers.get());
+        let batch = files.len().div_ceil(workers).max(1);
+        let files = Arc::new(files);
+
+        let handles = (0..files.len())
+            .step_by(batch)
+            .map(|start| {
+                let check = *self;
+                let files = files.clone();
+                tokio::task::spawn_blocking(move || {
+                    (start..files.len().min(start + batch))
+                        .filter(|&i| {
+                            let (path, size, sha1) = &files[i];
+                            !check.is_valid(path, *size, sha1)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut invalid = Vec::new();
+        for batch in futures::future::join_all(handles).await {
+            invalid.extend(batch?);
+        }
+        Ok(invalid)
+    }
+}
+
+impl fmt::Display for AssetCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AssetCheck::Exists => "exists",
+            AssetCheck::Size => "size",
+            AssetCheck::Hash => "hash",
+        })
+    }
+}
+
+impl FromStr for AssetCheck {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "exists" => Ok(AssetCheck::Exists),
+            "size" => Ok(AssetCheck::Size),
+            "hash" => Ok(AssetCheck::Hash),
+            _ => Err(anyhow!(
+                "Unknown asset check {}, use exists, size or hash",
+                s
+            )),
+        }
+    }
+}
+
+// how much of an existing install is trusted before launching
+#[derive(Debug, Clone, Copy)]
+pub struct VerifyPolicy {
+    pub asset_check: AssetCheck,
+    // hash every asset once this many days passed since the last full pass, whatever
+    // `asset_check` says
+    pub deep_verify_days: Option<u64>,
+    // skip steps whose files still have the size and mtime the last install recorded, without
+    // opening any of them. Otherwise libraries and the client jar are hashed every time
+    pub trust_stamps: bool,
+}
+
+impl Default for VerifyPolicy {
+    fn default() -> Self {
+        VerifyPolicy {
+            asset_check: AssetCheck::default(),
+            deep_verify_days: None,
+            trust_stamps: true,
+        }
+    }
+}
+
+impl VerifyPolicy {
+    // the check to run now, a deep verify when one is due
+    pub fn effective_check(&self, assets_dir: &Path) -> AssetCheck {
+        let Some(days) = self.deep_verify_days else {
+            return self.asset_check;
+        };
+
+        let due = last_deep_verify(assets_dir)
+            .is_none_or(|last| OffsetDateTime::now_utc() - last >= Duration::days(days as i64));
+        if due {
+            AssetCheck::Hash
+        } else {
+            self.asset_check
+        }
+    }
+}
+
+fn deep_verify_path(assets_dir: &Path) -> PathBuf {
+    assets_dir.join(DEEP_VERIFY_FILE)
+}
+
+pub fn last_deep_verify(assets_dir: &Path) -> Option<OffsetDateTime> {
+    let stamp = std::fs::read_to_string(deep_verify_path(assets_dir)).ok()?;
+    OffsetDateTime::parse(
+        stamp.trim(),
+        &time::format_description::well_known::Iso8601::DEFAULT,
+    )
+    .ok()
+}
+
+pub async fn record_deep_verify(assets_dir: &Path) -> anyhow::Result<()> {
+    let now = OffsetDateTime::now_utc()
+        .format(&time::format_description::well_known::Iso8601::DEFAULT)?;
+    write_atomic(&deep_verify_path(assets_dir), now).await
+}
@@ -0,0 +1,930 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::{format_description::well_known::Rfc2822, Duration, OffsetDateTime};
+use tracing::{debug, info, warn};
+
+use crate::net::NetworkStatus;
+
+const DEVICE_CODE_URL: &str = "https://login.microsoftonline.com/consumers/oauth2/v2.0/devicecode";
+const TOKEN_URL: &str = "https://login.microsoftonline.com/consumers/oauth2/v2.0/token";
+const XBOX_AUTH_URL: &str = "https://user.auth.xboxlive.com/user/authenticate";
+const XSTS_AUTH_URL: &str = "https://xsts.auth.xboxlive.com/xsts/authorize";
+const MINECRAFT_LOGIN_URL: &str =
+    "https://api.minecraftservices.com/authentication/login_with_xbox";
+const MINECRAFT_PROFILE_URL: &str = "https://api.minecraftservices.com/minecraft/profile";
+const PLAYER_ATTRIBUTES_URL: &str = "https://api.minecraftservices.com/player/attributes";
+const SESSION_PROFILE_URL: &str = "https://sessionserver.mojang.com/session/minecraft/profile";
+const SESSION_SERVICE: &str = "Minecraft session server";
+// launches don't wait longer than this for fresh profile properties
+const PROPERTIES_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+const FAMILY_SETTINGS_URL: &str = "https://account.xbox.com/Settings";
+const SCOPE: &str = "XboxLive.signin offline_access";
+// refreshed this long before they expire, so a launch never waits on a refresh
+pub const REFRESH_MARGIN: Duration = Duration::minutes(10);
+// after a failure that may go away on its own
+const REFRESH_RETRY: std::time::Duration = std::time::Duration::from_secs(60);
+// when no login expires soon, in case one is added meanwhile
+const REFRESH_IDLE: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+// Xbox Live rejects tokens from a clock further off than this, with errors that don't say why
+const MAX_CLOCK_SKEW: Duration = Duration::minutes(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Account {
+    pub username: String,
+    pub uuid: String,
+    pub access_token: String,
+    #[serde(with = "time::serde::iso8601")]
+    pub expires_at: OffsetDateTime,
+    pub refresh_token: String,
+    pub xuid: Option<String>,
+    // logins saved before privileges were tracked count as unrestricted
+    #[serde(default)]
+    pub privileges: Privileges,
+    // the signed textures property, so LAN games show the right skin and cape even offline
+    #[serde(default)]
+    pub properties: Vec<ProfileProperty>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProfileProperty {
+    pub name: String,
+    // base64 JSON
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl Account {
+    pub fn is_expired(&self) -> bool {
+        OffsetDateTime::now_utc() >= self.expires_at
+    }
+
+    pub fn expires_within(&self, margin: Duration) -> bool {
+        OffsetDateTime::now_utc() + margin >= self.expires_at
+    }
+
+    pub fn restrictions(&self) -> Vec<Restriction> {
+        let privileges = &self.privileges;
+        [
+            (!privileges.multiplayer_server).then_some(Restriction::NoMultiplayer),
+            (!privileges.multiplayer_realms).then_some(Restriction::NoRealms),
+            (!privileges.online_chat).then_some(Restriction::NoChat),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+// what the account's family settings allow, child accounts usually have some of these off
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Privileges {
+    pub online_chat: bool,
+    pub multiplayer_server: bool,
+    pub multiplayer_realms: bool,
+}
+
+impl Default for Privileges {
+    fn default() -> Self {
+        Privileges {
+            online_chat: true,
+            multiplayer_server: true,
+            multiplayer_realms: true,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Restriction {
+    NoMultiplayer,
+    NoRealms,
+    NoChat,
+}
+
+impl fmt::Display for Restriction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self {
+            Restriction::NoMultiplayer => "Joining multiplayer servers",
+            Restriction::NoRealms => "Playing on Realms",
+            Restriction::NoChat => "Online chat",
+        };
+        write!(
+            f,
+            "{} is turned off by this account's family settings, a parent can change that at {}",
+            what, FAMILY_SETTINGS_URL
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct AccountStore {
+    pub selected: Option<String>,
+    pub accounts: Vec<Account>,
+}
+
+impl AccountStore {
+    pub fn path(work_dir: &Path) -> PathBuf {
+        work_dir.join("accounts.json")
+    }
+
+    pub fn load(work_dir: &Path) -> anyhow::Result<AccountStore> {
+        let path = Self::path(work_dir);
+        if !path.exists() {
+            return Ok(AccountStore::default());
+        }
+
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, work_dir: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir_all(work_dir)?;
+        std::fs::write(Self::path(work_dir), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn selected_account(&self) -> Option<&Account> {
+        let selected = self.selected.as_ref()?;
+        self.accounts
+            .iter()
+            .find(|account| &account.uuid == selected)
+    }
+
+    pub fn find(&self, name_or_uuid: &str) -> Option<&Account> {
+        let uuid = name_or_uuid.replace('-', "");
+        self.accounts.iter().find(|account| {
+            account.username.eq_ignore_ascii_case(name_or_uuid)
+                || account.uuid.eq_ignore_ascii_case(&uuid)
+        })
+    }
+
+    // replaces any previous login for the same profile and selects it
+    pub fn upsert(&mut self, account: Account) {
+        self.accounts
+            .retain(|existing| existing.uuid != account.uuid);
+        self.selected = Some(account.uuid.clone());
+        self.accounts.push(account);
+    }
+
+    // swaps in a refreshed login without touching the selection
+    pub fn replace(&mut self, account: Account) {
+        match self
+            .accounts
+            .iter_mut()
+            .find(|existing| existing.uuid == account.uuid)
+        {
+            Some(existing) => *existing = account,
+            None => self.accounts.push(account),
+        }
+    }
+}
+
+// the selected account, or the one named by username or uuid, refreshed first when it expires
+// soon. Without a client id an expired login can't be refreshed, and only a dead refresh token
+// means running `login` again. The caller gets its own copy, concurrent launches never share one.
+// Offline the stored login is used as it is, singleplayer doesn't check it
+pub async fn launch_account(
+    client: &reqwest::Client,
+    client_id: Option<&str>,
+    work_dir: &Path,
+    name: Option<&str>,
+    network: NetworkStatus,
+) -> anyhow::Result<Option<Account>> {
+    let store = AccountStore::load(work_dir)?;
+    let account = match name {
+        Some(name) => Some(
+            store
+                .find(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("No logged in account named {}", name))?,
+        ),
+        None => store.selected_account().cloned(),
+    };
+    let Some(account) = account else {
+        return Ok(None);
+    };
+    if network == NetworkStatus::Offline {
+        if account.is_expired() {
+            warn!(
+                "The login for {} has expired and can't be refreshed offline, multiplayer won't work",
+                account.username
+            );
+        }
+        return Ok(Some(account));
+    }
+    if !account.expires_within(REFRESH_MARGIN) {
+        return Ok(Some(update_properties(client, work_dir, account).await));
+    }
+
+    let refreshed = match client_id {
+        Some(client_id) => refresh(client, client_id, &account).await,
+        None => Err(anyhow!("no client id is configured to refresh it with")),
+    };
+    match refreshed {
+        Ok(refreshed) => {
+            // reloaded so a refresh another launch did meanwhile isn't overwritten
+            let mut store = AccountStore::load(work_dir)?;
+            store.replace(refreshed.clone());
+            store.save(work_dir)?;
+            Ok(Some(refreshed))
+        }
+        // still good for a few minutes, enough to launch
+        Err(e) if !account.is_expired() => {
+            warn!(
+                "Could not refresh the login for {}: {}",
+                account.username, e
+            );
+            Ok(Some(account))
+        }
+        Err(e) => Err(anyhow!(
+            "Your login has expired and could not be refreshed ({}), run `login` again",
+            e
+        )),
+    }
+}
+
+// textures change with the skin and cape, so they are fetched again whenever the session server
+// answers quickly. Offline the ones cached at login are used
+async fn update_properties(client: &reqwest::Client, work_dir: &Path, account: Account) -> Account {
+    let fetched =
+        tokio::time::timeout(PROPERTIES_TIMEOUT, fetch_properties(client, &account.uuid)).await;
+    let properties = match fetched {
+        Ok(Ok(properties)) => properties,
+        Ok(Err(e)) => {
+            debug!("Could not update the profile properties: {}", e);
+            return account;
+        }
+        Err(_) => return account,
+    };
+    if properties == account.properties {
+        return account;
+    }
+
+    let account = Account {
+        properties,
+        ..account
+    };
+    let saved = AccountStore::load(work_dir).and_then(|mut store| {
+        store.replace(account.clone());
+        store.save(work_dir)
+    });
+    if let Err(e) = saved {
+        warn!("Could not save the updated profile properties: {}", e);
+    }
+    account
+}
+
+// keeps every stored login fresh in a long running process, so launches it triggers never
+// block on a refresh. Spawn `run` and drop the task to stop it
+pub struct TokenRefresher {
+    client: reqwest::Client,
+    client_id: String,
+    work_dir: PathBuf,
+    // refresh tokens that were rejected, left alone until the user logs in again
+    dead: HashSet<String>,
+}
+
+impl TokenRefresher {
+    pub fn new(client: reqwest::Client, client_id: String, work_dir: PathBuf) -> TokenRefresher {
+        TokenRefresher {
+            client,
+            client_id,
+            work_dir,
+            dead: HashSet::new(),
+        }
+    }
+
+    pub async fn run(mut self) {
+        loop {
+            let next = self.refresh_due().await;
+            debug!(next_in_secs = next.as_secs(), "token refresh");
+            tokio::time::sleep(next).await;
+        }
+    }
+
+    // refreshes what expires within REFRESH_MARGIN and returns how long until the next one does
+    pub async fn refresh_due(&mut self) -> std::time::Duration {
+        let store = match AccountStore::load(&self.work_dir) {
+            Ok(store) => store,
+            Err(e) => {
+                warn!("Could not read the stored logins: {}", e);
+                return REFRESH_RETRY;
+            }
+        };
+
+        let mut next = REFRESH_IDLE;
+        for account in store.accounts {
+            if self.dead.contains(&account.refresh_token) {
+                continue;
+            }
+            if !account.expires_within(REFRESH_MARGIN) {
+                let due = account.expires_at - REFRESH_MARGIN - OffsetDateTime::now_utc();
+                next = next.min(due.try_into().unwrap_or(REFRESH_RETRY));
+                continue;
+            }
+
+            match refresh(&self.client, &self.client_id, &account).await {
+                Ok(refreshed) => {
+                    info!("Refreshed the login for {}", refreshed.username);
+                    // reloaded so a concurrent `login` isn't overwritten
+                    let saved = AccountStore::load(&self.work_dir).and_then(|mut store| {
+                        store.replace(refreshed);
+                        store.save(&self.work_dir)
+                    });
+                    if let Err(e) = saved {
+                        warn!("Could not save the refreshed login: {}", e);
+                        next = next.min(REFRESH_RETRY);
+                    }
+                }
+                Err(e) => {
+                    let retryable = e
+                        .downcast_ref::<AuthError>()
+                        .is_some_and(AuthError::is_retryable);
+                    if retryable {
+                        warn!(
+                            "Could not refresh the login for {}, retrying: {}",
+                            account.username, e
+                        );
+                        next = next.min(REFRESH_RETRY);
+                    } else {
+                        warn!(
+                            "The login for {} needs `login` again: {}",
+                            account.username, e
+                        );
+                        self.dead.insert(account.refresh_token);
+                    }
+                }
+            }
+        }
+        next.max(std::time::Duration::from_secs(1))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeviceCode {
+    pub user_code: String,
+    pub device_code: String,
+    pub verification_uri: String,
+    pub expires_in: u64,
+    pub interval: u64,
+    pub message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct MsaToken {
+    access_token: String,
+    refresh_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct MsaTokenError {
+    error: String,
+    error_description: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct XboxToken {
+    token: String,
+    display_claims: XboxDisplayClaims,
+}
+
+#[derive(Deserialize, Debug)]
+struct XboxDisplayClaims {
+    xui: Vec<XboxUserInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+struct XboxUserInfo {
+    uhs: String,
+    xid: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MinecraftToken {
+    access_token: String,
+    expires_in: i64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PlayerAttributes {
+    privileges: PlayerPrivileges,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PlayerPrivileges {
+    online_chat: Privilege,
+    multiplayer_server: Privilege,
+    multiplayer_realms: Privilege,
+}
+
+#[derive(Deserialize, Debug)]
+struct Privilege {
+    enabled: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct SessionProfile {
+    #[serde(default)]
+    properties: Vec<ProfileProperty>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MinecraftProfile {
+    id: String,
+    name: String,
+}
+
+// Xbox Live's XErr codes for accounts that can't play yet
+const XERR_NO_XBOX_PROFILE: u64 = 2148916233;
+const XERR_REGION_UNAVAILABLE: u64 = 2148916235;
+const XERR_ADULT_VERIFICATION: u64 = 2148916236;
+const XERR_AGE_VERIFICATION: u64 = 2148916237;
+const XERR_CHILD_ACCOUNT: u64 = 2148916238;
+
+const MSA_SERVICE: &str = "Microsoft login";
+const XBOX_SERVICE: &str = "Xbox Live";
+const XSTS_SERVICE: &str = "Xbox Live security";
+const MINECRAFT_SERVICE: &str = "Minecraft services";
+
+// why a login or refresh failed, in terms the user can act on. Returned inside anyhow errors,
+// downcast to tell them apart
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum AuthError {
+    ClockSkew {
+        minutes: i64,
+        ahead: bool,
+    },
+    Declined,
+    DeviceCodeExpired,
+    // the refresh token was revoked or is too old to use
+    LoginExpired {
+        reason: String,
+    },
+    NoXboxProfile,
+    RegionUnavailable,
+    AdultVerificationRequired,
+    // under 18 and not added to a Microsoft family
+    ChildAccount,
+    NotOwned,
+    // unreachable, rate limited or failing on the server side
+    Unavailable {
+        service: &'static str,
+        status: Option<u16>,
+    },
+    Rejected {
+        service: &'static str,
+        message: String,
+    },
+}
+
+impl AuthError {
+    // whether the same request could succeed later without the user changing anything
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, AuthError::Unavailable { .. })
+    }
+
+    fn from_xerr(xerr: u64) -> Option<AuthError> {
+        match xerr {
+            XERR_NO_XBOX_PROFILE => Some(AuthError::NoXboxProfile),
+            XERR_REGION_UNAVAILABLE => Some(AuthError::RegionUnavailable),
+            XERR_ADULT_VERIFICATION | XERR_AGE_VERIFICATION => {
+                Some(AuthError::AdultVerificationRequired)
+            }
+            XERR_CHILD_ACCOUNT => Some(AuthError::ChildAccount),
+            _ => None,
+        }
+    }
+
+    fn request_failed(service: &'static str) -> impl Fn(reqwest::Error) -> AuthError {
+        move |error| {
+            if error.is_decode() {
+                AuthError::Rejected {
+                    service,
+                    message: format!("unexpected response, {}", error),
+                }
+            } else {
+                AuthError::Unavailable {
+                    service,
+                    status: error.status().map(|status| status.as_u16()),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::ClockSkew { minutes, ahead } => write!(
+                f,
+                "Your system clock is {} minutes {}, fix your system clock and try again, Microsoft login fails otherwise",
+                minutes,
+                if *ahead { "ahead" } else { "behind" }
+            ),
+            AuthError::Declined => write!(f, "The login was declined in the browser"),
+            AuthError::DeviceCodeExpired => {
+                write!(f, "Login timed out, the device code expired. Run `login` again")
+            }
+            AuthError::LoginExpired { reason } => {
+                write!(f, "Your login has expired ({}), run `login` again", reason)
+            }
+            AuthError::NoXboxProfile => write!(
+                f,
+                "This Microsoft account has no Xbox profile yet. Sign in once at https://www.xbox.com/live to create one, then try again"
+            ),
+            AuthError::RegionUnavailable => write!(
+                f,
+                "Xbox Live is not available in this account's country, so it can't log in to Minecraft"
+            ),
+            AuthError::AdultVerificationRequired => write!(
+                f,
+                "This account needs adult verification on https://account.xbox.com before it can log in"
+            ),
+            AuthError::ChildAccount => write!(
+                f,
+                "This is a child account. An adult has to add it to a Microsoft family at https://account.microsoft.com/family before it can log in, and may have to allow multiplayer in its family settings"
+            ),
+            AuthError::NotOwned => write!(f, "This Microsoft account does not own Minecraft"),
+            AuthError::Unavailable {
+                service,
+                status: Some(status),
+            } => write!(
+                f,
+                "{} is unavailable (HTTP {}), try again later",
+                service, status
+            ),
+            AuthError::Unavailable {
+                service,
+                status: None,
+            } => write!(
+                f,
+                "Could not reach {}, check your connection and try again",
+                service
+            ),
+            AuthError::Rejected { service, message } => {
+                write!(f, "{} rejected the login: {}", service, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+#[derive(Deserialize, Debug)]
+struct XboxError {
+    #[serde(rename = "XErr")]
+    xerr: u64,
+    #[serde(rename = "Message", default)]
+    message: String,
+}
+
+// a non-success response as an AuthError, rate limits and server errors are worth retrying
+async fn check_response(
+    service: &'static str,
+    response: reqwest::Response,
+) -> Result<reqwest::Response, AuthError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        return Err(AuthError::Unavailable {
+            service,
+            status: Some(status.as_u16()),
+        });
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    if let Ok(error) = serde_json::from_str::<XboxError>(&body) {
+        if let Some(error) = AuthError::from_xerr(error.xerr) {
+            return Err(error);
+        }
+        return Err(AuthError::Rejected {
+            service,
+            message: format!("XErr {} {}", error.xerr, error.message)
+                .trim()
+                .to_string(),
+        });
+    }
+    Err(AuthError::Rejected {
+        service,
+        message: format!("HTTP {}", status.as_u16()),
+    })
+}
+
+pub async fn login(
+    client: &reqwest::Client,
+    client_id: &str,
+    on_code: impl FnOnce(&DeviceCode),
+) -> anyhow::Result<Account> {
+    check_clock(client).await?;
+    let response = client
+        .post(DEVICE_CODE_URL)
+        .form(&[("client_id", client_id), ("scope", SCOPE)])
+        .send()
+        .await
+        .map_err(AuthError::request_failed(MSA_SERVICE))?;
+    let device_code = check_response(MSA_SERVICE, response)
+        .await?
+        .json::<DeviceCode>()
+        .await
+        .map_err(AuthError::request_failed(MSA_SERVICE))?;
+    on_code(&device_code);
+
+    let msa_token = poll_device_code(client, client_id, &device_code).await?;
+    authenticate_minecraft(client, msa_token).await
+}
+
+pub async fn refresh(
+    client: &reqwest::Client,
+    client_id: &str,
+    account: &Account,
+) -> anyhow::Result<Account> {
+    check_clock(client).await?;
+    let response = client
+        .post(TOKEN_URL)
+        .form(&[
+            ("client_id", client_id),
+            ("grant_type", "refresh_token"),
+            ("refresh_token", account.refresh_token.as_str()),
+            ("scope", SCOPE),
+        ])
+        .send()
+        .await
+        .map_err(AuthError::request_failed(MSA_SERVICE))?;
+
+    let status = response.status();
+    if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let error = response
+            .json::<MsaTokenError>()
+            .await
+            .map_err(AuthError::request_failed(MSA_SERVICE))?;
+        let reason = error.error_description.unwrap_or(error.error.clone());
+        if error.error == "invalid_grant" {
+            return Err(AuthError::LoginExpired { reason }.into());
+        }
+        return Err(AuthError::Rejected {
+            service: MSA_SERVICE,
+            message: reason,
+        }
+        .into());
+    }
+
+    let msa_token = check_response(MSA_SERVICE, response)
+        .await?
+        .json::<MsaToken>()
+        .await
+        .map_err(AuthError::request_failed(MSA_SERVICE))?;
+    authenticate_minecraft(client, msa_token).await
+}
+
+// compares the local clock with the Date header of the Xbox auth server. When the server can't
+// be reached or sends no usable date the check is skipped, auth then reports the real problem
+pub async fn check_clock(client: &reqwest::Client) -> Result<(), AuthError> {
+    let Ok(response) = client.head(XBOX_AUTH_URL).send().await else {
+        return Ok(());
+    };
+    let server_time = response
+        .headers()
+        .get(reqwest::header::DATE)
+        .and_then(|date| date.to_str().ok())
+        .and_then(|date| OffsetDateTime::parse(date, &Rfc2822).ok());
+    let Some(server_time) = server_time else {
+        return Ok(());
+    };
+
+    let skew = OffsetDateTime::now_utc() - server_time;
+    if skew.abs() > MAX_CLOCK_SKEW {
+        return Err(AuthError::ClockSkew {
+            minutes: skew.whole_minutes().abs(),
+            ahead: skew.is_positive(),
+        });
+    }
+    Ok(())
+}
+
+async fn poll_device_code(
+    client: &reqwest::Client,
+    client_id: &str,
+    device_code: &DeviceCode,
+) -> anyhow::Result<MsaToken> {
+    let deadline = OffsetDateTime::now_utc() + Duration::seconds(device_code.expires_in as i64);
+    let mut interval = std::time::Duration::from_secs(device_code.interval.max(1));
+
+    while OffsetDateTime::now_utc() < deadline {
+        tokio::time::sleep(interval).await;
+
+        let response = client
+            .post(TOKEN_URL)
+            .form(&[
+                ("client_id", client_id),
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                ("device_code", device_code.device_code.as_str()),
+            ])
+            .send()
+            .await
+            .map_err(AuthError::request_failed(MSA_SERVICE))?;
+
+        if response.status().is_success() {
+            return Ok(response
+                .json::<MsaToken>()
+                .await
+                .map_err(AuthError::request_failed(MSA_SERVICE))?);
+        }
+        if response.status().is_server_error() {
+            // the code stays valid, keep polling
+            continue;
+        }
+
+        let error = response
+            .json::<MsaTokenError>()
+            .await
+            .map_err(AuthError::request_failed(MSA_SERVICE))?;
+        match error.error.as_str() {
+            "authorization_pending" => continue,
+            "slow_down" => interval += std::time::Duration::from_secs(5),
+            "authorization_declined" => return Err(AuthError::Declined.into()),
+            "expired_token" => return Err(AuthError::DeviceCodeExpired.into()),
+            _ => {
+                return Err(AuthError::Rejected {
+                    service: MSA_SERVICE,
+                    message: error.error_description.unwrap_or(error.error),
+                }
+                .into())
+            }
+        }
+    }
+
+    Err(AuthError::DeviceCodeExpired.into())
+}
+
+async fn authorize_xbox(
+    client: &reqwest::Client,
+    service: &'static str,
+    url: &str,
+    body: serde_json::Value,
+) -> Result<XboxToken, AuthError> {
+    let response = client
+        .post(url)
+        .json(&body)
+        .send()
+        .await
+        .map_err(AuthError::request_failed(service))?;
+    check_response(service, response)
+        .await?
+        .json::<XboxToken>()
+        .await
+        .map_err(AuthError::request_failed(service))
+}
+
+async fn authenticate_minecraft(
+    client: &reqwest::Client,
+    msa_token: MsaToken,
+) -> anyhow::Result<Account> {
+    let xbox_token = authorize_xbox(
+        client,
+        XBOX_SERVICE,
+        XBOX_AUTH_URL,
+        json!({
+            "Properties": {
+                "AuthMethod": "RPS",
+                "SiteName": "user.auth.xboxlive.com",
+                "RpsTicket": format!("d={}", msa_token.access_token),
+            },
+            "RelyingParty": "http://auth.xboxlive.com",
+            "TokenType": "JWT",
+        }),
+    )
+    .await?;
+
+    // child accounts, missing profiles and banned regions surface here as XErr codes
+    let xsts_token = authorize_xbox(
+        client,
+        XSTS_SERVICE,
+        XSTS_AUTH_URL,
+        json!({
+            "Properties": {
+                "SandboxId": "RETAIL",
+                "UserTokens": [xbox_token.token],
+            },
+            "RelyingParty": "rp://api.minecraftservices.com/",
+            "TokenType": "JWT",
+        }),
+    )
+    .await?;
+
+    let user_info = xsts_token
+        .display_claims
+        .xui
+        .first()
+        .ok_or_else(|| anyhow!("XSTS response did not contain a user hash"))?;
+
+    let response = client
+        .post(MINECRAFT_LOGIN_URL)
+        .json(&json!({
+            "identityToken": format!("XBL3.0 x={};{}", user_info.uhs, xsts_token.token),
+        }))
+        .send()
+        .await
+        .map_err(AuthError::request_failed(MINECRAFT_SERVICE))?;
+    let minecraft_token = check_response(MINECRAFT_SERVICE, response)
+        .await?
+        .json::<MinecraftToken>()
+        .await
+        .map_err(AuthError::request_failed(MINECRAFT_SERVICE))?;
+
+    let profile_response = client
+        .get(MINECRAFT_PROFILE_URL)
+        .bearer_auth(&minecraft_token.access_token)
+        .send()
+        .await
+        .map_err(AuthError::request_failed(MINECRAFT_SERVICE))?;
+    if profile_response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(AuthError::NotOwned.into());
+    }
+    let profile = check_response(MINECRAFT_SERVICE, profile_response)
+        .await?
+        .json::<MinecraftProfile>()
+        .await
+        .map_err(AuthError::request_failed(MINECRAFT_SERVICE))?;
+
+    // only informs the user, a login shouldn't fail over it
+    let privileges = fetch_privileges(client, &minecraft_token.access_token)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Could not check the account's privileges: {}", e);
+            Privileges::default()
+        });
+
+    let properties = fetch_properties(client, &profile.id)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Could not fetch the profile's textures: {}", e);
+            vec![]
+        });
+
+    Ok(Account {
+        username: profile.name,
+        uuid: profile.id,
+        access_token: minecraft_token.access_token,
+        expires_at: OffsetDateTime::now_utc() + Duration::seconds(minecraft_token.expires_in),
+        refresh_token: msa_token.refresh_token,
+        xuid: user_info.xid.clone(),
+        privileges,
+        properties,
+    })
+}
+
+// the profile's properties with their signatures, which servers check before showing textures
+pub async fn fetch_properties(
+    client: &reqwest::Client,
+    uuid: &str,
+) -> Result<Vec<ProfileProperty>, AuthError> {
+    let response = client
+        .get(format!("{}/{}", SESSION_PROFILE_URL, uuid.replace('-', "")))
+        .query(&[("unsigned", "false")])
+        .send()
+        .await
+        .map_err(AuthError::request_failed(SESSION_SERVICE))?;
+    let profile = check_response(SESSION_SERVICE, response)
+        .await?
+        .json::<SessionProfile>()
+        .await
+        .map_err(AuthError::request_failed(SESSION_SERVICE))?;
+    Ok(profile.properties)
+}
+
+async fn fetch_privileges(
+    client: &reqwest::Client,
+    access_token: &str,
+) -> Result<Privileges, AuthError> {
+    let response = client
+        .get(PLAYER_ATTRIBUTES_URL)
+        .bearer_auth(access_token)
+        .send()
+        .await
+        .map_err(AuthError::request_failed(MINECRAFT_SERVICE))?;
+    let attributes = check_response(MINECRAFT_SERVICE, response)
+        .await?
+        .json::<PlayerAttributes>()
+        .await
+        .map_err(AuthError::request_failed(MINECRAFT_SERVICE))?;
+    Ok(Privileges {
+        online_chat: attributes.privileges.online_chat.enabled,
+        multiplayer_server: attributes.privileges.multiplayer_server.enabled,
+        multiplayer_realms: attributes.privileges.multiplayer_realms.enabled,
+    })
+}
@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use base64::Engine;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use crate::{net::Downloader, write_atomic};
+
+const LATEST_URL: &str = "https://authlib-injector.yushi.moe/artifact/latest.json";
+// servers may publish their API somewhere else than the address users know them by
+const API_LOCATION_HEADER: &str = "x-authlib-injector-api-location";
+const RECORD_FILE: &str = "authlib-injector.json";
+
+// what the injector release feed says about a build, and what is kept next to the jar
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct InjectorRelease {
+    version: String,
+    download_url: String,
+    checksums: InjectorChecksums,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct InjectorChecksums {
+    sha256: String,
+}
+
+fn injector_dir(cache_dir: &Path) -> PathBuf {
+    cache_dir.join("authlib-injector")
+}
+
+// the -javaagent arguments that point the game at a Yggdrasil-compatible auth server like
+// ely.by or Blessing Skin instead of Mojang. The injector is downloaded once and its hash
+// checked on every launch
+pub async fn jvm_args(
+    client: &reqwest::Client,
+    downloader: &dyn Downloader,
+    cache_dir: &Path,
+    server: &str,
+) -> anyhow::Result<Vec<String>> {
+    let (api_url, metadata) = resolve_api(client, server).await?;
+    let jar = ensure_injector(downloader, cache_dir).await?;
+    debug!(api = %api_url, jar = %jar.display(), "using authlib-injector");
+
+    let jar = dunce::canonicalize(&jar)?;
+    Ok(vec![
+        format!("-javaagent:{}={}", jar.display(), api_url),
+        // saves the injector fetching the metadata again before the game window opens
+        format!(
+            "-Dauthlibinjector.yggdrasil.prefetched={}",
+            base64::engine::general_purpose::STANDARD.encode(metadata)
+        ),
+    ])
+}
+
+// follows the API location header and returns the API root along with its metadata
+async fn resolve_api(client: &reqwest::Client, server: &str) -> anyhow::Result<(Url, Vec<u8>)> {
+    let mut url =
+        Url::parse(server).map_err(|e| anyhow!("Invalid auth server URL {}: {}", server, e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(anyhow!("Auth server URL {} must be http(s)", server));
+    }
+
+    // one redirect is all the spec allows
+    for _ in 0..2 {
+        let response = client
+            .get(url.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Could not reach the auth server at {}", url))?;
+        let location = response
+            .headers()
+            .get(API_LOCATION_HEADER)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| url.join(location).ok())
+            .filter(|location| location != &url);
+        match location {
+            Some(location) => url = location,
+            None => return Ok((url, response.bytes().await?.to_vec())),
+        }
+    }
+    Err(anyhow!("The auth server at {} redirects too often", server))
+}
+
+async fn ensure_injector(downloader: &dyn Downloader, cache_dir: &Path) -> anyhow::Result<PathBuf> {
+    let dir = injector_dir(cache_dir);
+    let recorded = std::fs::read_to_string(dir.join(RECORD_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_str::<InjectorRelease>(&json).ok());
+    if let Some(release) = recorded {
+        let jar = dir.join(jar_name(&release));
+        if std::fs::read(&jar).is_ok_and(|bytes| sha256_matches(&bytes, &release.checksums.sha256))
+        {
+            return Ok(jar);
+        }
+        warn!("The cached authlib-injector is missing or corrupt, downloading it again");
+    }
+
+    let release: InjectorRelease = serde_json::from_slice(&downloader.fetch(LATEST_URL).await?)
+        .context("Unexpected authlib-injector release metadata")?;
+    let bytes = downloader.fetch(&release.download_url).await?;
+    if !sha256_matches(&bytes, &release.checksums.sha256) {
+        return Err(anyhow!(
+            "Incorrect hash for authlib-injector {}",
+            release.version
+        ));
+    }
+
+    let jar = dir.join(jar_name(&release));
+    write_atomic(&jar, bytes).await?;
+    write_atomic(
+        &dir.join(RECORD_FILE),
+        serde_json::to_string_pretty(&release)?,
+    )
+    .await?;
+    Ok(jar)
+}
+
+fn jar_name(release: &InjectorRelease) -> String {
+    // the version comes from the network, keep it to a plain file name
+    let version = release.version.replace(
+        |c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-',
+        "_",
+    );
+    format!("authlib-injector-{}.jar", version)
+}
+
+fn sha256_matches(bytes: &[u8], sha256: &str) -> bool {
+    let hash = Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    hash.eq_ignore_ascii_case(sha256)
+}
@@ -0,0 +1,39 @@
+use serde::Serialize;
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
+
+// the version with the commit as semver build metadata, e.g. "0.1.0+4f2a9c1e0b3d". Goes into
+// `${launcher_version}`, which the game prints in crash reports, and into user agents
+pub const VERSION: &str = env!("MOD_LAUNCHER_VERSION");
+pub const USER_AGENT: &str = concat!("mod_launcher/", env!("MOD_LAUNCHER_VERSION"));
+
+// optional features compiled in, see Cargo.toml
+const FEATURES: &[(&str, bool)] = &[("client", cfg!(feature = "client"))];
+
+// which launcher build this is, stamped by build.rs. Builds from outside a git checkout have
+// no hash, builds without SOURCE_DATE_EPOCH outside one no date
+#[derive(Serialize, Debug, Clone)]
+pub struct BuildInfo {
+    // VERSION without the hash
+    pub version: &'static str,
+    pub git_hash: Option<&'static str>,
+    pub build_date: Option<String>,
+    pub features: Vec<&'static str>,
+}
+
+pub fn build_info() -> BuildInfo {
+    let build_date = option_env!("MOD_LAUNCHER_BUILD_EPOCH")
+        .and_then(|epoch| epoch.parse().ok())
+        .and_then(|epoch| OffsetDateTime::from_unix_timestamp(epoch).ok())
+        .and_then(|date| date.format(&Iso8601::DEFAULT).ok());
+
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: option_env!("MOD_LAUNCHER_GIT_HASH"),
+        build_date,
+        features: FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(feature, _)| *feature)
+            .collect(),
+    }
+}
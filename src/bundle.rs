@@ -0,0 +1,461 @@
+use std::{
+    collections::HashSet,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tracing::{debug, info};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    adoptium, cache, dedup,
+    instance::{self, Instance},
+    mods,
+    net::Downloader,
+    portable,
+    rules::Environment,
+    sha1_file, verify_installed, versions, AssetIndex,
+};
+
+const MANIFEST_FILE: &str = "bundle.json";
+const FORMAT_VERSION: u32 = 1;
+// entries go into the shared cache or the bundled instance's game dir
+const CACHE_PREFIX: &str = "cache/";
+const INSTANCE_PREFIX: &str = "instance/";
+
+// what a bundle is made from
+pub enum BundleSource<'a> {
+    Version(&'a str),
+    // the instance's version, mods and settings
+    Instance {
+        instances_dir: &'a Path,
+        name: &'a str,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct BundleManifest {
+    format_version: u32,
+    version: String,
+    instance: Option<Instance>,
+    // everything else in the archive, with what it has to hash to
+    files: Vec<BundledFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct BundledFile {
+    path: String,
+    sha1: String,
+    size: u64,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct BundleReport {
+    pub path: PathBuf,
+    pub version: String,
+    pub files: usize,
+    pub bytes: u64,
+    // Java runtimes that came along, by their directory under runtimes/
+    pub runtimes: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct InstalledBundle {
+    pub version: String,
+    pub instance: Option<String>,
+    // files taken from the bundle, the rest were in the cache already
+    pub written: usize,
+    pub verified: usize,
+    // the bundled Java for this machine, when there is one. A bundled instance uses it
+    pub java_path: Option<PathBuf>,
+}
+
+// packs `source` with everything it needs to run on a machine without network access into
+// `out`: version JSONs, the client jar, libraries for machines like this one, assets and, for
+// an instance, its mods and settings. The version has to be installed. Java runtimes in the
+// cache for the version's Java come along, `java` fetches Temurin's first
+pub async fn create(
+    downloader: &dyn Downloader,
+    cache_dir: &Path,
+    source: BundleSource<'_>,
+    out: &Path,
+    java: bool,
+) -> anyhow::Result<BundleReport> {
+    let (version, instance) = match source {
+        BundleSource::Version(version) => (version.to_string(), None),
+        BundleSource::Instance {
+            instances_dir,
+            name,
+        } => {
+            let instance = instance::load(instances_dir, name)?;
+            let game_dir = instance.game_dir(instances_dir);
+            (instance.version.clone(), Some((instance, game_dir)))
+        }
+    };
+    let info = versions::resolve_installed(cache_dir, &version)
+        .await
+        .with_context(|| format!("Install {} before bundling it", version))?;
+    let environment = Environment::current();
+    let major = info.java_version.major_version;
+    if java {
+        if std::env::consts::OS != "linux" {
+            return Err(anyhow!("Java runtimes can only be bundled on Linux"));
+        }
+        adoptium::ensure_runtime(downloader, cache_dir, major, &environment.os_arch).await?;
+    }
+
+    let mut cached = Vec::new();
+    for id in versions::lineage(cache_dir, &version)? {
+        cached.push(versions::json_path(cache_dir, &id));
+    }
+    cached.push(versions::jar_path(cache_dir, &info.jar));
+    let libraries_dir = cache::libraries_dir(cache_dir);
+    for artifact in info.library_artifacts(&environment) {
+        cached.push(libraries_dir.join(&artifact.path));
+    }
+    let assets_dir = cache::assets_dir(cache_dir);
+    let index_file = assets_dir
+        .join("indexes")
+        .join(format!("{}.json", info.asset_index.id));
+    let index: AssetIndex = serde_json::from_str(&std::fs::read_to_string(&index_file)?)?;
+    cached.push(index_file);
+    for object in index.objects.values() {
+        cached.push(assets_dir.join("objects").join(object.path()));
+    }
+    let mut runtimes = Vec::new();
+    let runtime_prefix = format!("temurin-{}-", major);
+    for entry in std::fs::read_dir(cache::runtimes_dir(cache_dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(&runtime_prefix) && entry.path().is_dir() {
+            cached.extend(dedup::walk_files(&entry.path())?);
+            runtimes.push(name);
+        }
+    }
+
+    let mut entries = Vec::new();
+    for file in cached {
+        let relative = file.strip_prefix(cache_dir)?;
+        if !file.is_file() {
+            return Err(anyhow!(
+                "{} is missing from the cache, install {} again",
+                relative.display(),
+                version
+            ));
+        }
+        entries.push((format!("{}{}", CACHE_PREFIX, entry_name(relative)), file));
+    }
+    let instance = match instance {
+        Some((instance, game_dir)) => {
+            let dirs = portable::OVERRIDES
+                .iter()
+                .map(|entry| game_dir.join(entry))
+                .chain(Some(mods::mods_dir(&game_dir)));
+            for path in dirs {
+                let files = match path.is_file() {
+                    true => vec![path],
+                    false => dedup::walk_files(&path)?,
+                };
+                for file in files {
+                    let relative = entry_name(file.strip_prefix(&game_dir)?);
+                    entries.push((format!("{}{}", INSTANCE_PREFIX, relative), file));
+                }
+            }
+            // nothing that only makes sense on this machine
+            Some(Instance {
+                java_path: None,
+                custom_game_dir: None,
+                account: None,
+                ca_certs: vec![],
+                components: vec![],
+                last_played: None,
+                ..instance
+            })
+        }
+        None => None,
+    };
+
+    let manifest = BundleManifest {
+        format_version: FORMAT_VERSION,
+        version: version.clone(),
+        instance,
+        files: vec![],
+    };
+    let path = out.to_path_buf();
+    let (files, bytes) =
+        tokio::task::spawn_blocking(move || write_bundle(&path, manifest, entries)).await??;
+    info!("Bundled {} with {} files", version, files);
+    Ok(BundleReport {
+        path: out.to_path_buf(),
+        version,
+        files,
+        bytes,
+        runtimes,
+    })
+}
+
+fn entry_name(relative: &Path) -> String {
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+// the files are hashed as they go in, bundle.json goes last with the hashes. Returns how many
+// files went in and their size
+fn write_bundle(
+    out: &Path,
+    mut manifest: BundleManifest,
+    entries: Vec<(String, PathBuf)>,
+) -> anyhow::Result<(usize, u64)> {
+    let mut partial = out.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let mut zip = ZipWriter::new(std::fs::File::create(&partial)?);
+    // jars, sounds and textures are compressed already
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut seen = HashSet::new();
+    let mut bytes = 0;
+    for (name, file) in entries {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let metadata = std::fs::metadata(&file)?;
+        let mut options = stored;
+        // runtimes need their executables to stay executable
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            options = options.unix_permissions(metadata.permissions().mode());
+        }
+        zip.start_file(name.as_str(), options)?;
+        let mut source = std::fs::File::open(&file)?;
+        let mut hasher = Sha1::new();
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = source.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            zip.write_all(&buffer[..read])?;
+        }
+        bytes += metadata.len();
+        manifest.files.push(BundledFile {
+            path: name,
+            sha1: format!("{:x}", hasher.finalize()),
+            size: metadata.len(),
+        });
+    }
+    zip.start_file(
+        MANIFEST_FILE,
+        FileOptions::default().compression_method(CompressionMethod::Deflated),
+    )?;
+    zip.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    zip.finish()?.flush()?;
+    std::fs::rename(&partial, out)?;
+    Ok((manifest.files.len(), bytes))
+}
+
+// an entry's path below its prefix, None for anything that could land outside of it
+fn safe_path(path: &str) -> Option<PathBuf> {
+    let parts = path.split('/').collect::<Vec<_>>();
+    let unsafe_part = |part: &&str| {
+        part.is_empty() || *part == "." || *part == ".." || part.contains(['\\', ':'])
+    };
+    match parts.iter().any(unsafe_part) {
+        true => None,
+        false => Some(parts.iter().collect()),
+    }
+}
+
+// provisions what a bundle holds without touching the network. Every file is checked against
+// the hash it was bundled with, and the version against its own JSONs once it is in place. A
+// bundled instance is created as `name`, the name it was bundled with by default
+pub async fn install(
+    cache_dir: &Path,
+    instances_dir: &Path,
+    bundle: &Path,
+    name: Option<&str>,
+) -> anyhow::Result<InstalledBundle> {
+    let manifest = read_manifest(bundle)?;
+    let mut instance = manifest.instance.clone();
+    if let Some(instance) = &mut instance {
+        if let Some(name) = name {
+            instance.name = name.to_string();
+        }
+        if instance::load(instances_dir, &instance.name).is_ok() {
+            return Err(anyhow!(
+                "An instance named {} already exists, install the bundle under another name",
+                instance.name
+            ));
+        }
+    }
+    let game_dir = instance
+        .as_ref()
+        .map(|instance| instance.game_dir(instances_dir));
+
+    let result = async {
+        let written = tokio::task::spawn_blocking({
+            let (bundle, cache_dir) = (bundle.to_path_buf(), cache_dir.to_path_buf());
+            let files = manifest.files;
+            let game_dir = game_dir.clone();
+            move || extract(&bundle, &files, &cache_dir, game_dir.as_deref())
+        })
+        .await??;
+
+        let report = verify_installed(cache_dir, &manifest.version).await?;
+        if !report.is_ok() {
+            return Err(anyhow!(
+                "{} is incomplete after installing the bundle: {} files missing, {} corrupt",
+                manifest.version,
+                report.missing.len(),
+                report.corrupt.len()
+            ));
+        }
+        anyhow::Ok((written, report.checked))
+    }
+    .await;
+    let (written, verified) = match result {
+        Ok(counts) => counts,
+        Err(e) => {
+            // a half installed instance would only be in the way of trying again
+            if let Some(instance) = &instance {
+                let _ = std::fs::remove_dir_all(instance.dir(instances_dir));
+            }
+            return Err(e);
+        }
+    };
+
+    let info = versions::resolve_installed(cache_dir, &manifest.version).await?;
+    let runtime = adoptium::runtime_dir(
+        cache_dir,
+        info.java_version.major_version,
+        &Environment::current().os_arch,
+    );
+    let java_path = Some(adoptium::java_binary(&runtime)).filter(|java| java.is_file());
+    if let Some(instance) = &mut instance {
+        instance.java_path = java_path.clone();
+        instance::save(instances_dir, instance)?;
+    }
+    Ok(InstalledBundle {
+        version: manifest.version,
+        instance: instance.map(|instance| instance.name),
+        written,
+        verified,
+        java_path,
+    })
+}
+
+fn read_manifest(bundle: &Path) -> anyhow::Result<BundleManifest> {
+    let mut archive = ZipArchive::new(
+        std::fs::File::open(bundle).with_context(|| format!("Missing {}", bundle.display()))?,
+    )?;
+    let mut manifest = String::new();
+    archive
+        .by_name(MANIFEST_FILE)
+        .with_context(|| format!("{} is not a bundle", bundle.display()))?
+        .read_to_string(&mut manifest)?;
+    let manifest: BundleManifest = serde_json::from_str(&manifest)?;
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(anyhow!(
+            "{} was made by a newer launcher, update to install it",
+            bundle.display()
+        ));
+    }
+
+    // nothing goes in without a hash to check it against
+    let listed = manifest
+        .files
+        .iter()
+        .map(|file| file.path.as_str())
+        .collect::<HashSet<_>>();
+    if let Some(name) = archive
+        .file_names()
+        .find(|name| *name != MANIFEST_FILE && !listed.contains(name))
+    {
+        return Err(anyhow!(
+            "{} in the bundle is not in its {}",
+            name,
+            MANIFEST_FILE
+        ));
+    }
+    for file in &manifest.files {
+        let safe = match file.path.split_once('/') {
+            Some(("cache", path)) => safe_path(path),
+            Some(("instance", path)) if manifest.instance.is_some() => safe_path(path),
+            _ => None,
+        };
+        if safe.is_none() {
+            return Err(anyhow!(
+                "Refusing to install {:?} from the bundle",
+                file.path
+            ));
+        }
+    }
+    Ok(manifest)
+}
+
+// returns how many files were written
+fn extract(
+    bundle: &Path,
+    files: &[BundledFile],
+    cache_dir: &Path,
+    game_dir: Option<&Path>,
+) -> anyhow::Result<usize> {
+    let mut archive = ZipArchive::new(std::fs::File::open(bundle)?)?;
+    let mut written = 0;
+    for file in files {
+        let target = match (file.path.strip_prefix(CACHE_PREFIX), game_dir) {
+            (Some(path), _) => cache_dir.join(safe_path(path).expect("checked")),
+            (None, Some(game_dir)) => {
+                let path = file.path.strip_prefix(INSTANCE_PREFIX).expect("checked");
+                game_dir.join(safe_path(path).expect("checked"))
+            }
+            (None, None) => unreachable!("instance files are refused without an instance"),
+        };
+        // the cache may have it already, from another bundle or from being online once
+        if target.is_file() && sha1_file(&target)? == file.sha1 {
+            continue;
+        }
+
+        let mut entry = archive.by_name(&file.path)?;
+        std::fs::create_dir_all(target.parent().expect("below a dir"))?;
+        let mut partial = target.as_os_str().to_owned();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+        let mut out = std::fs::File::create(&partial)?;
+        let mut hasher = Sha1::new();
+        let mut size = 0;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = entry.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            out.write_all(&buffer[..read])?;
+            size += read as u64;
+        }
+        drop(out);
+        if size != file.size || format!("{:x}", hasher.finalize()) != file.sha1 {
+            let _ = std::fs::remove_file(&partial);
+            return Err(anyhow!(
+                "{} in the bundle does not match its hash, the bundle is corrupt",
+                file.path
+            ));
+        }
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&partial, std::fs::Permissions::from_mode(mode))?;
+        }
+        std::fs::rename(&partial, &target)?;
+        debug!(path = %file.path, "installed from bundle");
+        written += 1;
+    }
+    Ok(written)
+}
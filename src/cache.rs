@@ -0,0 +1,252 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use directories::ProjectDirs;
+use fs2::FileExt;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use tracing::debug;
+
+use crate::{dedup, forge, instance, natives, rules::Environment, versions, AssetIndex};
+
+// libraries, assets, version JSONs/jars and Java runtimes shared by every work dir, in the
+// platform's cache location
+pub fn default_cache_dir() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "mod_launcher").map(|dirs| dirs.cache_dir().to_path_buf())
+}
+
+pub fn libraries_dir(cache_dir: &Path) -> PathBuf {
+    cache_dir.join("libraries")
+}
+
+pub fn assets_dir(cache_dir: &Path) -> PathBuf {
+    cache_dir.join("assets")
+}
+
+pub fn runtimes_dir(cache_dir: &Path) -> PathBuf {
+    cache_dir.join("runtimes")
+}
+
+fn locks_dir(cache_dir: &Path) -> PathBuf {
+    cache_dir.join("locks")
+}
+
+// an advisory lock on part of the cache, only other launcher processes honour it. Released
+// when dropped
+#[derive(Debug)]
+pub(crate) struct CacheLock {
+    _file: File,
+}
+
+fn open_lock(path: &Path) -> anyhow::Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?)
+}
+
+// held while installing, so `gc` never removes files an install is still writing. Any number of
+// installs share it
+pub(crate) async fn lock_shared(cache_dir: &Path) -> anyhow::Result<CacheLock> {
+    let mut file = open_lock(&locks_dir(cache_dir).join("cache.lock"))?;
+    if FileExt::try_lock_shared(&file).is_err() {
+        debug!("waiting for another launcher process to clean the cache");
+        file = tokio::task::spawn_blocking(move || FileExt::lock_shared(&file).map(|_| file))
+            .await??;
+    }
+    Ok(CacheLock { _file: file })
+}
+
+// one writer per file across processes. Files share 256 lock files by the hash of their path,
+// so unrelated downloads rarely wait on each other. Also returns whether another holder had to
+// be waited for, it may have just written the file
+pub(crate) async fn lock_file(cache_dir: &Path, file: &Path) -> anyhow::Result<(CacheLock, bool)> {
+    let stripe = format!("{:x}", Sha1::digest(file.as_os_str().as_encoded_bytes()));
+    let lock = open_lock(&locks_dir(cache_dir).join(format!("{}.lock", &stripe[..2])))?;
+    if lock.try_lock_exclusive().is_ok() {
+        return Ok((CacheLock { _file: lock }, false));
+    }
+    debug!(file = %file.display(), "waiting for another launcher process to write");
+    let lock = tokio::task::spawn_blocking(move || lock.lock_exclusive().map(|_| lock)).await??;
+    Ok((CacheLock { _file: lock }, true))
+}
+
+// versions some instance launches, what `gc` should keep when pruning versions
+pub fn versions_in_use(instances_dir: &Path) -> anyhow::Result<Vec<String>> {
+    let mut versions = instance::list(instances_dir)?
+        .into_iter()
+        .map(|instance| instance.version)
+        .collect::<Vec<_>>();
+    versions.sort();
+    versions.dedup();
+    Ok(versions)
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct CacheGcReport {
+    pub kept_versions: Vec<String>,
+    pub removed_versions: Vec<String>,
+    pub removed_files: Vec<PathBuf>,
+    pub freed_bytes: u64,
+}
+
+// removes libraries, assets and client jars no kept version references.
+// `keep` limits the kept versions (and their parents), every installed version is kept without it
+pub async fn gc(
+    cache_dir: &Path,
+    keep: Option<&[String]>,
+    dry_run: bool,
+) -> anyhow::Result<CacheGcReport> {
+    let mut report = CacheGcReport::default();
+    // not waited for, an install can take a while
+    let lock = open_lock(&locks_dir(cache_dir).join("cache.lock"))?;
+    if lock.try_lock_exclusive().is_err() {
+        return Err(anyhow!(
+            "Another launcher process is installing into the cache, try again once it finished"
+        ));
+    }
+
+    let installed = versions::installed(cache_dir)?;
+    let mut kept = HashSet::new();
+    for id in keep.unwrap_or(&installed) {
+        kept.extend(versions::lineage(cache_dir, id)?);
+    }
+
+    let environment = Environment::current();
+    let mut referenced = HashSet::new();
+    let mut natives_dirs = HashSet::new();
+    for id in installed {
+        if !kept.contains(&id) {
+            let dir = versions::version_dir(cache_dir, &id);
+            for file in dedup::walk_files(&dir)? {
+                report.freed_bytes += std::fs::metadata(&file)?.len();
+                report.removed_files.push(file);
+            }
+            if !dry_run {
+                std::fs::remove_dir_all(dir)?;
+            }
+            report.removed_versions.push(id);
+            continue;
+        }
+
+        // anything that can't be resolved would make us delete files it still needs
+        let info = versions::resolve_installed(cache_dir, &id).await?;
+        for artifact in info
+            .libraries
+            .iter()
+            .flat_map(|library| library.all_artifacts())
+        {
+            referenced.insert(libraries_dir(cache_dir).join(&artifact.path));
+        }
+        referenced.insert(versions::jar_path(cache_dir, &info.jar));
+        // what a loader's installer made, no version JSON names most of it
+        let processed = versions::version_dir(cache_dir, &id).join(forge::PROCESSED_FILE);
+        if let Ok(json) = std::fs::read_to_string(&processed) {
+            referenced.extend(serde_json::from_str::<Vec<PathBuf>>(&json)?);
+        }
+        let libraries = info.libraries_for(&environment).collect::<Vec<_>>();
+        natives_dirs.insert(natives::dir_for(cache_dir, &libraries, &environment));
+
+        let index_file = assets_dir(cache_dir)
+            .join("indexes")
+            .join(format!("{}.json", info.asset_index.id));
+        if index_file.exists() {
+            let index: AssetIndex = serde_json::from_str(&std::fs::read_to_string(&index_file)?)?;
+            for object in index.objects.values() {
+                referenced.insert(
+                    assets_dir(cache_dir)
+                        .join("objects")
+                        .join(&object.hash[..2])
+                        .join(&object.hash),
+                );
+            }
+        }
+        referenced.insert(index_file);
+        report.kept_versions.push(id);
+    }
+
+    let candidates = [
+        dedup::walk_files(&libraries_dir(cache_dir))?,
+        dedup::walk_files(&assets_dir(cache_dir).join("objects"))?,
+        dedup::walk_files(&assets_dir(cache_dir).join("indexes"))?,
+    ];
+    for file in candidates.into_iter().flatten() {
+        if referenced.contains(&file) {
+            continue;
+        }
+
+        report.freed_bytes += std::fs::metadata(&file)?.len();
+        if !dry_run {
+            std::fs::remove_file(&file)?;
+        }
+        report.removed_files.push(file);
+    }
+
+    // client jars of kept versions that no longer launch them, e.g. a stale copy under a modded id
+    for id in &report.kept_versions {
+        let jar = versions::jar_path(cache_dir, id);
+        if jar.exists() && !referenced.contains(&jar) {
+            report.freed_bytes += std::fs::metadata(&jar)?.len();
+            if !dry_run {
+                std::fs::remove_file(&jar)?;
+            }
+            report.removed_files.push(jar);
+        }
+    }
+
+    // natives no kept version extracts to, and those from before they were shared between versions
+    let mut stale_natives = report
+        .kept_versions
+        .iter()
+        .map(|id| versions::version_dir(cache_dir, id).join("natives"))
+        .filter(|dir| dir.is_dir())
+        .collect::<Vec<_>>();
+    let natives_root = natives::natives_root(cache_dir);
+    if natives_root.is_dir() {
+        for entry in std::fs::read_dir(&natives_root)? {
+            let dir = entry?.path();
+            if !natives_dirs.contains(&dir) {
+                stale_natives.push(dir);
+            }
+        }
+    }
+    for dir in stale_natives {
+        for file in dedup::walk_files(&dir)? {
+            report.freed_bytes += std::fs::metadata(&file)?.len();
+            report.removed_files.push(file);
+        }
+        if !dry_run {
+            std::fs::remove_dir_all(&dir)?;
+        }
+    }
+
+    if !dry_run {
+        remove_empty_dirs(&libraries_dir(cache_dir))?;
+        remove_empty_dirs(&assets_dir(cache_dir).join("objects"))?;
+    }
+
+    Ok(report)
+}
+
+fn remove_empty_dirs(dir: &Path) -> anyhow::Result<bool> {
+    if !dir.is_dir() {
+        return Ok(false);
+    }
+
+    let mut empty = true;
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !(path.is_dir() && remove_empty_dirs(&path)? && std::fs::remove_dir(&path).is_ok()) {
+            empty = false;
+        }
+    }
+    Ok(empty)
+}
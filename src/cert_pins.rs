@@ -0,0 +1,241 @@
+use std::{collections::BTreeMap, fmt, str::FromStr, sync::Arc, time::SystemTime};
+
+use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::Url;
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, RootCertStore, ServerName,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+// a certificate a pinned host may present
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum Pin {
+    // "sha256/<base64>", the hash of the certificate's public key the way curl's
+    // --pinnedpubkey takes it. Still matches after the certificate is renewed with the same key
+    Spki([u8; 32]),
+    // the certificate's SHA-256 fingerprint in hex, colons allowed, as
+    // `openssl x509 -fingerprint -sha256` prints it
+    Certificate([u8; 32]),
+}
+
+impl FromStr for Pin {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            anyhow!(
+                "Invalid certificate pin {}, use sha256/<base64 public key hash> or a SHA-256 fingerprint in hex",
+                s
+            )
+        };
+        if let Some(hash) = s.strip_prefix("sha256/") {
+            let hash = STANDARD.decode(hash).map_err(|_| invalid())?;
+            return Ok(Pin::Spki(hash.try_into().map_err(|_| invalid())?));
+        }
+        let hex = s.replace(':', "");
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut hash = [0; 32];
+        for (i, byte) in hash.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(Pin::Certificate(hash))
+    }
+}
+
+impl TryFrom<String> for Pin {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Pin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pin::Spki(hash) => write!(f, "sha256/{}", STANDARD.encode(hash)),
+            Pin::Certificate(hash) => {
+                let hex = hash.iter().map(|byte| format!("{:02X}", byte));
+                write!(f, "{}", hex.collect::<Vec<_>>().join(":"))
+            }
+        }
+    }
+}
+
+impl From<Pin> for String {
+    fn from(pin: Pin) -> Self {
+        pin.to_string()
+    }
+}
+
+// hosts, e.g. "piston-meta.mojang.com", to the certificates they may present. For deployments
+// that must not take files from a TLS-intercepting proxy: a pinned host's chain has to pass the
+// system's CAs and have a certificate matching one of its pins, the server's own or an
+// intermediate. It is checked during the handshake, nothing is sent to a host that fails it
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct CertPins(pub BTreeMap<String, Vec<Pin>>);
+
+impl CertPins {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // `chain` is what `host` presented, DER encoded and the server's own certificate first
+    pub fn check(&self, host: &str, chain: &[&[u8]]) -> anyhow::Result<()> {
+        let Some(pins) = self.0.get(host) else {
+            return Ok(());
+        };
+        let matches = chain.iter().any(|certificate| {
+            let fingerprint = <[u8; 32]>::from(Sha256::digest(certificate));
+            let key_hash = spki(certificate).map(|spki| <[u8; 32]>::from(Sha256::digest(spki)));
+            pins.iter().any(|pin| match pin {
+                Pin::Certificate(hash) => *hash == fingerprint,
+                Pin::Spki(hash) => Some(*hash) == key_hash,
+            })
+        });
+        if !matches {
+            return Err(anyhow!(
+                "The certificate of {} matches none of its pins, the connection may be intercepted",
+                host
+            ));
+        }
+        Ok(())
+    }
+
+    // before a request goes to `url`, `from` being the URL that redirected there
+    pub fn check_url(&self, from: Option<&Url>, url: &Url) -> anyhow::Result<()> {
+        let host = url.host_str().unwrap_or_default();
+        if self.0.contains_key(host) {
+            if url.scheme() != "https" {
+                return Err(anyhow!(
+                    "{} has certificate pins but was not reached over TLS",
+                    host
+                ));
+            }
+            return Ok(());
+        }
+        // a redirect from a pinned host is only as trustworthy as where it leads
+        match from.and_then(Url::host_str) {
+            Some(from) if self.0.contains_key(from) => Err(anyhow!(
+                "{} redirected to {}, which has no certificate pins",
+                from,
+                host
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // TLS for a client that checks these pins, trusting the system's CAs as the default
+    // client does
+    pub fn tls_config(&self) -> ClientConfig {
+        let mut roots = RootCertStore::empty();
+        match rustls_native_certs::load_native_certs() {
+            Ok(certificates) => {
+                roots.add_parsable_certificates(&certificates);
+            }
+            Err(e) => warn!("Could not load the system's CA certificates: {}", e),
+        }
+        let verifier = PinnedVerifier {
+            pins: self.clone(),
+            webpki: WebPkiVerifier::new(roots, None),
+        };
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth()
+    }
+}
+
+struct PinnedVerifier {
+    pins: CertPins,
+    webpki: WebPkiVerifier,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.webpki.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        let host = match server_name {
+            ServerName::DnsName(name) => name.as_ref().to_string(),
+            ServerName::IpAddress(ip) => ip.to_string(),
+            _ => String::new(),
+        };
+        let chain = std::iter::once(end_entity)
+            .chain(intermediates)
+            .map(|certificate| certificate.0.as_slice())
+            .collect::<Vec<_>>();
+        self.pins
+            .check(&host, &chain)
+            .map_err(|e| rustls::Error::General(e.to_string()))?;
+        Ok(verified)
+    }
+}
+
+// the element at the start of `der`: its tag, the whole element and its contents
+fn element(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *der.first()?;
+    let first = *der.get(1)?;
+    let (len, header) = match first {
+        0..=0x7f => (first as usize, 2),
+        _ => {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > 4 {
+                return None;
+            }
+            let len = der
+                .get(2..2 + count)?
+                .iter()
+                .fold(0, |len, byte| len << 8 | *byte as usize);
+            (len, 2 + count)
+        }
+    };
+    let whole = der.get(..header.checked_add(len)?)?;
+    Some((tag, whole, &whole[header..]))
+}
+
+const SEQUENCE: u8 = 0x30;
+// [0], the optional version in front of the serial number
+const VERSION: u8 = 0xa0;
+
+// the SubjectPublicKeyInfo of a certificate, what SPKI pins hash. It follows the serial
+// number, signature algorithm, issuer, validity and subject in the signed part
+fn spki(certificate: &[u8]) -> Option<&[u8]> {
+    let (SEQUENCE, _, certificate) = element(certificate)? else {
+        return None;
+    };
+    let (SEQUENCE, _, mut rest) = element(certificate)? else {
+        return None;
+    };
+    if element(rest)?.0 == VERSION {
+        rest = &rest[element(rest)?.1.len()..];
+    }
+    for _ in 0..5 {
+        rest = &rest[element(rest)?.1.len()..];
+    }
+    match element(rest)? {
+        (SEQUENCE, spki, _) => Some(spki),
+        _ => None,
+    }
+}
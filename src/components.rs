@@ -0,0 +1,540 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use time::OffsetDateTime;
+use tracing::{debug, info};
+use zip::{ZipArchive, ZipWriter};
+
+use crate::{instance::Instance, loader::LoaderKind, sha1_file, versions};
+
+// patched client jars, jarmods/<hash of the client jar and every jar mod>.jar
+const JAR_MODS_DIR: &str = "jarmods";
+
+// what a launch is built from, as an ordered stack with the vanilla version at the bottom.
+// Loaders sit on it through their version JSON's `inheritsFrom` and may add libraries and
+// arguments or replace the main class. Agents and jar mods go on top in the order the instance
+// lists them, a later jar mod's classes winning over an earlier one's
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Component {
+    Vanilla {
+        version: String,
+    },
+    // a loader profile in versions/, `kind` is None when neither its id nor the instance tell
+    Loader {
+        kind: Option<LoaderKind>,
+        id: String,
+    },
+    // passed to the JVM as -javaagent:<path>[=<options>]
+    Agent {
+        path: PathBuf,
+        #[serde(default)]
+        options: Option<String>,
+    },
+    // a zip of classes and resources patched over the client jar
+    JarMod {
+        path: PathBuf,
+    },
+}
+
+impl Component {
+    // agents and jar mods, the rest comes from the version
+    pub fn is_extra(&self) -> bool {
+        matches!(self, Component::Agent { .. } | Component::JarMod { .. })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Component::Agent { path, .. } | Component::JarMod { path } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Component::Vanilla { version } => write!(f, "vanilla {}", version),
+            Component::Loader {
+                kind: Some(kind),
+                id,
+            } => write!(f, "{} {}", kind, id),
+            Component::Loader { kind: None, id } => write!(f, "loader {}", id),
+            Component::Agent {
+                path,
+                options: Some(options),
+            } => write!(f, "agent {}={}", path.display(), options),
+            Component::Agent {
+                path,
+                options: None,
+            } => write!(f, "agent {}", path.display()),
+            Component::JarMod { path } => write!(f, "jar mod {}", path.display()),
+        }
+    }
+}
+
+// the whole stack for a version and the extra components on top of it. A version that isn't
+// installed yet is taken to be vanilla unless its id names a loader, whose game version is
+// then unknown
+pub fn stack(
+    cache_dir: &Path,
+    version: &str,
+    loader: Option<LoaderKind>,
+    extra: &[Component],
+) -> anyhow::Result<Vec<Component>> {
+    let lineage = versions::lineage(cache_dir, version)?;
+    let mut stack = Vec::new();
+    if lineage.is_empty() {
+        stack.push(match LoaderKind::from_version_id(version) {
+            Some(kind) => Component::Loader {
+                kind: Some(kind),
+                id: version.to_string(),
+            },
+            None => Component::Vanilla {
+                version: version.to_string(),
+            },
+        });
+    }
+    for (position, id) in lineage.into_iter().rev().enumerate() {
+        stack.push(match position {
+            0 => Component::Vanilla { version: id },
+            _ => Component::Loader {
+                kind: LoaderKind::from_version_id(&id).or(loader),
+                id,
+            },
+        });
+    }
+    stack.extend(extra.iter().cloned());
+    Ok(stack)
+}
+
+// the components an instance may list itself, each at most once and all of them existing
+pub fn validate(extra: &[Component]) -> anyhow::Result<()> {
+    let mut conflicts = Vec::new();
+    check_layers(extra, 0, &mut conflicts);
+    match conflicts.first() {
+        Some(conflict) => Err(anyhow!("{}", conflict.describe(extra))),
+        None => Ok(()),
+    }
+}
+
+// something wrong with a stack, found before it's saved or installed. Positions index the stack
+// as `stack` returns it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Conflict {
+    TwoLoaders {
+        first: usize,
+        second: usize,
+    },
+    // the instance's `loader` says one thing and its version another, which throws off mod
+    // checks and Java selection
+    LoaderMismatch {
+        expected: Option<LoaderKind>,
+        found: Option<LoaderKind>,
+    },
+    // a loader profile made for another game version than the one below it
+    WrongGameVersion {
+        position: usize,
+        game_version: String,
+    },
+    // older than the loader goes back, only community ports like Legacy Fabric run there
+    UnsupportedGameVersion {
+        position: usize,
+        loader: LoaderKind,
+        game_version: String,
+    },
+    // loaders patch the client jar themselves and expect it unmodified
+    JarModOnLoader {
+        position: usize,
+        loader_position: usize,
+    },
+    DuplicatePath {
+        first: usize,
+        second: usize,
+    },
+    MissingFile {
+        position: usize,
+    },
+    // vanilla and loader layers come from the version, not the instance's components
+    NotExtra {
+        position: usize,
+    },
+}
+
+// what resolves a conflict, for frontends to offer as actions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Fix {
+    Remove { position: usize },
+    // the instance's `loader`, None for vanilla
+    SetLoader { loader: Option<LoaderKind> },
+}
+
+// a conflict with everything a frontend needs to show it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ComponentConflict {
+    #[serde(flatten)]
+    pub conflict: Conflict,
+    // the instance can't launch like this, frontends should refuse to save it
+    pub fatal: bool,
+    pub message: String,
+    // empty when only picking another version helps
+    pub fixes: Vec<Fix>,
+}
+
+impl Conflict {
+    pub fn is_fatal(&self) -> bool {
+        !matches!(
+            self,
+            Conflict::LoaderMismatch { .. }
+                | Conflict::UnsupportedGameVersion { .. }
+                | Conflict::JarModOnLoader { .. }
+        )
+    }
+
+    pub fn fixes(&self) -> Vec<Fix> {
+        match *self {
+            Conflict::LoaderMismatch { found, .. } => vec![Fix::SetLoader { loader: found }],
+            Conflict::JarModOnLoader { position, .. }
+            | Conflict::MissingFile { position }
+            | Conflict::NotExtra { position }
+            | Conflict::DuplicatePath {
+                second: position, ..
+            } => vec![Fix::Remove { position }],
+            Conflict::TwoLoaders { .. }
+            | Conflict::WrongGameVersion { .. }
+            | Conflict::UnsupportedGameVersion { .. } => vec![],
+        }
+    }
+
+    pub fn describe(&self, stack: &[Component]) -> String {
+        let name = |position: usize| stack[position].to_string();
+        let loader_name = |loader: Option<LoaderKind>| match loader {
+            Some(loader) => loader.to_string(),
+            None => String::from("vanilla"),
+        };
+        match self {
+            Conflict::TwoLoaders { first, second } => format!(
+                "{} and {} are two different loaders, only one can run",
+                name(*first),
+                name(*second)
+            ),
+            Conflict::LoaderMismatch { expected, found } => format!(
+                "The instance is set to {}, but its version is {}",
+                loader_name(*expected),
+                loader_name(*found)
+            ),
+            Conflict::WrongGameVersion {
+                position,
+                game_version,
+            } => format!("{} is not for Minecraft {}", name(*position), game_version),
+            Conflict::UnsupportedGameVersion {
+                loader,
+                game_version,
+                ..
+            } => format!(
+                "{} does not support Minecraft {}, only ports of it do",
+                loader, game_version
+            ),
+            Conflict::JarModOnLoader {
+                position,
+                loader_position,
+            } => format!(
+                "{} changes the client jar, which {} expects unmodified",
+                name(*position),
+                name(*loader_position)
+            ),
+            Conflict::DuplicatePath { second, .. } => format!("{} is listed twice", name(*second)),
+            Conflict::MissingFile { position } => match stack[*position].path() {
+                Some(path) => format!("{} does not exist", path.display()),
+                None => format!("{} does not exist", name(*position)),
+            },
+            Conflict::NotExtra { position } => format!(
+                "{} comes from the instance's version, not its components",
+                name(*position)
+            ),
+        }
+    }
+}
+
+// everything wrong with a stack from `stack`, given the loader the instance is set to
+pub fn check(
+    cache_dir: &Path,
+    stack: &[Component],
+    loader: Option<LoaderKind>,
+) -> Vec<ComponentConflict> {
+    let base = stack
+        .iter()
+        .position(Component::is_extra)
+        .unwrap_or(stack.len());
+    let mut conflicts = Vec::new();
+    check_layers(stack, base, &mut conflicts);
+
+    let loaders = stack[..base]
+        .iter()
+        .enumerate()
+        .filter_map(|(position, component)| match component {
+            Component::Loader { kind, .. } => Some((position, *kind)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let mut kinds = loaders
+        .iter()
+        .filter_map(|(position, kind)| Some((*position, (*kind)?)));
+    if let Some((first, kind)) = kinds.next() {
+        if let Some((second, _)) = kinds.find(|(_, other)| *other != kind) {
+            conflicts.push(Conflict::TwoLoaders { first, second });
+        }
+    }
+    let top_loader = loaders.last().copied();
+    let found = top_loader.and_then(|(_, kind)| kind);
+    if found != loader && !(top_loader.is_some() && found.is_none()) {
+        conflicts.push(Conflict::LoaderMismatch {
+            expected: loader,
+            found,
+        });
+    }
+
+    if let Some(Component::Vanilla {
+        version: game_version,
+    }) = stack.first()
+    {
+        let released = release_time(cache_dir, game_version);
+        for &(position, kind) in &loaders {
+            let Some(kind) = kind else {
+                continue;
+            };
+            let Component::Loader { id, .. } = &stack[position] else {
+                continue;
+            };
+            let made_for = match kind {
+                LoaderKind::Fabric | LoaderKind::Quilt => {
+                    Some(id.ends_with(&format!("-{}", game_version)))
+                }
+                LoaderKind::Forge => Some(id.starts_with(&format!("{}-", game_version))),
+                // NeoForge ids carry their own version only
+                LoaderKind::NeoForge => None,
+            };
+            if made_for == Some(false) {
+                conflicts.push(Conflict::WrongGameVersion {
+                    position,
+                    game_version: game_version.clone(),
+                });
+            }
+            if let (Some(released), Some(earliest)) = (released, kind.earliest_release()) {
+                if released < earliest {
+                    conflicts.push(Conflict::UnsupportedGameVersion {
+                        position,
+                        loader: kind,
+                        game_version: game_version.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    if let Some((loader_position, _)) = top_loader {
+        for (position, component) in stack.iter().enumerate().skip(base) {
+            if matches!(component, Component::JarMod { .. }) {
+                conflicts.push(Conflict::JarModOnLoader {
+                    position,
+                    loader_position,
+                });
+            }
+        }
+    }
+
+    conflicts
+        .into_iter()
+        .map(|conflict| ComponentConflict {
+            fatal: conflict.is_fatal(),
+            message: conflict.describe(stack),
+            fixes: conflict.fixes(),
+            conflict,
+        })
+        .collect()
+}
+
+// the version layers are the first `base`, with vanilla at the bottom. Everything above them
+// has to be an agent or jar mod the instance lists
+fn check_layers(stack: &[Component], base: usize, conflicts: &mut Vec<Conflict>) {
+    for (position, component) in stack.iter().enumerate() {
+        let misplaced = match component {
+            Component::Vanilla { .. } => position != 0 || base == 0,
+            Component::Loader { .. } => position >= base,
+            _ => false,
+        };
+        if misplaced {
+            conflicts.push(Conflict::NotExtra { position });
+            continue;
+        }
+        let Some(path) = component.path() else {
+            continue;
+        };
+        if !path.is_file() {
+            conflicts.push(Conflict::MissingFile { position });
+        }
+        if let Some(first) = stack[..position]
+            .iter()
+            .position(|other| other.path() == Some(path))
+        {
+            conflicts.push(Conflict::DuplicatePath {
+                first,
+                second: position,
+            });
+        }
+    }
+}
+
+// from the cached manifest, or the installed JSON for versions that aren't in it
+fn release_time(cache_dir: &Path, id: &str) -> Option<OffsetDateTime> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Released {
+        #[serde(with = "time::serde::iso8601")]
+        release_time: OffsetDateTime,
+    }
+
+    if let Some(version) = versions::cached_manifest(cache_dir)
+        .as_ref()
+        .and_then(|manifest| manifest.find_version_by_id(id))
+    {
+        return Some(version.release_time);
+    }
+    let json = std::fs::read_to_string(versions::json_path(cache_dir, id)).ok()?;
+    Some(serde_json::from_str::<Released>(&json).ok()?.release_time)
+}
+
+// the conflicts of an instance as it's saved, or as an edit would leave it
+pub fn check_instance(
+    cache_dir: &Path,
+    instance: &Instance,
+) -> anyhow::Result<Vec<ComponentConflict>> {
+    let stack = stack(
+        cache_dir,
+        &instance.version,
+        instance.loader,
+        &instance.components,
+    )?;
+    Ok(check(cache_dir, &stack, instance.loader))
+}
+
+// what the components above the version add to a launch
+#[derive(Debug, Clone, Default)]
+pub struct Layered {
+    pub jvm_args: Vec<String>,
+    // the patched client jar, when there are jar mods
+    pub client_jar: Option<PathBuf>,
+}
+
+// a dry run works out the patched jar's path without writing it
+pub fn apply(
+    cache_dir: &Path,
+    client_jar: &Path,
+    extra: &[Component],
+    dry_run: bool,
+) -> anyhow::Result<Layered> {
+    validate(extra)?;
+
+    let mut layered = Layered::default();
+    let mut jar_mods = Vec::new();
+    for component in extra {
+        match component {
+            Component::Agent { path, options } => {
+                let path = dunce::canonicalize(path)?;
+                layered.jvm_args.push(match options {
+                    Some(options) => format!("-javaagent:{}={}", path.display(), options),
+                    None => format!("-javaagent:{}", path.display()),
+                });
+            }
+            Component::JarMod { path } => jar_mods.push(path.as_path()),
+            _ => unreachable!("validated"),
+        }
+    }
+    if !jar_mods.is_empty() {
+        layered.client_jar = Some(patched_jar(cache_dir, client_jar, &jar_mods, dry_run)?);
+    }
+    Ok(layered)
+}
+
+// the same client jar and jar mods in the same order always give the same file, so it's only
+// built once
+fn patched_jar(
+    cache_dir: &Path,
+    client_jar: &Path,
+    jar_mods: &[&Path],
+    dry_run: bool,
+) -> anyhow::Result<PathBuf> {
+    let mut hasher = Sha1::new();
+    for input in std::iter::once(client_jar).chain(jar_mods.iter().copied()) {
+        // missing only in a dry run, before the client jar is downloaded
+        let hash = sha1_file(input).unwrap_or_default();
+        hasher.update(hash.as_bytes());
+    }
+    let dest = cache_dir
+        .join(JAR_MODS_DIR)
+        .join(format!("{:x}.jar", hasher.finalize()));
+    if dry_run || dest.exists() {
+        return Ok(dest);
+    }
+
+    info!("Patching {} jar mods into the client jar", jar_mods.len());
+    std::fs::create_dir_all(dest.parent().unwrap())?;
+    let partial = dest.with_extension("jar.part");
+    let mut writer = ZipWriter::new(std::fs::File::create(&partial)?);
+
+    let mut archives = Vec::new();
+    for path in std::iter::once(client_jar).chain(jar_mods.iter().copied()) {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Could not open {}", path.display()))?;
+        archives.push(
+            ZipArchive::new(file).with_context(|| format!("{} is not a zip", path.display()))?,
+        );
+    }
+    // which archive each entry is taken from, the last one that has it
+    let mut sources = HashMap::new();
+    for (index, archive) in archives.iter().enumerate() {
+        for name in archive.file_names() {
+            sources.insert(name.to_string(), index);
+        }
+    }
+
+    // every entry from the archive it is taken from, archives and entries in order
+    for (index, archive) in archives.iter_mut().enumerate() {
+        for position in 0..archive.len() {
+            let entry = archive.by_index_raw(position)?;
+            let name = entry.name().to_string();
+            if sources.get(&name) != Some(&index) || is_signature(&name) {
+                continue;
+            }
+            debug!(entry = name, source = index, "jar mod entry");
+            writer.raw_copy_file(entry)?;
+            // written once, a later archive listing it again is skipped
+            sources.remove(&name);
+        }
+    }
+    writer.finish()?;
+    std::fs::rename(&partial, &dest)?;
+    Ok(dest)
+}
+
+// the client jar's signature stops matching once anything in it changes
+fn is_signature(name: &str) -> bool {
+    let Some(file) = name.strip_prefix("META-INF/") else {
+        return false;
+    };
+    !file.contains('/')
+        && [".SF", ".RSA", ".DSA", ".EC"]
+            .iter()
+            .any(|extension| file.to_ascii_uppercase().ends_with(extension))
+}
@@ -0,0 +1,352 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::{AssetCheck, VerifyPolicy},
+    cache,
+    cert_pins::CertPins,
+    curseforge, mirror,
+    net::{HttpProvider, Throttle, UrlManifest},
+    services::ServiceOverrides,
+    sessions,
+    signing::TrustPolicy,
+    watchdog::Timeouts,
+    LaunchOptions,
+};
+
+pub const CONFIG_FILE: &str = "launcher.toml";
+const ENV_PREFIX: &str = "MOD_LAUNCHER_";
+const LOW_MEMORY_CONCURRENCY: usize = 2;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct Config {
+    // where launcher.toml was loaded from, everything else is relative to it
+    #[serde(skip)]
+    pub work_dir: PathBuf,
+    pub java_path: Option<PathBuf>,
+    pub max_memory_mb: Option<u64>,
+    pub download_concurrency: usize,
+    // bytes per second for all downloads together, unlimited when unset
+    pub download_limit: Option<u64>,
+    // start the game once it is playable from the cache, missing assets download while it runs
+    pub defer_assets: bool,
+    // write pack and archive imports to a staging dir and move it in whole, fewer files for
+    // real-time antivirus scanners to catch mid-install
+    pub batched_writes: bool,
+    // for devices with little RAM, see Config::limit_memory
+    pub low_memory: bool,
+    // see LaunchOptions::telemetry_opt_out, instances can override it
+    pub telemetry_opt_out: bool,
+    // Raspberry Pi class boards, see arm_linux.rs. Detected when unset
+    pub arm_profile: Option<bool>,
+    // how many queued tasks run at once
+    pub task_parallelism: usize,
+    pub mirror_url: Option<String>,
+    // more mirrors, downloads are spread over them and mirror_url by how fast each answers from
+    // here, see mirror::selector
+    pub mirrors: Vec<String>,
+    // a manifest in Mojang's v1 or v2 format to list versions from instead of Mojang's
+    pub manifest_url: Option<String>,
+    // how much asset objects already downloaded are trusted
+    pub asset_check: AssetCheck,
+    // days between full hash passes over the assets, never when unset
+    pub asset_deep_verify_days: Option<u64>,
+    // trust files whose size and mtime match the last install instead of hashing them
+    pub trust_file_stamps: bool,
+    pub instances_dir: Option<PathBuf>,
+    // libraries, assets and versions shared between work dirs
+    pub cache_dir: Option<PathBuf>,
+    pub client_id: Option<String>,
+    pub curseforge_api_key: Option<String>,
+    // something answering like api.curseforge.com/v1 to ask instead, e.g. a proxy adding the key
+    pub curseforge_api_url: Option<String>,
+    pub service_overrides: ServiceOverrides,
+    // environment variables for every game, instances can override them one by one
+    pub env: BTreeMap<String, String>,
+    // how long installers, processors and other helpers may run, see watchdog.rs
+    pub timeouts: Timeouts,
+    // [cert_pins] "piston-meta.mojang.com" = ["sha256/..."], see cert_pins.rs
+    pub cert_pins: CertPins,
+    // base64 Ed25519 public keys whose bundles and instance archives are trusted, see signing.rs
+    pub trusted_keys: Vec<String>,
+    // refuse bundles and archives not signed by one of them
+    pub require_signatures: bool,
+    // cohorts this machine is in, e.g. "staff", for update channel releases staged to them
+    pub update_cohorts: Vec<String>,
+    // where launches fetch an updated known issues ruleset from, see known_issues.rs. The
+    // built-in one is used when unset
+    pub known_issues_url: Option<String>,
+    // MiB of each game's output kept for `session dump-log`, 0 keeps none
+    pub output_buffer_mb: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            work_dir: PathBuf::new(),
+            java_path: None,
+            max_memory_mb: None,
+            download_concurrency: 4,
+            download_limit: None,
+            defer_assets: false,
+            batched_writes: false,
+            low_memory: false,
+            telemetry_opt_out: false,
+            arm_profile: None,
+            task_parallelism: 2,
+            mirror_url: None,
+            mirrors: vec![],
+            manifest_url: None,
+            asset_check: AssetCheck::default(),
+            asset_deep_verify_days: None,
+            trust_file_stamps: true,
+            instances_dir: None,
+            cache_dir: None,
+            client_id: None,
+            curseforge_api_key: None,
+            curseforge_api_url: None,
+            service_overrides: ServiceOverrides::default(),
+            env: BTreeMap::new(),
+            timeouts: Timeouts::default(),
+            cert_pins: CertPins::default(),
+            trusted_keys: vec![],
+            require_signatures: false,
+            update_cohorts: vec![],
+            known_issues_url: None,
+            output_buffer_mb: sessions::DEFAULT_OUTPUT_BUFFER_MB,
+        }
+    }
+}
+
+impl Config {
+    pub fn path(work_dir: &Path) -> PathBuf {
+        work_dir.join(CONFIG_FILE)
+    }
+
+    // file values, then MOD_LAUNCHER_* environment variables on top
+    pub fn load(work_dir: &Path) -> anyhow::Result<Config> {
+        let path = Self::path(work_dir);
+        let mut config = if path.exists() {
+            toml::from_str(&std::fs::read_to_string(&path)?)
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+        } else {
+            Config::default()
+        };
+
+        config.work_dir = work_dir.to_path_buf();
+        config.apply_env()?;
+        if config.low_memory {
+            config.limit_memory();
+        }
+        Ok(config)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.work_dir)?;
+        std::fs::write(Self::path(&self.work_dir), toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn apply_env(&mut self) -> anyhow::Result<()> {
+        let var = |name: &str| std::env::var(format!("{}{}", ENV_PREFIX, name)).ok();
+
+        if let Some(java_path) = var("JAVA") {
+            self.java_path = Some(PathBuf::from(java_path));
+        }
+        if let Some(max_memory) = var("MAX_MEMORY") {
+            self.max_memory_mb = Some(
+                max_memory
+                    .parse()
+                    .context("MOD_LAUNCHER_MAX_MEMORY must be a number of MiB")?,
+            );
+        }
+        if let Some(concurrency) = var("DOWNLOAD_CONCURRENCY") {
+            self.download_concurrency = concurrency
+                .parse()
+                .context("MOD_LAUNCHER_DOWNLOAD_CONCURRENCY must be a number")?;
+        }
+        if let Some(limit) = var("DOWNLOAD_LIMIT") {
+            self.download_limit = Some(
+                limit
+                    .parse()
+                    .context("MOD_LAUNCHER_DOWNLOAD_LIMIT must be a number of bytes per second")?,
+            );
+        }
+        if let Some(defer) = var("DEFER_ASSETS") {
+            self.defer_assets = defer
+                .parse()
+                .context("MOD_LAUNCHER_DEFER_ASSETS must be true or false")?;
+        }
+        if let Some(batched) = var("BATCHED_WRITES") {
+            self.batched_writes = batched
+                .parse()
+                .context("MOD_LAUNCHER_BATCHED_WRITES must be true or false")?;
+        }
+        if let Some(low_memory) = var("LOW_MEMORY") {
+            self.low_memory = low_memory
+                .parse()
+                .context("MOD_LAUNCHER_LOW_MEMORY must be true or false")?;
+        }
+        if let Some(opt_out) = var("TELEMETRY_OPT_OUT") {
+            self.telemetry_opt_out = opt_out
+                .parse()
+                .context("MOD_LAUNCHER_TELEMETRY_OPT_OUT must be true or false")?;
+        }
+        if let Some(arm_profile) = var("ARM_PROFILE") {
+            self.arm_profile = Some(
+                arm_profile
+                    .parse()
+                    .context("MOD_LAUNCHER_ARM_PROFILE must be true or false")?,
+            );
+        }
+        if let Some(parallelism) = var("TASK_PARALLELISM") {
+            self.task_parallelism = parallelism
+                .parse()
+                .context("MOD_LAUNCHER_TASK_PARALLELISM must be a number")?;
+        }
+        if let Some(mirror_url) = var("MIRROR_URL") {
+            self.mirror_url = Some(mirror_url);
+        }
+        if let Some(manifest_url) = var("MANIFEST_URL") {
+            self.manifest_url = Some(manifest_url);
+        }
+        if let Some(asset_check) = var("ASSET_CHECK") {
+            self.asset_check = asset_check.parse()?;
+        }
+        if let Some(days) = var("ASSET_DEEP_VERIFY_DAYS") {
+            self.asset_deep_verify_days = Some(
+                days.parse()
+                    .context("MOD_LAUNCHER_ASSET_DEEP_VERIFY_DAYS must be a number of days")?,
+            );
+        }
+        if let Some(trust) = var("TRUST_FILE_STAMPS") {
+            self.trust_file_stamps = trust
+                .parse()
+                .context("MOD_LAUNCHER_TRUST_FILE_STAMPS must be true or false")?;
+        }
+        if let Some(instances_dir) = var("INSTANCES_DIR") {
+            self.instances_dir = Some(PathBuf::from(instances_dir));
+        }
+        if let Some(cache_dir) = var("CACHE_DIR") {
+            self.cache_dir = Some(PathBuf::from(cache_dir));
+        }
+        if let Some(client_id) = var("CLIENT_ID") {
+            self.client_id = Some(client_id);
+        }
+        if let Some(api_key) = var("CURSEFORGE_API_KEY") {
+            self.curseforge_api_key = Some(api_key);
+        }
+        if let Some(api_url) = var("CURSEFORGE_API_URL") {
+            self.curseforge_api_url = Some(api_url);
+        }
+        if let Some(required) = var("REQUIRE_SIGNATURES") {
+            self.require_signatures = required
+                .parse()
+                .context("MOD_LAUNCHER_REQUIRE_SIGNATURES must be true or false")?;
+        }
+        if let Some(timeout) = var("HELPER_TIMEOUT") {
+            self.timeouts.helper_secs = timeout
+                .parse()
+                .context("MOD_LAUNCHER_HELPER_TIMEOUT must be a number of seconds")?;
+        }
+
+        Ok(())
+    }
+
+    // fewer downloads and tasks in flight, each holds buffers and connections. Launches only
+    // keep the end of the game's output
+    pub fn limit_memory(&mut self) {
+        self.low_memory = true;
+        self.download_concurrency = self.download_concurrency.min(LOW_MEMORY_CONCURRENCY);
+        self.task_parallelism = 1;
+    }
+
+    pub fn instances_dir(&self) -> PathBuf {
+        match &self.instances_dir {
+            Some(dir) if dir.is_absolute() => dir.clone(),
+            Some(dir) => self.work_dir.join(dir),
+            None => self.work_dir.join("instances"),
+        }
+    }
+
+    // falls back to the work dir when the platform has no cache location
+    pub fn cache_dir(&self) -> PathBuf {
+        match &self.cache_dir {
+            Some(dir) if dir.is_absolute() => dir.clone(),
+            Some(dir) => self.work_dir.join(dir),
+            None => cache::default_cache_dir().unwrap_or_else(|| self.work_dir.clone()),
+        }
+    }
+
+    pub fn http_provider(&self, client: reqwest::Client) -> HttpProvider {
+        let mut http = HttpProvider::new(client, self.mirror_url.as_deref());
+        if let Some(manifest_url) = &self.manifest_url {
+            http.manifest = Arc::new(UrlManifest::new(manifest_url));
+        }
+        http.throttle = self
+            .download_limit
+            .map(|limit| Arc::new(Throttle::new(limit)));
+        http.pin(&self.cert_pins);
+        http.mirrors =
+            mirror::selector(self.mirror_url.as_deref(), &self.mirrors, &self.cache_dir())
+                .map(Arc::new);
+        http
+    }
+
+    // None without an API key
+    pub fn curseforge_api(&self) -> Option<curseforge::Api> {
+        let mut api = curseforge::Api::new(self.curseforge_api_key.as_deref()?);
+        if let Some(url) = &self.curseforge_api_url {
+            api.url = url.trim_end_matches('/').to_string();
+        }
+        Some(api)
+    }
+
+    pub fn verify_policy(&self) -> VerifyPolicy {
+        VerifyPolicy {
+            asset_check: self.asset_check,
+            deep_verify_days: self.asset_deep_verify_days,
+            trust_stamps: self.trust_file_stamps,
+        }
+    }
+
+    pub fn trust_policy(&self) -> TrustPolicy {
+        TrustPolicy {
+            trusted_keys: self.trusted_keys.clone(),
+            required: self.require_signatures,
+        }
+    }
+
+    pub fn launch_options(&self) -> LaunchOptions {
+        LaunchOptions {
+            work_dir: Some(self.work_dir.clone()),
+            cache_dir: Some(self.cache_dir()),
+            java_path: self.java_path.clone(),
+            max_memory_mb: self.max_memory_mb,
+            download_concurrency: Some(self.download_concurrency),
+            download_limit: self.download_limit,
+            defer_assets: self.defer_assets,
+            low_memory: self.low_memory,
+            telemetry_opt_out: self.telemetry_opt_out,
+            arm_profile: self.arm_profile,
+            mirror_url: self.mirror_url.clone(),
+            mirrors: self.mirrors.clone(),
+            cert_pins: self.cert_pins.clone(),
+            manifest_url: self.manifest_url.clone(),
+            service_overrides: self.service_overrides.clone(),
+            verify_policy: self.verify_policy(),
+            env: self.env.clone(),
+            timeouts: self.timeouts,
+            output_buffer_mb: Some(self.output_buffer_mb),
+            ..Default::default()
+        }
+    }
+}
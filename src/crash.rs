@@ -0,0 +1,161 @@
+use std::{
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    time::SystemTime,
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    known_issues::{self, KnownIssue, Ruleset},
+    mods::ModInfo,
+};
+
+const LOG_TAIL_LINES: usize = 50;
+// ids that show up in every stack trace and never explain a crash
+const PLATFORM_IDS: &[&str] = &[
+    "minecraft",
+    "java",
+    "fabricloader",
+    "fabric",
+    "quilt_loader",
+    "forge",
+    "neoforge",
+    "mixin",
+    "mixinextras",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CrashInfo {
+    // None when the process was killed by a signal
+    pub exit_code: Option<i32>,
+    pub crash_report: Option<PathBuf>,
+    // the report's "Description:" line, e.g. "Ticking entity"
+    pub description: Option<String>,
+    pub log_tail: Vec<String>,
+    // most likely culprit first
+    pub suspected_mods: Vec<String>,
+    // causes the known issues ruleset recognized, with what to do about them
+    #[serde(default)]
+    pub known_issues: Vec<KnownIssue>,
+    // the session whose output sessions::read_output has, when it was kept
+    #[serde(default)]
+    pub session: Option<String>,
+}
+
+// None for a clean exit. `launched_at` keeps reports from earlier sessions out of it
+pub fn detect(
+    game_dir: &Path,
+    status: ExitStatus,
+    launched_at: SystemTime,
+    output: &str,
+    mods: &[ModInfo],
+    rules: &Ruleset,
+) -> Option<CrashInfo> {
+    if status.success() {
+        return None;
+    }
+
+    let crash_report = newest_crash_report(game_dir, launched_at);
+    let report_text = crash_report
+        .as_ref()
+        .and_then(|path| std::fs::read_to_string(path).ok());
+
+    let log = std::fs::read_to_string(game_dir.join("logs").join("latest.log"))
+        .unwrap_or_else(|_| output.to_string());
+    let lines = log.lines().collect::<Vec<_>>();
+    let log_tail = lines[lines.len().saturating_sub(LOG_TAIL_LINES)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+
+    // the crash report has the cleanest stack trace, the log is the fallback for hard crashes
+    let trace = report_text.as_deref().unwrap_or(&log);
+    let searched = format!(
+        "{}\n{}\n{}",
+        report_text.as_deref().unwrap_or_default(),
+        log,
+        output
+    );
+    Some(CrashInfo {
+        exit_code: status.code(),
+        description: report_text.as_deref().and_then(description),
+        crash_report,
+        log_tail,
+        suspected_mods: suspect_mods(trace, mods),
+        known_issues: rules.matches(status.code(), &searched, &known_issues::system_language()),
+        session: None,
+    })
+}
+
+fn newest_crash_report(game_dir: &Path, since: SystemTime) -> Option<PathBuf> {
+    std::fs::read_dir(game_dir.join("crash-reports"))
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".txt"))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .filter(|(modified, _)| *modified >= since)
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+fn description(report: &str) -> Option<String> {
+    report
+        .lines()
+        .find_map(|line| line.strip_prefix("Description:"))
+        .map(|description| description.trim().to_string())
+}
+
+pub fn suspect_mods(trace: &str, mods: &[ModInfo]) -> Vec<String> {
+    let mut suspects: Vec<String> = Vec::new();
+    let mut suspect = |id: &str| {
+        let id = id.trim().to_lowercase();
+        if !id.is_empty() && !PLATFORM_IDS.contains(&id.as_str()) && !suspects.contains(&id) {
+            suspects.push(id);
+        }
+    };
+
+    // Forge names them outright: "Suspected Mods:\n\tSome Mod (somemod), Version: 1.0"
+    let suspected_line = Regex::new(r"Suspected Mods?:\s*[^(]*?\(([\w-]+)\)").unwrap();
+    for caps in suspected_line.captures_iter(trace) {
+        suspect(&caps[1]);
+    }
+
+    // mixin handlers carry the owning mod id: handler$zza000$sodium$onTick
+    let mixin_handler = Regex::new(r"\$[a-z]{3}\d{3}\$([a-z0-9_]+)\$").unwrap();
+    // jar names in frames: at foo.Bar.baz(Bar.java:1) ~[somemod-1.0.jar:?]
+    let frame_jar = Regex::new(r"\[([^\]\[:]+\.jar)").unwrap();
+    let frame_class = Regex::new(r"^\s*at ([\w$.]+)\.[\w$<>]+\(").unwrap();
+
+    let installed = mods
+        .iter()
+        .filter_map(|info| Some((info, info.metadata.as_ref()?)))
+        .collect::<Vec<_>>();
+    for line in trace.lines() {
+        for caps in mixin_handler.captures_iter(line) {
+            suspect(&caps[1]);
+        }
+
+        for caps in frame_jar.captures_iter(line) {
+            if let Some((_, metadata)) =
+                installed.iter().find(|(info, _)| info.file_name == caps[1])
+            {
+                suspect(&metadata.id);
+            }
+        }
+
+        // package segments naming an installed mod, e.g. me.jellysquid.mods.sodium.client
+        if let Some(caps) = frame_class.captures(line) {
+            let class = caps[1].to_lowercase();
+            for (_, metadata) in &installed {
+                let id = metadata.id.to_lowercase();
+                if class.split('.').any(|segment| segment == id) {
+                    suspect(&id);
+                }
+            }
+        }
+    }
+
+    suspects
+}
@@ -0,0 +1,448 @@
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    download_artifact,
+    instance::{self, Instance},
+    loader::{Loader, LoaderKind},
+    staging::{Staging, WriteStats},
+    FileInfo,
+};
+
+const API_URL: &str = "https://api.curseforge.com/v1";
+
+// where CurseForge API requests go and the key they're made with, see Config::curseforge_api
+#[derive(Debug, Clone)]
+pub struct Api {
+    pub url: String,
+    pub key: String,
+}
+
+impl Api {
+    // CurseForge's own API
+    pub fn new(key: &str) -> Api {
+        Api {
+            url: API_URL.to_string(),
+            key: key.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PackManifest {
+    pub minecraft: PackMinecraft,
+    pub manifest_type: String,
+    pub manifest_version: u32,
+    pub name: String,
+    pub version: String,
+    pub author: Option<String>,
+    pub files: Vec<PackFile>,
+    pub overrides: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PackMinecraft {
+    pub version: String,
+    pub mod_loaders: Vec<PackModLoader>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PackModLoader {
+    pub id: String,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PackFile {
+    #[serde(rename = "projectID")]
+    pub project_id: u32,
+    #[serde(rename = "fileID")]
+    pub file_id: u32,
+    #[serde(default = "default_required")]
+    pub required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportedPack {
+    pub name: String,
+    pub version: String,
+    pub minecraft_version: String,
+    pub loader: Option<Loader>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiResponse<T> {
+    data: T,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ApiFile {
+    id: u32,
+    #[serde(default)]
+    mod_id: u32,
+    #[serde(default)]
+    display_name: String,
+    file_name: String,
+    file_length: u64,
+    download_url: Option<String>,
+    hashes: Vec<ApiFileHash>,
+    #[serde(default)]
+    file_fingerprint: u32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ApiFingerprints {
+    exact_matches: Vec<ApiFingerprintMatch>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiFingerprintMatch {
+    file: ApiFile,
+}
+
+// a file of a CurseForge project
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProjectFile {
+    pub project_id: u32,
+    pub file_id: u32,
+    pub display_name: String,
+}
+
+impl From<ApiFile> for ProjectFile {
+    fn from(file: ApiFile) -> ProjectFile {
+        ProjectFile {
+            project_id: file.mod_id,
+            file_id: file.id,
+            display_name: match file.display_name.is_empty() {
+                true => file.file_name,
+                false => file.display_name,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiFileHash {
+    value: String,
+    algo: u8,
+}
+
+impl ApiFile {
+    // the name goes into mods/ as it is, so it has to be a plain file name
+    fn check_file_name(&self) -> anyhow::Result<()> {
+        let mut components = Path::new(&self.file_name).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) if !self.file_name.contains(['/', '\\', ':']) => {
+                Ok(())
+            }
+            _ => Err(anyhow!(
+                "CurseForge file {} has an unsafe file name {:?}",
+                self.id,
+                self.file_name
+            )),
+        }
+    }
+
+    fn file_info(&self) -> anyhow::Result<FileInfo> {
+        // algo 1 is sha1, 2 is md5
+        let sha1 = self
+            .hashes
+            .iter()
+            .find(|hash| hash.algo == 1)
+            .ok_or_else(|| anyhow!("CurseForge file {} has no sha1 hash", self.id))?;
+
+        // authors can opt out of third-party distribution, in which case the API
+        // hides the url but the file is still served from the CDN
+        let url = self.download_url.clone().unwrap_or_else(|| {
+            format!(
+                "https://edge.forgecdn.net/files/{}/{}/{}",
+                self.id / 1000,
+                self.id % 1000,
+                self.file_name
+            )
+        });
+
+        Ok(FileInfo {
+            sha1: sha1.value.clone(),
+            size: self.file_length,
+            url,
+        })
+    }
+}
+
+pub fn read_manifest(pack_zip: &Path) -> anyhow::Result<PackManifest> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(pack_zip)?)?;
+    let mut manifest = String::new();
+    archive
+        .by_name("manifest.json")
+        .context("Pack does not contain a manifest.json")?
+        .read_to_string(&mut manifest)?;
+
+    let manifest: PackManifest = serde_json::from_str(&manifest)?;
+    if manifest.manifest_type != "minecraftModpack" {
+        return Err(anyhow!("Unsupported manifest type {}", manifest.manifest_type));
+    }
+
+    Ok(manifest)
+}
+
+// `batched` writes the pack into a staging dir first, see Staging
+pub async fn import_pack(
+    pack_zip: &Path,
+    game_dir: &Path,
+    api: &Api,
+    client: &reqwest::Client,
+    batched: bool,
+) -> anyhow::Result<ImportedPack> {
+    let manifest = read_manifest(pack_zip)?;
+
+    let file_ids = manifest
+        .files
+        .iter()
+        .filter(|file| file.required)
+        .map(|file| file.file_id)
+        .collect::<Vec<_>>();
+    let files = resolve_files(&file_ids, api, client).await?;
+    // before anything is written
+    for file in &files {
+        file.check_file_name()?;
+    }
+
+    let staging = batched.then(|| Staging::new(game_dir)).transpose()?;
+    let write_dir = staging.as_ref().map_or(game_dir, Staging::dir);
+    let mods_dir = write_dir.join("mods");
+    for chunked_files in files.chunks(4) {
+        let futures = chunked_files
+            .iter()
+            .map(|file| {
+                let client = client.clone();
+                let path = mods_dir.join(&file.file_name);
+
+                async move { download_artifact(&path, &file.file_info()?, &client, None).await }
+            })
+            .collect::<Vec<_>>();
+
+        for result in futures::future::join_all(futures).await {
+            result?;
+        }
+    }
+
+    if let Some(overrides) = &manifest.overrides {
+        let pack_zip = pack_zip.to_path_buf();
+        let overrides = overrides.clone();
+        let write_dir = write_dir.to_path_buf();
+        let stats = tokio::task::spawn_blocking(move || {
+            let stats = WriteStats::default();
+            extract_overrides(&pack_zip, &overrides, &write_dir, &stats)?;
+            anyhow::Ok(stats)
+        })
+        .await??;
+        stats.warn_if_slow(&[game_dir]);
+    }
+    if let Some(staging) = staging {
+        staging.commit()?;
+    }
+
+    let loader = manifest
+        .minecraft
+        .mod_loaders
+        .iter()
+        .find(|loader| loader.primary)
+        .or(manifest.minecraft.mod_loaders.first())
+        .map(|loader| loader.id.parse())
+        .transpose()?;
+
+    Ok(ImportedPack {
+        name: manifest.name,
+        version: manifest.version,
+        minecraft_version: manifest.minecraft.version,
+        loader,
+    })
+}
+
+// a new instance holding the pack, on the pack's game version until its loader is installed.
+// `name` defaults to the pack's
+pub async fn import_instance(
+    instances_dir: &Path,
+    pack_zip: &Path,
+    name: Option<&str>,
+    api: &Api,
+    client: &reqwest::Client,
+    batched: bool,
+) -> anyhow::Result<(Instance, ImportedPack)> {
+    let manifest = read_manifest(pack_zip)?;
+    let name = name.unwrap_or(&manifest.name);
+    let instance = instance::create(instances_dir, name, &manifest.minecraft.version)?;
+
+    match import_pack(pack_zip, &instance.game_dir(instances_dir), api, client, batched).await {
+        Ok(imported) => Ok((instance, imported)),
+        // a half imported instance would only be in the way of trying again
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(instance.dir(instances_dir));
+            Err(e)
+        }
+    }
+}
+
+async fn resolve_files(
+    file_ids: &[u32],
+    api: &Api,
+    client: &reqwest::Client,
+) -> anyhow::Result<Vec<ApiFile>> {
+    let response = client
+        .post(format!("{}/mods/files", api.url))
+        .header("x-api-key", &api.key)
+        .json(&serde_json::json!({ "fileIds": file_ids }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ApiResponse<Vec<ApiFile>>>()
+        .await?;
+
+    Ok(response.data)
+}
+
+pub(crate) fn extract_overrides(
+    pack_zip: &Path,
+    overrides: &str,
+    game_dir: &Path,
+    stats: &WriteStats,
+) -> anyhow::Result<()> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(pack_zip)?)?;
+    let prefix = PathBuf::from(overrides);
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        // enclosed_name rejects absolute paths and `..` traversal
+        let Some(name) = entry.enclosed_name().map(Path::to_path_buf) else {
+            continue;
+        };
+        let Ok(relative) = name.strip_prefix(&prefix) else {
+            continue;
+        };
+        // `overrides/../instance.json` stays in the archive but not under the prefix
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            continue;
+        }
+
+        let target = game_dir.join(relative);
+        if entry.is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else {
+            std::fs::create_dir_all(target.parent().unwrap())?;
+            stats.write(&target, &mut entry)?;
+        }
+    }
+
+    Ok(())
+}
+
+// CurseForge identifies files by MurmurHash2 with seed 1 over their bytes, leaving out tabs,
+// line feeds, carriage returns and spaces
+pub fn fingerprint(bytes: &[u8]) -> u32 {
+    const M: u32 = 0x5bd1_e995;
+    let bytes = bytes
+        .iter()
+        .copied()
+        .filter(|byte| !matches!(byte, 9 | 10 | 13 | 32))
+        .collect::<Vec<_>>();
+    let mut h = 1 ^ bytes.len() as u32;
+    let mut chunks = bytes.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> 24;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M) ^ k;
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u32) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^ (h >> 15)
+}
+
+// the files with these fingerprints, keyed by fingerprint. Fingerprints CurseForge doesn't
+// know are left out
+pub async fn files_by_fingerprint(
+    client: &reqwest::Client,
+    api: &Api,
+    fingerprints: &[u32],
+) -> anyhow::Result<HashMap<u32, ProjectFile>> {
+    let response = client
+        .post(format!("{}/fingerprints", api.url))
+        .header("x-api-key", &api.key)
+        .json(&serde_json::json!({ "fingerprints": fingerprints }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ApiResponse<ApiFingerprints>>()
+        .await?;
+
+    Ok(response
+        .data
+        .exact_matches
+        .into_iter()
+        .map(|found| (found.file.file_fingerprint, ProjectFile::from(found.file)))
+        .collect())
+}
+
+// the newest file of a project for `game_version`, and for `loader` when it is a mod
+pub async fn latest_file(
+    client: &reqwest::Client,
+    api: &Api,
+    project_id: u32,
+    game_version: &str,
+    loader: Option<LoaderKind>,
+) -> anyhow::Result<Option<ProjectFile>> {
+    let mut request = client
+        .get(format!("{}/mods/{}/files", api.url, project_id))
+        .header("x-api-key", &api.key)
+        .query(&[("gameVersion", game_version)]);
+    // CurseForge's ModLoaderType
+    let loader_type = loader.map(|loader| match loader {
+        LoaderKind::Forge => 1,
+        LoaderKind::Fabric => 4,
+        LoaderKind::Quilt => 5,
+        LoaderKind::NeoForge => 6,
+    });
+    if let Some(loader_type) = loader_type {
+        request = request.query(&[("modLoaderType", loader_type)]);
+    }
+    let response = request
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ApiResponse<Vec<ApiFile>>>()
+        .await?;
+
+    // file ids only grow, the newest file has the highest
+    Ok(response
+        .data
+        .into_iter()
+        .max_by_key(|file| file.id)
+        .map(ProjectFile::from))
+}
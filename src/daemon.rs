@@ -0,0 +1,632 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc, Notify},
+    task::JoinHandle,
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    args::QuickPlay,
+    auth::{self, AccountStore},
+    build_info, components,
+    config::Config,
+    instance::{self, InstanceWatcher},
+    launch_minecraft, loader_matrix,
+    net::{self, NetworkStatus},
+    protocol::{
+        AuthParams, CheckParams, GcParams, InstallParams, JobId, JobStarted, LaunchParams,
+        LoaderDefaultsParams, Notification, RpcError, SessionOutputParams, TaskParams, TaskQueued,
+        VersionsParams, APP_ERROR, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR,
+        UNAUTHORIZED,
+    },
+    sessions,
+    state::{AccountSummary, LauncherState},
+    tasks::{self, TaskContext, TaskKind, TaskQueue},
+    versions, LaunchOptions, VersionType,
+};
+
+const INFO_FILE: &str = "daemon.json";
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+// how a frontend finds and authenticates to a running daemon, kept in <work_dir>/daemon.json
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DaemonInfo {
+    pub port: u16,
+    pub token: String,
+    pub pid: u32,
+}
+
+impl DaemonInfo {
+    pub fn path(work_dir: &Path) -> PathBuf {
+        work_dir.join(INFO_FILE)
+    }
+
+    pub fn load(work_dir: &Path) -> anyhow::Result<DaemonInfo> {
+        let path = Self::path(work_dir);
+        let json = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("No daemon info at {}: {}", path.display(), e))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    // only the user running the daemon may read the token
+    fn save(&self, work_dir: &Path) -> anyhow::Result<()> {
+        use std::io::Write;
+
+        std::fs::create_dir_all(work_dir)?;
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(Self::path(work_dir))?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+// listens on loopback only, port 0 picks a free one
+pub async fn bind(port: u16) -> anyhow::Result<(TcpListener, DaemonInfo)> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    let mut token = [0u8; 32];
+    getrandom::getrandom(&mut token).map_err(|e| anyhow!("Could not generate a token: {}", e))?;
+    let info = DaemonInfo {
+        port: listener.local_addr()?.port(),
+        token: token.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        pid: std::process::id(),
+    };
+    Ok((listener, info))
+}
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    // absent for notifications, which get no response
+    id: Option<Value>,
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        RpcError::new(APP_ERROR, format!("{:#}", e))
+    }
+}
+
+impl From<serde_json::Error> for RpcError {
+    fn from(e: serde_json::Error) -> Self {
+        RpcError::new(APP_ERROR, e.to_string())
+    }
+}
+
+fn params<T: DeserializeOwned + Default>(params: Value) -> Result<T, RpcError> {
+    if params.is_null() {
+        return Ok(T::default());
+    }
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+struct Daemon {
+    info: DaemonInfo,
+    config: Config,
+    client: reqwest::Client,
+    next_job: AtomicU64,
+    // launches still going, shutting down would cut them off. Tasks start over after a restart
+    running: AtomicUsize,
+    shutdown: Notify,
+    // something was queued or resumed, the runner needn't wait for its next look at the queue
+    tasks_ready: Notify,
+    // instance, task and account changes by any process, every authenticated connection
+    // subscribes
+    changes: broadcast::Sender<Notification>,
+}
+
+// one line per message going out on a connection
+type Outbox = mpsc::UnboundedSender<String>;
+
+fn notify(outbox: &Outbox, notification: Notification) {
+    let mut message = serde_json::to_value(notification).expect("notifications serialize");
+    message["jsonrpc"] = json!("2.0");
+    // the frontend may have disconnected, the job carries on regardless
+    let _ = outbox.send(message.to_string());
+}
+
+fn respond(outbox: &Outbox, id: Value, result: Result<Value, RpcError>) {
+    let message = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    };
+    let _ = outbox.send(message.to_string());
+}
+
+// serves newline-delimited JSON-RPC 2.0 for GUI frontends until a client calls `shutdown`.
+// Every connection has to `auth` with the token from daemon.json before anything else
+pub async fn serve(listener: TcpListener, info: DaemonInfo, config: Config) -> anyhow::Result<()> {
+    let work_dir = config.work_dir.clone();
+    info.save(&work_dir)?;
+    info!("Daemon listening on 127.0.0.1:{}", info.port);
+
+    let client = net::client();
+    // logins stay fresh for launches the frontend asks for later
+    let refresher = config.client_id.clone().map(|client_id| {
+        tokio::spawn(auth::TokenRefresher::new(client.clone(), client_id, work_dir.clone()).run())
+    });
+
+    let daemon = Arc::new(Daemon {
+        info,
+        config,
+        client,
+        next_job: AtomicU64::new(1),
+        running: AtomicUsize::new(0),
+        shutdown: Notify::new(),
+        tasks_ready: Notify::new(),
+        // a task sends one per percent of progress
+        changes: broadcast::channel(256).0,
+    });
+    let watcher = tokio::spawn(watch_instances(daemon.clone()));
+    let accounts = tokio::spawn(watch_accounts(daemon.clone()));
+    let runner = tokio::spawn(run_tasks(daemon.clone()));
+    let result = loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, address)) => {
+                    debug!(%address, "daemon connection");
+                    let daemon = daemon.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = connection(stream, daemon).await {
+                            debug!("daemon connection closed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => break Err(e.into()),
+            },
+            _ = daemon.shutdown.notified() => break Ok(()),
+        }
+    };
+
+    if let Some(refresher) = refresher {
+        refresher.abort();
+    }
+    watcher.abort();
+    accounts.abort();
+    runner.abort();
+    let _ = std::fs::remove_file(DaemonInfo::path(&work_dir));
+    result
+}
+
+async fn watch_instances(daemon: Arc<Daemon>) {
+    let mut watcher = match InstanceWatcher::new(&daemon.config.instances_dir()) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            warn!("Not watching instances for changes: {:#}", e);
+            return;
+        }
+    };
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    loop {
+        interval.tick().await;
+        match watcher.poll() {
+            Ok(changes) => {
+                for change in changes {
+                    debug!(name = change.name, kind = ?change.kind, "instance changed");
+                    // nobody may be connected
+                    let _ = daemon
+                        .changes
+                        .send(Notification::InstanceChanged(Box::new(change)));
+                }
+            }
+            Err(e) => debug!("could not poll instances: {:#}", e),
+        }
+    }
+}
+
+// logins, logouts and switching accounts, from the CLI as well
+async fn watch_accounts(daemon: Arc<Daemon>) {
+    let work_dir = &daemon.config.work_dir;
+    let summaries = || AccountStore::load(work_dir).map(|store| AccountSummary::list(&store));
+    let mut last = summaries().unwrap_or_default();
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    loop {
+        interval.tick().await;
+        match summaries() {
+            Ok(accounts) if accounts != last => {
+                last = accounts.clone();
+                let _ = daemon
+                    .changes
+                    .send(Notification::AccountsChanged { accounts });
+            }
+            Ok(_) => {}
+            Err(e) => debug!("could not read accounts: {:#}", e),
+        }
+    }
+}
+
+// runs the work dir's task queue, including what the CLI or an earlier daemon queued. Running
+// tasks that a shutdown interrupts start over next time
+async fn run_tasks(daemon: Arc<Daemon>) {
+    let config = &daemon.config;
+    let mut queue = match TaskQueue::open(&config.work_dir) {
+        Ok(queue) => queue,
+        Err(e) => {
+            warn!("Not running queued tasks: {:#}", e);
+            return;
+        }
+    };
+    let context = TaskContext {
+        client: daemon.client.clone(),
+        cache_dir: config.cache_dir(),
+        instances_dir: config.instances_dir(),
+        http: config.http_provider(daemon.client.clone()),
+        download_concurrency: config.download_concurrency,
+        verify_policy: config.verify_policy(),
+    };
+    let (changed, mut changes) = mpsc::unbounded_channel();
+    queue.observe(changed);
+    let forward = {
+        let daemon = daemon.clone();
+        async move {
+            while let Some(task) = changes.recv().await {
+                let _ = daemon
+                    .changes
+                    .send(Notification::TaskChanged(Box::new(task)));
+            }
+        }
+    };
+    tokio::spawn(forward);
+    loop {
+        if let Err(e) = tasks::run(&mut queue, context.clone(), config.task_parallelism).await {
+            warn!("Task runner stopped: {:#}", e);
+        }
+        tokio::select! {
+            _ = daemon.tasks_ready.notified() => {}
+            _ = tokio::time::sleep(WATCH_INTERVAL) => {}
+        }
+    }
+}
+
+// passes changes on until the connection closes
+fn forward_changes(daemon: &Daemon, outbox: Outbox) -> JoinHandle<()> {
+    let mut changes = daemon.changes.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match changes.recv().await {
+                Ok(change) => notify(&outbox, change),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    debug!(missed, "connection fell behind on changes")
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+async fn connection(stream: TcpStream, daemon: Arc<Daemon>) -> anyhow::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let (outbox, mut messages) = mpsc::unbounded_channel::<String>();
+    // jobs keep sending after the request that started them returned
+    let writer = tokio::spawn(async move {
+        while let Some(mut message) = messages.recv().await {
+            message.push('\n');
+            if write.write_all(message.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut authenticated = false;
+    let mut forwarder = None;
+    let mut shutdown = false;
+    let mut lines = BufReader::new(read).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                respond(
+                    &outbox,
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, e.to_string())),
+                );
+                continue;
+            }
+        };
+        let id = request.get("id").cloned();
+        let request = match serde_json::from_value::<Request>(request) {
+            Ok(request) if request.jsonrpc == "2.0" => request,
+            _ => {
+                respond(
+                    &outbox,
+                    id.unwrap_or(Value::Null),
+                    Err(RpcError::new(INVALID_REQUEST, "Invalid request")),
+                );
+                continue;
+            }
+        };
+
+        let result = if request.method == "auth" {
+            params::<AuthParams>(request.params).and_then(|auth| {
+                authenticated = auth.token == daemon.info.token;
+                match authenticated {
+                    true => Ok(Value::Null),
+                    false => Err(RpcError::new(UNAUTHORIZED, "Invalid token")),
+                }
+            })
+        } else if !authenticated {
+            Err(RpcError::new(
+                UNAUTHORIZED,
+                "Call auth with the daemon token first",
+            ))
+        } else {
+            daemon.call(&request.method, request.params, &outbox).await
+        };
+        if authenticated && forwarder.is_none() {
+            forwarder = Some(forward_changes(&daemon, outbox.clone()));
+        }
+        shutdown = request.method == "shutdown" && result.is_ok();
+        if let Some(id) = request.id {
+            respond(&outbox, id, result);
+        }
+        if shutdown {
+            break;
+        }
+    }
+
+    // it holds on to the outbox, the writer would wait for it forever
+    if let Some(forwarder) = forwarder {
+        forwarder.abort();
+    }
+    drop(outbox);
+    let _ = writer.await;
+    // only once the reply is out, the process exits right after
+    if shutdown {
+        daemon.shutdown.notify_one();
+    }
+    Ok(())
+}
+
+impl Daemon {
+    async fn call(
+        self: &Arc<Self>,
+        method: &str,
+        params: Value,
+        outbox: &Outbox,
+    ) -> Result<Value, RpcError> {
+        let work_dir = &self.config.work_dir;
+        match method {
+            "versions.list" => {
+                let VersionsParams { all } = self::params(params)?;
+                let http = self.config.http_provider(self.client.clone());
+                let manifest =
+                    versions::manifest(&http, &self.config.cache_dir(), http.probe().await).await?;
+                let versions = manifest
+                    .versions
+                    .iter()
+                    .filter(|version| all || version.vtype == VersionType::Release)
+                    .collect::<Vec<_>>();
+                Ok(serde_json::to_value(versions)?)
+            }
+            "install" => {
+                let InstallParams { version } = self::params(params)?;
+                if version.is_empty() {
+                    return Err(RpcError::new(INVALID_PARAMS, "Missing version"));
+                }
+                self.enqueue(TaskKind::InstallVersion { version })
+            }
+            "cache.gc" => {
+                let GcParams { prune_versions } = self::params(params)?;
+                self.enqueue(TaskKind::CacheGc { prune_versions })
+            }
+            "tasks.list" => {
+                let queue = TaskQueue::open(work_dir)?;
+                Ok(serde_json::to_value(queue.tasks().collect::<Vec<_>>())?)
+            }
+            // the runner picks pauses and cancels up on its next look at the queue
+            "tasks.pause" | "tasks.resume" | "tasks.cancel" => {
+                let TaskParams { id } = self::params(params)?;
+                let mut queue = TaskQueue::open(work_dir)?;
+                match method {
+                    "tasks.pause" => queue.pause(id)?,
+                    "tasks.resume" => {
+                        queue.resume(id)?;
+                        self.tasks_ready.notify_one();
+                    }
+                    _ => queue.cancel(id)?,
+                }
+                Ok(Value::Null)
+            }
+            "launch" => {
+                let launch = self::params::<LaunchParams>(params)?;
+                if launch.server.is_some() && launch.world.is_some() {
+                    return Err(RpcError::new(INVALID_PARAMS, "Pass either server or world"));
+                }
+                Ok(serde_json::to_value(JobStarted {
+                    job: self.launch(launch, outbox.clone()),
+                })?)
+            }
+            "sessions.list" => Ok(serde_json::to_value(sessions::list(work_dir)?)?),
+            "sessions.output" => {
+                let SessionOutputParams { id } = self::params(params)?;
+                Ok(serde_json::to_value(sessions::read_output(work_dir, &id)?)?)
+            }
+            "accounts.list" => Ok(serde_json::to_value(AccountSummary::list(
+                &AccountStore::load(work_dir)?,
+            ))?),
+            // the first view in one call, notifications keep it current after that
+            "state.snapshot" => Ok(serde_json::to_value(LauncherState::snapshot(
+                &self.config,
+            )?)?),
+            "instances.check" => {
+                let CheckParams { instance } = self::params(params)?;
+                let instance =
+                    instance.ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing instance"))?;
+                Ok(serde_json::to_value(components::check_instance(
+                    &self.config.cache_dir(),
+                    &instance,
+                )?)?)
+            }
+            "loaders.defaults" => {
+                let LoaderDefaultsParams { game_version } = self::params(params)?;
+                let http = self.config.http_provider(self.client.clone());
+                Ok(serde_json::to_value(
+                    loader_matrix::defaults(
+                        &self.client,
+                        &self.config.cache_dir(),
+                        &game_version,
+                        http.probe().await,
+                    )
+                    .await?,
+                )?)
+            }
+            "build.info" => Ok(serde_json::to_value(build_info::build_info())?),
+            "instances.list" => Ok(serde_json::to_value(instance::list(
+                &self.config.instances_dir(),
+            )?)?),
+            "shutdown" => match self.running.load(Ordering::SeqCst) {
+                0 => Ok(Value::Null),
+                running => Err(RpcError::new(
+                    APP_ERROR,
+                    format!("{} jobs are still running", running),
+                )),
+            },
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method {}", method),
+            )),
+        }
+    }
+
+    fn start_job(&self) -> JobId {
+        self.running.fetch_add(1, Ordering::SeqCst);
+        self.next_job.fetch_add(1, Ordering::SeqCst)
+    }
+
+    fn finish_job(&self, outbox: Outbox, notification: Notification) {
+        notify(&outbox, notification);
+        // dropped first, a shutdown waits for the connection's last message to go out
+        drop(outbox);
+        self.running.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn enqueue(&self, kind: TaskKind) -> Result<Value, RpcError> {
+        let task = TaskQueue::open(&self.config.work_dir)?.enqueue(kind)?;
+        self.tasks_ready.notify_one();
+        Ok(serde_json::to_value(TaskQueued { task })?)
+    }
+
+    // streams the game's output as `launch.output` until `launch.finished`
+    fn launch(self: &Arc<Self>, launch: LaunchParams, outbox: Outbox) -> JobId {
+        let job = self.start_job();
+        let daemon = self.clone();
+        tokio::spawn(async move {
+            let (lines, mut output) = mpsc::unbounded_channel::<String>();
+            let forward = {
+                let outbox = outbox.clone();
+                tokio::spawn(async move {
+                    while let Some(line) = output.recv().await {
+                        notify(&outbox, Notification::LaunchOutput { job, line });
+                    }
+                })
+            };
+
+            let result = daemon.run_launch(job, launch, &outbox, lines).await;
+            // every line is out before the frontend hears the game stopped
+            let _ = forward.await;
+            let finished = match result {
+                Ok(crash) => Notification::LaunchFinished {
+                    job,
+                    crash,
+                    error: None,
+                },
+                Err(e) => {
+                    warn!("Launch failed: {:#}", e);
+                    Notification::LaunchFinished {
+                        job,
+                        crash: None,
+                        error: Some(format!("{:#}", e)),
+                    }
+                }
+            };
+            daemon.finish_job(outbox, finished);
+        });
+        job
+    }
+
+    async fn run_launch(
+        &self,
+        job: JobId,
+        launch: LaunchParams,
+        outbox: &Outbox,
+        output: mpsc::UnboundedSender<String>,
+    ) -> anyhow::Result<Option<crate::crash::CrashInfo>> {
+        let config = &self.config;
+        let http = config.http_provider(self.client.clone());
+        // frontends show why a launch skips logins and downloads
+        let network = http.probe().await;
+        notify(
+            outbox,
+            Notification::NetworkStatus {
+                job,
+                status: network,
+            },
+        );
+        let (mut options, instance_account) = match &launch.instance {
+            Some(name) => {
+                let instances_dir = config.instances_dir();
+                let update = instance::track_latest(
+                    &instances_dir,
+                    name,
+                    &http,
+                    &config.cache_dir(),
+                    network,
+                )
+                .await?;
+                if let Some(update) = update {
+                    notify(outbox, Notification::InstanceUpdated { job, update });
+                }
+                let instance = instance::mark_played(&config.instances_dir(), name)?;
+                (instance.launch_options(config), instance.account)
+            }
+            None => (
+                LaunchOptions {
+                    version: launch.version,
+                    ..config.launch_options()
+                },
+                None,
+            ),
+        };
+        let account = launch.account.or(instance_account);
+        options.account = auth::launch_account(
+            &self.client,
+            config.client_id.as_deref(),
+            &config.work_dir,
+            account.as_deref(),
+            network,
+        )
+        .await?;
+        options.offline = network == NetworkStatus::Offline;
+        options.quick_play = launch
+            .server
+            .map(QuickPlay::Multiplayer)
+            .or(launch.world.map(QuickPlay::Singleplayer));
+        options.safe_mode = launch.safe_mode;
+        options.output = Some(output);
+        launch_minecraft(options).await
+    }
+}
@@ -0,0 +1,397 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::sha1_file;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ExternalLauncher {
+    Vanilla,
+    MultiMc,
+    Prism,
+    CurseForge,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ExternalInstall {
+    pub launcher: ExternalLauncher,
+    pub root: PathBuf,
+}
+
+impl fmt::Display for ExternalLauncher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExternalLauncher::Vanilla => "the vanilla launcher",
+            ExternalLauncher::MultiMc => "MultiMC",
+            ExternalLauncher::Prism => "Prism Launcher",
+            ExternalLauncher::CurseForge => "the CurseForge app",
+        })
+    }
+}
+
+impl ExternalInstall {
+    pub(crate) fn libraries_dir(&self) -> PathBuf {
+        self.root.join("libraries")
+    }
+
+    fn objects_dir(&self) -> PathBuf {
+        self.root.join("assets").join("objects")
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StoreKind {
+    Library,
+    AssetObject,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DuplicateFile {
+    pub kind: StoreKind,
+    pub launcher: ExternalLauncher,
+    pub external_path: PathBuf,
+    pub store_path: PathBuf,
+    pub size: u64,
+    // false for the one copy that is moved into the store when the store doesn't have the file
+    // yet, the other copies are linked to it
+    pub in_store: bool,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct DedupReport {
+    pub installs: Vec<ExternalInstall>,
+    pub files: Vec<DuplicateFile>,
+    pub reclaimable_bytes: u64,
+    // store paths the installs have different files for, left alone
+    pub conflicts: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMode {
+    Symlink,
+    // works without elevated privileges on Windows, but only within one volume
+    Hardlink,
+}
+
+pub fn detect_installs() -> Vec<ExternalInstall> {
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+    let app_data = std::env::var_os("APPDATA").map(PathBuf::from);
+
+    let mut candidates = Vec::new();
+    if let Some(app_data) = &app_data {
+        candidates.push((ExternalLauncher::Vanilla, app_data.join(".minecraft")));
+        candidates.push((ExternalLauncher::Prism, app_data.join("PrismLauncher")));
+    }
+    if let Some(home) = &home {
+        let mac_support = home.join("Library").join("Application Support");
+        let xdg_data = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.join(".local").join("share"));
+
+        candidates.extend([
+            (ExternalLauncher::Vanilla, home.join(".minecraft")),
+            (ExternalLauncher::Vanilla, mac_support.join("minecraft")),
+            (ExternalLauncher::Prism, xdg_data.join("PrismLauncher")),
+            (ExternalLauncher::Prism, mac_support.join("PrismLauncher")),
+            (ExternalLauncher::MultiMc, xdg_data.join("multimc")),
+            (ExternalLauncher::MultiMc, home.join("MultiMC")),
+            (
+                ExternalLauncher::CurseForge,
+                home.join("curseforge").join("minecraft").join("Install"),
+            ),
+        ]);
+    }
+
+    candidates
+        .into_iter()
+        .filter(|(_, root)| root.join("libraries").is_dir() || root.join("assets").is_dir())
+        .map(|(launcher, root)| ExternalInstall { launcher, root })
+        .collect()
+}
+
+pub fn scan(cache_dir: &Path, installs: Vec<ExternalInstall>) -> anyhow::Result<DedupReport> {
+    let store_libraries = cache_dir.join("libraries");
+    let store_objects = cache_dir.join("assets").join("objects");
+
+    let mut report = DedupReport::default();
+    let mut candidates = Vec::new();
+    for install in &installs {
+        let libraries_dir = install.libraries_dir();
+        for file in walk_files(&libraries_dir)? {
+            let relative = file.strip_prefix(&libraries_dir)?;
+            if let Some(duplicate) = compare(
+                StoreKind::Library,
+                install.launcher,
+                file.clone(),
+                store_libraries.join(relative),
+            )? {
+                candidates.push(duplicate);
+            }
+        }
+
+        let objects_dir = install.objects_dir();
+        for file in walk_files(&objects_dir)? {
+            let relative = file.strip_prefix(&objects_dir)?;
+            if let Some(duplicate) = compare(
+                StoreKind::AssetObject,
+                install.launcher,
+                file.clone(),
+                store_objects.join(relative),
+            )? {
+                candidates.push(duplicate);
+            }
+        }
+    }
+
+    let mut by_store_path = BTreeMap::<PathBuf, Vec<DuplicateFile>>::new();
+    for candidate in candidates {
+        by_store_path
+            .entry(candidate.store_path.clone())
+            .or_default()
+            .push(candidate);
+    }
+    for (store_path, mut copies) in by_store_path {
+        // compare already checked these against the store's copy
+        if copies[0].in_store {
+            report.files.extend(copies);
+            continue;
+        }
+        // a file only one install has gains nothing from moving
+        if copies.len() < 2 {
+            continue;
+        }
+        if !same_external_contents(&copies)? {
+            report.conflicts.push(store_path);
+            continue;
+        }
+        for copy in copies.iter_mut().skip(1) {
+            copy.in_store = true;
+        }
+        report.files.extend(copies);
+    }
+
+    report.reclaimable_bytes = report
+        .files
+        .iter()
+        .filter(|file| file.in_store)
+        .map(|file| file.size)
+        .sum();
+    report.installs = installs;
+    Ok(report)
+}
+
+fn compare(
+    kind: StoreKind,
+    launcher: ExternalLauncher,
+    external_path: PathBuf,
+    store_path: PathBuf,
+) -> anyhow::Result<Option<DuplicateFile>> {
+    let external_meta = std::fs::symlink_metadata(&external_path)?;
+    // already linked by a previous migration
+    if external_meta.file_type().is_symlink() {
+        return Ok(None);
+    }
+
+    let size = external_meta.len();
+    let in_store = match std::fs::metadata(&store_path) {
+        Ok(store_meta) => {
+            if store_meta.len() != size || !same_contents(kind, &external_path, &store_path)? {
+                return Ok(None);
+            }
+            if same_file(&external_meta, &store_meta) {
+                return Ok(None);
+            }
+            true
+        }
+        Err(_) => false,
+    };
+
+    Ok(Some(DuplicateFile {
+        kind,
+        launcher,
+        external_path,
+        store_path,
+        size,
+        in_store,
+    }))
+}
+
+// copies of a file the store doesn't have yet, asset objects also have to match their name
+fn same_external_contents(copies: &[DuplicateFile]) -> anyhow::Result<bool> {
+    let mut expected = match copies[0].kind {
+        StoreKind::AssetObject => copies[0]
+            .store_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned()),
+        StoreKind::Library => None,
+    };
+    for copy in copies {
+        let sha1 = sha1_file(&copy.external_path)?;
+        match &expected {
+            Some(expected) if *expected != sha1 => return Ok(false),
+            Some(_) => {}
+            None => expected = Some(sha1),
+        }
+    }
+    Ok(true)
+}
+
+fn same_contents(kind: StoreKind, a: &Path, b: &Path) -> anyhow::Result<bool> {
+    match kind {
+        // asset objects are named after their hash
+        StoreKind::AssetObject => Ok(true),
+        StoreKind::Library => Ok(sha1_file(a)? == sha1_file(b)?),
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &std::fs::Metadata, _b: &std::fs::Metadata) -> bool {
+    false
+}
+
+// moves one copy of each file the store doesn't have into it, then replaces every external copy
+// with a link
+pub fn migrate(report: &DedupReport, mode: LinkMode) -> anyhow::Result<u64> {
+    let mut reclaimed = 0;
+    // store paths whose copy couldn't be moved, the other copies stay as they are too
+    let mut skipped = HashSet::new();
+    for file in &report.files {
+        if skipped.contains(&file.store_path) {
+            continue;
+        }
+        if !file.in_store {
+            // something put it there since the scan, its contents weren't compared
+            if file.store_path.exists() {
+                skipped.insert(&file.store_path);
+                continue;
+            }
+            std::fs::create_dir_all(file.store_path.parent().unwrap())?;
+            if std::fs::rename(&file.external_path, &file.store_path).is_err() {
+                // different volume
+                std::fs::copy(&file.external_path, &file.store_path)?;
+            }
+        } else {
+            reclaimed += file.size;
+        }
+
+        if file.external_path.exists() {
+            std::fs::remove_file(&file.external_path)?;
+        }
+        link(&file.store_path, &file.external_path, mode)?;
+    }
+
+    Ok(reclaimed)
+}
+
+fn link(original: &Path, link: &Path, mode: LinkMode) -> std::io::Result<()> {
+    match mode {
+        LinkMode::Hardlink => std::fs::hard_link(original, link),
+        #[cfg(unix)]
+        LinkMode::Symlink => std::os::unix::fs::symlink(original, link),
+        #[cfg(windows)]
+        LinkMode::Symlink => std::os::windows::fs::symlink_file(original, link),
+    }
+}
+
+pub(crate) fn walk_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn install(root: &Path, files: &[(&str, &str)]) -> ExternalInstall {
+        for (path, contents) in files {
+            let path = root.join("libraries").join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        ExternalInstall {
+            launcher: ExternalLauncher::Vanilla,
+            root: root.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn only_matching_copies_move_into_the_store() {
+        let dir = std::env::temp_dir().join(format!("mod_launcher_dedup_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache_dir = dir.join("cache");
+        let installs = vec![
+            install(
+                &dir.join("first"),
+                &[
+                    ("shared.jar", "same"),
+                    ("clash.jar", "one"),
+                    ("alone.jar", "x"),
+                ],
+            ),
+            install(
+                &dir.join("second"),
+                &[("shared.jar", "same"), ("clash.jar", "two")],
+            ),
+        ];
+
+        let report = scan(&cache_dir, installs).unwrap();
+        let store_path = cache_dir.join("libraries").join("shared.jar");
+        assert_eq!(report.files.len(), 2);
+        assert!(report
+            .files
+            .iter()
+            .all(|file| file.store_path == store_path));
+        assert_eq!(report.reclaimable_bytes, 4);
+        assert_eq!(
+            report.conflicts,
+            [cache_dir.join("libraries").join("clash.jar")]
+        );
+
+        assert_eq!(migrate(&report, LinkMode::Hardlink).unwrap(), 4);
+        assert_eq!(std::fs::read_to_string(&store_path).unwrap(), "same");
+        for install in ["first", "second"] {
+            let libraries = dir.join(install).join("libraries");
+            assert_eq!(
+                std::fs::read_to_string(libraries.join("shared.jar")).unwrap(),
+                "same"
+            );
+            assert!(libraries.join("clash.jar").is_file());
+        }
+        assert!(!cache_dir.join("libraries").join("clash.jar").exists());
+        assert!(!cache_dir.join("libraries").join("alone.jar").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,431 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{adoptium, cache, dedup, instance, rules::Environment, saves, versions};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Category {
+    // vanilla version JSONs and client jars
+    Versions,
+    // modded versions, loader installers and what their processors made
+    Loaders,
+    Libraries,
+    Assets,
+    Runtimes,
+    Natives,
+    // everything in an instance's dir but its world backups
+    Instances,
+    Backups,
+    // files in the cache no installed version uses, what `cache gc` removes
+    Orphans,
+    // the rest of the work dir and cache: settings, logs, metadata
+    Other,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DiskItem {
+    pub category: Category,
+    // the version, instance or runtime, or the dir for categories that aren't split up
+    pub name: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CleanupOptions {
+    // also plan removing versions no instance launches
+    pub prune_versions: bool,
+    // world backups kept per world, instances' backup_keep when unset
+    pub keep_backups: Option<usize>,
+}
+
+// one step of a cleanup, run by the subsystem that owns the files
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum CleanupAction {
+    // `cache::gc` over every installed version
+    RemoveOrphans {
+        files: usize,
+        bytes: u64,
+    },
+    // `cache::gc` keeping only what instances launch, on top of the orphans
+    PruneVersions {
+        versions: Vec<String>,
+        bytes: u64,
+    },
+    // `saves::prune`
+    PruneBackups {
+        instance: String,
+        keep: usize,
+        backups: usize,
+        bytes: u64,
+    },
+    // a Temurin runtime no installed version needs and no instance points at
+    RemoveRuntime {
+        name: String,
+        bytes: u64,
+    },
+}
+
+impl CleanupAction {
+    pub fn bytes(&self) -> u64 {
+        match self {
+            CleanupAction::RemoveOrphans { bytes, .. }
+            | CleanupAction::PruneVersions { bytes, .. }
+            | CleanupAction::PruneBackups { bytes, .. }
+            | CleanupAction::RemoveRuntime { bytes, .. } => *bytes,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct DiskReport {
+    // largest first
+    pub items: Vec<DiskItem>,
+    pub totals: BTreeMap<Category, u64>,
+    pub total_bytes: u64,
+    pub plan: Vec<CleanupAction>,
+}
+
+impl DiskReport {
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.plan.iter().map(CleanupAction::bytes).sum()
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct CleanupReport {
+    pub done: Vec<CleanupAction>,
+    pub freed_bytes: u64,
+}
+
+#[derive(Default)]
+struct Tally {
+    items: BTreeMap<(Category, String), (usize, u64)>,
+}
+
+impl Tally {
+    fn add(&mut self, category: Category, name: &str, bytes: u64) {
+        let entry = self.items.entry((category, name.to_string())).or_default();
+        entry.0 += 1;
+        entry.1 += bytes;
+    }
+
+    // every file under `dir`, minus the subtrees in `skip`, which are tallied on their own
+    fn walk(
+        &mut self,
+        dir: &Path,
+        skip: &[&Path],
+        mut classify: impl FnMut(&Path) -> (Category, String),
+    ) -> anyhow::Result<()> {
+        for file in dedup::walk_files(dir)? {
+            if skip.iter().any(|skip| file.starts_with(skip)) {
+                continue;
+            }
+            let (category, name) = classify(file.strip_prefix(dir)?);
+            self.add(category, &name, size(&file));
+        }
+        Ok(())
+    }
+}
+
+fn size(file: &Path) -> u64 {
+    std::fs::symlink_metadata(file).map_or(0, |metadata| metadata.len())
+}
+
+fn first_part(relative: &Path) -> Option<String> {
+    match relative.components().next()? {
+        Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+        _ => None,
+    }
+}
+
+fn second_part(relative: &Path) -> Option<String> {
+    match relative.components().nth(1)? {
+        Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+        _ => None,
+    }
+}
+
+// versions whose JSON inherits from another one, which is what a loader installs
+fn modded_versions(cache_dir: &Path) -> anyhow::Result<HashSet<String>> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Parent {
+        inherits_from: Option<String>,
+    }
+
+    let mut modded = HashSet::new();
+    for id in versions::installed(cache_dir)? {
+        let inherits = std::fs::read_to_string(versions::json_path(cache_dir, &id))
+            .ok()
+            .and_then(|json| serde_json::from_str::<Parent>(&json).ok())
+            .is_some_and(|parent| parent.inherits_from.is_some());
+        if inherits {
+            modded.insert(id);
+        }
+    }
+    Ok(modded)
+}
+
+fn classify_cache(
+    relative: &Path,
+    modded: &HashSet<String>,
+    orphans: &HashSet<PathBuf>,
+    cache_dir: &Path,
+) -> (Category, String) {
+    let top = first_part(relative).unwrap_or_default();
+    if orphans.contains(&cache_dir.join(relative)) {
+        return (Category::Orphans, top);
+    }
+    match top.as_str() {
+        "versions" => match second_part(relative) {
+            // the manifest and other files directly under versions/
+            Some(id) if relative.components().count() > 2 => match modded.contains(&id) {
+                true => (Category::Loaders, id),
+                false => (Category::Versions, id),
+            },
+            _ => (Category::Other, top),
+        },
+        "libraries" => (Category::Libraries, top),
+        "assets" => (Category::Assets, top),
+        "natives" => (Category::Natives, top),
+        "runtimes" => (
+            Category::Runtimes,
+            second_part(relative).unwrap_or_default(),
+        ),
+        "loaders" | "processors" => (Category::Loaders, top),
+        _ => (Category::Other, top),
+    }
+}
+
+// attributes every file the launcher manages, the work dir, the cache and the instances, to
+// what it belongs to, and plans a cleanup from what `options` allows
+pub async fn analyze(
+    work_dir: &Path,
+    cache_dir: &Path,
+    instances_dir: &Path,
+    options: &CleanupOptions,
+) -> anyhow::Result<DiskReport> {
+    let mut report = DiskReport::default();
+    let mut tally = Tally::default();
+
+    // a dry run of the cache's own gc says what it would take
+    let orphans = match cache::gc(cache_dir, None, true).await {
+        Ok(gc) => Some(gc),
+        Err(e) => {
+            warn!("Could not tell which cached files are unused: {:#}", e);
+            None
+        }
+    };
+    let orphan_files = orphans
+        .iter()
+        .flat_map(|gc| gc.removed_files.iter().cloned())
+        .collect::<HashSet<_>>();
+    if let Some(gc) = orphans.as_ref().filter(|gc| gc.freed_bytes > 0) {
+        report.plan.push(CleanupAction::RemoveOrphans {
+            files: gc.removed_files.len(),
+            bytes: gc.freed_bytes,
+        });
+    }
+    if options.prune_versions {
+        let keep = cache::versions_in_use(instances_dir)?;
+        match cache::gc(cache_dir, Some(&keep), true).await {
+            Ok(gc) if !gc.removed_versions.is_empty() => {
+                let orphan_bytes = orphans.as_ref().map_or(0, |gc| gc.freed_bytes);
+                report.plan.push(CleanupAction::PruneVersions {
+                    versions: gc.removed_versions,
+                    bytes: gc.freed_bytes.saturating_sub(orphan_bytes),
+                });
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Could not tell which versions are unused: {:#}", e),
+        }
+    }
+
+    let modded = modded_versions(cache_dir)?;
+    // the work dir often holds the cache and the instances, each file is tallied once
+    let nested_work_dir = work_dir != cache_dir && work_dir.starts_with(cache_dir);
+    let skip = match nested_work_dir {
+        true => vec![instances_dir, work_dir],
+        false => vec![instances_dir],
+    };
+    tally.walk(cache_dir, &skip, |relative| {
+        classify_cache(relative, &modded, &orphan_files, cache_dir)
+    })?;
+    let skip = match nested_work_dir {
+        true => vec![instances_dir],
+        false => vec![instances_dir, cache_dir],
+    };
+    tally.walk(work_dir, &skip, |relative| {
+        (Category::Other, first_part(relative).unwrap_or_default())
+    })?;
+
+    let instances = instance::list(instances_dir)?;
+    for instance in &instances {
+        let dir = instance.dir(instances_dir);
+        let backups = saves::backups_dir(&instance.game_dir(instances_dir));
+        tally.walk(&dir, &[], |relative| {
+            match dir.join(relative).starts_with(&backups) {
+                true => (Category::Backups, instance.name.clone()),
+                false => (Category::Instances, instance.name.clone()),
+            }
+        })?;
+        // a game dir outside the instance, like an imported profile's, is still its own
+        if !backups.starts_with(&dir) {
+            tally.walk(&backups, &[], |_| {
+                (Category::Backups, instance.name.clone())
+            })?;
+        }
+
+        let Some(keep) = options.keep_backups.or(instance.backup_keep) else {
+            continue;
+        };
+        let mut seen = BTreeMap::<String, usize>::new();
+        let pruned = saves::list_backups(&instance.game_dir(instances_dir), None)?
+            .into_iter()
+            .filter(|backup| {
+                let index = seen.entry(backup.world.clone()).or_default();
+                *index += 1;
+                *index > keep
+            })
+            .collect::<Vec<_>>();
+        if !pruned.is_empty() {
+            report.plan.push(CleanupAction::PruneBackups {
+                instance: instance.name.clone(),
+                keep,
+                backups: pruned.len(),
+                bytes: pruned.iter().map(|backup| backup.size).sum(),
+            });
+        }
+    }
+    // instances dir files outside any instance, like the lock
+    let dirs = instances
+        .iter()
+        .map(|instance| instance.dir(instances_dir))
+        .collect::<Vec<_>>();
+    let dirs = dirs.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+    tally.walk(instances_dir, &dirs, |_| {
+        (Category::Other, String::from("instances"))
+    })?;
+
+    for (name, bytes) in unused_runtimes(cache_dir, &instances).await? {
+        report
+            .plan
+            .push(CleanupAction::RemoveRuntime { name, bytes });
+    }
+
+    for ((category, name), (files, bytes)) in tally.items {
+        *report.totals.entry(category).or_default() += bytes;
+        report.total_bytes += bytes;
+        report.items.push(DiskItem {
+            category,
+            name,
+            files,
+            bytes,
+        });
+    }
+    report
+        .items
+        .sort_by_key(|item| std::cmp::Reverse(item.bytes));
+    Ok(report)
+}
+
+// the Temurin runtimes no installed version needs and no instance uses, with their sizes. None
+// are when a version can't be resolved, it might need any of them
+async fn unused_runtimes(
+    cache_dir: &Path,
+    instances: &[instance::Instance],
+) -> anyhow::Result<Vec<(String, u64)>> {
+    let runtimes_dir = cache::runtimes_dir(cache_dir);
+    if !runtimes_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let arch = Environment::current().os_arch;
+    let mut needed = HashSet::new();
+    for id in versions::installed(cache_dir)? {
+        match versions::resolve_installed(cache_dir, &id).await {
+            Ok(info) => needed.insert(adoptium::runtime_dir(
+                cache_dir,
+                info.java_version.major_version,
+                &arch,
+            )),
+            Err(_) => return Ok(vec![]),
+        };
+    }
+
+    let mut unused = Vec::new();
+    for entry in std::fs::read_dir(&runtimes_dir)? {
+        let dir = entry?.path();
+        let name = dir.file_name().unwrap().to_string_lossy().to_string();
+        // runtimes the launcher didn't download are left alone
+        if !name.starts_with("temurin-") {
+            continue;
+        }
+        let used = instances.iter().any(|instance| {
+            instance
+                .java_path
+                .as_ref()
+                .is_some_and(|java| java.starts_with(&dir))
+        });
+        if !needed.contains(&dir) && !used {
+            let bytes = dedup::walk_files(&dir)?.iter().map(|file| size(file)).sum();
+            unused.push((name, bytes));
+        }
+    }
+    unused.sort();
+    Ok(unused)
+}
+
+// runs a plan from `analyze` through the subsystems that own the files. Each step checks again
+// what it removes, a plan that went stale only removes less
+pub async fn clean(
+    cache_dir: &Path,
+    instances_dir: &Path,
+    plan: &[CleanupAction],
+) -> anyhow::Result<CleanupReport> {
+    let mut report = CleanupReport::default();
+    let prune_versions = plan
+        .iter()
+        .any(|action| matches!(action, CleanupAction::PruneVersions { .. }));
+    for action in plan {
+        let freed = match action {
+            // pruning versions removes the orphans along the way
+            CleanupAction::RemoveOrphans { .. } if prune_versions => continue,
+            CleanupAction::RemoveOrphans { .. } => {
+                cache::gc(cache_dir, None, false).await?.freed_bytes
+            }
+            CleanupAction::PruneVersions { .. } => {
+                let keep = cache::versions_in_use(instances_dir)?;
+                cache::gc(cache_dir, Some(&keep), false).await?.freed_bytes
+            }
+            CleanupAction::PruneBackups { instance, keep, .. } => {
+                let instance = instance::load(instances_dir, instance)?;
+                let game_dir = instance.game_dir(instances_dir);
+                saves::prune(&game_dir, None, Some(*keep), None)?
+                    .iter()
+                    .map(|backup| backup.size)
+                    .sum()
+            }
+            CleanupAction::RemoveRuntime { name, bytes } => {
+                let dir = cache::runtimes_dir(cache_dir).join(name);
+                // not while a launch is unpacking it
+                let (_lock, _) = cache::lock_file(cache_dir, &dir).await?;
+                std::fs::remove_dir_all(&dir)?;
+                *bytes
+            }
+        };
+        report.freed_bytes += freed;
+        report.done.push(action.clone());
+    }
+    info!("Freed {} MiB", report.freed_bytes / 1024 / 1024);
+    Ok(report)
+}
@@ -0,0 +1,450 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    assets::AssetCheck, cache, install_state::FileStamp, net::Downloader, sha1_file, write_atomic,
+    FileInfo,
+};
+
+// how quickly the transfer rate follows changes in throughput, long enough that one slow file
+// doesn't make the ETA jump around
+const RATE_TIME_CONSTANT: Duration = Duration::from_secs(5);
+// progress reports closer together than this are folded into the next sample
+const MIN_RATE_SAMPLE: Duration = Duration::from_millis(250);
+// what every host served so far against what its metadata declared, kept in the cache dir
+const SOURCES_FILE: &str = "download_sources.json";
+// a host with wrong sizes in this many installs in a row is warned about, one off could be a
+// file that changed upstream
+const MISDECLARED_STREAK: u32 = 2;
+
+// one file to fetch, checked against `sha1` unless that is empty like for some maven libraries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadTask {
+    pub url: String,
+    pub path: PathBuf,
+    pub sha1: String,
+    pub size: u64,
+}
+
+impl DownloadTask {
+    pub(crate) fn for_file(info: &FileInfo, path: PathBuf) -> DownloadTask {
+        DownloadTask {
+            url: info.url.clone(),
+            path,
+            sha1: info.sha1.clone(),
+            size: info.size,
+        }
+    }
+
+    // downloads into a `.part` sibling that survives restarts, the file itself only ever appears
+    // whole and checked. In the shared cache the file is locked meanwhile, so two launcher
+    // processes never write the same `.part`
+    pub(crate) async fn run(
+        &self,
+        downloader: &dyn Downloader,
+        cache_dir: Option<&Path>,
+    ) -> anyhow::Result<Fetched> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let _lock = match cache_dir {
+            Some(cache_dir) => {
+                let (lock, waited) = cache::lock_file(cache_dir, &self.path).await?;
+                let existing = vec![(self.path.clone(), self.size, self.sha1.clone())];
+                if waited && AssetCheck::Hash.find_invalid(existing).await?.is_empty() {
+                    debug!(file = %self.path.display(), "downloaded by another launcher process");
+                    return Ok(Fetched {
+                        size: tokio::fs::metadata(&self.path).await?.len(),
+                        transferred: 0,
+                    });
+                }
+                Some(lock)
+            }
+            None => None,
+        };
+        let mut part = self.path.as_os_str().to_owned();
+        part.push(".part");
+        let part = PathBuf::from(part);
+
+        let mut existing = tokio::fs::metadata(&part)
+            .await
+            .map_or(0, |part| part.len());
+        // too long to be the start of this file
+        if existing > self.size && self.size > 0 {
+            tokio::fs::remove_file(&part).await?;
+            existing = 0;
+        }
+        let mut resumed = existing > 0;
+        let mut transferred = 0;
+        loop {
+            downloader.fetch_to(&self.url, &part).await?;
+            let size = tokio::fs::metadata(&part).await?.len();
+            transferred += size.saturating_sub(existing);
+            existing = 0;
+            let matches = self.sha1.is_empty() || {
+                let hashed = part.clone();
+                tokio::task::spawn_blocking(move || sha1_file(&hashed)).await?? == self.sha1
+            };
+            if matches {
+                tokio::fs::rename(&part, &self.path).await?;
+                return Ok(Fetched { size, transferred });
+            }
+            tokio::fs::remove_file(&part).await?;
+            // the part may have been left by a different file at the same URL, worth one fresh try
+            if !resumed {
+                return Err(anyhow!("Incorrect hash for {}", self.url));
+            }
+            resumed = false;
+        }
+    }
+}
+
+// what a download actually came to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Fetched {
+    pub size: u64,
+    // less than `size` when a `.part` was resumed or another process fetched the file
+    pub transferred: u64,
+}
+
+// a file that arrived at a different size than its metadata said
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Misdeclared {
+    pub url: String,
+    pub declared: u64,
+    pub actual: u64,
+}
+
+// declared against actual bytes for one plan's downloads
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct DownloadAccount {
+    pub files: usize,
+    // from the metadata's `size` fields, files without one count as 0
+    pub declared_bytes: u64,
+    // the files as they arrived
+    pub actual_bytes: u64,
+    // what went over the network this time
+    pub transferred_bytes: u64,
+    // files with a declared size they didn't match
+    pub misdeclared: Vec<Misdeclared>,
+    #[serde(skip)]
+    sources: BTreeMap<String, SourceStats>,
+}
+
+impl DownloadAccount {
+    fn record(&mut self, task: &DownloadTask, fetched: Fetched) {
+        self.files += 1;
+        self.declared_bytes += task.size;
+        self.actual_bytes += fetched.size;
+        self.transferred_bytes += fetched.transferred;
+        let misdeclared = task.size > 0 && task.size != fetched.size;
+        if misdeclared {
+            debug!(url = %task.url, declared = task.size, actual = fetched.size, "size differs from the metadata");
+            self.misdeclared.push(Misdeclared {
+                url: task.url.clone(),
+                declared: task.size,
+                actual: fetched.size,
+            });
+        }
+
+        let host = reqwest::Url::parse(&task.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        let source = self.sources.entry(host).or_default();
+        source.files += 1;
+        source.misdeclared_files += misdeclared as u64;
+        source.declared_bytes += task.size;
+        source.actual_bytes += fetched.size;
+    }
+
+    // adds this plan's numbers to every host's, warning about hosts that keep declaring wrong
+    // sizes. A mirror serving truncated or replaced files looks like that for files without a
+    // hash to catch it
+    async fn save_sources(&self, cache_dir: &Path) -> anyhow::Result<()> {
+        if self.sources.is_empty() {
+            return Ok(());
+        }
+        let mut saved = source_stats(cache_dir);
+        for (host, counted) in &self.sources {
+            let source = saved.entry(host.clone()).or_default();
+            source.files += counted.files;
+            source.misdeclared_files += counted.misdeclared_files;
+            source.declared_bytes += counted.declared_bytes;
+            source.actual_bytes += counted.actual_bytes;
+            source.misdeclared_streak = match counted.misdeclared_files {
+                0 => 0,
+                _ => source.misdeclared_streak + 1,
+            };
+            if source.misdeclared_streak >= MISDECLARED_STREAK {
+                warn!(
+                    "{} declared the wrong size for {} of {} files, in {} installs in a row. If it is a mirror it may be broken",
+                    host, counted.misdeclared_files, counted.files, source.misdeclared_streak
+                );
+            }
+        }
+        write_atomic(
+            &cache_dir.join(SOURCES_FILE),
+            serde_json::to_string_pretty(&saved)?,
+        )
+        .await
+    }
+}
+
+// one host's downloads across every install into a cache
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SourceStats {
+    pub files: u64,
+    pub misdeclared_files: u64,
+    pub declared_bytes: u64,
+    pub actual_bytes: u64,
+    // installs in a row with wrong sizes, reset by one without
+    pub misdeclared_streak: u32,
+}
+
+// by host, empty before the first download into the cache
+pub fn source_stats(cache_dir: &Path) -> BTreeMap<String, SourceStats> {
+    std::fs::read_to_string(cache_dir.join(SOURCES_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+#[derive(Serialize, Deserialize)]
+struct JournalEntry {
+    path: PathBuf,
+    stamp: FileStamp,
+}
+
+// files an interrupted install already downloaded and checked, so running it again neither
+// fetches nor hashes them. One JSON line per file, appended as downloads finish
+#[derive(Debug)]
+pub struct DownloadJournal {
+    path: PathBuf,
+    done: HashMap<PathBuf, FileStamp>,
+}
+
+impl DownloadJournal {
+    pub fn open(path: &Path) -> DownloadJournal {
+        let done = std::fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            // the last line may have been cut off by the interruption
+            .filter_map(|line| serde_json::from_str::<JournalEntry>(line).ok())
+            .map(|entry| (entry.path, entry.stamp))
+            .collect::<HashMap<_, _>>();
+        if !done.is_empty() {
+            debug!(files = done.len(), "continuing an interrupted download");
+        }
+        DownloadJournal {
+            path: path.to_path_buf(),
+            done,
+        }
+    }
+
+    // downloaded before and not touched since
+    pub fn is_done(&self, file: &Path) -> bool {
+        self.done
+            .get(file)
+            .is_some_and(|stamp| Some(*stamp) == FileStamp::of(file))
+    }
+
+    fn record(&self, file: &Path) -> anyhow::Result<()> {
+        let Some(stamp) = FileStamp::of(file) else {
+            return Ok(());
+        };
+        let line = serde_json::to_string(&JournalEntry {
+            path: file.to_path_buf(),
+            stamp,
+        })?;
+        let mut journal = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(journal, "{}", line)?;
+        Ok(())
+    }
+
+    // once everything is in place the install state takes over
+    pub fn finish(self) -> anyhow::Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+// everything an install still has to fetch, libraries, the client jar and asset objects alike,
+// run by one scheduler so a slow phase doesn't leave the others waiting
+#[derive(Debug, Default)]
+pub struct DownloadPlan {
+    tasks: Vec<DownloadTask>,
+    paths: HashSet<PathBuf>,
+    // set for files in the shared cache, see DownloadTask::run
+    cache_dir: Option<PathBuf>,
+}
+
+impl DownloadPlan {
+    pub fn in_cache(cache_dir: &Path) -> DownloadPlan {
+        DownloadPlan {
+            cache_dir: Some(cache_dir.to_path_buf()),
+            ..Default::default()
+        }
+    }
+
+    // a file queued twice, like an object shared by several asset names, is fetched once
+    pub fn push(&mut self, task: DownloadTask) {
+        if self.paths.insert(task.path.clone()) {
+            self.tasks.push(task);
+        }
+    }
+
+    pub fn tasks(&self) -> &[DownloadTask] {
+        &self.tasks
+    }
+
+    pub fn into_tasks(self) -> Vec<DownloadTask> {
+        self.tasks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.tasks.iter().map(|task| task.size).sum()
+    }
+
+    // reports (downloaded, total) bytes. The total starts out as the declared sizes and follows
+    // the actual ones as files arrive. The largest files start first so one big jar doesn't end
+    // up alone at the tail of the install
+    pub async fn run(
+        mut self,
+        downloader: &dyn Downloader,
+        concurrency: usize,
+        journal: Option<&DownloadJournal>,
+        on_progress: &(dyn Fn(u64, u64) + Send + Sync),
+    ) -> anyhow::Result<DownloadAccount> {
+        self.tasks.sort_by_key(|task| Reverse(task.size));
+        let declared = self.total_bytes();
+        let account = Mutex::new(DownloadAccount::default());
+        on_progress(0, declared);
+
+        let account = &account;
+        let cache_dir = self.cache_dir.as_deref();
+        let mut results = futures::stream::iter(self.tasks)
+            .map(|task| async move {
+                let fetched = task.run(downloader, cache_dir).await?;
+                if let Some(journal) = journal {
+                    // losing the record only costs hashing the file again
+                    if let Err(e) = journal.record(&task.path) {
+                        warn!(
+                            "Could not record the download of {}: {}",
+                            task.path.display(),
+                            e
+                        );
+                    }
+                }
+                let (done, total) = {
+                    let mut account = account.lock().unwrap();
+                    account.record(&task, fetched);
+                    let total = declared - account.declared_bytes + account.actual_bytes;
+                    (account.actual_bytes, total)
+                };
+                on_progress(done, total);
+                anyhow::Ok(())
+            })
+            .buffer_unordered(concurrency.max(1));
+        // the first failure drops the rest, partial files never replace anything
+        while let Some(result) = results.next().await {
+            result?;
+        }
+        drop(results);
+
+        let account = std::mem::take(&mut *account.lock().unwrap());
+        if let Some(cache_dir) = cache_dir {
+            // only statistics, the download itself went fine
+            if let Err(e) = account.save_sources(cache_dir).await {
+                warn!("Could not save download statistics: {:#}", e);
+            }
+        }
+        Ok(account)
+    }
+}
+
+// where an install stands in bytes, for frontends to show a bar and time left
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ProgressEstimate {
+    pub done: u64,
+    pub total: u64,
+    pub percent: u64,
+    pub bytes_per_second: u64,
+    // unknown until the rate has been measured
+    pub eta_seconds: Option<u64>,
+}
+
+// turns the (done, total) progress callbacks into a smoothed transfer rate and an ETA. The
+// rate is an exponential moving average weighted by how long each sample took
+#[derive(Debug, Default)]
+pub struct RateEstimator {
+    state: Mutex<RateState>,
+}
+
+#[derive(Debug, Default)]
+struct RateState {
+    last_sample: Option<(Instant, u64)>,
+    rate: Option<f64>,
+}
+
+impl RateEstimator {
+    pub fn update(&self, done: u64, total: u64) -> ProgressEstimate {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        match state.last_sample {
+            // a new plan started counting from zero again
+            Some((_, last_done)) if done < last_done => {
+                *state = RateState::default();
+                state.last_sample = Some((now, done));
+            }
+            Some((at, last_done)) => {
+                let elapsed = now.duration_since(at);
+                if elapsed >= MIN_RATE_SAMPLE {
+                    let sample = (done - last_done) as f64 / elapsed.as_secs_f64();
+                    let weight =
+                        1.0 - (-elapsed.as_secs_f64() / RATE_TIME_CONSTANT.as_secs_f64()).exp();
+                    state.rate = Some(match state.rate {
+                        Some(rate) => rate + weight * (sample - rate),
+                        None => sample,
+                    });
+                    state.last_sample = Some((now, done));
+                }
+            }
+            None => state.last_sample = Some((now, done)),
+        }
+
+        let remaining = total.saturating_sub(done);
+        let rate = state.rate.unwrap_or(0.0);
+        ProgressEstimate {
+            done,
+            total,
+            percent: (done * 100).checked_div(total).unwrap_or(100).min(100),
+            bytes_per_second: rate as u64,
+            eta_seconds: match remaining {
+                0 => Some(0),
+                _ if rate >= 1.0 => Some((remaining as f64 / rate).ceil() as u64),
+                _ => None,
+            },
+        }
+    }
+}
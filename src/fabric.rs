@@ -0,0 +1,312 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    cache,
+    loader::LoaderKind,
+    net::NetworkStatus,
+    versions::{self, maven_path},
+    write_atomic,
+};
+
+const FABRIC_META_URL: &str = "https://meta.fabricmc.net/v2";
+// Quilt's meta is a fork of Fabric's with the same endpoints
+const QUILT_META_URL: &str = "https://meta.quiltmc.org/v3";
+// loader lists and profiles as last fetched, loaders/<kind>/<game version>.json and
+// loaders/<kind>/<game version>/<loader version>.json. A profile never changes once published, so
+// with it and the libraries it names in the cache a loader installs again without the network
+const LOADERS_DIR: &str = "loaders";
+
+#[derive(Deserialize, Debug)]
+struct LoaderEntry {
+    loader: LoaderVersion,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoaderVersion {
+    pub version: String,
+    // Quilt's meta leaves it out and marks betas in the version instead
+    #[serde(default)]
+    pub stable: Option<bool>,
+}
+
+impl LoaderVersion {
+    pub fn is_stable(&self) -> bool {
+        self.stable.unwrap_or(!self.version.contains('-'))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Profile {
+    id: String,
+    #[serde(default)]
+    libraries: Vec<ProfileLibrary>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProfileLibrary {
+    name: String,
+}
+
+// a loader profile in the cache
+#[derive(Serialize, Debug, Clone)]
+pub struct CachedLoader {
+    pub kind: LoaderKind,
+    pub game_version: String,
+    pub loader_version: String,
+    pub id: String,
+    // the game version and every library the loader adds are cached too
+    pub complete: bool,
+}
+
+pub(crate) fn meta_url(kind: LoaderKind) -> anyhow::Result<&'static str> {
+    match kind {
+        LoaderKind::Fabric => Ok(FABRIC_META_URL),
+        LoaderKind::Quilt => Ok(QUILT_META_URL),
+        _ => Err(anyhow!("{} isn't installed through Fabric's meta", kind)),
+    }
+}
+
+fn loaders_dir(cache_dir: &Path, kind: LoaderKind) -> PathBuf {
+    cache_dir.join(LOADERS_DIR).join(kind.id())
+}
+
+fn list_path(cache_dir: &Path, kind: LoaderKind, game_version: &str) -> PathBuf {
+    loaders_dir(cache_dir, kind).join(format!("{}.json", game_version))
+}
+
+fn profile_path(
+    cache_dir: &Path,
+    kind: LoaderKind,
+    game_version: &str,
+    loader_version: &str,
+) -> PathBuf {
+    loaders_dir(cache_dir, kind)
+        .join(game_version)
+        .join(format!("{}.json", loader_version))
+}
+
+// newest first. Offline, the list cached the last time it was fetched
+pub async fn loader_versions(
+    client: &reqwest::Client,
+    cache_dir: &Path,
+    kind: LoaderKind,
+    game_version: &str,
+    network: NetworkStatus,
+) -> anyhow::Result<Vec<LoaderVersion>> {
+    let path = list_path(cache_dir, kind, game_version);
+    let json = match network {
+        NetworkStatus::Online => {
+            let json = client
+                .get(format!(
+                    "{}/versions/loader/{}",
+                    meta_url(kind)?,
+                    game_version
+                ))
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            write_atomic(&path, &json).await?;
+            json
+        }
+        NetworkStatus::Offline => std::fs::read_to_string(&path).map_err(|_| {
+            anyhow!(
+                "Offline, and no {} loader list for {} was cached while online",
+                kind,
+                game_version
+            )
+        })?,
+    };
+
+    Ok(serde_json::from_str::<Vec<LoaderEntry>>(&json)?
+        .into_iter()
+        .map(|entry| entry.loader)
+        .collect())
+}
+
+// offline only loaders with a cached profile are candidates, so whatever another instance
+// installed before is picked up
+pub async fn latest_loader_version(
+    client: &reqwest::Client,
+    cache_dir: &Path,
+    kind: LoaderKind,
+    game_version: &str,
+    network: NetworkStatus,
+) -> anyhow::Result<String> {
+    let mut loaders = match network {
+        NetworkStatus::Online => {
+            loader_versions(client, cache_dir, kind, game_version, network).await?
+        }
+        NetworkStatus::Offline => offline_candidates(cache_dir, kind, game_version)?,
+    };
+    loaders.sort_by_key(|loader| !loader.is_stable());
+
+    loaders
+        .first()
+        .map(|loader| loader.version.clone())
+        .ok_or_else(|| match network {
+            NetworkStatus::Online => {
+                anyhow!("{} does not support Minecraft {}", kind, game_version)
+            }
+            NetworkStatus::Offline => anyhow!(
+                "Offline, and no {} loader for {} was installed while online",
+                kind,
+                game_version
+            ),
+        })
+}
+
+// cached profiles in the order of the cached list, newest name first without one
+fn offline_candidates(
+    cache_dir: &Path,
+    kind: LoaderKind,
+    game_version: &str,
+) -> anyhow::Result<Vec<LoaderVersion>> {
+    let cached = cached(cache_dir, Some(kind), Some(game_version))?;
+    let is_cached = |version: &str| cached.iter().any(|loader| loader.loader_version == version);
+
+    let listed = std::fs::read_to_string(list_path(cache_dir, kind, game_version))
+        .ok()
+        .and_then(|json| serde_json::from_str::<Vec<LoaderEntry>>(&json).ok());
+    Ok(match listed {
+        Some(listed) => listed
+            .into_iter()
+            .map(|entry| entry.loader)
+            .filter(|loader| is_cached(&loader.version))
+            .collect(),
+        None => cached
+            .into_iter()
+            .rev()
+            .map(|loader| LoaderVersion {
+                version: loader.loader_version,
+                stable: None,
+            })
+            .collect(),
+    })
+}
+
+// writes the loader's version JSON to versions/<id>/<id>.json and returns the id. A profile
+// fetched once is reused, offline as well
+pub async fn install(
+    client: &reqwest::Client,
+    cache_dir: &Path,
+    kind: LoaderKind,
+    game_version: &str,
+    loader_version: Option<&str>,
+    network: NetworkStatus,
+) -> anyhow::Result<String> {
+    let loader_version = match loader_version {
+        Some(version) => version.to_string(),
+        None => latest_loader_version(client, cache_dir, kind, game_version, network).await?,
+    };
+
+    let path = profile_path(cache_dir, kind, game_version, &loader_version);
+    let profile = match std::fs::read_to_string(&path) {
+        Ok(profile) => {
+            debug!(path = %path.display(), "cached loader profile");
+            profile
+        }
+        Err(_) if network == NetworkStatus::Offline => {
+            return Err(anyhow!(
+                "Offline, and {} loader {} for {} was never installed while online",
+                kind,
+                loader_version,
+                game_version
+            ))
+        }
+        Err(_) => {
+            let profile = client
+                .get(format!(
+                    "{}/versions/loader/{}/{}/profile/json",
+                    meta_url(kind)?,
+                    game_version,
+                    loader_version
+                ))
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            write_atomic(&path, &profile).await?;
+            profile
+        }
+    };
+    let id = serde_json::from_str::<Profile>(&profile)?.id;
+
+    write_atomic(&versions::json_path(cache_dir, &id), profile).await?;
+
+    Ok(id)
+}
+
+// loader profiles in the cache, by kind, game version and loader version. Profiles that don't
+// parse are skipped
+pub fn cached(
+    cache_dir: &Path,
+    kind: Option<LoaderKind>,
+    game_version: Option<&str>,
+) -> anyhow::Result<Vec<CachedLoader>> {
+    let libraries_dir = cache::libraries_dir(cache_dir);
+    let mut loaders = Vec::new();
+    for loader_kind in [LoaderKind::Fabric, LoaderKind::Quilt] {
+        if kind.is_some_and(|kind| kind != loader_kind) {
+            continue;
+        }
+        for game_dir in read_dir_sorted(&loaders_dir(cache_dir, loader_kind))? {
+            let Some(game) = game_dir.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if !game_dir.is_dir() || game_version.is_some_and(|version| version != game) {
+                continue;
+            }
+            for path in read_dir_sorted(&game_dir)? {
+                let Some(loader_version) = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.strip_suffix(".json"))
+                else {
+                    continue;
+                };
+                let profile = match std::fs::read_to_string(&path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|json| Ok(serde_json::from_str::<Profile>(&json)?))
+                {
+                    Ok(profile) => profile,
+                    Err(e) => {
+                        debug!(path = %path.display(), "unreadable loader profile: {}", e);
+                        continue;
+                    }
+                };
+
+                let complete = versions::json_path(cache_dir, game).exists()
+                    && profile.libraries.iter().all(|library| {
+                        maven_path(&library.name)
+                            .is_some_and(|path| libraries_dir.join(path).exists())
+                    });
+                loaders.push(CachedLoader {
+                    kind: loader_kind,
+                    game_version: game.to_string(),
+                    loader_version: loader_version.to_string(),
+                    id: profile.id,
+                    complete,
+                });
+            }
+        }
+    }
+    Ok(loaders)
+}
+
+fn read_dir_sorted(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    paths.sort();
+    Ok(paths)
+}
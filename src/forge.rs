@@ -0,0 +1,725 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tracing::{debug, info};
+
+use crate::{
+    assets::{AssetCheck, VerifyPolicy},
+    cache,
+    dedup::{ExternalInstall, ExternalLauncher},
+    download::{DownloadPlan, DownloadTask},
+    install_version_with,
+    loader::{LoaderBuild, LoaderKind, Promotion},
+    loader_matrix,
+    net::{Downloader, MetaProvider, NetworkStatus},
+    rules::Environment,
+    sha1_file,
+    versions::{self, maven_path, LibraryJson},
+    watchdog::{self, Limits},
+    write_atomic, FileInfo,
+};
+
+const FORGE_MAVEN: &str = "https://maven.minecraftforge.net";
+const NEOFORGE_MAVEN: &str = "https://maven.neoforged.net/releases";
+// the shared store of processor outputs, see ProcessorRun
+const PROCESSORS_DIR: &str = "processors";
+// versions/<id>/processed.json, the libraries the installer's processors wrote for the version
+pub(crate) const PROCESSED_FILE: &str = "processed.json";
+// versions/<id>/installer.log, what the processors printed the last time the version installed
+const INSTALLER_LOG: &str = "installer.log";
+
+// install_profile.json from an installer of 1.13 or later. Older installers have no processors
+// and a different layout altogether
+#[derive(Deserialize, Debug)]
+struct InstallProfile {
+    minecraft: String,
+    // what processors are given for {KEY}, per side
+    #[serde(default)]
+    data: HashMap<String, SidedValue>,
+    #[serde(default)]
+    processors: Vec<Processor>,
+    #[serde(default)]
+    libraries: Vec<LibraryJson>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SidedValue {
+    client: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Processor {
+    // both sides when unset
+    sides: Option<Vec<String>>,
+    jar: String,
+    #[serde(default)]
+    classpath: Vec<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    // file -> expected sha1, both written like args
+    #[serde(default)]
+    outputs: HashMap<String, String>,
+}
+
+// what the installer's processors run with
+pub struct ProcessorEnv<'a> {
+    pub java_path: &'a Path,
+    // launchers being migrated from, their libraries may hold what the processors would make
+    pub external: &'a [ExternalInstall],
+    pub on_progress: &'a (dyn Fn(&InstallerProgress) + Send + Sync),
+    // a processor running past these is killed and the install fails
+    pub limits: Limits,
+}
+
+// where the installer is at. Sent when a processor starts, message None, and for every line it
+// prints while it runs
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct InstallerProgress {
+    // the processor, e.g. binarypatcher or "installertools mcp_data"
+    pub phase: String,
+    // 1-based, of `steps`
+    pub step: usize,
+    pub steps: usize,
+    // when the line carries a percentage or an n/m count
+    pub fraction: Option<f64>,
+    pub message: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct VersionId {
+    id: String,
+}
+
+// the build to install when none is asked for by number: the recommended one, the latest one
+// for game versions that never got a recommendation
+pub async fn select_build(
+    client: &reqwest::Client,
+    cache_dir: &Path,
+    kind: LoaderKind,
+    game_version: &str,
+    build: Option<&str>,
+    network: NetworkStatus,
+) -> anyhow::Result<LoaderBuild> {
+    if !matches!(kind, LoaderKind::Forge | LoaderKind::NeoForge) {
+        return Err(anyhow!("{} isn't installed through an installer", kind));
+    }
+    if let Some(build) = build {
+        return Ok(LoaderBuild {
+            kind,
+            game_version: game_version.to_string(),
+            version: build.to_string(),
+            promotion: Promotion::Requested,
+        });
+    }
+
+    let matrix = loader_matrix::matrix(client, cache_dir, kind, network).await?;
+    let entry = matrix
+        .get(game_version)
+        .ok_or_else(|| anyhow!("{} has no builds for Minecraft {}", kind, game_version))?;
+    let (version, promotion) = match &entry.recommended {
+        Some(recommended) => (recommended.clone(), Promotion::Recommended),
+        None => (entry.latest.clone(), Promotion::Latest),
+    };
+    debug!(%kind, game_version, version, ?promotion, "selected loader build");
+    Ok(LoaderBuild {
+        kind,
+        game_version: game_version.to_string(),
+        version,
+        promotion,
+    })
+}
+
+fn installer_coordinate(build: &LoaderBuild) -> String {
+    match build.kind {
+        LoaderKind::NeoForge => format!("net.neoforged:neoforge:{}:installer", build.version),
+        _ => format!(
+            "net.minecraftforge:forge:{}-{}:installer",
+            build.game_version, build.version
+        ),
+    }
+}
+
+// runs the build's installer the way its own client install would: the version JSON goes to
+// versions/, the libraries to the cache and the processors patch the game jar. Returns the id of
+// the installed version
+pub async fn install(
+    meta: &dyn MetaProvider,
+    downloader: &dyn Downloader,
+    cache_dir: &Path,
+    build: &LoaderBuild,
+    env: &ProcessorEnv<'_>,
+    concurrency: usize,
+    verify_policy: VerifyPolicy,
+) -> anyhow::Result<String> {
+    let libraries_dir = cache::libraries_dir(cache_dir);
+    let coordinate = installer_coordinate(build);
+    let installer_path = libraries_dir.join(maven_path(&coordinate).expect("a valid coordinate"));
+    let maven = match build.kind {
+        LoaderKind::NeoForge => NEOFORGE_MAVEN,
+        _ => FORGE_MAVEN,
+    };
+    // maven has no hash to check against, a jar that's there is kept
+    if !installer_path.exists() {
+        info!("Downloading the {} {} installer", build.kind, build.version);
+        let info = FileInfo {
+            sha1: String::new(),
+            size: 0,
+            url: format!("{}/{}", maven, maven_path(&coordinate).unwrap()),
+        };
+        DownloadTask::for_file(&info, installer_path.clone())
+            .run(downloader, Some(cache_dir))
+            .await?;
+    }
+
+    let mut installer = zip::ZipArchive::new(std::fs::File::open(&installer_path)?)
+        .with_context(|| format!("{} is not a jar", installer_path.display()))?;
+    let (Some(profile), Some(version_json)) = (
+        read_entry(&mut installer, "install_profile.json")?,
+        read_entry(&mut installer, "version.json")?,
+    ) else {
+        return Err(anyhow!(
+            "The {} {} installer is too old, only Minecraft 1.13 and later is supported",
+            build.kind,
+            build.version
+        ));
+    };
+    let profile: InstallProfile = serde_json::from_str(&profile)?;
+    let id = serde_json::from_str::<VersionId>(&version_json)?.id;
+
+    // processors read the vanilla jar
+    let install = |version: String| async move {
+        install_version_with(
+            meta,
+            downloader,
+            cache_dir,
+            &version,
+            concurrency,
+            verify_policy,
+            &|_, _| {},
+        )
+        .await
+    };
+    install(profile.minecraft.clone()).await?;
+
+    let libraries = profile
+        .libraries
+        .into_iter()
+        .map(LibraryJson::into_library)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let environment = Environment::current();
+    let artifacts = libraries
+        .iter()
+        .flat_map(|library| library.artifacts_for(&environment))
+        .filter(|artifact| !artifact.info.url.is_empty())
+        .collect::<Vec<_>>();
+    let invalid = AssetCheck::Hash
+        .find_invalid(
+            artifacts
+                .iter()
+                .map(|artifact| {
+                    let info = &artifact.info;
+                    (
+                        libraries_dir.join(&artifact.path),
+                        info.size,
+                        info.sha1.clone(),
+                    )
+                })
+                .collect(),
+        )
+        .await?;
+    let mut plan = DownloadPlan::in_cache(cache_dir);
+    for i in invalid {
+        let artifact = artifacts[i];
+        plan.push(DownloadTask::for_file(
+            &artifact.info,
+            libraries_dir.join(&artifact.path),
+        ));
+    }
+    plan.run(downloader, concurrency.max(1), None, &|_, _| {})
+        .await?;
+
+    let work_dir = versions::version_dir(cache_dir, &id).join("installer");
+    let data = processor_data(
+        &profile.data,
+        &mut installer,
+        &installer_path,
+        &work_dir,
+        cache_dir,
+        &profile.minecraft,
+    )?;
+    let processors = profile
+        .processors
+        .iter()
+        .filter(|processor| {
+            processor
+                .sides
+                .as_ref()
+                .is_none_or(|sides| sides.iter().any(|side| side == "client"))
+        })
+        .collect::<Vec<_>>();
+    let log_path = versions::version_dir(cache_dir, &id).join(INSTALLER_LOG);
+    std::fs::create_dir_all(versions::version_dir(cache_dir, &id))?;
+    let mut log = std::fs::File::create(&log_path)?;
+    let mut processed = Vec::new();
+    for (i, processor) in processors.iter().enumerate() {
+        let call = ProcessorCall::resolve(processor, &data, &libraries_dir)?;
+        let key = call.key(&coordinate);
+        let phase = phase_name(processor);
+        let report = |fraction, message| {
+            (env.on_progress)(&InstallerProgress {
+                phase: phase.clone(),
+                step: i + 1,
+                steps: processors.len(),
+                fraction,
+                message,
+            })
+        };
+        report(None, None);
+        let step = format!(
+            "{} processor {}/{}: {}",
+            build.kind,
+            i + 1,
+            processors.len(),
+            processor.jar
+        );
+        let outputs = if let Some(outputs) = reuse_outputs(cache_dir, &call, &key)? {
+            info!("Reused the outputs of {}", step);
+            outputs
+        } else if let Some((launcher, outputs)) =
+            adopt_outputs(&call, &libraries_dir, env.external)?
+        {
+            info!("Took the outputs of {} from {}", step, launcher);
+            outputs
+        } else {
+            info!("Running {}", step);
+            let on_line = |line: &str| report(parse_fraction(line), Some(line.to_string()));
+            run_processor(cache_dir, processor, &call, &key, env, &mut log, &on_line)
+                .await
+                .with_context(|| format!("See {} for its output", log_path.display()))?
+        };
+        processed.extend(outputs);
+    }
+    let _ = std::fs::remove_dir_all(&work_dir);
+    // kept by `gc` for as long as the version is, the game reads them from libraries/
+    processed.retain(|file| file.starts_with(&libraries_dir));
+    write_atomic(
+        &versions::version_dir(cache_dir, &id).join(PROCESSED_FILE),
+        serde_json::to_string_pretty(&processed)?,
+    )
+    .await?;
+
+    write_atomic(&versions::json_path(cache_dir, &id), version_json).await?;
+    // the rest of the loader's libraries, what the processors made is already there
+    install(id.clone()).await?;
+    Ok(id)
+}
+
+fn read_entry(
+    archive: &mut zip::ZipArchive<std::fs::File>,
+    name: &str,
+) -> anyhow::Result<Option<String>> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut contents = String::new();
+    entry.read_to_string(&mut contents)?;
+    Ok(Some(contents))
+}
+
+// the profile's client values with [coordinates] turned into library paths and /files taken out
+// of the installer, plus what the installer itself provides
+fn processor_data(
+    data: &HashMap<String, SidedValue>,
+    installer: &mut zip::ZipArchive<std::fs::File>,
+    installer_path: &Path,
+    work_dir: &Path,
+    cache_dir: &Path,
+    minecraft: &str,
+) -> anyhow::Result<HashMap<String, String>> {
+    let libraries_dir = cache::libraries_dir(cache_dir);
+    let mut resolved = HashMap::new();
+    for (key, value) in data {
+        let value = &value.client;
+        let value = if let Some(coordinate) = coordinate(value) {
+            library_path(&libraries_dir, coordinate)?
+        } else if let Some(name) = value.strip_prefix('/') {
+            let path = work_dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap_or(work_dir))?;
+            let mut entry = installer
+                .by_name(name)
+                .with_context(|| format!("The installer has no {}", name))?;
+            std::io::copy(&mut entry, &mut std::fs::File::create(&path)?)?;
+            path.display().to_string()
+        } else {
+            value.clone()
+        };
+        resolved.insert(key.clone(), value);
+    }
+
+    let minecraft_jar = versions::jar_path(cache_dir, minecraft);
+    for (key, value) in [
+        ("SIDE", "client".to_string()),
+        ("MINECRAFT_JAR", minecraft_jar.display().to_string()),
+        ("MINECRAFT_VERSION", minecraft.to_string()),
+        ("ROOT", cache_dir.display().to_string()),
+        ("INSTALLER", installer_path.display().to_string()),
+        ("LIBRARY_DIR", libraries_dir.display().to_string()),
+    ] {
+        resolved.insert(key.to_string(), value);
+    }
+    Ok(resolved)
+}
+
+// "[group:artifact:version]" -> the coordinate
+fn coordinate(value: &str) -> Option<&str> {
+    value.strip_prefix('[')?.strip_suffix(']')
+}
+
+fn library_path(libraries_dir: &Path, coordinate: &str) -> anyhow::Result<String> {
+    let path = maven_path(coordinate).ok_or_else(|| anyhow!("Invalid library {}", coordinate))?;
+    Ok(libraries_dir.join(path).display().to_string())
+}
+
+// {KEY} from the data, [coordinate] as a library path, 'quoted' and anything else as it is
+fn substitute(
+    value: &str,
+    data: &HashMap<String, String>,
+    libraries_dir: &Path,
+) -> anyhow::Result<String> {
+    if let Some(key) = value
+        .strip_prefix('{')
+        .and_then(|key| key.strip_suffix('}'))
+    {
+        let value = data
+            .get(key)
+            .ok_or_else(|| anyhow!("The installer gives no value for {{{}}}", key))?;
+        // data values are quoted literals too, e.g. the sha1 of an output
+        return Ok(value.trim_matches('\'').to_string());
+    }
+    if let Some(coordinate) = coordinate(value) {
+        return library_path(libraries_dir, coordinate);
+    }
+    Ok(value.trim_matches('\'').to_string())
+}
+
+// a processor with its arguments and outputs filled in
+struct ProcessorCall {
+    jar: PathBuf,
+    classpath: String,
+    args: Vec<String>,
+    // declared by the installer, file -> sha1
+    outputs: Vec<(PathBuf, String)>,
+}
+
+impl ProcessorCall {
+    fn resolve(
+        processor: &Processor,
+        data: &HashMap<String, String>,
+        libraries_dir: &Path,
+    ) -> anyhow::Result<ProcessorCall> {
+        let jar = PathBuf::from(library_path(libraries_dir, &processor.jar)?);
+        let classpath = std::iter::once(Ok(jar.display().to_string()))
+            .chain(
+                processor
+                    .classpath
+                    .iter()
+                    .map(|coordinate| library_path(libraries_dir, coordinate)),
+            )
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .join(Environment::current().classpath_separator());
+        let args = processor
+            .args
+            .iter()
+            .map(|arg| substitute(arg, data, libraries_dir))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let outputs = processor
+            .outputs
+            .iter()
+            .map(|(file, sha1)| {
+                Ok((
+                    PathBuf::from(substitute(file, data, libraries_dir)?),
+                    substitute(sha1, data, libraries_dir)?,
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(ProcessorCall {
+            jar,
+            classpath,
+            args,
+            outputs,
+        })
+    }
+
+    // what the run is filed under in the store: the installer, the processor and its arguments.
+    // The files it reads are checked against the record
+    fn key(&self, installer: &str) -> String {
+        let mut hasher = Sha1::new();
+        for part in [installer, &self.classpath]
+            .into_iter()
+            .chain(self.args.iter().map(String::as_str))
+        {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    // the arguments naming files, the ones read and the ones written alike
+    fn files(&self) -> Vec<PathBuf> {
+        let mut files = self
+            .args
+            .iter()
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .chain(self.outputs.iter().map(|(file, _)| file.clone()))
+            .collect::<Vec<_>>();
+        files.sort();
+        files.dedup();
+        files
+    }
+}
+
+// a processor run as kept in the shared cache, processors/<key>.json, with what it wrote under
+// processors/objects/<sha1>. Installing the same build again, for another instance or after a
+// `gc`, copies the outputs back instead of running the processor
+#[derive(Serialize, Deserialize, Debug)]
+struct ProcessorRun {
+    // file -> sha1, as they were when it ran
+    inputs: BTreeMap<PathBuf, String>,
+    outputs: BTreeMap<PathBuf, String>,
+}
+
+fn processors_dir(cache_dir: &Path) -> PathBuf {
+    cache_dir.join(PROCESSORS_DIR)
+}
+
+// the files the recorded run wrote, None when it read different files or its outputs are gone
+fn reuse_outputs(
+    cache_dir: &Path,
+    call: &ProcessorCall,
+    key: &str,
+) -> anyhow::Result<Option<Vec<PathBuf>>> {
+    let record = processors_dir(cache_dir).join(format!("{}.json", key));
+    let Some(run) = std::fs::read_to_string(&record)
+        .ok()
+        .and_then(|json| serde_json::from_str::<ProcessorRun>(&json).ok())
+    else {
+        return Ok(None);
+    };
+    let unchanged = run
+        .inputs
+        .iter()
+        .all(|(file, sha1)| sha1_file(file).is_ok_and(|actual| actual == *sha1));
+    let stored = |sha1: &String| processors_dir(cache_dir).join("objects").join(sha1);
+    if !unchanged || !run.outputs.values().all(|sha1| stored(sha1).exists()) {
+        debug!(jar = %call.jar.display(), "processor inputs changed since the recorded run");
+        return Ok(None);
+    }
+
+    for (file, sha1) in &run.outputs {
+        if sha1_file(file).is_ok_and(|actual| actual == *sha1) {
+            continue;
+        }
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(stored(sha1), file)?;
+    }
+    Ok(Some(run.outputs.into_keys().collect()))
+}
+
+// the declared outputs as another launcher's install of the same build left them in its
+// libraries/, copied over once every one of them matches its hash. Processors that declare
+// nothing can't be checked and always run
+fn adopt_outputs(
+    call: &ProcessorCall,
+    libraries_dir: &Path,
+    external: &[ExternalInstall],
+) -> anyhow::Result<Option<(ExternalLauncher, Vec<PathBuf>)>> {
+    if call.outputs.is_empty() {
+        return Ok(None);
+    }
+    for install in external {
+        let sources = call
+            .outputs
+            .iter()
+            .map(|(file, sha1)| {
+                let source = install
+                    .libraries_dir()
+                    .join(file.strip_prefix(libraries_dir).ok()?);
+                sha1_file(&source)
+                    .is_ok_and(|actual| actual == *sha1)
+                    .then_some((source, file))
+            })
+            .collect::<Option<Vec<_>>>();
+        let Some(sources) = sources else {
+            continue;
+        };
+
+        for (source, file) in &sources {
+            debug!(from = %source.display(), to = %file.display(), "adopting processor output");
+            if let Some(parent) = file.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let temp = file.with_extension("adopt.tmp");
+            std::fs::copy(source, &temp)?;
+            std::fs::rename(&temp, file)?;
+        }
+        let outputs = sources.into_iter().map(|(_, file)| file.clone()).collect();
+        return Ok(Some((install.launcher, outputs)));
+    }
+    Ok(None)
+}
+
+// the processor's artifact, and the task for the multi-purpose installertools
+fn phase_name(processor: &Processor) -> String {
+    let artifact = processor.jar.split(':').nth(1).unwrap_or(&processor.jar);
+    let task = processor
+        .args
+        .iter()
+        .skip_while(|arg| *arg != "--task")
+        .nth(1);
+    match task {
+        Some(task) => format!("{} {}", artifact, task.to_ascii_lowercase()),
+        None => artifact.to_string(),
+    }
+}
+
+// "42%" or "12/200", as processors print them
+fn parse_fraction(line: &str) -> Option<f64> {
+    let percent = Regex::new(r"(\d{1,3}(?:\.\d+)?)\s*%").unwrap();
+    if let Some(captures) = percent.captures(line) {
+        let percent = captures[1].parse::<f64>().ok()?;
+        return (percent <= 100.0).then_some(percent / 100.0);
+    }
+    let count = Regex::new(r"\b(\d+)\s*/\s*(\d+)\b").unwrap();
+    let captures = count.captures(line)?;
+    let (done, total) = (
+        captures[1].parse::<f64>().ok()?,
+        captures[2].parse::<f64>().ok()?,
+    );
+    (total > 0.0 && done <= total).then_some(done / total)
+}
+
+// runs the processor and files its outputs in the store, returns the files it wrote. Its output
+// goes to `log` and, line by line, to `on_line`
+async fn run_processor(
+    cache_dir: &Path,
+    processor: &Processor,
+    call: &ProcessorCall,
+    key: &str,
+    env: &ProcessorEnv<'_>,
+    log: &mut std::fs::File,
+    on_line: &(dyn Fn(&str) + Sync),
+) -> anyhow::Result<Vec<PathBuf>> {
+    let main_class = main_class(&call.jar)?;
+    let stamp = |file: &Path| {
+        std::fs::metadata(file)
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| (metadata.len(), metadata.modified().ok()))
+    };
+    let files = call.files();
+    let before = files.iter().map(|file| stamp(file)).collect::<Vec<_>>();
+
+    debug!(jar = %processor.jar, args = ?call.args, "running processor");
+    writeln!(log, "> {} {}", processor.jar, call.args.join(" "))?;
+    let mut command = tokio::process::Command::new(env.java_path);
+    command
+        .arg("-cp")
+        .arg(&call.classpath)
+        .arg(&main_class)
+        .args(&call.args);
+    // the last lines go into the error, the whole output stays in the log
+    let finished = watchdog::run(&mut command, env.limits, &mut |line| {
+        debug!(target: "installer", "{}", line);
+        writeln!(log, "{}", line)?;
+        on_line(line);
+        Ok(())
+    })
+    .await
+    .with_context(|| format!("Processor {} did not finish", processor.jar))?;
+    if !finished.status.success() {
+        return Err(anyhow!(
+            "Processor {} failed with {}:\n{}",
+            processor.jar,
+            finished.status,
+            finished.tail.join("\n")
+        ));
+    }
+
+    for (file, sha1) in &call.outputs {
+        let actual = sha1_file(file).with_context(|| {
+            format!(
+                "Processor {} did not write {}",
+                processor.jar,
+                file.display()
+            )
+        })?;
+        if actual != *sha1 {
+            return Err(anyhow!(
+                "Processor {} wrote {} with hash {}, expected {}",
+                processor.jar,
+                file.display(),
+                actual,
+                sha1
+            ));
+        }
+    }
+
+    // most processors declare no outputs, what they wrote is whatever changed
+    let mut run = ProcessorRun {
+        inputs: BTreeMap::new(),
+        outputs: BTreeMap::new(),
+    };
+    for (file, before) in files.iter().zip(before) {
+        let Some(after) = stamp(file) else {
+            continue;
+        };
+        let written =
+            before != Some(after) || call.outputs.iter().any(|(output, _)| output == file);
+        match written {
+            true => run.outputs.insert(file.clone(), sha1_file(file)?),
+            false => run.inputs.insert(file.clone(), sha1_file(file)?),
+        };
+    }
+    let objects_dir = processors_dir(cache_dir).join("objects");
+    std::fs::create_dir_all(&objects_dir)?;
+    for (file, sha1) in &run.outputs {
+        let stored = objects_dir.join(sha1);
+        if !stored.exists() {
+            let temp = stored.with_extension("tmp");
+            std::fs::copy(file, &temp)?;
+            std::fs::rename(&temp, &stored)?;
+        }
+    }
+    write_atomic(
+        &processors_dir(cache_dir).join(format!("{}.json", key)),
+        serde_json::to_string_pretty(&run)?,
+    )
+    .await?;
+    Ok(run.outputs.into_keys().collect())
+}
+
+// from the jar's manifest, processors are plain executable jars
+fn main_class(jar: &Path) -> anyhow::Result<String> {
+    let mut archive = zip::ZipArchive::new(
+        std::fs::File::open(jar).with_context(|| format!("Missing {}", jar.display()))?,
+    )?;
+    let manifest = read_entry(&mut archive, "META-INF/MANIFEST.MF")?.unwrap_or_default();
+    manifest
+        .lines()
+        .find_map(|line| line.strip_prefix("Main-Class:"))
+        .map(|class| class.trim().to_string())
+        .ok_or_else(|| anyhow!("{} has no Main-Class", jar.display()))
+}
@@ -0,0 +1,361 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf, MAIN_SEPARATOR},
+};
+
+use anyhow::{anyhow, Context};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    build_info, cache, instance::Instance, prepare_launch, rules::Environment, saves, versions,
+    write_atomic, AssetIndex, LaunchOptions, PreparedLaunch,
+};
+
+const INSTANCE_FILE: &str = "instance.json";
+// stands in for the frozen directory in arguments until they are quoted for the start script
+const ROOT: char = '\0';
+
+#[derive(Serialize, Debug, Default)]
+pub struct FreezeReport {
+    pub dir: PathBuf,
+    pub script: PathBuf,
+    pub files: usize,
+    pub bytes: u64,
+    // the copied Java, None when the script uses the java on the PATH
+    pub java: Option<PathBuf>,
+    // arguments naming files outside the instance and the launcher's stores, left as they are
+    pub external: Vec<String>,
+}
+
+// a directory whose paths in the launch arguments move into the frozen one
+struct Root {
+    from: PathBuf,
+    to: &'static str,
+}
+
+struct Rewriter {
+    // longest first, the game dir usually lives in the work dir
+    roots: Vec<Root>,
+    delimiters: &'static str,
+    // (root, path under it) for everything the arguments name
+    referenced: BTreeSet<(usize, PathBuf)>,
+    external: Vec<String>,
+}
+
+impl Rewriter {
+    fn new(roots: Vec<Root>) -> Rewriter {
+        let mut roots = roots
+            .into_iter()
+            .filter_map(|root| {
+                let from = dunce::canonicalize(&root.from).ok()?;
+                Some(Root { from, to: root.to })
+            })
+            .collect::<Vec<_>>();
+        roots.sort_by_key(|root| std::cmp::Reverse(root.from.as_os_str().len()));
+        let delimiters = match Environment::current().classpath_separator() {
+            ";" => ";=,",
+            _ => ":=,",
+        };
+        Rewriter {
+            roots,
+            delimiters,
+            referenced: BTreeSet::new(),
+            external: Vec::new(),
+        }
+    }
+
+    // paths, the classpath's entries and the values of -Dkey=path included, are made relative
+    // to ROOT
+    fn rewrite(&mut self, arg: &str) -> String {
+        let mut rewritten = String::new();
+        let mut rest = arg;
+        loop {
+            let end = rest
+                .find(|c| self.delimiters.contains(c))
+                .unwrap_or(rest.len());
+            rewritten.push_str(&self.rewrite_path(&rest[..end]));
+            let Some(delimiter) = rest[end..].chars().next() else {
+                return rewritten;
+            };
+            rewritten.push(delimiter);
+            rest = &rest[end + delimiter.len_utf8()..];
+        }
+    }
+
+    fn rewrite_path(&mut self, token: &str) -> String {
+        let path = Path::new(token);
+        for (index, root) in self.roots.iter().enumerate() {
+            let Ok(relative) = path.strip_prefix(&root.from) else {
+                continue;
+            };
+            self.referenced.insert((index, relative.to_path_buf()));
+            let rest = &token[root.from.as_os_str().len()..];
+            return format!("{}{}{}{}", ROOT, MAIN_SEPARATOR, root.to, rest);
+        }
+        if path.is_absolute() && path.exists() && !self.external.iter().any(|e| e == token) {
+            self.external.push(token.to_string());
+        }
+        token.to_string()
+    }
+}
+
+// copies `from` to `to`, following links so nothing in the copy points back. `skip` is left out
+fn copy_tree(
+    from: &Path,
+    to: &Path,
+    skip: Option<&Path>,
+    report: &mut FreezeReport,
+) -> anyhow::Result<()> {
+    let mut visited = HashSet::new();
+    let mut pending = vec![(from.to_path_buf(), to.to_path_buf())];
+    while let Some((from, to)) = pending.pop() {
+        if skip.is_some_and(|skip| from == skip) {
+            continue;
+        }
+        let metadata = std::fs::metadata(&from)
+            .with_context(|| format!("Could not read {}", from.display()))?;
+        if metadata.is_file() {
+            if let Some(parent) = to.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            report.bytes += std::fs::copy(&from, &to)
+                .with_context(|| format!("Could not copy {}", from.display()))?;
+            report.files += 1;
+            continue;
+        }
+        // a link back up the tree would never end
+        if !metadata.is_dir() || !visited.insert(dunce::canonicalize(&from)?) {
+            continue;
+        }
+        std::fs::create_dir_all(&to)?;
+        for entry in std::fs::read_dir(&from)? {
+            let entry = entry?;
+            pending.push((entry.path(), to.join(entry.file_name())));
+        }
+    }
+    Ok(())
+}
+
+fn sh_word(arg: &str) -> String {
+    if arg.is_empty() {
+        return String::from("''");
+    }
+    arg.split(ROOT)
+        .map(|part| match part {
+            "" => String::new(),
+            part => format!("'{}'", part.replace('\'', "'\\''")),
+        })
+        .collect::<Vec<_>>()
+        .join("\"$ROOT\"")
+}
+
+// java.exe splits its command line the way the C runtime does, where \" is a quote
+fn cmd_word(arg: &str) -> String {
+    let parts = arg
+        .split(ROOT)
+        .map(|part| part.replace('%', "%%").replace('"', "\\\""))
+        .collect::<Vec<_>>();
+    format!("\"{}\"", parts.join("%ROOT%"))
+}
+
+// copies an instance with everything its launch uses, libraries, natives, assets, the Java
+// runtime with `java` and the launcher's stores it references, into `out` along with a start
+// script. The result runs from wherever it is moved to without the launcher or its cache, as
+// the offline player. The version has to be installed, nothing is downloaded
+pub async fn freeze(
+    instance: &Instance,
+    mut options: LaunchOptions,
+    out: &Path,
+    java: bool,
+) -> anyhow::Result<FreezeReport> {
+    if out.exists() && std::fs::read_dir(out)?.next().is_some() {
+        return Err(anyhow!("{} is not empty", out.display()));
+    }
+    options.offline = true;
+    options.safe_mode = false;
+    options.account = None;
+    let PreparedLaunch {
+        plan,
+        game_args,
+        version_id,
+        work_path,
+        cache_path,
+        ..
+    } = prepare_launch(&options, false)
+        .await
+        .with_context(|| format!("Install {} before freezing it", instance.name))?;
+
+    // <runtime>/bin/java, links like /usr/bin/java followed to the actual install
+    let java_binary = match plan.java_path.components().count() > 1 {
+        true => dunce::canonicalize(&plan.java_path).ok(),
+        false => None,
+    };
+    let java_root = java_binary
+        .as_deref()
+        .and_then(Path::parent)
+        .and_then(Path::parent)
+        .filter(|root| java && root.join("lib").is_dir())
+        .map(Path::to_path_buf);
+    if java && java_root.is_none() {
+        warn!(
+            "Could not tell which Java runtime {} belongs to, the frozen instance will use the java on the PATH",
+            plan.java_path.display()
+        );
+    }
+    let mut roots = vec![
+        Root {
+            from: plan.game_dir.clone(),
+            to: "game",
+        },
+        Root {
+            from: cache_path.clone(),
+            to: "cache",
+        },
+        Root {
+            from: work_path,
+            to: "work",
+        },
+    ];
+    if let Some(java_root) = &java_root {
+        roots.push(Root {
+            from: java_root.clone(),
+            to: "runtime",
+        });
+    }
+    let mut rewriter = Rewriter::new(roots);
+
+    let java_path = match (&java_root, &java_binary) {
+        (Some(_), Some(java_binary)) => rewriter.rewrite(&java_binary.to_string_lossy()),
+        _ => String::from("java"),
+    };
+    let jvm_args = plan
+        .jvm_args
+        .iter()
+        .map(|arg| rewriter.rewrite(arg))
+        .collect::<Vec<_>>();
+    let game_args = game_args
+        .iter()
+        .map(|arg| rewriter.rewrite(arg))
+        .collect::<Vec<_>>();
+    let env = plan
+        .env
+        .iter()
+        .map(|(key, value)| (key.clone(), rewriter.rewrite(value)))
+        .collect::<Vec<_>>();
+
+    std::fs::create_dir_all(out)?;
+    let out = dunce::canonicalize(out)?;
+    let mut report = FreezeReport {
+        dir: out.clone(),
+        java: java_root.as_ref().map(|_| out.join("runtime")),
+        ..Default::default()
+    };
+    // all of the game dir and the runtime, of the stores only what the arguments name
+    let game_dir = dunce::canonicalize(&plan.game_dir)?;
+    let backups = saves::backups_dir(&game_dir);
+    copy_tree(&game_dir, &out.join("game"), Some(&backups), &mut report)?;
+    if let Some(java_root) = &java_root {
+        copy_tree(java_root, &out.join("runtime"), None, &mut report)?;
+    }
+    let assets_dir = dunce::canonicalize(cache::assets_dir(&cache_path))?;
+    for (index, relative) in &rewriter.referenced {
+        let root = &rewriter.roots[*index];
+        if matches!(root.to, "game" | "runtime") {
+            continue;
+        }
+        let from = root.from.join(relative);
+        // the assets of this version only, not every version's in the cache
+        if from == assets_dir {
+            let info = versions::resolve_installed(&cache_path, &version_id).await?;
+            let index_file = format!("indexes/{}.json", info.asset_index.id);
+            let to = out.join(root.to).join(relative);
+            copy_tree(
+                &from.join(&index_file),
+                &to.join(&index_file),
+                None,
+                &mut report,
+            )?;
+            let index: AssetIndex =
+                serde_json::from_str(&std::fs::read_to_string(from.join(&index_file))?)?;
+            for object in index.objects.values() {
+                let object = format!("objects/{}", object.path());
+                copy_tree(&from.join(&object), &to.join(&object), None, &mut report)?;
+            }
+            continue;
+        }
+        if from.exists() {
+            copy_tree(&from, &out.join(root.to).join(relative), None, &mut report)?;
+        }
+    }
+    // for whoever looks at the directory later, the launch doesn't need them
+    for id in versions::lineage(&cache_path, &version_id)? {
+        let json = versions::json_path(&cache_path, &id);
+        let relative = json.strip_prefix(&cache_path)?;
+        copy_tree(&json, &out.join("cache").join(relative), None, &mut report)?;
+    }
+    write_atomic(
+        &out.join(INSTANCE_FILE),
+        serde_json::to_string_pretty(instance)?,
+    )
+    .await?;
+    for external in &rewriter.external {
+        warn!(
+            "{} is outside the instance and the launcher's stores, the frozen instance still refers to it",
+            external
+        );
+    }
+    report.external = rewriter.external;
+
+    let header = format!(
+        "{} ({}), frozen by mod_launcher {}. Runs from wherever this directory is moved to",
+        instance.name,
+        plan.version,
+        build_info::VERSION
+    );
+    let command = std::iter::once(&java_path)
+        .chain(&jvm_args)
+        .chain(std::iter::once(&plan.main_class))
+        .chain(&game_args);
+    let (script, contents) = match cfg!(windows) {
+        true => {
+            let mut script = format!(
+                "@echo off\r\nrem {}\r\nset \"ROOT=%~dp0\"\r\nset \"ROOT=%ROOT:~0,-1%\"\r\ncd /d \"%ROOT%\\game\"\r\n",
+                header
+            );
+            for (key, value) in &env {
+                script.push_str(&format!("set {}={}\r\n", key, cmd_word(value)));
+            }
+            let command = command.map(|arg| cmd_word(arg)).collect::<Vec<_>>();
+            script.push_str(&format!("{} %*\r\n", command.join(" ")));
+            ("start.bat", script)
+        }
+        false => {
+            let mut script = format!(
+                "#!/bin/sh\n# {}\nROOT=\"$(cd \"$(dirname \"$0\")\" && pwd)\"\ncd \"$ROOT/game\" || exit 1\n",
+                header
+            );
+            for (key, value) in &env {
+                script.push_str(&format!("export {}={}\n", key, sh_word(value)));
+            }
+            let command = command.map(|arg| sh_word(arg)).collect::<Vec<_>>();
+            script.push_str(&format!("exec {} \"$@\"\n", command.join(" \\\n  ")));
+            ("start.sh", script)
+        }
+    };
+    report.script = out.join(script);
+    write_atomic(&report.script, contents).await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&report.script, std::fs::Permissions::from_mode(0o755))?;
+    }
+    info!(
+        "Froze {} into {}, {} files ({} MiB)",
+        instance.name,
+        out.display(),
+        report.files,
+        report.bytes / 1024 / 1024
+    );
+    Ok(report)
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use regex::Regex;
+
+// fraction of the max heap that counts as "near the limit" after a collection
+const PRESSURE_THRESHOLD: f64 = 0.9;
+// fraction of collections that must be under pressure before we warn
+const PRESSURE_RATIO: f64 = 0.75;
+// don't draw conclusions from a handful of collections
+const MIN_SAMPLES: usize = 5;
+
+#[derive(Debug, Default)]
+pub struct GcReport {
+    pub collections: usize,
+    pub pressured_collections: usize,
+    pub max_heap_mb: Option<u64>,
+    pub out_of_memory: bool,
+}
+
+impl GcReport {
+    pub fn heap_pressure(&self) -> bool {
+        self.collections >= MIN_SAMPLES
+            && self.pressured_collections as f64 >= self.collections as f64 * PRESSURE_RATIO
+    }
+
+    pub fn needs_attention(&self) -> bool {
+        self.out_of_memory || self.heap_pressure()
+    }
+
+    pub fn suggested_max_memory_mb(&self) -> Option<u64> {
+        if !self.needs_attention() {
+            return None;
+        }
+
+        // grow by half, rounded up to the next 512M step
+        let current = self.max_heap_mb?;
+        let suggested = (current + current / 2).div_ceil(512) * 512;
+        Some(suggested.max(current + 512))
+    }
+
+    pub fn warning(&self) -> Option<String> {
+        if !self.needs_attention() {
+            return None;
+        }
+
+        let reason = if self.out_of_memory {
+            String::from("The game ran out of memory (OutOfMemoryError).")
+        } else {
+            format!(
+                "The heap was above {:.0}% of its maximum after {} of {} garbage collections.",
+                PRESSURE_THRESHOLD * 100.0,
+                self.pressured_collections,
+                self.collections
+            )
+        };
+
+        Some(match (self.max_heap_mb, self.suggested_max_memory_mb()) {
+            (Some(current), Some(suggested)) => format!(
+                "{} Consider raising the max memory from {}M to {}M (-Xmx{}M).",
+                reason, current, suggested, suggested
+            ),
+            _ => format!("{} Consider raising the max memory with -Xmx.", reason),
+        })
+    }
+}
+
+pub fn logging_args(log_file: &Path, java_major: u8) -> Vec<String> {
+    let log_file = log_file.to_string_lossy();
+    if java_major >= 9 {
+        vec![format!("-Xlog:gc,gc+init:file={}:uptime,level,tags", log_file)]
+    } else {
+        vec![
+            format!("-Xloggc:{}", log_file),
+            String::from("-XX:+PrintGCDetails"),
+        ]
+    }
+}
+
+pub fn analyze(gc_log: &str, game_output: &str, max_heap_mb: Option<u64>) -> GcReport {
+    // "24M->3M(256M)" (unified logging) or "33280K->5102K(125952K)" (java 8 -Xloggc)
+    let collection_regex =
+        Regex::new(r"(\d+)([KMG])->(?<after>\d+)(?<after_unit>[KMG])\((?<cap>\d+)(?<cap_unit>[KMG])\)").unwrap();
+    let max_capacity_regex = Regex::new(r"Heap Max Capacity: (?<max>\d+)(?<unit>[KMG])").unwrap();
+
+    let max_heap_mb = max_heap_mb.or_else(|| {
+        max_capacity_regex
+            .captures(gc_log)
+            .map(|caps| to_mb(&caps["max"], &caps["unit"]))
+    });
+
+    let mut report = GcReport {
+        max_heap_mb,
+        out_of_memory: game_output.contains("java.lang.OutOfMemoryError")
+            || gc_log.contains("java.lang.OutOfMemoryError"),
+        ..Default::default()
+    };
+
+    // without a known max, the largest committed heap we saw is the best guess
+    let mut largest_capacity = 0;
+    let mut samples = Vec::new();
+    for line in gc_log.lines() {
+        // java 8 -XX:+PrintGCDetails also prints per-generation breakdowns on the same line,
+        // the whole-heap figure is the last one
+        if let Some(caps) = collection_regex.captures_iter(line).last() {
+            let after = to_mb(&caps["after"], &caps["after_unit"]);
+            let capacity = to_mb(&caps["cap"], &caps["cap_unit"]);
+            largest_capacity = largest_capacity.max(capacity);
+            samples.push(after);
+        }
+    }
+
+    let max_heap = report.max_heap_mb.unwrap_or(largest_capacity);
+    if report.max_heap_mb.is_none() && largest_capacity > 0 {
+        report.max_heap_mb = Some(largest_capacity);
+    }
+
+    report.collections = samples.len();
+    if max_heap > 0 {
+        report.pressured_collections = samples
+            .iter()
+            .filter(|after| **after as f64 >= max_heap as f64 * PRESSURE_THRESHOLD)
+            .count();
+    }
+
+    report
+}
+
+fn to_mb(value: &str, unit: &str) -> u64 {
+    let value: u64 = value.parse().unwrap_or(0);
+    match unit {
+        "K" => value / 1024,
+        "G" => value * 1024,
+        _ => value,
+    }
+}
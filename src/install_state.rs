@@ -0,0 +1,107 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::{versions, write_atomic};
+
+const STATE_FILE: &str = "install.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum InstallStep {
+    Libraries,
+    Client,
+    Assets,
+}
+
+// enough to tell a file was replaced or modified without reading it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileStamp {
+    size: u64,
+    modified_ms: u64,
+}
+
+impl FileStamp {
+    pub(crate) fn of(file: &Path) -> Option<FileStamp> {
+        let metadata = std::fs::metadata(file).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(FileStamp {
+            size: metadata.len(),
+            modified_ms: modified.as_millis() as u64,
+        })
+    }
+}
+
+// which steps of a version's install finished and the files they left behind, so a re-run
+// after an interruption only redoes what is missing instead of re-hashing everything
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct InstallState {
+    // keyed by path relative to the cache dir
+    steps: BTreeMap<InstallStep, BTreeMap<String, FileStamp>>,
+}
+
+impl InstallState {
+    fn path(cache_dir: &Path, version_id: &str) -> PathBuf {
+        versions::version_dir(cache_dir, version_id).join(STATE_FILE)
+    }
+
+    // a missing or unreadable state just means starting over
+    pub fn load(cache_dir: &Path, version_id: &str) -> InstallState {
+        std::fs::read_to_string(Self::path(cache_dir, version_id))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub async fn save(&self, cache_dir: &Path, version_id: &str) -> anyhow::Result<()> {
+        write_atomic(
+            &Self::path(cache_dir, version_id),
+            serde_json::to_string_pretty(self)?,
+        )
+        .await
+    }
+
+    // complete when recorded for exactly these files and none of them changed since
+    pub fn is_complete(&self, cache_dir: &Path, step: InstallStep, files: &[PathBuf]) -> bool {
+        let Some(recorded) = self.steps.get(&step) else {
+            return false;
+        };
+
+        recorded.len() == files.len()
+            && files.iter().all(|file| {
+                relative(cache_dir, file)
+                    .and_then(|relative| recorded.get(&relative))
+                    .is_some_and(|recorded| Some(*recorded) == FileStamp::of(file))
+            })
+    }
+
+    pub fn complete(
+        &mut self,
+        cache_dir: &Path,
+        step: InstallStep,
+        files: &[PathBuf],
+    ) -> anyhow::Result<()> {
+        let mut recorded = BTreeMap::new();
+        for file in files {
+            if let Some(relative) = relative(cache_dir, file) {
+                let stamp = FileStamp::of(file)
+                    .ok_or_else(|| anyhow!("Failed to read {}", file.display()))?;
+                recorded.insert(relative, stamp);
+            }
+        }
+        self.steps.insert(step, recorded);
+        Ok(())
+    }
+}
+
+fn relative(cache_dir: &Path, file: &Path) -> Option<String> {
+    file.strip_prefix(cache_dir)
+        .ok()
+        .map(|relative| relative.to_string_lossy().replace('\\', "/"))
+}
@@ -0,0 +1,701 @@
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Context};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{
+    adopt::AdoptedFile,
+    components::Component,
+    config::Config,
+    loader::{LoaderBuild, LoaderKind},
+    net::{MetaProvider, NetworkStatus},
+    services::ServiceOverrides,
+    update_channel::InstalledRelease,
+    versions, LaunchOptions,
+};
+
+const INSTANCE_FILE: &str = "instance.json";
+// the CLI, the daemon and GUIs may all edit instances at once. Writers hold this lock for their
+// whole read-modify-write and replace instance.json by renaming, so readers never see half a
+// file and need no lock
+const LOCK_FILE: &str = ".lock";
+// what was resolved when the instance was set up, e.g. which build "Forge for 1.20.1" meant at
+// the time, so the same one can be installed again later or elsewhere
+const LOCKFILE: &str = "instance.lock.json";
+const ICON_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "ico", "icns"];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct Instance {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub loader: Option<LoaderKind>,
+    #[serde(default)]
+    pub max_memory_mb: Option<u64>,
+    #[serde(default)]
+    pub gc_logging: bool,
+    #[serde(default)]
+    pub skip_jvm_templates: bool,
+    #[serde(default)]
+    pub service_overrides: ServiceOverrides,
+    // file name of the icon inside the instance dir
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default, with = "time::serde::iso8601::option")]
+    pub last_played: Option<time::OffsetDateTime>,
+    #[serde(default)]
+    pub java_path: Option<PathBuf>,
+    // appended after the launcher's own JVM arguments
+    #[serde(default)]
+    pub jvm_args: Vec<String>,
+    // game files outside the instance dir, e.g. a profile imported from the official launcher
+    #[serde(default)]
+    pub custom_game_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub backup_worlds: bool,
+    #[serde(default)]
+    pub backup_keep: Option<usize>,
+    // username or uuid of the login this instance plays as, the selected one when unset
+    #[serde(default)]
+    pub account: Option<String>,
+    // on top of launcher.toml's, e.g. __GL_THREADED_OPTIMIZATIONS or SDL_VIDEODRIVER
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    // trusted by the game on top of Java's defaults, for networks that intercept TLS
+    #[serde(default)]
+    pub ca_certs: Vec<PathBuf>,
+    // moves `version` along with the manifest's latest release or snapshot, pinned when unset
+    #[serde(default)]
+    pub channel: Option<Channel>,
+    // launcher.toml's telemetry_opt_out when unset
+    #[serde(default)]
+    pub telemetry_opt_out: Option<bool>,
+    // agents and jar mods layered over `version`, bottom first, see components.rs
+    #[serde(default)]
+    pub components: Vec<Component>,
+    // the published channel.json this instance takes its mods and settings from, see
+    // update_channel.rs
+    #[serde(default)]
+    pub update_url: Option<String>,
+    // the game's language, e.g. de_de, set in options.txt until the game or the player sets one
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+impl Instance {
+    pub fn new(name: &str, version: &str) -> Instance {
+        Instance {
+            name: name.to_string(),
+            version: version.to_string(),
+            loader: None,
+            max_memory_mb: None,
+            gc_logging: false,
+            skip_jvm_templates: false,
+            service_overrides: ServiceOverrides::default(),
+            icon: None,
+            description: None,
+            tags: vec![],
+            group: None,
+            last_played: None,
+            java_path: None,
+            jvm_args: vec![],
+            custom_game_dir: None,
+            backup_worlds: false,
+            backup_keep: None,
+            account: None,
+            env: BTreeMap::new(),
+            ca_certs: vec![],
+            channel: None,
+            telemetry_opt_out: None,
+            components: vec![],
+            update_url: None,
+            language: None,
+        }
+    }
+
+    pub fn dir(&self, instances_dir: &Path) -> PathBuf {
+        instances_dir.join(&self.name)
+    }
+
+    pub fn game_dir(&self, instances_dir: &Path) -> PathBuf {
+        match &self.custom_game_dir {
+            Some(game_dir) => game_dir.clone(),
+            None => self.dir(instances_dir).join(".minecraft"),
+        }
+    }
+
+    pub fn icon_path(&self, instances_dir: &Path) -> Option<PathBuf> {
+        self.icon
+            .as_ref()
+            .map(|icon| self.dir(instances_dir).join(icon))
+    }
+
+    pub fn add_tag(&mut self, tag: &str) {
+        let tag = tag.trim();
+        if !tag.is_empty() && !self.tags.iter().any(|existing| existing == tag) {
+            self.tags.push(tag.to_string());
+        }
+    }
+
+    pub fn remove_tag(&mut self, tag: &str) {
+        self.tags.retain(|existing| existing != tag.trim());
+    }
+
+    // instance settings take precedence over the launcher-wide config
+    pub fn launch_options(&self, config: &Config) -> LaunchOptions {
+        let mut options = config.launch_options();
+        options.version = Some(self.version.clone());
+        options.instance = Some(self.name.clone());
+        options.game_dir = Some(self.game_dir(&config.instances_dir()));
+        options.max_memory_mb = self.max_memory_mb.or(config.max_memory_mb);
+        if let Some(java_path) = &self.java_path {
+            options.java_path = Some(java_path.clone());
+        }
+        options.extra_jvm_args = self.jvm_args.clone();
+        options.components = self.components.clone();
+        options.env.extend(self.env.clone());
+        options.ca_certs = self.ca_certs.clone();
+        options.gc_logging = self.gc_logging;
+        options.loader = self.loader;
+        options.skip_jvm_templates = self.skip_jvm_templates;
+        options.backup_worlds = self.backup_worlds;
+        options.backup_keep = self.backup_keep;
+        options.language = self.language.clone();
+        if let Some(opt_out) = self.telemetry_opt_out {
+            options.telemetry_opt_out = opt_out;
+        }
+        if !self.service_overrides.is_empty() {
+            options.service_overrides = self.service_overrides.clone();
+        }
+        options
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    Release,
+    Snapshot,
+}
+
+impl FromStr for Channel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "release" => Ok(Channel::Release),
+            "snapshot" => Ok(Channel::Snapshot),
+            _ => Err(anyhow!("Unknown channel {}, use release or snapshot", s)),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Release => f.write_str("release"),
+            Channel::Snapshot => f.write_str("snapshot"),
+        }
+    }
+}
+
+// an instance moved to a newer version by its channel
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VersionUpdate {
+    pub instance: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    #[default]
+    Name,
+    // most recently played first, never played last
+    LastPlayed,
+    // newest first
+    Version,
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "name" => Ok(SortKey::Name),
+            "last-played" => Ok(SortKey::LastPlayed),
+            "version" => Ok(SortKey::Version),
+            _ => Err(anyhow!(
+                "Unknown sort key {}, use name, last-played or version",
+                s
+            )),
+        }
+    }
+}
+
+// filters are combined, an empty query lists every instance by name
+#[derive(Debug, Clone, Default)]
+pub struct InstanceQuery {
+    pub group: Option<String>,
+    // only instances outside of any group, takes precedence over `group`
+    pub ungrouped: bool,
+    // instances must carry every one of these
+    pub tags: Vec<String>,
+    pub loader: Option<LoaderKind>,
+    pub sort: SortKey,
+    pub reverse: bool,
+}
+
+impl InstanceQuery {
+    pub fn matches(&self, instance: &Instance) -> bool {
+        let matches_group = if self.ungrouped {
+            instance.group.is_none()
+        } else {
+            self.group
+                .as_ref()
+                .is_none_or(|group| instance.group.as_ref() == Some(group))
+        };
+
+        matches_group
+            && self.tags.iter().all(|tag| instance.tags.contains(tag))
+            && self
+                .loader
+                .is_none_or(|loader| instance.loader == Some(loader))
+    }
+
+    pub fn apply(&self, mut instances: Vec<Instance>) -> Vec<Instance> {
+        instances.retain(|instance| self.matches(instance));
+        instances.sort_by(|a, b| {
+            let ordering = match self.sort {
+                SortKey::Name => Ordering::Equal,
+                // None sorts before Some, so flipping puts never played instances last
+                SortKey::LastPlayed => b.last_played.cmp(&a.last_played),
+                SortKey::Version => compare_versions(&b.version, &a.version),
+            };
+            ordering.then_with(|| a.name.cmp(&b.name))
+        });
+        if self.reverse {
+            instances.reverse();
+        }
+        instances
+    }
+}
+
+// compares digit runs numerically so 1.9 sorts before 1.20
+pub(crate) fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn segments(version: &str) -> Vec<(u64, &str)> {
+        let mut segments = Vec::new();
+        let mut rest = version;
+        while !rest.is_empty() {
+            let split = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let (digits, tail) = rest.split_at(split);
+            let split = tail
+                .find(|c: char| c.is_ascii_digit())
+                .unwrap_or(tail.len());
+            let (text, tail) = tail.split_at(split);
+            segments.push((digits.parse().unwrap_or(0), text));
+            rest = tail;
+        }
+        segments
+    }
+
+    segments(a).cmp(&segments(b))
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InstanceGroup {
+    // None holds the ungrouped instances
+    pub name: Option<String>,
+    pub instances: Vec<Instance>,
+}
+
+fn validate_name(name: &str) -> anyhow::Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ' '));
+
+    if !valid {
+        return Err(anyhow!(
+            "Invalid instance name {:?}, use letters, digits, spaces, '-', '_' or '.'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+fn lock(instances_dir: &Path) -> anyhow::Result<File> {
+    std::fs::create_dir_all(instances_dir)?;
+    let lock = File::create(instances_dir.join(LOCK_FILE))?;
+    lock.lock_exclusive()
+        .with_context(|| format!("Could not lock {}", instances_dir.display()))?;
+    Ok(lock)
+}
+
+pub fn create(instances_dir: &Path, name: &str, version: &str) -> anyhow::Result<Instance> {
+    validate_name(name)?;
+
+    let _lock = lock(instances_dir)?;
+    let instance = Instance::new(name, version);
+    if instance.dir(instances_dir).exists() {
+        return Err(anyhow!("Instance {} already exists", name));
+    }
+
+    std::fs::create_dir_all(instance.game_dir(instances_dir))?;
+    write(instances_dir, &instance)?;
+    Ok(instance)
+}
+
+pub fn load(instances_dir: &Path, name: &str) -> anyhow::Result<Instance> {
+    validate_name(name)?;
+
+    let path = instances_dir.join(name).join(INSTANCE_FILE);
+    if !path.exists() {
+        return Err(anyhow!("Instance {} does not exist", name));
+    }
+
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+pub fn save(instances_dir: &Path, instance: &Instance) -> anyhow::Result<()> {
+    validate_name(&instance.name)?;
+
+    let _lock = lock(instances_dir)?;
+    write(instances_dir, instance)
+}
+
+// with the lock held
+fn write(instances_dir: &Path, instance: &Instance) -> anyhow::Result<()> {
+    let dir = instance.dir(instances_dir);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(INSTANCE_FILE);
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, serde_json::to_string_pretty(instance)?)?;
+    std::fs::rename(&temp, &path)?;
+    Ok(())
+}
+
+// read-modify-write of a single instance, other writers wait until it's done
+pub fn update(
+    instances_dir: &Path,
+    name: &str,
+    edit: impl FnOnce(&mut Instance),
+) -> anyhow::Result<Instance> {
+    validate_name(name)?;
+
+    let _lock = lock(instances_dir)?;
+    let mut instance = load(instances_dir, name)?;
+    edit(&mut instance);
+    // renames aren't supported through here, the directory is keyed by name
+    instance.name = name.to_string();
+    write(instances_dir, &instance)?;
+    Ok(instance)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub loader: Option<LoaderBuild>,
+    // the update channel release the instance is on
+    #[serde(default)]
+    pub release: Option<InstalledRelease>,
+    // random, decides which staged update channel releases reach the instance
+    #[serde(default)]
+    pub follower_id: Option<String>,
+    // mods and resource packs added by hand, by path in the game dir, see adopt.rs
+    #[serde(default)]
+    pub content: BTreeMap<String, AdoptedFile>,
+}
+
+// empty until something was locked
+pub fn lockfile(instances_dir: &Path, name: &str) -> anyhow::Result<Lockfile> {
+    validate_name(name)?;
+
+    let path = instances_dir.join(name).join(LOCKFILE);
+    match std::fs::read_to_string(&path) {
+        Ok(json) => serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Lockfile::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// `update` that edits the lockfile along with the instance, under the same lock
+pub fn update_locked(
+    instances_dir: &Path,
+    name: &str,
+    edit: impl FnOnce(&mut Instance, &mut Lockfile),
+) -> anyhow::Result<(Instance, Lockfile)> {
+    validate_name(name)?;
+
+    let _lock = lock(instances_dir)?;
+    let mut instance = load(instances_dir, name)?;
+    let mut lockfile = lockfile(instances_dir, name)?;
+    edit(&mut instance, &mut lockfile);
+    instance.name = name.to_string();
+
+    let path = instance.dir(instances_dir).join(LOCKFILE);
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, serde_json::to_string_pretty(&lockfile)?)?;
+    std::fs::rename(&temp, &path)?;
+    write(instances_dir, &instance)?;
+    Ok((instance, lockfile))
+}
+
+pub fn set_icon(
+    instances_dir: &Path,
+    name: &str,
+    source: Option<&Path>,
+) -> anyhow::Result<Instance> {
+    validate_name(name)?;
+
+    let _lock = lock(instances_dir)?;
+    let mut instance = load(instances_dir, name)?;
+    if let Some(old_icon) = instance.icon_path(instances_dir) {
+        if old_icon.exists() {
+            std::fs::remove_file(old_icon)?;
+        }
+    }
+
+    instance.icon = match source {
+        Some(source) => {
+            let extension = source
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| ext.to_ascii_lowercase())
+                .filter(|ext| ICON_EXTENSIONS.contains(&ext.as_str()))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Unsupported icon {}, expected one of {}",
+                        source.display(),
+                        ICON_EXTENSIONS.join(", ")
+                    )
+                })?;
+
+            let file_name = format!("icon.{}", extension);
+            std::fs::copy(source, instance.dir(instances_dir).join(&file_name))?;
+            Some(file_name)
+        }
+        None => None,
+    };
+
+    write(instances_dir, &instance)?;
+    Ok(instance)
+}
+
+pub fn list(instances_dir: &Path) -> anyhow::Result<Vec<Instance>> {
+    if !instances_dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut instances = Vec::new();
+    for entry in std::fs::read_dir(instances_dir)? {
+        let path = entry?.path().join(INSTANCE_FILE);
+        if path.exists() {
+            instances.push(serde_json::from_str::<Instance>(&std::fs::read_to_string(
+                path,
+            )?)?);
+        }
+    }
+
+    instances.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(instances)
+}
+
+pub fn query(instances_dir: &Path, query: &InstanceQuery) -> anyhow::Result<Vec<Instance>> {
+    Ok(query.apply(list(instances_dir)?))
+}
+
+// instances bucketed by group, named groups alphabetically and ungrouped ones last
+pub fn groups(instances_dir: &Path, query: &InstanceQuery) -> anyhow::Result<Vec<InstanceGroup>> {
+    let mut groups: Vec<InstanceGroup> = Vec::new();
+    for instance in self::query(instances_dir, query)? {
+        match groups.iter_mut().find(|group| group.name == instance.group) {
+            Some(group) => group.instances.push(instance),
+            None => groups.push(InstanceGroup {
+                name: instance.group.clone(),
+                instances: vec![instance],
+            }),
+        }
+    }
+
+    groups.sort_by(|a, b| match (&a.name, &b.name) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+    Ok(groups)
+}
+
+pub fn mark_played(instances_dir: &Path, name: &str) -> anyhow::Result<Instance> {
+    update(instances_dir, name, |instance| {
+        instance.last_played = Some(time::OffsetDateTime::now_utc());
+    })
+}
+
+// points an instance that follows a channel at the latest version before it launches. The game
+// dir stays where it is, so saves, options and mods carry over to the new version, which the
+// launch then installs
+pub async fn track_latest(
+    instances_dir: &Path,
+    name: &str,
+    meta: &dyn MetaProvider,
+    cache_dir: &Path,
+    network: NetworkStatus,
+) -> anyhow::Result<Option<VersionUpdate>> {
+    let instance = load(instances_dir, name)?;
+    let Some(channel) = instance.channel else {
+        return Ok(None);
+    };
+    if network == NetworkStatus::Offline {
+        return Ok(None);
+    }
+    // a loader is installed for one game version, following the game would leave it behind
+    if let Some(loader) = instance.loader {
+        warn!(
+            "{} uses {}, only vanilla instances follow the latest {}",
+            name, loader, channel
+        );
+        return Ok(None);
+    }
+
+    let latest = versions::manifest(meta, cache_dir, network).await?.latest;
+    let latest = match channel {
+        Channel::Release => latest.release,
+        Channel::Snapshot => latest.snapshot,
+    };
+    if latest == instance.version {
+        return Ok(None);
+    }
+
+    update(instances_dir, name, |instance| {
+        instance.version = latest.clone()
+    })?;
+    info!("Updated {} from {} to {}", name, instance.version, latest);
+    Ok(Some(VersionUpdate {
+        instance: name.to_string(),
+        from: instance.version,
+        to: latest,
+    }))
+}
+
+pub fn delete(instances_dir: &Path, name: &str) -> anyhow::Result<()> {
+    validate_name(name)?;
+
+    let _lock = lock(instances_dir)?;
+    let instance = load(instances_dir, name)?;
+    std::fs::remove_dir_all(instance.dir(instances_dir))?;
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Removed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InstanceChange {
+    pub kind: ChangeKind,
+    pub name: String,
+    // as it is now, None once removed
+    pub instance: Option<Instance>,
+}
+
+// notices instances added, edited or removed by any process, hand edits included, by comparing
+// every instance.json with what it held at the last poll
+pub struct InstanceWatcher {
+    instances_dir: PathBuf,
+    known: BTreeMap<String, (String, Instance)>,
+}
+
+impl InstanceWatcher {
+    pub fn new(instances_dir: &Path) -> anyhow::Result<InstanceWatcher> {
+        Ok(InstanceWatcher {
+            instances_dir: instances_dir.to_path_buf(),
+            known: read_all(instances_dir)?,
+        })
+    }
+
+    // what changed since the last poll, in name order
+    pub fn poll(&mut self) -> anyhow::Result<Vec<InstanceChange>> {
+        let current = read_all(&self.instances_dir)?;
+        let mut changes = Vec::new();
+        for (name, (json, instance)) in &current {
+            let kind = match self.known.get(name) {
+                None => ChangeKind::Added,
+                Some((known, _)) if known != json => ChangeKind::Modified,
+                Some(_) => continue,
+            };
+            changes.push(InstanceChange {
+                kind,
+                name: name.clone(),
+                instance: Some(instance.clone()),
+            });
+        }
+        for name in self.known.keys() {
+            if !current.contains_key(name) {
+                changes.push(InstanceChange {
+                    kind: ChangeKind::Removed,
+                    name: name.clone(),
+                    instance: None,
+                });
+            }
+        }
+        changes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        self.known = current;
+        Ok(changes)
+    }
+}
+
+// instance.json files that don't parse count as missing, fixing one adds it back
+fn read_all(instances_dir: &Path) -> anyhow::Result<BTreeMap<String, (String, Instance)>> {
+    let mut instances = BTreeMap::new();
+    if !instances_dir.exists() {
+        return Ok(instances);
+    }
+
+    for entry in std::fs::read_dir(instances_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let json = match std::fs::read_to_string(entry.path().join(INSTANCE_FILE)) {
+            Ok(json) => json,
+            // not an instance, or deleted since read_dir
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        match serde_json::from_str::<Instance>(&json) {
+            Ok(instance) => {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                instances.insert(name, (json, instance));
+            }
+            Err(e) => debug!(path = %entry.path().display(), "unreadable instance: {}", e),
+        }
+    }
+    Ok(instances)
+}
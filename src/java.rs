@@ -0,0 +1,476 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tracing::{debug, warn};
+
+use crate::{
+    install_state::FileStamp,
+    jvm_templates::VersionEra,
+    loader::LoaderKind,
+    watchdog::{self, Finished, Hung, Limits},
+    write_atomic,
+};
+
+// probe results, so discovery only runs `java` for binaries it hasn't seen or that changed
+const DISCOVERY_CACHE_FILE: &str = "java.json";
+// where distributions and manual installs keep their JVMs
+const LINUX_JVM_DIRS: &[&str] = &[
+    "/usr/lib/jvm",
+    "/usr/lib64/jvm",
+    "/usr/java",
+    "/opt/java",
+    "/opt/jdk",
+];
+
+// the newest Java a loader runs on, for loaders that break on later releases
+struct LoaderJavaLimit {
+    loader: LoaderKind,
+    eras: &'static [VersionEra],
+    max_major: u8,
+    // some builds in the range lift the limit, so those only warn
+    fatal: bool,
+}
+
+const LOADER_LIMITS: &[LoaderJavaLimit] = &[
+    // LaunchWrapper casts the system class loader to URLClassLoader, which Java 9 removed
+    LoaderJavaLimit {
+        loader: LoaderKind::Forge,
+        eras: &[VersionEra::PreFlattening],
+        max_major: 8,
+        fatal: true,
+    },
+    // only the last 1.16.5 builds run on anything newer
+    LoaderJavaLimit {
+        loader: LoaderKind::Forge,
+        eras: &[VersionEra::Flattening],
+        max_major: 8,
+        fatal: false,
+    },
+];
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum JavaIssue {
+    // `java -version` failed or printed something we don't understand
+    Unknown {
+        java_path: PathBuf,
+    },
+    TooOld {
+        java_major: u8,
+        required_major: u8,
+        // Mojang's runtime component, e.g. "java-runtime-gamma"
+        runtime: String,
+    },
+    LoaderTooNew {
+        loader: LoaderKind,
+        java_major: u8,
+        max_major: u8,
+        fatal: bool,
+    },
+}
+
+impl JavaIssue {
+    // fatal issues stop the launch, the JVM would only fail with something cryptic
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            JavaIssue::Unknown { .. } => false,
+            JavaIssue::TooOld { .. } => true,
+            JavaIssue::LoaderTooNew { fatal, .. } => *fatal,
+        }
+    }
+
+    pub fn suggested_major(&self) -> Option<u8> {
+        match self {
+            JavaIssue::Unknown { .. } => None,
+            JavaIssue::TooOld { required_major, .. } => Some(*required_major),
+            JavaIssue::LoaderTooNew { max_major, .. } => Some(*max_major),
+        }
+    }
+}
+
+impl fmt::Display for JavaIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JavaIssue::Unknown { java_path } => write!(
+                f,
+                "Could not determine the version of {}, it may not be able to run this version",
+                java_path.display()
+            ),
+            JavaIssue::TooOld {
+                java_major,
+                required_major,
+                runtime,
+            } => write!(
+                f,
+                "This version needs Java {} or newer but the selected Java is {}. Install Java {} ({}) and select it with --java",
+                required_major, java_major, required_major, runtime
+            ),
+            JavaIssue::LoaderTooNew {
+                loader,
+                java_major,
+                max_major,
+                fatal,
+            } => write!(
+                f,
+                "The {} loader for this version {} on Java {}, the selected Java is {}. Select a Java {} install with --java",
+                loader,
+                if *fatal { "only runs" } else { "usually only runs" },
+                max_major,
+                java_major,
+                max_major
+            ),
+        }
+    }
+}
+
+// "1.8.0_382" is Java 8, "17.0.2" and "21-ea" are what they say
+pub fn parse_major(version_output: &str) -> Option<u8> {
+    let version = Regex::new(r#"version "([^"]+)""#)
+        .unwrap()
+        .captures(version_output)?[1]
+        .to_string();
+
+    let mut parts = version.split(|c: char| !c.is_ascii_digit());
+    let major = parts.next()?.parse().ok()?;
+    if major == 1 {
+        parts.next()?.parse().ok()
+    } else {
+        Some(major)
+    }
+}
+
+fn parse_arch(settings_output: &str) -> Option<String> {
+    Regex::new(r"os\.arch = (\S+)")
+        .unwrap()
+        .captures(settings_output)
+        .map(|captures| captures[1].to_string())
+}
+
+// a Java that hangs instead of printing its version counts as missing, with a warning saying so
+async fn run_probe(command: &mut tokio::process::Command, limits: Limits) -> Option<Finished> {
+    match watchdog::output(command, limits).await {
+        Ok(output) => Some(output),
+        Err(e) => {
+            if e.is::<Hung>() {
+                warn!("{}", e);
+            }
+            None
+        }
+    }
+}
+
+// None when the binary is missing or prints something unexpected
+pub async fn probe_major(java_path: &Path, limits: Limits) -> Option<u8> {
+    let mut command = tokio::process::Command::new(java_path);
+    command.arg("-version");
+    let output = run_probe(&mut command, limits).await?;
+
+    // the version goes to stderr
+    parse_major(&output.stderr).or_else(|| parse_major(&output.stdout))
+}
+
+pub fn check_compatibility(
+    java_path: &Path,
+    java_major: Option<u8>,
+    required_major: u8,
+    runtime: &str,
+    loader: Option<LoaderKind>,
+    release_time: OffsetDateTime,
+) -> Vec<JavaIssue> {
+    let Some(java_major) = java_major else {
+        return vec![JavaIssue::Unknown {
+            java_path: java_path.to_path_buf(),
+        }];
+    };
+
+    let mut issues = Vec::new();
+    if java_major < required_major {
+        issues.push(JavaIssue::TooOld {
+            java_major,
+            required_major,
+            runtime: runtime.to_string(),
+        });
+    }
+
+    let era = VersionEra::of(release_time);
+    for limit in LOADER_LIMITS {
+        if loader == Some(limit.loader) && limit.eras.contains(&era) && java_major > limit.max_major
+        {
+            issues.push(JavaIssue::LoaderTooNew {
+                loader: limit.loader,
+                java_major,
+                max_major: limit.max_major,
+                fatal: limit.fatal,
+            });
+        }
+    }
+    issues
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct JavaInstall {
+    pub path: PathBuf,
+    pub major: u8,
+    // as the JVM reports it, "amd64", "aarch64", "x86"...
+    pub arch: String,
+}
+
+impl JavaInstall {
+    // an x86 Java runs on arm Macs and Windows through emulation, slower but it runs
+    pub fn is_native(&self) -> bool {
+        let arch = match std::env::consts::ARCH {
+            "x86_64" => "amd64",
+            "x86" => "x86",
+            arch => arch,
+        };
+        self.arch == arch || (arch == "amd64" && self.arch == "x86_64")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct DiscoveryCache {
+    installs: Vec<ProbedInstall>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ProbedInstall {
+    #[serde(flatten)]
+    install: JavaInstall,
+    // the binary as it was when probed
+    stamp: FileStamp,
+}
+
+// runs `java` once to read both the version and the architecture
+pub async fn probe(java_path: &Path, limits: Limits) -> Option<JavaInstall> {
+    let mut command = tokio::process::Command::new(java_path);
+    command.args(["-XshowSettings:properties", "-version"]);
+    let output = run_probe(&mut command, limits).await?;
+    let output = format!("{}\n{}", output.stderr, output.stdout);
+
+    Some(JavaInstall {
+        path: java_path.to_path_buf(),
+        major: parse_major(&output)?,
+        arch: parse_arch(&output).unwrap_or_default(),
+    })
+}
+
+fn java_binary(home: &Path) -> PathBuf {
+    // javaw avoids popping up a console window on Windows
+    let binary = if cfg!(windows) { "javaw.exe" } else { "java" };
+    home.join("bin").join(binary)
+}
+
+// every directory in `parent`, as Java homes
+fn homes_in(parent: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(parent) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+// JavaHome values from the registry, where installers for Windows record themselves
+fn registry_homes() -> Vec<PathBuf> {
+    if !cfg!(windows) {
+        return vec![];
+    }
+
+    let mut homes = Vec::new();
+    for key in [
+        r"HKLM\SOFTWARE\JavaSoft",
+        r"HKLM\SOFTWARE\WOW6432Node\JavaSoft",
+        r"HKLM\SOFTWARE\Eclipse Adoptium",
+        r"HKLM\SOFTWARE\Microsoft\JDK",
+    ] {
+        let Ok(output) = std::process::Command::new("reg")
+            .args(["query", key, "/s"])
+            .output()
+        else {
+            continue;
+        };
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let mut parts = line.split_whitespace();
+            let name = parts.next();
+            if matches!(name, Some("JavaHome") | Some("Path")) && parts.next() == Some("REG_SZ") {
+                homes.push(PathBuf::from(parts.collect::<Vec<_>>().join(" ")));
+            }
+        }
+    }
+    homes
+}
+
+// the binaries worth probing: JAVA_HOME, PATH and where package managers and installers put them
+fn candidates() -> Vec<PathBuf> {
+    let mut homes = Vec::new();
+    if let Some(java_home) = std::env::var_os("JAVA_HOME") {
+        homes.push(PathBuf::from(java_home));
+    }
+    let home_dir = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+
+    if cfg!(target_os = "linux") {
+        for parent in LINUX_JVM_DIRS {
+            homes.extend(homes_in(Path::new(parent)));
+        }
+    }
+    if cfg!(target_os = "macos") {
+        let mut parents = vec![PathBuf::from("/Library/Java/JavaVirtualMachines")];
+        parents.extend(
+            home_dir
+                .iter()
+                .map(|home| home.join("Library/Java/JavaVirtualMachines")),
+        );
+        for parent in parents {
+            homes.extend(
+                homes_in(&parent)
+                    .into_iter()
+                    .map(|jvm| jvm.join("Contents/Home")),
+            );
+        }
+        // Homebrew's kegs, openjdk@17 and friends
+        for prefix in ["/opt/homebrew/opt", "/usr/local/opt"] {
+            for keg in homes_in(Path::new(prefix)) {
+                let is_openjdk = keg
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with("openjdk"));
+                if is_openjdk {
+                    homes.push(keg.join("libexec/openjdk.jdk/Contents/Home"));
+                }
+            }
+        }
+    }
+    if cfg!(windows) {
+        homes.extend(registry_homes());
+        for vendor in ["Java", "Eclipse Adoptium", "Microsoft", "Zulu", "BellSoft"] {
+            for program_files in ["ProgramFiles", "ProgramFiles(x86)"] {
+                if let Some(program_files) = std::env::var_os(program_files) {
+                    homes.extend(homes_in(&PathBuf::from(program_files).join(vendor)));
+                }
+            }
+        }
+    }
+    // SDKMAN and IntelliJ's downloads
+    for dir in [".sdkman/candidates/java", ".jdks"] {
+        if let Some(home_dir) = &home_dir {
+            homes.extend(homes_in(&home_dir.join(dir)));
+        }
+    }
+
+    let mut binaries = homes
+        .iter()
+        .map(|home| java_binary(home))
+        .collect::<Vec<_>>();
+    let binary = java_binary(Path::new("")).file_name().unwrap().to_owned();
+    if let Some(path) = std::env::var_os("PATH") {
+        binaries.extend(std::env::split_paths(&path).map(|dir| dir.join(&binary)));
+    }
+
+    // symlinks like /usr/bin/java lead to a home found above
+    let mut seen = std::collections::HashSet::new();
+    binaries
+        .into_iter()
+        .filter(|binary| binary.is_file())
+        .map(|binary| dunce::canonicalize(&binary).unwrap_or(binary))
+        .filter(|binary| seen.insert(binary.clone()))
+        .collect()
+}
+
+fn discovery_cache_path(cache_dir: &Path) -> PathBuf {
+    cache_dir.join(DISCOVERY_CACHE_FILE)
+}
+
+// finds the Java installs on this machine. Binaries probed before are only probed again when
+// they changed, `refresh` probes everything
+pub async fn discover(
+    cache_dir: &Path,
+    refresh: bool,
+    limits: Limits,
+) -> anyhow::Result<Vec<JavaInstall>> {
+    let cached = match refresh {
+        true => DiscoveryCache::default(),
+        false => std::fs::read_to_string(discovery_cache_path(cache_dir))
+            .ok()
+            .and_then(|json| serde_json::from_str::<DiscoveryCache>(&json).ok())
+            .unwrap_or_default(),
+    };
+
+    let probes = candidates().into_iter().map(|binary| {
+        let stamp = FileStamp::of(&binary);
+        let cached = cached
+            .installs
+            .iter()
+            .find(|probed| probed.install.path == binary && Some(probed.stamp) == stamp)
+            .cloned();
+        async move {
+            match cached {
+                Some(probed) => Some(probed),
+                None => Some(ProbedInstall {
+                    install: probe(&binary, limits).await?,
+                    stamp: stamp?,
+                }),
+            }
+        }
+    });
+    let mut probed = futures::future::join_all(probes)
+        .await
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    probed.sort_by(|a, b| {
+        b.install
+            .major
+            .cmp(&a.install.major)
+            .then_with(|| a.install.path.cmp(&b.install.path))
+    });
+    debug!(count = probed.len(), "discovered java installs");
+
+    let installs = probed.iter().map(|probed| probed.install.clone()).collect();
+    let cache = DiscoveryCache { installs: probed };
+    write_atomic(
+        &discovery_cache_path(cache_dir),
+        serde_json::to_string_pretty(&cache)?,
+    )
+    .await?;
+    Ok(installs)
+}
+
+// the install that best fits a version: the required major exactly, else the oldest newer one
+// without fatal issues, native builds before emulated ones
+pub fn select(
+    installs: &[JavaInstall],
+    required_major: u8,
+    loader: Option<LoaderKind>,
+    release_time: OffsetDateTime,
+) -> Option<&JavaInstall> {
+    installs
+        .iter()
+        .filter(|install| {
+            check_compatibility(
+                &install.path,
+                Some(install.major),
+                required_major,
+                "",
+                loader,
+                release_time,
+            )
+            .iter()
+            .all(|issue| !issue.is_fatal())
+        })
+        .min_by_key(|install| {
+            (
+                install.major != required_major,
+                !install.is_native(),
+                install.major,
+            )
+        })
+}
@@ -0,0 +1,95 @@
+use time::{macros::datetime, OffsetDateTime};
+
+use crate::loader::LoaderKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VersionEra {
+    // before 1.13
+    PreFlattening,
+    // 1.13 to 1.16, the last versions targeting Java 8
+    Flattening,
+    // 1.17 onwards, Java 16+ with the module system
+    Modern,
+}
+
+impl VersionEra {
+    // keyed on release time rather than the id so snapshots fall into the right era
+    pub fn of(release_time: OffsetDateTime) -> VersionEra {
+        if release_time < datetime!(2018-07-18 00:00 UTC) {
+            VersionEra::PreFlattening
+        } else if release_time < datetime!(2021-06-08 00:00 UTC) {
+            VersionEra::Flattening
+        } else {
+            VersionEra::Modern
+        }
+    }
+}
+
+pub struct JvmTemplate {
+    pub name: &'static str,
+    // None applies to every loader, including vanilla
+    pub loaders: Option<&'static [LoaderKind]>,
+    pub eras: &'static [VersionEra],
+    // upper bound on release time for fixes that only matter to a range of versions
+    pub released_before: Option<OffsetDateTime>,
+    pub args: &'static [&'static str],
+}
+
+impl JvmTemplate {
+    pub fn applies_to(&self, loader: Option<LoaderKind>, release_time: OffsetDateTime) -> bool {
+        let loader_matches = self
+            .loaders
+            .is_none_or(|loaders| loader.is_some_and(|loader| loaders.contains(&loader)));
+        let era_matches = self.eras.contains(&VersionEra::of(release_time));
+        let release_matches = self
+            .released_before
+            .is_none_or(|released_before| release_time < released_before);
+
+        loader_matches && era_matches && release_matches
+    }
+}
+
+const ALL_ERAS: &[VersionEra] = &[
+    VersionEra::PreFlattening,
+    VersionEra::Flattening,
+    VersionEra::Modern,
+];
+
+pub const TEMPLATES: &[JvmTemplate] = &[
+    JvmTemplate {
+        name: "log4shell",
+        loaders: None,
+        eras: ALL_ERAS,
+        // 1.18.1 ships a patched log4j
+        released_before: Some(datetime!(2021-12-10 00:00 UTC)),
+        args: &["-Dlog4j2.formatMsgNoLookups=true"],
+    },
+    JvmTemplate {
+        name: "legacy-forge-certificates",
+        loaders: Some(&[LoaderKind::Forge]),
+        eras: &[VersionEra::PreFlattening],
+        released_before: None,
+        args: &[
+            "-Dfml.ignoreInvalidMinecraftCertificates=true",
+            "-Dfml.ignorePatchDiscrepancies=true",
+        ],
+    },
+    JvmTemplate {
+        name: "modular-forge-opens",
+        loaders: Some(&[LoaderKind::Forge, LoaderKind::NeoForge]),
+        eras: &[VersionEra::Modern],
+        released_before: None,
+        args: &[
+            "--add-opens=java.base/java.util.jar=ALL-UNNAMED",
+            "--add-opens=java.base/java.lang.invoke=ALL-UNNAMED",
+        ],
+    },
+];
+
+pub fn template_args(loader: Option<LoaderKind>, release_time: OffsetDateTime) -> Vec<String> {
+    TEMPLATES
+        .iter()
+        .filter(|template| template.applies_to(loader, release_time))
+        .flat_map(|template| template.args.iter().map(|arg| arg.to_string()))
+        .collect()
+}
@@ -0,0 +1,288 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::write_atomic;
+
+const RULES_FILE: &str = "known_issues.json";
+// how long a downloaded ruleset is used before launches fetch it again
+const REFRESH_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+const FALLBACK_LANGUAGE: &str = "en";
+// shipped with the launcher, a downloaded ruleset replaces it once its version is higher
+const BUILTIN: &str = r#"{
+  "version": 1,
+  "rules": [
+    {
+      "id": "gpu-driver-crash",
+      "exit_codes": [-1073740791],
+      "patterns": ["EXCEPTION_ACCESS_VIOLATION.*(atio6axx|atioglxx|nvoglv|ig[0-9a-z]+icd)"],
+      "guidance": {
+        "en": "The graphics driver crashed the game. Update the driver from your GPU vendor's website, and on laptops make sure Java runs on the dedicated GPU",
+        "de": "Der Grafiktreiber hat das Spiel abstürzen lassen. Aktualisiere den Treiber von der Website des GPU-Herstellers und achte bei Laptops darauf, dass Java die dedizierte Grafikkarte nutzt",
+        "fr": "Le pilote graphique a fait planter le jeu. Mettez à jour le pilote depuis le site du fabricant de la carte graphique et, sur un portable, vérifiez que Java utilise la carte graphique dédiée",
+        "es": "El controlador gráfico hizo que el juego se cerrara. Actualiza el controlador desde la web del fabricante de la GPU y, en portátiles, comprueba que Java use la GPU dedicada"
+      }
+    },
+    {
+      "id": "pixel-format-not-accelerated",
+      "patterns": ["Pixel format not accelerated"],
+      "guidance": {
+        "en": "OpenGL is not hardware accelerated, the graphics driver is missing or the generic one is in use. Install the driver from your GPU vendor",
+        "de": "OpenGL wird nicht von der Hardware beschleunigt, der Grafiktreiber fehlt oder der generische ist in Benutzung. Installiere den Treiber des GPU-Herstellers",
+        "fr": "OpenGL n'est pas accéléré matériellement, le pilote graphique manque ou le pilote générique est utilisé. Installez le pilote du fabricant de la carte graphique",
+        "es": "OpenGL no tiene aceleración por hardware, falta el controlador gráfico o se usa el genérico. Instala el controlador del fabricante de la GPU"
+      }
+    },
+    {
+      "id": "mixin-apply-failed",
+      "patterns": ["Mixin apply for mod (\\S+) failed", "Mixin apply failed (\\S+\\.json)"],
+      "guidance": {
+        "en": "The mixins of $1 could not be applied. That mod doesn't fit this game version or clashes with another mod, update or remove it",
+        "de": "Die Mixins von $1 konnten nicht angewendet werden. Die Mod passt nicht zu dieser Spielversion oder kollidiert mit einer anderen Mod, aktualisiere oder entferne sie",
+        "fr": "Les mixins de $1 n'ont pas pu être appliqués. Ce mod ne correspond pas à cette version du jeu ou entre en conflit avec un autre mod, mettez-le à jour ou retirez-le",
+        "es": "No se pudieron aplicar los mixins de $1. Ese mod no encaja con esta versión del juego o choca con otro mod, actualízalo o quítalo"
+      }
+    },
+    {
+      "id": "java-too-old",
+      "patterns": ["UnsupportedClassVersionError.*class file version (\\d+)"],
+      "guidance": {
+        "en": "Something needs a newer Java than the one the game runs on (class file version $1). Leave java_path unset so the launcher picks the right Java",
+        "de": "Etwas braucht ein neueres Java als das, mit dem das Spiel läuft (Klassendateiversion $1). Lass java_path leer, damit der Launcher das passende Java wählt",
+        "fr": "Un élément nécessite une version de Java plus récente que celle du jeu (version de fichier de classe $1). Laissez java_path vide pour que le lanceur choisisse le bon Java",
+        "es": "Algo necesita un Java más reciente que el que usa el juego (versión de archivo de clase $1). Deja java_path sin definir para que el launcher elija el Java adecuado"
+      }
+    },
+    {
+      "id": "out-of-memory",
+      "patterns": ["java\\.lang\\.OutOfMemoryError"],
+      "guidance": {
+        "en": "The game ran out of memory. Raise max_memory_mb for the instance, or remove memory hungry mods and resource packs",
+        "de": "Dem Spiel ist der Speicher ausgegangen. Erhöhe max_memory_mb der Instanz oder entferne speicherhungrige Mods und Ressourcenpakete",
+        "fr": "Le jeu a manqué de mémoire. Augmentez max_memory_mb pour l'instance ou retirez les mods et packs de ressources gourmands en mémoire",
+        "es": "El juego se quedó sin memoria. Aumenta max_memory_mb de la instancia o quita mods y paquetes de recursos que consuman mucha memoria"
+      }
+    }
+  ]
+}"#;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Ruleset {
+    // only a higher version replaces the ruleset in use
+    pub version: u64,
+    pub rules: Vec<Rule>,
+}
+
+// a known cause of failed launches. It applies when any of its exit codes or patterns match
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rule {
+    pub id: String,
+    // as the process reports them, Windows' NTSTATUS codes negative
+    #[serde(default)]
+    pub exit_codes: Vec<i64>,
+    // regexes matched against every line of the log and the crash report
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    // by language, e.g. "en" or "pt-br". $1 and the like are replaced with the matching
+    // pattern's groups
+    pub guidance: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct KnownIssue {
+    pub id: String,
+    pub guidance: String,
+}
+
+pub fn rules_path(cache_dir: &Path) -> PathBuf {
+    cache_dir.join(RULES_FILE)
+}
+
+// the user's language from the locale, e.g. "de" for de_DE.UTF-8
+pub fn system_language() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|locale| !locale.is_empty())
+        .and_then(|locale| {
+            let language = locale.split(['.', '@']).next()?.replace('_', "-");
+            match language.as_str() {
+                "" | "C" | "POSIX" => None,
+                _ => Some(language.to_lowercase()),
+            }
+        })
+        .unwrap_or_else(|| FALLBACK_LANGUAGE.to_string())
+}
+
+impl Ruleset {
+    pub fn builtin() -> Ruleset {
+        serde_json::from_str(BUILTIN).expect("the built-in ruleset parses")
+    }
+
+    // the downloaded ruleset when it is newer than the built-in one
+    pub fn load(cache_dir: &Path) -> Ruleset {
+        let builtin = Ruleset::builtin();
+        let path = rules_path(cache_dir);
+        let downloaded = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<Ruleset>(&json).ok());
+        match downloaded {
+            Some(downloaded) if downloaded.version > builtin.version => downloaded,
+            _ => builtin,
+        }
+    }
+
+    // the issues `text` and the exit code point to, in the ruleset's order, with guidance in
+    // `language` or English when the rule doesn't have it
+    pub fn matches(&self, exit_code: Option<i32>, text: &str, language: &str) -> Vec<KnownIssue> {
+        let mut issues = Vec::new();
+        for rule in &self.rules {
+            let Some(template) = rule.guidance_for(language) else {
+                continue;
+            };
+            let by_exit_code = exit_code.is_some_and(|code| {
+                rule.exit_codes
+                    .iter()
+                    .any(|rule_code| *rule_code == code as i64 || *rule_code == code as u32 as i64)
+            });
+            let guidance = match by_exit_code {
+                true => Some(template.clone()),
+                false => rule.match_patterns(text, template),
+            };
+            if let Some(guidance) = guidance {
+                issues.push(KnownIssue {
+                    id: rule.id.clone(),
+                    guidance,
+                });
+            }
+        }
+        issues
+    }
+}
+
+impl Rule {
+    fn guidance_for(&self, language: &str) -> Option<&String> {
+        let language = language.to_lowercase();
+        let primary = language.split('-').next().unwrap_or_default();
+        self.guidance
+            .get(&language)
+            .or_else(|| self.guidance.get(primary))
+            .or_else(|| self.guidance.get(FALLBACK_LANGUAGE))
+            .or_else(|| self.guidance.values().next())
+    }
+
+    fn match_patterns(&self, text: &str, template: &str) -> Option<String> {
+        for pattern in &self.patterns {
+            let regex = match Regex::new(pattern) {
+                Ok(regex) => regex,
+                Err(e) => {
+                    debug!(rule = %self.id, "skipping invalid pattern: {}", e);
+                    continue;
+                }
+            };
+            if let Some(caps) = text.lines().find_map(|line| regex.captures(line)) {
+                let mut guidance = String::new();
+                caps.expand(template, &mut guidance);
+                return Some(guidance);
+            }
+        }
+        None
+    }
+}
+
+// fetches the ruleset at `url` and keeps it for the next crash. Refused when it doesn't parse
+pub async fn update(
+    client: &reqwest::Client,
+    cache_dir: &Path,
+    url: &str,
+) -> anyhow::Result<Ruleset> {
+    let json = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()
+        .with_context(|| format!("Could not download the known issues from {}", url))?
+        .text()
+        .await?;
+    let ruleset = serde_json::from_str::<Ruleset>(&json)
+        .map_err(|e| anyhow!("{} is not a known issues ruleset: {}", url, e))?;
+    write_atomic(&rules_path(cache_dir), &json).await?;
+    info!(
+        "Updated the known issues to version {}, {} rules",
+        ruleset.version,
+        ruleset.rules.len()
+    );
+    Ok(ruleset)
+}
+
+// `update` when the downloaded ruleset is a day old or missing
+pub async fn refresh(client: &reqwest::Client, cache_dir: &Path, url: &str) -> anyhow::Result<()> {
+    let fresh = std::fs::metadata(rules_path(cache_dir))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < REFRESH_AFTER);
+    if !fresh {
+        update(client, cache_dir, url).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_issues_give_localized_guidance() {
+        let rules = Ruleset::builtin();
+        let ids = |issues: &[KnownIssue]| {
+            issues
+                .iter()
+                .map(|issue| issue.id.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // 0xC0000409 as Windows reports it
+        let issues = rules.matches(Some(-1073740791), "", "en");
+        assert_eq!(ids(&issues), vec!["gpu-driver-crash"]);
+        assert!(rules.matches(Some(1), "", "en").is_empty());
+
+        let log = "[main/ERROR]: Mixin apply for mod sodium failed sodium.mixins.json:MixinFoo\n\
+            org.lwjgl.LWJGLException: Pixel format not accelerated";
+        let issues = rules.matches(Some(1), log, "en");
+        assert_eq!(
+            ids(&issues),
+            vec!["pixel-format-not-accelerated", "mixin-apply-failed"]
+        );
+        assert!(issues[1].guidance.starts_with("The mixins of sodium "));
+        // the region falls back to the language, an unknown language to English
+        let german = rules.matches(Some(1), log, "de-at");
+        assert!(german[1].guidance.starts_with("Die Mixins von sodium "));
+        assert_eq!(rules.matches(Some(1), log, "xx"), issues);
+
+        // a downloaded ruleset only replaces the built-in one when it is newer
+        let cache_dir =
+            std::env::temp_dir().join(format!("mod_launcher_known_issues_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        std::fs::create_dir_all(&cache_dir).unwrap();
+        let downloaded = r#"{"version": 1000, "rules": [{"id": "custom", "patterns": ["Custom (\\w+)"], "guidance": {"en": "custom $1"}}]}"#;
+        std::fs::write(rules_path(&cache_dir), downloaded).unwrap();
+        let issues = Ruleset::load(&cache_dir).matches(None, "Custom failure", "fr");
+        assert_eq!(issues[0].guidance, "custom failure");
+        let outdated = downloaded.replace("1000", "0");
+        std::fs::write(rules_path(&cache_dir), outdated).unwrap();
+        assert_eq!(
+            Ruleset::load(&cache_dir).version,
+            Ruleset::builtin().version
+        );
+
+        std::fs::remove_dir_all(cache_dir).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use tracing::debug;
+
+use crate::{cache, versions, write_atomic, AssetIndex};
+
+// built into the client jar, every other language is an asset object
+const ENGLISH: &str = "en_us";
+
+// "de-DE" and "de_DE" to de_de, how instances store it
+pub fn normalize(language: &str) -> anyhow::Result<String> {
+    let code = language.trim().to_ascii_lowercase().replace('-', "_");
+    if code.is_empty() || !code.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(anyhow!("{} is not a language code like de_de", language));
+    }
+    Ok(code)
+}
+
+// the language codes a version's asset index has, spelled the way that version's options.txt
+// wants them: de_de since 1.11, de_DE before
+fn available(index: &AssetIndex) -> Vec<String> {
+    let mut codes = index
+        .objects
+        .keys()
+        .filter_map(|key| {
+            let file = key
+                .strip_prefix("minecraft/lang/")
+                .or_else(|| key.strip_prefix("lang/"))?;
+            let (code, extension) = file.rsplit_once('.')?;
+            matches!(extension, "json" | "lang").then(|| code.to_string())
+        })
+        .collect::<Vec<_>>();
+    let mixed_case = codes
+        .iter()
+        .any(|code| code.chars().any(|c| c.is_ascii_uppercase()));
+    codes.push(match mixed_case {
+        true => String::from("en_US"),
+        false => ENGLISH.to_string(),
+    });
+    codes.sort_by_key(|code| code.to_ascii_lowercase());
+    codes.dedup();
+    codes
+}
+
+// the code as the version spells it, an error naming the close ones when the version has no
+// such language
+fn find(index: &AssetIndex, language: &str) -> anyhow::Result<String> {
+    let codes = available(index);
+    if let Some(code) = codes
+        .iter()
+        .find(|code| code.eq_ignore_ascii_case(language))
+    {
+        return Ok(code.clone());
+    }
+    let prefix = language.split('_').next().unwrap_or_default();
+    let close = codes
+        .iter()
+        .filter(|code| code.to_ascii_lowercase().split('_').next() == Some(prefix))
+        .cloned()
+        .collect::<Vec<_>>();
+    Err(match close.is_empty() {
+        true => anyhow!(
+            "{} is not one of the game's {} languages",
+            language,
+            codes.len()
+        ),
+        false => anyhow!(
+            "{} is not one of the game's languages, did you mean {}?",
+            language,
+            close.join(", ")
+        ),
+    })
+}
+
+fn load_index(cache_dir: &Path, index_id: &str) -> anyhow::Result<AssetIndex> {
+    let path = cache::assets_dir(cache_dir)
+        .join("indexes")
+        .join(format!("{}.json", index_id));
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+// checks `language` against an installed version's assets, versions that aren't installed yet
+// are checked on launch
+pub async fn check_installed(
+    cache_dir: &Path,
+    version_id: &str,
+    language: &str,
+) -> anyhow::Result<()> {
+    let Ok(info) = versions::resolve_installed(cache_dir, version_id).await else {
+        return Ok(());
+    };
+    let Ok(index) = load_index(cache_dir, &info.asset_index.id) else {
+        return Ok(());
+    };
+    find(&index, language).map(|_| ())
+}
+
+// sets `lang` in options.txt unless the game or the player already did. Fails when the version's
+// assets don't have the language, before the game silently falls back to English
+pub(crate) async fn apply(
+    game_dir: &Path,
+    cache_dir: &Path,
+    index_id: &str,
+    language: &str,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    // a dry run may not have fetched the index
+    let index = match load_index(cache_dir, index_id) {
+        Ok(index) => index,
+        Err(_) if dry_run => return Ok(()),
+        Err(e) => return Err(e.context(format!("Could not read asset index {}", index_id))),
+    };
+    let code = find(&index, language)?;
+    if dry_run {
+        return Ok(());
+    }
+    let path = game_dir.join("options.txt");
+    let existing = tokio::fs::read_to_string(&path).await.unwrap_or_default();
+    let mut lines = existing.lines().collect::<Vec<_>>();
+    if lines.iter().any(|line| line.starts_with("lang:")) {
+        return Ok(());
+    }
+    debug!(path = %path.display(), %code, "setting the game language");
+    let setting = format!("lang:{}", code);
+    lines.push(&setting);
+    write_atomic(&path, lines.join("\n") + "\n").await
+}
+
+// drops `lang` from options.txt so the next launch writes the instance's language again
+pub async fn reset(game_dir: &Path) -> anyhow::Result<()> {
+    let path = game_dir.join("options.txt");
+    let Ok(existing) = tokio::fs::read_to_string(&path).await else {
+        return Ok(());
+    };
+    let lines = existing
+        .lines()
+        .filter(|line| !line.starts_with("lang:"))
+        .collect::<Vec<_>>();
+    write_atomic(&path, lines.join("\n") + "\n").await
+}
@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use tracing::debug;
+
+use crate::{
+    rules::{OsConstraint, Rule, RuleAction},
+    AssetIndex, LaunchArgument, LaunchArguments, RuleType,
+};
+
+// minimumLauncherVersion of 1.13, the first version with `arguments` instead of a single
+// `minecraftArguments` string and no JVM arguments at all
+pub(crate) const ARGUMENTS_LAUNCHER_VERSION: u32 = 21;
+
+// what the official launcher passed before versions listed their own JVM arguments. The rules
+// match what 1.13+ JSONs carry for the same quirks
+pub(crate) fn arguments(minecraft_arguments: &str) -> LaunchArguments {
+    let os_rule = |name: Option<&str>, arch: Option<&str>| Rule {
+        action: RuleAction::Allow,
+        features: None,
+        os: Some(OsConstraint {
+            name: name.map(str::to_string),
+            arch: arch.map(str::to_string),
+        }),
+    };
+
+    LaunchArguments {
+        game: minecraft_arguments
+            .split_whitespace()
+            .map(|arg| LaunchArgument::String(arg.to_string()))
+            .collect(),
+        jvm: vec![
+            // Intel's Windows drivers pick their fast path by executable name
+            LaunchArgument::Rules {
+                rules: vec![os_rule(Some("windows"), None)],
+                value: RuleType::String(String::from(
+                    "-XX:HeapDumpPath=MojangTricksIntelDriversForPerformance_javaw.exe_minecraft.exe.heapdump",
+                )),
+            },
+            // 32-bit JVMs default to a stack too small for world generation
+            LaunchArgument::Rules {
+                rules: vec![os_rule(None, Some("x86"))],
+                value: RuleType::String(String::from("-Xss1M")),
+            },
+            LaunchArgument::String(String::from("-Djava.library.path=${natives_directory}")),
+            LaunchArgument::String(String::from("-cp")),
+            LaunchArgument::String(String::from("${classpath}")),
+        ],
+    }
+}
+
+// `${auth_session}`, the one value 1.5 and older take for the whole login
+pub(crate) fn auth_session(access_token: &str, uuid: &str) -> String {
+    format!("token:{}:{}", access_token, uuid)
+}
+
+// the directory `${game_assets}` points at. 1.6 and 1.7 read assets by name from a virtual
+// copy of the store, 1.5 and older from resources/ in the game dir. Objects are linked in
+// where the file system allows it, so the copies cost nothing
+pub(crate) fn prepare_assets(
+    assets_dir: &Path,
+    index_id: &str,
+    game_dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let (index, target) = read_index(assets_dir, index_id, game_dir)?;
+    let Some(target) = target else {
+        return Ok(assets_dir.to_path_buf());
+    };
+
+    let objects_dir = assets_dir.join("objects");
+    let mut linked = 0;
+    for (name, object) in &index.objects {
+        let dest = target.join(name);
+        let up_to_date = std::fs::metadata(&dest).is_ok_and(|dest| dest.len() == object.size);
+        if up_to_date {
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let _ = std::fs::remove_file(&dest);
+        let source = objects_dir.join(object.path());
+        if std::fs::hard_link(&source, &dest).is_err() {
+            std::fs::copy(&source, &dest)
+                .with_context(|| format!("Could not copy asset {}", name))?;
+        }
+        linked += 1;
+    }
+    debug!(target = %target.display(), linked, "legacy assets");
+    Ok(target)
+}
+
+// where prepare_assets puts the copies, without making them
+pub(crate) fn assets_target(
+    assets_dir: &Path,
+    index_id: &str,
+    game_dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let (_, target) = read_index(assets_dir, index_id, game_dir)?;
+    Ok(target.unwrap_or_else(|| assets_dir.to_path_buf()))
+}
+
+// None when the version reads the store directly
+fn read_index(
+    assets_dir: &Path,
+    index_id: &str,
+    game_dir: &Path,
+) -> anyhow::Result<(AssetIndex, Option<PathBuf>)> {
+    let index_file = assets_dir
+        .join("indexes")
+        .join(format!("{}.json", index_id));
+    let index: AssetIndex = serde_json::from_str(&std::fs::read_to_string(&index_file)?)
+        .with_context(|| format!("Failed to parse {}", index_file.display()))?;
+    let target = if index.map_to_resources {
+        Some(game_dir.join("resources"))
+    } else if index.is_virtual {
+        Some(assets_dir.join("virtual").join(index_id))
+    } else {
+        None
+    };
+    Ok((index, target))
+}
@@ -1,181 +1,1401 @@
-use std::{collections::HashMap, path::{Path, PathBuf}};
-
-use anyhow::Error;
+//! Installs and launches Minecraft, vanilla or with a mod loader, for the `mod_launcher` CLI,
+//! its daemon and frontends built on this crate.
+//!
+//! Frontends build on [`prelude`], the rest of the API is the launcher's own and hidden from
+//! these docs. Within a 0.x minor version nothing in the prelude changes incompatibly: an item
+//! leaves it only after a release where it is still there, marked
+//! `#[deprecated(since = "0.x.0", note = "use ... instead")]` with the note naming its
+//! replacement. Enums that gain variants as the launcher grows are `#[non_exhaustive]`, match
+//! them with a `_` arm. So are [`LaunchOptions`], [`Config`](prelude::Config) and
+//! [`Instance`](prelude::Instance), which gain fields: start from [`LaunchOptions::new`],
+//! `Config::load` or `Instance::new` and set what you need.
+//!
+//! ```no_run
+//! use mod_launcher::prelude::*;
+//!
+//! # async fn launch() -> anyhow::Result<()> {
+//! let mut options = LaunchOptions::new("1.20.1");
+//! options.loader = Some(LoaderKind::Fabric);
+//! options.quick_play = Some(QuickPlay::Singleplayer(String::from("World")));
+//! options.resolution = Some(Resolution {
+//!     width: 854,
+//!     height: 480,
+//! });
+//! let modded = match options.loader {
+//!     Some(LoaderKind::Fabric | LoaderKind::Quilt) => "fabric-like",
+//!     Some(_) => "other",
+//!     None => "vanilla",
+//! };
+//! println!("launching {}", modded);
+//! launch_minecraft(options).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{anyhow, Context};
 use regex::Regex;
-use reqwest;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
-use text_io;
-use tokio::task::JoinSet;
-
-pub async fn launch_minecraft() -> anyhow::Result<()> {
-    let client = reqwest::Client::new();
-    let version_manifest = retrieve_versions(&client).await.unwrap();
-
-    let work_path = std::env::current_dir()?.join("run");
-    println!("{:?}", work_path);
-
-    println!("Launching latest version...");
-    let version = version_manifest
-        .find_version_by_id(&version_manifest.latest.snapshot)
-        .unwrap();
-    let info = version.resolve_version_info(&client).await.unwrap();
-
-    // download libraries
-    let libraries_path = work_path.join("libraries");
-    for chunked_libs in info.libraries.chunks(4) {
-        let futures = chunked_libs
-            .iter()
-            .map(|lib| {
-                let client_clone = client.clone();
-                let path_clone = libraries_path.clone();
-
-                async move {
-                    let artifact = &lib.downloads.artifact;
-                    download_artifact(
-                        &path_clone.join(&artifact.path),
-                        &artifact.info,
-                        &client_clone,
-                    )
-                    .await
-                }
-            })
-            .collect::<Vec<_>>();
-        let results = futures::future::join_all(futures).await;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::{
+    args::{AuthValues, GameDirs, LaunchContext, QuickPlay, Resolution, UserType},
+    assets::{AssetCheck, VerifyPolicy},
+    auth::Account,
+    cert_pins::CertPins,
+    components::Component,
+    crash::CrashInfo,
+    download::{DownloadJournal, DownloadPlan, DownloadTask},
+    install_state::{InstallState, InstallStep},
+    loader::LoaderKind,
+    net::{Downloader, HttpProvider, MetaProvider, NetworkStatus, UrlManifest},
+    rules::{Environment, Rule},
+    services::ServiceOverrides,
+    watchdog::Timeouts,
+};
+
+#[doc(hidden)]
+pub mod adopt;
+#[doc(hidden)]
+pub mod adoptium;
+#[doc(hidden)]
+pub mod args;
+#[doc(hidden)]
+pub mod arm_linux;
+#[doc(hidden)]
+pub mod assets;
+#[doc(hidden)]
+pub mod auth;
+#[doc(hidden)]
+pub mod authlib;
+#[doc(hidden)]
+pub mod bundle;
+#[doc(hidden)]
+pub mod build_info;
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod cert_pins;
+#[doc(hidden)]
+pub mod components;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod crash;
+#[doc(hidden)]
+pub mod curseforge;
+#[doc(hidden)]
+pub mod daemon;
+#[doc(hidden)]
+pub mod dedup;
+#[doc(hidden)]
+pub mod disk;
+#[doc(hidden)]
+pub mod download;
+#[doc(hidden)]
+pub mod fabric;
+#[doc(hidden)]
+pub mod forge;
+#[doc(hidden)]
+pub mod freeze;
+#[doc(hidden)]
+pub mod gc;
+#[doc(hidden)]
+pub mod install_state;
+#[doc(hidden)]
+pub mod instance;
+#[doc(hidden)]
+pub mod java;
+#[doc(hidden)]
+pub mod jvm_templates;
+#[doc(hidden)]
+pub mod known_issues;
+#[doc(hidden)]
+pub mod language;
+mod legacy;
+#[doc(hidden)]
+pub mod loader;
+#[doc(hidden)]
+pub mod loader_matrix;
+#[doc(hidden)]
+pub mod mirror;
+#[doc(hidden)]
+pub mod modrinth;
+#[doc(hidden)]
+pub mod mods;
+#[doc(hidden)]
+pub mod natives;
+#[doc(hidden)]
+pub mod net;
+#[doc(hidden)]
+pub mod packs;
+#[doc(hidden)]
+pub mod portable;
+#[doc(hidden)]
+pub mod preflight;
+pub mod prelude;
+#[doc(hidden)]
+pub mod protocol;
+#[doc(hidden)]
+pub mod rules;
+#[doc(hidden)]
+pub mod safe_mode;
+#[doc(hidden)]
+pub mod saves;
+#[doc(hidden)]
+pub mod search;
+#[doc(hidden)]
+pub mod services;
+#[doc(hidden)]
+pub mod sessions;
+#[doc(hidden)]
+pub mod shortcuts;
+#[doc(hidden)]
+pub mod signing;
+#[doc(hidden)]
+pub mod skins;
+#[doc(hidden)]
+pub mod staging;
+#[doc(hidden)]
+pub mod state;
+#[doc(hidden)]
+pub mod steam;
+#[doc(hidden)]
+pub mod tasks;
+#[doc(hidden)]
+pub mod telemetry;
+#[doc(hidden)]
+pub mod truststore;
+#[doc(hidden)]
+pub mod update_channel;
+#[doc(hidden)]
+pub mod vanilla;
+#[doc(hidden)]
+pub mod versions;
+#[doc(hidden)]
+pub mod watchdog;
+
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct LaunchOptions {
+    // defaults to the latest snapshot
+    pub version: Option<String>,
+    // the instance being launched, for the session registry
+    pub instance: Option<String>,
+    pub work_dir: Option<PathBuf>,
+    // shared libraries, assets and versions, defaults to the work dir
+    pub cache_dir: Option<PathBuf>,
+    pub game_dir: Option<PathBuf>,
+    pub account: Option<Account>,
+    // defaults to $JAVA_HOME, then `java` on the PATH
+    pub java_path: Option<PathBuf>,
+    pub max_memory_mb: Option<u64>,
+    pub download_concurrency: Option<usize>,
+    // bytes per second, unlimited when unset
+    pub download_limit: Option<u64>,
+    // start as soon as libraries and the client jar are cached, one asset at a time follows
+    pub defer_assets: bool,
+    // keep only the end of the game's output for crash and GC analysis
+    pub low_memory: bool,
+    // leave identifying arguments out and turn the game's optional telemetry off, see telemetry.rs
+    pub telemetry_opt_out: bool,
+    // see arm_linux.rs, detected from the platform when None
+    pub arm_profile: Option<bool>,
+    pub mirror_url: Option<String>,
+    // picked between per download along with mirror_url, see mirror::selector
+    pub mirrors: Vec<String>,
+    // see cert_pins.rs
+    pub cert_pins: CertPins,
+    pub gc_logging: bool,
+    pub loader: Option<LoaderKind>,
+    pub skip_jvm_templates: bool,
+    pub service_overrides: ServiceOverrides,
+    // user supplied, added after everything else so they win over the defaults
+    pub extra_jvm_args: Vec<String>,
+    // agents and jar mods on top of the version, see components.rs
+    pub components: Vec<Component>,
+    // only essential mods, the default heap and no extra JVM arguments, see safe_mode.rs
+    pub safe_mode: bool,
+    // a private meta server's manifest in place of Mojang's
+    pub manifest_url: Option<String>,
+    // zip every world before launching
+    pub backup_worlds: bool,
+    // backups kept per world, all of them when None
+    pub backup_keep: Option<usize>,
+    // join a server or open a world straight away, on versions that support it
+    pub quick_play: Option<QuickPlay>,
+    // the window size, versions without `has_custom_resolution` arguments open at their default
+    pub resolution: Option<Resolution>,
+    // how thoroughly asset objects already on disk are checked before launching
+    pub verify_policy: VerifyPolicy,
+    // set for the game process on top of the launcher's own environment
+    pub env: BTreeMap<String, String>,
+    // PEM or DER certificates the game trusts on top of Java's defaults
+    pub ca_certs: Vec<PathBuf>,
+    // for Java probes and keytool, the game itself runs as long as it likes
+    pub timeouts: Timeouts,
+    // launch what is installed without touching the network, see HttpProvider::probe
+    pub offline: bool,
+    // receives the game's output line by line while it runs
+    pub output: Option<tokio::sync::mpsc::UnboundedSender<String>>,
+    // the game's language when options.txt doesn't set one yet, see language.rs
+    pub language: Option<String>,
+    // MiB of the game's output kept on disk per session, see sessions::OutputRing. The default
+    // when None, 0 keeps none
+    pub output_buffer_mb: Option<u64>,
+}
+
+// everything a launch resolved, from the version down to the command line, see
+// LaunchOptions::dry_run
+#[derive(Serialize, Debug, Clone)]
+pub struct LaunchPlan {
+    pub version: String,
+    // bottom to top, see components.rs
+    pub components: Vec<Component>,
+    // build_info::VERSION, what `${launcher_version}` expands to
+    pub launcher_version: String,
+    pub main_class: String,
+    pub java_path: PathBuf,
+    // None when `java -version` couldn't be made sense of
+    pub java_major: Option<u8>,
+    pub game_dir: PathBuf,
+    pub natives_dir: PathBuf,
+    pub classpath: Vec<PathBuf>,
+    pub jvm_args: Vec<String>,
+    // the access token and session are redacted
+    pub game_args: Vec<String>,
+    // set for the game on top of the launcher's own environment
+    pub env: BTreeMap<String, String>,
+    // what the launch would download first, empty for a real launch
+    pub downloads: Vec<PlannedDownload>,
+    pub download_bytes: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PlannedDownload {
+    pub url: String,
+    pub path: PathBuf,
+    pub size: u64,
+}
 
-        for result in results {
-            result.unwrap()
+impl LaunchOptions {
+    // everything else at its default, set fields on what this returns
+    pub fn new(version: &str) -> LaunchOptions {
+        LaunchOptions {
+            version: Some(version.to_string()),
+            ..Default::default()
         }
     }
 
-    // download client
-    let client_jar_path = work_path.join(format!("{}.jar", info.id));
-    download_artifact(&client_jar_path, &info.downloads.client, &client).await?;
+    // safe mode leaves the heap to the JVM
+    fn max_memory_mb(&self) -> Option<u64> {
+        self.max_memory_mb.filter(|_| !self.safe_mode)
+    }
 
-    // retrieve assets
-    let assets_dir = work_path.join("assets");
-    let indexes_dir = assets_dir.join("indexes");
-    let objects_dir = assets_dir.join("objects");
+    // resolves everything a launch would without downloading files, writing to the game dir or
+    // starting the game. Version JSONs still come from the network when they aren't cached
+    pub async fn dry_run(&self) -> anyhow::Result<LaunchPlan> {
+        Ok(prepare_launch(self, true).await?.plan)
+    }
+}
+
+// what prepare_launch hands over to start the game
+struct PreparedLaunch {
+    plan: LaunchPlan,
+    // the plan's game_args with the secrets in place
+    game_args: Vec<String>,
+    version_id: String,
+    deferred_assets: Option<DeferredAssets>,
+    http: HttpProvider,
+    work_path: PathBuf,
+    cache_path: PathBuf,
+    installed_mods: Vec<mods::ModInfo>,
+    gc_log: PathBuf,
+}
+
+// returns what is known about the crash when the game exits abnormally
+pub async fn launch_minecraft(options: LaunchOptions) -> anyhow::Result<Option<CrashInfo>> {
+    let PreparedLaunch {
+        plan,
+        game_args,
+        version_id,
+        deferred_assets,
+        http,
+        work_path,
+        cache_path,
+        installed_mods,
+        gc_log,
+    } = prepare_launch(&options, false).await?;
+    let game_dir = plan.game_dir;
+
+    if options.backup_worlds {
+        for backup in saves::backup_all(&game_dir, options.backup_keep)? {
+            info!("Backed up {} to {}", backup.world, backup.path.display());
+        }
+    }
 
-    let index_file = indexes_dir.join(format!("{}.json", &info.asset_index.id));
-    download_artifact(&index_file, &info.asset_index.info, &client).await?;
-    let index_json = tokio::fs::read_to_string(index_file).await?;
-    let index_json: AssetIndex = serde_json::from_str(index_json.as_str())?;
-
-    for chunked_objects in index_json
-        .objects
-        .values()
-        .filter(|obj| {
-            let hash_prefix: String = obj.hash.chars().take(2).collect();
-            let asset_file = objects_dir.join(&hash_prefix).join(&obj.hash);
-            !asset_file.exists()
+    // the servers only keep one session per account, the older game gets disconnected
+    if let Some(account) = &options.account {
+        let shared = sessions::list(&work_path)?.into_iter().find(|session| {
+            session
+                .account
+                .as_ref()
+                .is_some_and(|playing| playing.uuid == account.uuid)
+        });
+        if let Some(session) = shared {
+            warn!(
+                "{} is already playing {} (session {}), joining a server will disconnect it",
+                account.username, session.version, session.id
+            );
+        }
+    }
+
+    let launched_at = std::time::SystemTime::now();
+    let mut child = tokio::process::Command::new(&plan.java_path)
+        .args(plan.jvm_args)
+        .arg(&plan.main_class)
+        .args(game_args)
+        // mods and drivers resolve relative paths against the game dir
+        .current_dir(&game_dir)
+        .envs(&plan.env)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+    let session = sessions::Session::new(
+        &plan.version,
+        options.instance.as_deref(),
+        &game_dir,
+        options.account.as_ref(),
+        child.id(),
+    );
+    let _session = session.register(&work_path)?;
+    let output_buffer_mb = options
+        .output_buffer_mb
+        .unwrap_or(sessions::DEFAULT_OUTPUT_BUFFER_MB);
+    let ring = match output_buffer_mb {
+        0 => None,
+        mb => match session.output_ring(&work_path, mb * 1024 * 1024) {
+            Ok(ring) => Some(std::sync::Mutex::new(ring)),
+            Err(e) => {
+                warn!("Could not keep the game's output: {:#}", e);
+                None
+            }
+        },
+    };
+    let background = deferred_assets.map(|deferred| {
+        let (http, cache_path, version_id) = (http.clone(), cache_path.clone(), version_id.clone());
+        tokio::spawn(async move {
+            if let Err(e) = deferred.run(&http, &cache_path, &version_id).await {
+                warn!("Could not download the remaining assets: {:#}", e);
+            }
         })
-        .collect::<Vec<_>>()
-        .chunks(4)
-    {
-        let futures = chunked_objects
-            .iter()
-            .map(|obj| {
-                let client = client.clone();
-                let hash_prefix: String = obj.hash.chars().take(2).collect();
-                let asset_file = objects_dir.join(&hash_prefix).join(&obj.hash);
-
-                async move {
-                    let obj_bytes = client
-                        .get(format!(
-                            "https://resources.download.minecraft.net/{}/{}",
-                            hash_prefix, obj.hash
-                        ))
-                        .send()
-                        .await?
-                        .bytes()
-                        .await?;
-
-                    tokio::fs::create_dir_all(&asset_file.parent().unwrap()).await?;
-                    tokio::fs::write(&asset_file, obj_bytes).await?;
-
-                    Ok(())
-                }
-            })
-            .collect::<Vec<_>>();
+    });
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let output_limit = options.low_memory.then_some(LOW_MEMORY_OUTPUT_BYTES);
+    let (stdout, stderr, status) = async {
+        tokio::join!(
+            read_output(stdout, options.output.as_ref(), ring.as_ref(), output_limit),
+            read_output(stderr, options.output.as_ref(), ring.as_ref(), output_limit),
+            child.wait()
+        )
+    }
+    .instrument(info_span!("launch", version = %plan.version, session = %session.id))
+    .await;
+    let (stdout, stderr, status) = (stdout?, stderr?, status?);
+    debug!(%status, "game exited");
+    // whatever is left continues from its partial files on the next launch
+    if let Some(background) = background {
+        background.abort();
+    }
 
-        let results: Vec<Result<(), Error>> = futures::future::join_all(futures).await;
-        for result in results {
-            result.unwrap();
+    let gc_log = if options.gc_logging {
+        tokio::fs::read_to_string(&gc_log).await.unwrap_or_default()
+    } else {
+        String::new()
+    };
+    let output = format!("{}\n{}", stdout, stderr);
+    drop((stdout, stderr));
+    let gc_report = gc::analyze(&gc_log, &output, options.max_memory_mb());
+    if let Some(warning) = gc_report.warning() {
+        warn!("{}", warning);
+    }
+
+    let kept = ring.is_some().then_some(session.id);
+    Ok(crash::detect(
+        &game_dir,
+        status,
+        launched_at,
+        &output,
+        &installed_mods,
+        &known_issues::Ruleset::load(&cache_path),
+    )
+    .map(|crash| CrashInfo {
+        session: kept,
+        ..crash
+    }))
+}
+
+// everything up to starting the game. A `dry_run` downloads nothing and leaves the game dir,
+// natives and legacy assets alone, arguments for what only a real launch sets up are placeholders
+async fn prepare_launch(options: &LaunchOptions, dry_run: bool) -> anyhow::Result<PreparedLaunch> {
+    // validate before spending time on downloads
+    let service_args = options.service_overrides.jvm_args()?;
+    if let Some(account) = &options.account {
+        args::validate_username(&account.username)?;
+        let restrictions = account.restrictions();
+        if matches!(options.quick_play, Some(QuickPlay::Multiplayer(_)))
+            && restrictions.contains(&auth::Restriction::NoMultiplayer)
+        {
+            return Err(anyhow!("{}", auth::Restriction::NoMultiplayer));
+        }
+        for restriction in restrictions {
+            warn!("{}", restriction);
+        }
+    }
+    if let Some(quick_play) = &options.quick_play {
+        quick_play.validate()?;
+    }
+    for name in options.env.keys() {
+        args::validate_env_var(name)?;
+    }
+
+    let client = net::client();
+    let mirror = options.mirror_url.as_deref();
+    let mut http = HttpProvider::new(client.clone(), mirror);
+    if let Some(manifest_url) = &options.manifest_url {
+        http.manifest = Arc::new(UrlManifest::new(manifest_url));
+    }
+    http.throttle = options
+        .download_limit
+        .map(|limit| Arc::new(net::Throttle::new(limit)));
+    http.pin(&options.cert_pins);
+    let concurrency = options.download_concurrency.unwrap_or(4).max(1);
+
+    let work_path = match &options.work_dir {
+        Some(work_dir) => work_dir.clone(),
+        None => default_work_dir()?,
+    };
+    debug!(work_dir = %work_path.display());
+    preflight::check_writable(&work_path)?;
+    let cache_path = options.cache_dir.clone().unwrap_or_else(|| work_path.clone());
+    http.mirrors = mirror::selector(mirror, &options.mirrors, &cache_path).map(Arc::new);
+
+    let network = match options.offline {
+        true => NetworkStatus::Offline,
+        false => NetworkStatus::Online,
+    };
+    let version_id = match &options.version {
+        Some(version) => version.clone(),
+        None => versions::manifest(&http, &cache_path, network).await?.latest.snapshot,
+    };
+    info!("Launching {}", version_id);
+    let (info, deferred_assets, downloads) = match network {
+        // without a network the plan can only be what is cached
+        NetworkStatus::Online if dry_run => {
+            let planned = plan_install(
+                &http,
+                &http,
+                &cache_path,
+                &version_id,
+                &DownloadSchedule {
+                    concurrency,
+                    defer_assets: false,
+                },
+                options.verify_policy,
+                true,
+            )
+            .await?;
+            (planned.info, None, planned.plan.into_tasks())
+        }
+        NetworkStatus::Online => {
+            let (info, deferred_assets) = download_version(
+                &http,
+                &http,
+                &cache_path,
+                &version_id,
+                DownloadSchedule {
+                    concurrency,
+                    defer_assets: options.defer_assets,
+                },
+                options.verify_policy,
+                &|_, _| {},
+            )
+            .await?;
+            (info, deferred_assets, Vec::new())
         }
+        NetworkStatus::Offline => (
+            resolve_offline(&cache_path, &version_id).await?,
+            None,
+            Vec::new(),
+        ),
+    };
+    let mut environment = Environment::current();
+    if let Some(quick_play) = &options.quick_play {
+        environment.features.push(quick_play.feature().to_string());
+    }
+    if options.resolution.is_some() {
+        environment.features.push(Resolution::FEATURE.to_string());
+    }
+    let libraries = info.libraries_for(&environment).collect::<Vec<_>>();
+    let libraries_path = cache::libraries_dir(&cache_path);
+    // safe mode leaves agents and jar mods out with the other customizations
+    let extra_components = match options.safe_mode {
+        true => &[][..],
+        false => &options.components[..],
+    };
+    let layered = components::apply(
+        &cache_path,
+        &versions::jar_path(&cache_path, &info.jar),
+        extra_components,
+        dry_run,
+    )?;
+    let client_jar_path = layered
+        .client_jar
+        .clone()
+        .unwrap_or_else(|| versions::jar_path(&cache_path, &info.jar));
+    // the extra components are valid by now, the rest can only be warned about this late
+    let stack = components::stack(&cache_path, &version_id, options.loader, extra_components)?;
+    for conflict in components::check(&cache_path, &stack, options.loader) {
+        warn!("{}", conflict.message);
+    }
+    let assets_dir = cache::assets_dir(&cache_path);
+
+    let game_dir = options
+        .game_dir
+        .clone()
+        .unwrap_or_else(|| work_path.join(".minecraft"));
+    if !dry_run {
+        std::fs::create_dir_all(&game_dir)?;
+    }
+    // the overlay stands in for the game dir from here on
+    let game_dir = match options.safe_mode {
+        true => {
+            let overlay = safe_mode::overlay_dir(
+                &work_path,
+                options.instance.as_deref().unwrap_or("default"),
+            );
+            if !dry_run {
+                let disabled = safe_mode::prepare_overlay(&game_dir, &overlay)?;
+                info!(
+                    "Safe mode: {} mods left out, default heap and no custom JVM arguments",
+                    disabled
+                );
+            }
+            overlay
+        }
+        false => game_dir,
+    };
+    let arm_profile = options
+        .arm_profile
+        .unwrap_or_else(|| arm_linux::detect(&environment));
+    let mut env = options.env.clone();
+    if arm_profile {
+        info!("Using the ARM Linux profile");
+        arm_linux::apply_env(&mut env);
+        if !dry_run {
+            arm_linux::write_default_options(&game_dir).await?;
+        }
+    }
+    if let Some(code) = &options.language {
+        language::apply(&game_dir, &cache_path, &info.asset_index.id, code, dry_run).await?;
+    }
+
+    // catch Java mismatches here rather than letting the JVM fail with an UnsupportedClassVersionError
+    // an explicitly chosen Java always wins over discovery
+    let java_path = match &options.java_path {
+        Some(java_path) => java_path.clone(),
+        None => {
+            let installs = java::discover(&cache_path, false, options.timeouts.probe())
+                .instrument(info_span!("java_discovery"))
+                .await?;
+            match java::select(
+                &installs,
+                info.java_version.major_version,
+                options.loader,
+                info.release_time,
+            ) {
+                Some(install) => {
+                    info!("Using Java {} at {}", install.major, install.path.display());
+                    install.path.clone()
+                }
+                // Mojang has no runtime for these boards and distributions often only package
+                // the newest Java
+                None if arm_profile => {
+                    let (major, arch) = (info.java_version.major_version, &environment.os_arch);
+                    match dry_run {
+                        true => adoptium::java_binary(&adoptium::runtime_dir(&cache_path, major, arch)),
+                        false => adoptium::ensure_runtime(&http, &cache_path, major, arch)
+                            .instrument(info_span!("adoptium", major))
+                            .await
+                            .unwrap_or_else(|e| {
+                                warn!("Could not get Java {} from Adoptium: {:#}", major, e);
+                                default_java_path()
+                            }),
+                    }
+                }
+                None => default_java_path(),
+            }
+        }
+    };
+    let java_major = java::probe_major(&java_path, options.timeouts.probe())
+        .instrument(info_span!("java", path = %java_path.display()))
+        .await;
+    debug!(java = %java_path.display(), major = ?java_major, "probed java");
+    let java_issues = java::check_compatibility(
+        &java_path,
+        java_major,
+        info.java_version.major_version,
+        &info.java_version.component,
+        options.loader,
+        info.release_time,
+    );
+    for issue in java_issues.iter().filter(|issue| !issue.is_fatal()) {
+        warn!("{}", issue);
+    }
+    if let Some(issue) = java_issues.iter().find(|issue| issue.is_fatal()) {
+        return Err(anyhow!("{}", issue));
+    }
+
+    let installed_mods = mods::list_mods(&game_dir)?;
+    for issue in mods::check_compatibility(&installed_mods, options.loader) {
+        warn!("{}", issue);
     }
 
-    let game_dir = work_path.join(".minecraft");
-    std::fs::create_dir_all(&game_dir).unwrap();
+    let natives_dir = natives::dir_for(&cache_path, &libraries, &environment);
+    if !dry_run {
+        natives::extract(&libraries_path, &natives_dir, &libraries, &environment)?;
+    }
 
-    let mut classpath = info.libraries
+    let mut classpath_entries = libraries
         .iter()
-        .map(|lib| {
-            let path = libraries_path.join(&lib.downloads.artifact.path);
-            canonicalize_and_str(&path).unwrap()
+        .filter_map(|lib| lib.downloads.artifact.as_ref())
+        .map(|artifact| libraries_path.join(&artifact.path))
+        .collect::<Vec<_>>();
+    classpath_entries.push(client_jar_path.clone());
+    let classpath = classpath_entries
+        .iter()
+        .map(canonicalize_and_str)
+        .collect::<anyhow::Result<Vec<_>>>()?
+        .join(environment.classpath_separator());
+    debug!(%classpath);
+
+    // the game hands these to the LAN server it hosts, which otherwise can't show textures offline
+    let profile_properties = match &options.account {
+        Some(account) if !account.properties.is_empty() => {
+            Some(serde_json::to_string(&account.properties)?)
+        }
+        _ => None,
+    };
+    let game_assets = match info.is_legacy() {
+        // the index may not be downloaded yet
+        true if dry_run => legacy::assets_target(&assets_dir, &info.asset_index.id, &game_dir)
+            .unwrap_or_else(|_| assets_dir.clone()),
+        true => legacy::prepare_assets(&assets_dir, &info.asset_index.id, &game_dir)?,
+        false => assets_dir.clone(),
+    };
+    let (player_name, player_uuid, access_token, xuid) = match &options.account {
+        Some(account) => (
+            account.username.clone(),
+            account.uuid.clone(),
+            account.access_token.clone(),
+            account.xuid.clone().unwrap_or_default(),
+        ),
+        None => (
+            String::from("Test"),
+            String::from("fa7dae1b-e8ca-4540-9195-356e364db0af"),
+            String::from("0"),
+            String::new(),
+        ),
+    };
+
+    // third-party auth servers hand out Yggdrasil tokens, not Microsoft ones
+    let user_type = match options.service_overrides.authlib_injector {
+        Some(_) => UserType::Mojang,
+        None => UserType::Msa,
+    };
+    let launch_context = LaunchContext::builder(&info.id, &info.asset_index.id)
+        .auth(AuthValues {
+            player_name,
+            uuid: player_uuid,
+            access_token,
+            xuid,
+            user_type,
+            user_properties: profile_properties.clone().unwrap_or_else(|| String::from("{}")),
+        })
+        .dirs(GameDirs {
+            game_directory: canonicalize_and_str(&game_dir)?,
+            assets_root: canonicalize_and_str(&assets_dir)?,
+            game_assets: canonicalize_and_str(&game_assets)?,
+            natives_directory: canonicalize_and_str(&natives_dir)?,
         })
+        .classpath(classpath)
+        .resolution(options.resolution)
+        .quick_play(options.quick_play.clone())
+        .build();
+
+    let mut jvm_args = resolve_arguments(info.arguments.jvm, &launch_context, &environment)?;
+    if !options.skip_jvm_templates {
+        let template_args = jvm_templates::template_args(options.loader, info.release_time)
+            .into_iter()
+            .filter(|arg| !jvm_args.contains(arg))
+            .collect::<Vec<_>>();
+        jvm_args.splice(0..0, template_args);
+    }
+    jvm_args.splice(0..0, service_args);
+    if let Some(server) = options.service_overrides.authlib_injector.as_ref().filter(|_| dry_run) {
+        jvm_args.insert(0, format!("-javaagent:<authlib-injector>={}", server));
+    } else if let Some(server) = &options.service_overrides.authlib_injector {
+        let injector_args = authlib::jvm_args(&client, &http, &cache_path, server)
+            .instrument(info_span!("authlib_injector", server = %server))
+            .await?;
+        jvm_args.splice(0..0, injector_args);
+    }
+    if !options.ca_certs.is_empty() && dry_run {
+        jvm_args.insert(0, String::from("-Djavax.net.ssl.trustStore=<truststore>"));
+    } else if !options.ca_certs.is_empty() {
+        let dir = work_path
+            .join("truststores")
+            .join(options.instance.as_deref().unwrap_or("default"));
+        let limits = options.timeouts.helper();
+        let store = truststore::build(&java_path, &options.ca_certs, &dir, limits)
+            .instrument(info_span!("truststore"))
+            .await?;
+        jvm_args.splice(0..0, truststore::jvm_args(&store)?);
+    }
+    if let Some(max_memory) = options.max_memory_mb() {
+        jvm_args.insert(0, format!("-Xmx{}M", max_memory));
+    }
+
+    let gc_log = game_dir.join("logs").join("gc.log");
+    if options.gc_logging {
+        if !dry_run {
+            tokio::fs::create_dir_all(gc_log.parent().unwrap()).await?;
+            if gc_log.exists() {
+                tokio::fs::remove_file(&gc_log).await?;
+            }
+        }
+        // the flags of the Java that actually runs, an overridden Java 8 rejects -Xlog
+        let gc_java = java_major.unwrap_or(info.java_version.major_version);
+        jvm_args.splice(0..0, gc::logging_args(&gc_log, gc_java));
+    }
+    jvm_args.extend(layered.jvm_args);
+    if !options.safe_mode {
+        jvm_args.extend(options.extra_jvm_args.iter().cloned());
+    }
+
+    let mut game_args = resolve_arguments(info.arguments.game, &launch_context, &environment)?;
+    if let Some(properties) = profile_properties {
+        if !game_args.iter().any(|arg| arg == "--profileProperties") {
+            game_args.extend([String::from("--profileProperties"), properties]);
+        }
+    }
+    if options.telemetry_opt_out {
+        telemetry::filter_jvm_args(&mut jvm_args);
+        telemetry::filter_game_args(&mut game_args);
+        if !dry_run {
+            telemetry::disable_in_options(&game_dir).await?;
+        }
+    }
+    debug!(?jvm_args);
+    // the access token is an argument, it never goes to the logs or a printed plan
+    let secrets = launch_context.secrets();
+    let redacted_game_args = game_args
+        .iter()
+        .map(|arg| if secrets.contains(arg) { String::from("<redacted>") } else { arg.clone() })
         .collect::<Vec<_>>();
-    classpath.push(canonicalize_and_str(&client_jar_path).unwrap());
-    let classpath = classpath.join(";");
-    
-    println!("{}", classpath);
-
-    let arg_query = ArgumentQuery {
-        constants: HashMap::from([
-            (String::from("auth_player_name"), String::from("Test")),
-            (String::from("version_name"), info.id.clone()),
-            (String::from("game_directory"), canonicalize_and_str(&game_dir).unwrap()),
-            (String::from("assets_root"), canonicalize_and_str(&assets_dir).unwrap()),
-            (String::from("assets_index_name"), info.asset_index.id.clone()),
-            (String::from("auth_uuid"), String::from("fa7dae1b-e8ca-4540-9195-356e364db0af")),
-            (String::from("clientid"), String::from("")),
-            (String::from("auth_xuid"), String::from("")),
-            (String::from("user_type"), String::from("msa")),
-            (String::from("version_type"), String::from("ModLauncher")),
-            (String::from("natives_directory"), canonicalize_and_str(&libraries_path).unwrap()),
-            (String::from("launcher_name"), String::from("ModLauncher")),
-            (String::from("launcher_version"), String::from("0.1.0")),
-            (String::from("classpath"), classpath)
-        ]),
-        features: vec![],
-        os_properties: OSProperties { name: String::from("windows"), arch: String::from("x86_64") }
+    debug!(game_args = ?redacted_game_args);
+    if let Some(quick_play) = &options.quick_play {
+        if !game_args.iter().any(|arg| arg == quick_play.value()) {
+            warn!("{} does not support quick play, starting at the title screen", info.id);
+        }
+    }
+
+    // relative paths would resolve against the game dir once it is the working directory,
+    // bare names like `java` are still looked up on the PATH
+    let java_path = match java_path.components().count() > 1 {
+        true => dunce::canonicalize(&java_path).unwrap_or(java_path),
+        false => java_path,
     };
 
-    let jvm_args = dbg!(resolve_arguments(info.arguments.jvm, &arg_query));
-    let game_args = dbg!(resolve_arguments(info.arguments.game, &arg_query));
+    let plan = LaunchPlan {
+        version: info.id.clone(),
+        components: stack,
+        launcher_version: build_info::VERSION.to_string(),
+        main_class: info.main_class.clone(),
+        java_path,
+        java_major,
+        game_dir,
+        natives_dir,
+        classpath: classpath_entries,
+        jvm_args,
+        game_args: redacted_game_args,
+        env,
+        download_bytes: downloads.iter().map(|task| task.size).sum(),
+        downloads: downloads
+            .into_iter()
+            .map(|task| PlannedDownload {
+                url: task.url,
+                path: task.path,
+                size: task.size,
+            })
+            .collect(),
+    };
+    Ok(PreparedLaunch {
+        plan,
+        game_args,
+        version_id,
+        deferred_assets,
+        http,
+        work_path,
+        cache_path,
+        installed_mods,
+        gc_log,
+    })
+}
 
-    let output = tokio::process::Command::new(r"C:\Users\xande\.jdks\temurin-17.0.10\bin\javaw.exe")
-        .args(jvm_args)
-        .arg(info.main_class)
-        .args(game_args)
-        .output()
+// game output kept per stream in low memory mode, plenty for a stack trace
+const LOW_MEMORY_OUTPUT_BYTES: usize = 256 * 1024;
+
+// collects a game output stream while passing each line on as it arrives and into the session's
+// ring. With a `limit` only about that many bytes from the end are kept
+async fn read_output(
+    stream: impl tokio::io::AsyncRead + Unpin,
+    sink: Option<&tokio::sync::mpsc::UnboundedSender<String>>,
+    ring: Option<&std::sync::Mutex<sessions::OutputRing>>,
+    limit: Option<usize>,
+) -> std::io::Result<String> {
+    use tokio::io::AsyncBufReadExt;
+
+    let mut reader = tokio::io::BufReader::new(stream);
+    let mut collected = String::new();
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line).await? > 0 {
+        // some mods print in the platform's codepage
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\r', '\n']);
+        debug!(target: "minecraft", "{}", text);
+        if let Some(sink) = sink {
+            let _ = sink.send(text.to_string());
+        }
+        if let Some(ring) = ring {
+            if let Err(e) = ring.lock().unwrap().push_line(text) {
+                debug!("could not keep output: {}", e);
+            }
+        }
+        collected.push_str(text);
+        collected.push('\n');
+        line.clear();
+        // trimmed in bulk, not once per line
+        if let Some(limit) = limit.filter(|limit| collected.len() > limit * 2) {
+            let cut = collected.len() - limit;
+            let cut = collected.as_bytes()[cut..]
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .map_or(collected.len(), |newline| cut + newline + 1);
+            collected.drain(..cut);
+        }
+    }
+    Ok(collected)
+}
+
+// downloads everything a version needs without launching it, reporting (done, total) files
+pub async fn install_version(
+    http: &HttpProvider,
+    cache_dir: &Path,
+    version_id: &str,
+    concurrency: usize,
+    verify_policy: VerifyPolicy,
+    on_progress: &(dyn Fn(u64, u64) + Send + Sync),
+) -> anyhow::Result<()> {
+    install_version_with(
+        http,
+        http,
+        cache_dir,
+        version_id,
+        concurrency,
+        verify_policy,
+        on_progress,
+    )
+    .await
+}
+
+// install_version with the manifest, version JSONs and files coming from somewhere else
+pub async fn install_version_with(
+    meta: &dyn MetaProvider,
+    downloader: &dyn Downloader,
+    cache_dir: &Path,
+    version_id: &str,
+    concurrency: usize,
+    verify_policy: VerifyPolicy,
+    on_progress: &(dyn Fn(u64, u64) + Send + Sync),
+) -> anyhow::Result<()> {
+    download_version(
+        meta,
+        downloader,
+        cache_dir,
+        version_id,
+        DownloadSchedule {
+            concurrency: concurrency.max(1),
+            defer_assets: false,
+        },
+        verify_policy,
+        on_progress,
+    )
+    .await?;
+    Ok(())
+}
+
+// resolves a version and fills the cache with its libraries, client jar and assets.
+// safe to re-run after an interruption, finished steps are skipped and partial ones continue
+async fn download_version(
+    meta: &dyn MetaProvider,
+    downloader: &dyn Downloader,
+    cache_path: &Path,
+    version_id: &str,
+    schedule: DownloadSchedule,
+    verify_policy: VerifyPolicy,
+    on_progress: &(dyn Fn(u64, u64) + Send + Sync),
+) -> anyhow::Result<(VersionInfo, Option<DeferredAssets>)> {
+    // other launcher processes may install into the same cache, only `gc` has to wait
+    let _cache_lock = cache::lock_shared(cache_path).await?;
+    let InstallPlan {
+        info,
+        plan,
+        deferred,
+        finished_steps,
+        journal,
+        mut state,
+        check,
+    } = plan_install(meta, downloader, cache_path, version_id, &schedule, verify_policy, false)
+        .await?;
+    preflight::check(&info, &info.library_artifacts(&Environment::current()), cache_path)?;
+
+    let files = plan.tasks().len();
+    let bytes = plan.total_bytes();
+    plan.run(downloader, schedule.concurrency, Some(&journal), on_progress)
+        .instrument(info_span!("download", files, bytes))
         .await?;
-    println!("{}", String::from_utf8(output.stdout)?);
-    println!("{}", String::from_utf8(output.stderr)?);
 
+    for (step, files) in finished_steps {
+        state.complete(cache_path, step, &files)?;
+    }
+    state.save(cache_path, version_id).await?;
+    journal.finish()?;
+    if check == AssetCheck::Hash {
+        assets::record_deep_verify(&cache::assets_dir(cache_path)).await?;
+    }
+
+    Ok((info, deferred))
+}
+
+// what an install still has to fetch, and which steps it covers
+struct InstallPlan {
+    info: VersionInfo,
+    plan: DownloadPlan,
+    deferred: Option<DeferredAssets>,
+    finished_steps: Vec<(InstallStep, Vec<PathBuf>)>,
+    journal: DownloadJournal,
+    state: InstallState,
+    check: AssetCheck,
+}
+
+// checks what is in the cache the way an install does. Only the asset index is downloaded, the
+// objects can't be planned without it, and a `dry_run` leaves even that out
+async fn plan_install(
+    meta: &dyn MetaProvider,
+    downloader: &dyn Downloader,
+    cache_path: &Path,
+    version_id: &str,
+    schedule: &DownloadSchedule,
+    verify_policy: VerifyPolicy,
+    dry_run: bool,
+) -> anyhow::Result<InstallPlan> {
+    let info = versions::resolve(meta, cache_path, version_id)
+        .instrument(info_span!("manifest", version = version_id))
+        .await?;
+    let state = InstallState::load(cache_path, version_id);
+    let environment = Environment::current();
+    let artifacts = info.library_artifacts(&environment);
+    let trust_stamps = verify_policy.trust_stamps;
+    // what an install that was cut off already fetched, those files are not checked again
+    let journal = DownloadJournal::open(
+        &versions::version_dir(cache_path, version_id).join("downloads.jsonl"),
+    );
+
+    // everything missing or corrupt goes into one plan, the steps it covers are recorded once
+    // it ran
+    let mut plan = DownloadPlan::in_cache(cache_path);
+    let mut finished_steps = Vec::new();
+    let mut deferred = None;
+
+    async {
+        let libraries_path = cache::libraries_dir(cache_path);
+        let library_files = artifacts
+            .iter()
+            .map(|artifact| libraries_path.join(&artifact.path))
+            .collect::<Vec<_>>();
+        if !(trust_stamps && state.is_complete(cache_path, InstallStep::Libraries, &library_files))
+        {
+            // everything is hashed up front, in parallel, so only what is missing or corrupt queues
+            let pending = (0..artifacts.len())
+                .filter(|&i| !journal.is_done(&library_files[i]))
+                .collect::<Vec<_>>();
+            let invalid = AssetCheck::Hash
+                .find_invalid(
+                    pending
+                        .iter()
+                        .map(|&i| {
+                            let info = &artifacts[i].info;
+                            (library_files[i].clone(), info.size, info.sha1.clone())
+                        })
+                        .collect(),
+                )
+                .await?;
+            debug!(total = artifacts.len(), invalid = invalid.len(), "checked libraries");
+            for i in invalid.into_iter().map(|i| pending[i]) {
+                // Forge's patched jars have no URL, its installer's processors make them
+                if artifacts[i].info.url.is_empty() {
+                    return Err(anyhow!(
+                        "{} is missing, install the loader again to have its installer make it",
+                        artifacts[i].path
+                    ));
+                }
+                plan.push(DownloadTask::for_file(
+                    &artifacts[i].info,
+                    library_files[i].clone(),
+                ));
+            }
+            finished_steps.push((InstallStep::Libraries, library_files));
+        } else {
+            debug!("libraries unchanged since the last install");
+        }
+
+        let client = &info.downloads.client;
+        let client_jar_path = versions::jar_path(cache_path, &info.jar);
+        let client_files = vec![client_jar_path.clone()];
+        if !(trust_stamps && state.is_complete(cache_path, InstallStep::Client, &client_files)) {
+            let existing = vec![(client_jar_path.clone(), client.size, client.sha1.clone())];
+            if !AssetCheck::Hash.find_invalid(existing).await?.is_empty() {
+                plan.push(DownloadTask::for_file(client, client_jar_path));
+            }
+            finished_steps.push((InstallStep::Client, client_files));
+        }
+        anyhow::Ok(())
+    }
+    .instrument(info_span!("libraries", count = artifacts.len()))
+    .await?;
+    // legacy versions get their assets copied before they start, the copies can't wait
+    let defer_assets = schedule.defer_assets && plan.is_empty() && !info.is_legacy();
+
+    let assets_dir = cache::assets_dir(cache_path);
+    let check = verify_policy.effective_check(&assets_dir);
+    async {
+        let indexes_dir = assets_dir.join("indexes");
+        let objects_dir = assets_dir.join("objects");
+
+        // small, and the objects can't be planned without it
+        let index_file = indexes_dir.join(format!("{}.json", &info.asset_index.id));
+        if dry_run {
+            let index = &info.asset_index.info;
+            let existing = vec![(index_file.clone(), index.size, index.sha1.clone())];
+            if !AssetCheck::Hash.find_invalid(existing).await?.is_empty() {
+                warn!("The asset index is not downloaded yet, the plan leaves its objects out");
+                plan.push(DownloadTask::for_file(index, index_file));
+                return Ok(());
+            }
+        } else {
+            download_artifact(
+                &index_file,
+                &info.asset_index.info,
+                downloader,
+                Some(cache_path),
+            )
+            .await?;
+        }
+        let index_json = tokio::fs::read_to_string(&index_file).await?;
+        let index_json: AssetIndex = serde_json::from_str(index_json.as_str())?;
+
+        let object_path = |obj: &Asset| objects_dir.join(obj.path());
+        let mut asset_files = index_json
+            .objects
+            .values()
+            .map(object_path)
+            .collect::<Vec<_>>();
+        asset_files.push(index_file);
+        asset_files.sort();
+        asset_files.dedup();
+        // the recorded stamps are as good as a size check, only hashing has to look at every object
+        if trust_stamps
+            && check != AssetCheck::Hash
+            && state.is_complete(cache_path, InstallStep::Assets, &asset_files)
+        {
+            debug!("assets unchanged since the last install");
+            return Ok(());
+        }
+
+        // invalid objects are fetched again and replace what is there
+        let objects = index_json
+            .objects
+            .values()
+            .filter(|obj| !journal.is_done(&object_path(obj)))
+            .collect::<Vec<_>>();
+        let invalid = check
+            .find_invalid(
+                objects
+                    .iter()
+                    .map(|obj| (object_path(obj), obj.size, obj.hash.clone()))
+                    .collect(),
+            )
+            .await?;
+        if !invalid.is_empty() {
+            info!(count = invalid.len(), %check, "asset objects to download");
+        }
+        let mut asset_plan = DownloadPlan::in_cache(cache_path);
+        for i in invalid {
+            let obj = objects[i];
+            asset_plan.push(DownloadTask {
+                url: format!("{}/{}", net::RESOURCES_URL, obj.path()),
+                path: object_path(obj),
+                sha1: obj.hash.clone(),
+                size: obj.size,
+            });
+        }
+        if defer_assets && !asset_plan.is_empty() {
+            info!("The game is playable from the cache, assets download while it runs");
+            deferred = Some(DeferredAssets {
+                plan: asset_plan,
+                files: asset_files,
+            });
+            return Ok(());
+        }
+        for task in asset_plan.into_tasks() {
+            plan.push(task);
+        }
+        finished_steps.push((InstallStep::Assets, asset_files));
+        anyhow::Ok(())
+    }
+    .instrument(info_span!("assets", index = %info.asset_index.id))
+    .await?;
+
+    Ok(InstallPlan {
+        info,
+        plan,
+        deferred,
+        finished_steps,
+        journal,
+        state,
+        check,
+    })
+}
+
+struct DownloadSchedule {
+    concurrency: usize,
+    // hand missing assets back instead of waiting for them when nothing else is missing
+    defer_assets: bool,
+}
+
+// asset objects left for after the game started, see LaunchOptions::defer_assets
+struct DeferredAssets {
+    plan: DownloadPlan,
+    files: Vec<PathBuf>,
+}
+
+impl DeferredAssets {
+    // one at a time, the game is already using the connection
+    async fn run(
+        self,
+        downloader: &dyn Downloader,
+        cache_path: &Path,
+        version_id: &str,
+    ) -> anyhow::Result<()> {
+        let count = self.plan.tasks().len();
+        let _cache_lock = cache::lock_shared(cache_path).await?;
+        self.plan.run(downloader, 1, None, &|_, _| {}).await?;
+        let mut state = InstallState::load(cache_path, version_id);
+        state.complete(cache_path, InstallStep::Assets, &self.files)?;
+        state.save(cache_path, version_id).await?;
+        info!("Downloaded the remaining {} assets", count);
+        Ok(())
+    }
+}
+
+// an installed version as it is on disk. Missing asset objects only cost sounds or textures,
+// missing jars would stop the game from starting
+async fn resolve_offline(cache_path: &Path, version_id: &str) -> anyhow::Result<VersionInfo> {
+    let info = versions::resolve_installed(cache_path, version_id)
+        .await
+        .with_context(|| format!("{} can't be installed while offline", version_id))?;
+    let libraries_path = cache::libraries_dir(cache_path);
+    let missing = info
+        .library_artifacts(&Environment::current())
+        .iter()
+        .map(|artifact| libraries_path.join(&artifact.path))
+        .chain([versions::jar_path(cache_path, &info.jar)])
+        .find(|path| !path.exists());
+    if let Some(path) = missing {
+        return Err(anyhow!(
+            "{} is missing and can't be downloaded while offline",
+            path.display()
+        ));
+    }
+    Ok(info)
+}
+
+// writes through a temporary sibling so an interrupted write never leaves a truncated file behind.
+// Every writer gets its own sibling, two processes writing the same file just replace each other
+pub(crate) async fn write_atomic(path: &Path, bytes: impl AsRef<[u8]>) -> anyhow::Result<()> {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(
+        ".{}-{}.part",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    tokio::fs::write(&temp, bytes).await?;
+    tokio::fs::rename(&temp, path).await?;
     Ok(())
 }
 
-async fn retrieve_versions(client: &reqwest::Client) -> anyhow::Result<VersionManifest> {
-    let body = client
-        .get("https://piston-meta.mojang.com/mc/game/version_manifest_v2.json")
-        .send()
-        .await?
-        .json::<VersionManifest>()
+pub fn default_work_dir() -> anyhow::Result<PathBuf> {
+    Ok(std::env::current_dir()?.join("run"))
+}
+
+pub fn default_java_path() -> PathBuf {
+    // javaw avoids popping up a console window on Windows
+    let binary = if cfg!(windows) { "javaw.exe" } else { "java" };
+    match std::env::var_os("JAVA_HOME") {
+        Some(java_home) => PathBuf::from(java_home).join("bin").join(binary),
+        None => PathBuf::from(binary),
+    }
+}
+
+// mirrors serve every upstream host under its own directory: <mirror>/<host>/<path>
+pub fn mirrored_url(url: &str, mirror: Option<&str>) -> String {
+    let Some(mirror) = mirror else {
+        return url.to_string();
+    };
+
+    match url.split_once("://") {
+        Some((_, host_and_path)) => format!("{}/{}", mirror.trim_end_matches('/'), host_and_path),
+        None => url.to_string(),
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct VerifyReport {
+    pub checked: usize,
+    pub missing: Vec<PathBuf>,
+    pub corrupt: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.corrupt.is_empty()
+    }
+
+    async fn check(&mut self, path: PathBuf, sha1: &String) -> anyhow::Result<()> {
+        self.checked += 1;
+        if !path.exists() {
+            self.missing.push(path);
+        } else if !sha1.is_empty() {
+            let hashed = path.clone();
+            if tokio::task::spawn_blocking(move || sha1_file(&hashed)).await?? != *sha1 {
+                self.corrupt.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub async fn verify_version(
+    cache_dir: &Path,
+    version_id: &str,
+    mirror: Option<&str>,
+) -> anyhow::Result<VerifyReport> {
+    let http = HttpProvider::new(net::client(), mirror);
+    let info = versions::resolve(&http, cache_dir, version_id).await?;
+    verify_info(cache_dir, &info).await
+}
+
+// verify_version for a version that is installed, without touching the network
+pub async fn verify_installed(cache_dir: &Path, version_id: &str) -> anyhow::Result<VerifyReport> {
+    let info = versions::resolve_installed(cache_dir, version_id).await?;
+    verify_info(cache_dir, &info).await
+}
+
+async fn verify_info(cache_dir: &Path, info: &VersionInfo) -> anyhow::Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+
+    let libraries_path = cache::libraries_dir(cache_dir);
+    for artifact in info.library_artifacts(&Environment::current()) {
+        report
+            .check(libraries_path.join(&artifact.path), &artifact.info.sha1)
+            .await?;
+    }
+
+    report
+        .check(
+            versions::jar_path(cache_dir, &info.jar),
+            &info.downloads.client.sha1,
+        )
         .await?;
 
-    Ok(body)
+    let assets_dir = cache::assets_dir(cache_dir);
+    let index_file = assets_dir
+        .join("indexes")
+        .join(format!("{}.json", &info.asset_index.id));
+    report
+        .check(index_file.clone(), &info.asset_index.info.sha1)
+        .await?;
+    if !report.is_ok() {
+        return Ok(report);
+    }
+
+    let index_json: AssetIndex =
+        serde_json::from_str(&tokio::fs::read_to_string(index_file).await?)?;
+    let objects_dir = assets_dir.join("objects");
+    for obj in index_json.objects.values() {
+        let hash_prefix: String = obj.hash.chars().take(2).collect();
+        report
+            .check(objects_dir.join(&hash_prefix).join(&obj.hash), &obj.hash)
+            .await?;
+    }
+
+    Ok(report)
+}
+
+pub async fn retrieve_versions(
+    client: &reqwest::Client,
+    mirror: Option<&str>,
+) -> anyhow::Result<VersionManifest> {
+    HttpProvider::new(client.clone(), mirror)
+        .version_manifest()
+        .await
 }
 
+// `cache_dir` is set for files in the shared cache, see DownloadTask::run
 async fn download_artifact(
-    path: &PathBuf,
+    path: &Path,
     file_info: &FileInfo,
-    client: &reqwest::Client,
+    downloader: &dyn Downloader,
+    cache_dir: Option<&Path>,
 ) -> anyhow::Result<()> {
-    if path.exists() {
-        if check_sha1_matches(tokio::fs::read(&path).await?.as_slice(), &file_info.sha1) {
-            return Ok(()); // no need to re-download
-        }
+    let existing = vec![(path.to_path_buf(), file_info.size, file_info.sha1.clone())];
+    if AssetCheck::Hash.find_invalid(existing).await?.is_empty() {
+        return Ok(()); // no need to re-download
     }
 
     // let head = client.head(&artifact.info.url).send().await?;
@@ -183,19 +1403,20 @@ async fn download_artifact(
     //     panic!("Unexpected size. Got {} expected {}", head.content_length().unwrap(), artifact.info.size)
     // }
 
-    let bytes = client.get(&file_info.url).send().await?.bytes().await?;
-
-    if !check_sha1_matches(&bytes, &file_info.sha1) {
-        panic!("Incorrect hash")
-    }
-
-    tokio::fs::create_dir_all(&path.parent().unwrap()).await?;
-    tokio::fs::write(&path, bytes).await?;
-
+    // streamed to disk, jars in packs can be large. Files from maven repositories don't always
+    // come with a hash
+    DownloadTask::for_file(file_info, path.to_path_buf())
+        .run(downloader, cache_dir)
+        .await?;
     Ok(())
 }
 
-fn resolve_arguments(arguments: Vec<LaunchArgument>, arg_query: &ArgumentQuery) -> Vec<String> {
+// values are checked before they're substituted so none of them can smuggle in extra arguments
+fn resolve_arguments(
+    arguments: Vec<LaunchArgument>,
+    context: &LaunchContext,
+    environment: &Environment,
+) -> anyhow::Result<Vec<String>> {
     let mut resolved = Vec::new();
     let arg_regex = Regex::new(r"\$\{(?<key>\w+)}").unwrap();
     
@@ -203,24 +1424,8 @@ fn resolve_arguments(arguments: Vec<LaunchArgument>, arg_query: &ArgumentQuery)
         let mut str_forms = match arg {
             LaunchArgument::String(str) => vec![str],
             LaunchArgument::Rules { rules, value } => {
-                let add_arguments = rules.iter().all(|rule| {
-                    let passed_features = rule.features.as_ref().map_or(true, |features|{
-                        features.iter().all(|(feature, state)| arg_query.features.contains(feature) || !state)
-                    });
-
-                    let passed_os = rule.os.as_ref().map_or(true, |os| {
-                        let passed_name = os.name.as_ref()
-                            .map_or(true, |name| arg_query.os_properties.name == *name);
-                        let passed_arch = os.arch.as_ref()
-                            .map_or(true, |arch| arg_query.os_properties.arch == *arch);
-                        passed_name && passed_arch
-                    });
-    
+                let add_arguments = rules::is_allowed(Some(&rules), environment);
 
-                    let passed = passed_features && passed_os;
-                    passed != (matches!(rule.action, RuleAction::Deny))
-                });
-                
                 if add_arguments {
                     match value {
                         RuleType::String(str) => vec![str],
@@ -233,12 +1438,18 @@ fn resolve_arguments(arguments: Vec<LaunchArgument>, arg_query: &ArgumentQuery)
         };
 
         for arg in str_forms.iter_mut() {
+            for caps in arg_regex.captures_iter(arg) {
+                if let Some(value) = context.value(&caps["key"]) {
+                    args::check_value(&caps["key"], &value, caps[0].len() == arg.len())?;
+                }
+            }
             *arg = arg_regex.replace_all(arg, |caps: &regex::Captures| {
                 let key = caps["key"].to_string();
-                match arg_query.constants.get(&key) {
-                    Some(x) => x.clone(),
+                match context.value(&key) {
+                    Some(x) => x.into_owned(),
+                    // not a placeholder the launcher knows, every one it does has a value
                     None => {
-                        println!("Could not find key {}", key);
+                        warn!("Could not find key {}", key);
                         String::from("")
                     }
                 }
@@ -248,21 +1459,17 @@ fn resolve_arguments(arguments: Vec<LaunchArgument>, arg_query: &ArgumentQuery)
         resolved.append(&mut str_forms);
     }
     
-    resolved
+    Ok(resolved)
 }
 
-struct ArgumentQuery {
-    constants: HashMap<String, String>,
-    features: Vec<String>,
-    os_properties: OSProperties,
-}
-
-struct OSProperties {
-    name: String,
-    arch: String,
+// hashes in chunks, jars and objects never have to fit in memory at once
+pub(crate) fn sha1_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha1::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
-fn check_sha1_matches(bytes: impl AsRef<[u8]>, sha1: &String) -> bool {
+pub(crate) fn check_sha1_matches(bytes: impl AsRef<[u8]>, sha1: &String) -> bool {
     let mut hasher = Sha1::new();
     hasher.update(bytes);
     let result = hasher.finalize();
@@ -272,85 +1479,93 @@ fn check_sha1_matches(bytes: impl AsRef<[u8]>, sha1: &String) -> bool {
 }
 
 fn canonicalize_and_str(path: &PathBuf) -> anyhow::Result<String> {
-    dbg!(path);
-    Ok(dunce::canonicalize(path)?.into_os_string().into_string().unwrap())
-    
+    // what a dry run would download doesn't exist yet
+    let path = dunce::canonicalize(path).or_else(|_| std::path::absolute(path))?;
+    Ok(path.into_os_string().into_string().unwrap())
 }
 
-#[derive(Deserialize, Debug)]
-struct VersionManifest {
-    latest: LatestVersion,
-    versions: Vec<Version>,
+#[derive(Deserialize, Serialize, Debug)]
+pub struct VersionManifest {
+    pub latest: LatestVersion,
+    pub versions: Vec<Version>,
 }
 
 impl VersionManifest {
-    fn find_version_by_id(&self, id: &str) -> Option<&Version> {
+    pub fn find_version_by_id(&self, id: &str) -> Option<&Version> {
         self.versions.iter().find(|x| x.id == id)
     }
 }
 
-#[derive(Deserialize, Debug)]
-struct LatestVersion {
-    release: String,
-    snapshot: String,
+#[derive(Deserialize, Serialize, Debug)]
+pub struct LatestVersion {
+    pub release: String,
+    pub snapshot: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct Version {
-    id: String,
+pub struct Version {
+    pub id: String,
     #[serde(rename = "type")]
-    vtype: VersionType,
-    url: String,
-    time: String,
+    pub vtype: VersionType,
+    pub url: String,
+    pub time: String,
     #[serde(with = "time::serde::iso8601")]
-    release_time: time::OffsetDateTime,
-    sha1: String,
-    compliance_level: u8,
+    pub release_time: time::OffsetDateTime,
+    // missing from v1 manifests
+    #[serde(default)]
+    pub sha1: String,
+    #[serde(default)]
+    pub compliance_level: u8,
 }
 
-impl Version {
-    async fn resolve_version_info(&self, client: &reqwest::Client) -> anyhow::Result<VersionInfo> {
-        let body = client
-            .get(&self.url)
-            .send()
-            .await?
-            .json::<VersionInfo>()
-            .await?;
-
-        Ok(body)
-    }
-}
-
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
-enum VersionType {
+pub enum VersionType {
     Release,
     Snapshot,
     OldBeta,
     OldAlpha,
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+// a fully resolved version, see versions::resolve
+#[derive(Debug)]
 struct VersionInfo {
     arguments: LaunchArguments,
     asset_index: AssetIndexFile,
-    assets: String,
-    compliance_level: u8,
     downloads: VersionDownloads,
     id: String,
+    // the version whose client jar is launched, differs from `id` for modded versions
+    jar: String,
     java_version: JavaVersion,
     libraries: Vec<Library>,
-    logging: LoggingConfiguration,
     main_class: String,
-    minimum_launcher_version: u8,
-    #[serde(with = "time::serde::iso8601")]
+    // the official launcher release a version needs, tells apart the eras that start differently
+    minimum_launcher_version: u32,
     release_time: time::OffsetDateTime,
-    #[serde(with = "time::serde::iso8601")]
-    time: time::OffsetDateTime,
-    #[serde(rename = "type")]
-    vtype: VersionType,
+}
+
+impl VersionInfo {
+    // from before 1.13, see legacy::arguments
+    fn is_legacy(&self) -> bool {
+        self.minimum_launcher_version < legacy::ARGUMENTS_LAUNCHER_VERSION
+    }
+
+    fn libraries_for<'a>(&'a self, env: &'a Environment) -> impl Iterator<Item = &'a Library> {
+        versions::dedup_libraries(
+            self.libraries
+                .iter()
+                .filter(|lib| rules::is_allowed(lib.rules.as_deref(), env)),
+        )
+        .into_iter()
+    }
+
+    // classpath jars and natives jars alike
+    fn library_artifacts<'a>(&'a self, env: &'a Environment) -> Vec<&'a Artifact> {
+        self.libraries_for(env)
+            .flat_map(|lib| lib.artifacts_for(env))
+            .collect()
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -366,9 +1581,6 @@ struct AssetIndexFile {
 #[serde(rename_all = "camelCase")]
 struct VersionDownloads {
     client: FileInfo,
-    client_mappings: Option<FileInfo>,
-    server: Option<FileInfo>,
-    server_mappings: Option<FileInfo>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -378,48 +1590,52 @@ struct JavaVersion {
     major_version: u8,
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug)]
 struct Library {
     downloads: LibraryDownloads,
     name: String,
     rules: Option<Vec<Rule>>,
+    // natives split out the pre-1.19 way: os name -> classifier, "${arch}" standing for 32 or 64
+    natives: Option<HashMap<String, String>>,
+    // paths skipped when extracting those natives, usually META-INF/
+    extract_exclude: Vec<String>,
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct LibraryDownloads {
-    artifact: Artifact,
-}
+impl Library {
+    fn native_classifier(&self, env: &Environment) -> Option<String> {
+        let classifier = self.natives.as_ref()?.get(&env.os_name)?;
+        Some(classifier.replace("${arch}", env.arch_bits()))
+    }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct Artifact {
-    path: String,
-    #[serde(flatten)]
-    info: FileInfo,
-}
+    // the jar to extract natives from, 1.19+ lists natives as plain classpath libraries instead
+    fn native_artifact(&self, env: &Environment) -> Option<&Artifact> {
+        self.downloads.classifiers.get(&self.native_classifier(env)?)
+    }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct LoggingConfiguration {
-    client: Option<SidedLoggingConfiguration>,
-    server: Option<SidedLoggingConfiguration>,
+    // what this platform downloads
+    fn artifacts_for<'a>(&'a self, env: &Environment) -> impl Iterator<Item = &'a Artifact> {
+        self.downloads.artifact.iter().chain(self.native_artifact(env))
+    }
+
+    // every platform's files
+    fn all_artifacts(&self) -> impl Iterator<Item = &Artifact> {
+        self.downloads.artifact.iter().chain(self.downloads.classifiers.values())
+    }
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct SidedLoggingConfiguration {
-    argument: String,
-    file: File,
-    #[serde(rename = "type")]
-    ltype: String,
+struct LibraryDownloads {
+    // natives-only libraries have none
+    artifact: Option<Artifact>,
+    #[serde(default)]
+    classifiers: HashMap<String, Artifact>,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct File {
-    id: String,
+struct Artifact {
+    path: String,
     #[serde(flatten)]
     info: FileInfo,
 }
@@ -445,13 +1661,6 @@ enum LaunchArgument {
     Rules { rules: Vec<Rule>, value: RuleType },
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "snake_case")]
-enum RuleAction {
-    Allow,
-    Deny,
-}
-
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
 enum RuleType {
@@ -459,22 +1668,14 @@ enum RuleType {
     Array(Vec<String>),
 }
 
-#[derive(Deserialize, Debug)]
-struct Rule {
-    action: RuleAction,
-    features: Option<HashMap<String, bool>>,
-    os: Option<ArgumentRuleOSConstraint>,
-}
-
-#[derive(Deserialize, Debug)]
-struct ArgumentRuleOSConstraint {
-    name: Option<String>,
-    arch: Option<String>,
-}
-
 #[derive(Deserialize)]
 struct AssetIndex {
     objects: HashMap<String, Asset>,
+    // 1.6 and 1.7 want the objects copied out under their names, 1.5 and older into resources/
+    #[serde(default, rename = "virtual")]
+    is_virtual: bool,
+    #[serde(default)]
+    map_to_resources: bool,
 }
 
 #[derive(Deserialize)]
@@ -482,3 +1683,10 @@ struct Asset {
     hash: String,
     size: u64,
 }
+
+impl Asset {
+    // where the object lives, under both the objects dir and the resources URL
+    fn path(&self) -> String {
+        format!("{}/{}", &self.hash[..2.min(self.hash.len())], self.hash)
+    }
+}
@@ -0,0 +1,123 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use time::{macros::datetime, OffsetDateTime};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum LoaderKind {
+    Fabric,
+    Quilt,
+    Forge,
+    NeoForge,
+}
+
+impl LoaderKind {
+    pub fn id(&self) -> &'static str {
+        match self {
+            LoaderKind::Fabric => "fabric",
+            LoaderKind::Quilt => "quilt",
+            LoaderKind::Forge => "forge",
+            LoaderKind::NeoForge => "neoforge",
+        }
+    }
+
+    // from the id its installer gives the version JSON, e.g. fabric-loader-0.15.0-1.20.4 or
+    // 1.20.1-forge-47.2.0
+    pub fn from_version_id(id: &str) -> Option<LoaderKind> {
+        let id = id.to_ascii_lowercase();
+        if id.starts_with("fabric-loader-") {
+            Some(LoaderKind::Fabric)
+        } else if id.starts_with("quilt-loader-") {
+            Some(LoaderKind::Quilt)
+        } else if id.contains("neoforge") {
+            Some(LoaderKind::NeoForge)
+        } else if id.contains("forge") {
+            Some(LoaderKind::Forge)
+        } else {
+            None
+        }
+    }
+
+    // the first game version the loader exists for, by release date. Forge goes back further
+    // than this launcher does
+    pub fn earliest_release(&self) -> Option<OffsetDateTime> {
+        match self {
+            // 18w43b
+            LoaderKind::Fabric | LoaderKind::Quilt => Some(datetime!(2018-10-24 00:00 UTC)),
+            // forked from Forge at 1.20.1
+            LoaderKind::NeoForge => Some(datetime!(2023-06-12 00:00 UTC)),
+            LoaderKind::Forge => None,
+        }
+    }
+}
+
+impl fmt::Display for LoaderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+impl FromStr for LoaderKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fabric" => Ok(LoaderKind::Fabric),
+            "quilt" => Ok(LoaderKind::Quilt),
+            "forge" => Ok(LoaderKind::Forge),
+            "neoforge" => Ok(LoaderKind::NeoForge),
+            _ => Err(anyhow!("Unknown mod loader {}", s)),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Loader {
+    pub kind: LoaderKind,
+    pub version: String,
+}
+
+impl FromStr for Loader {
+    type Err = anyhow::Error;
+
+    // "<kind>-<version>", the format used by CurseForge manifests (e.g. "forge-47.2.0")
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, version) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Malformed loader id {}", s))?;
+
+        Ok(Loader {
+            kind: kind.parse()?,
+            version: version.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Loader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.kind, self.version)
+    }
+}
+
+// how the build an instance got was chosen
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Promotion {
+    // asked for by number
+    Requested,
+    Recommended,
+    // there was no recommended build for the game version
+    Latest,
+}
+
+// a loader build picked for a game version, as recorded in the instance lockfile
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LoaderBuild {
+    pub kind: LoaderKind,
+    pub game_version: String,
+    pub version: String,
+    pub promotion: Promotion,
+}
@@ -0,0 +1,259 @@
+use std::{cmp::Ordering, collections::HashMap, path::Path};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    fabric::{self, LoaderVersion},
+    instance::compare_versions,
+    loader::LoaderKind,
+    net::NetworkStatus,
+    write_atomic,
+};
+
+const FORGE_PROMOTIONS_URL: &str =
+    "https://files.minecraftforge.net/net/minecraftforge/forge/promotions_slim.json";
+const NEOFORGE_VERSIONS_URL: &str =
+    "https://maven.neoforged.net/api/maven/versions/releases/net/neoforged/neoforge";
+// each source's response as last fetched, loaders/matrix/<kind>.json. Kept as fetched so the
+// matrix is worked out the same way online and off
+const MATRIX_DIR: &str = "loaders/matrix";
+
+// what a loader offers for one game version
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MatrixEntry {
+    pub game_version: String,
+    // the newest stable or promoted build, None when the loader has none yet
+    pub recommended: Option<String>,
+    pub latest: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LoaderMatrix {
+    pub kind: LoaderKind,
+    // newest game version first
+    pub entries: Vec<MatrixEntry>,
+}
+
+impl LoaderMatrix {
+    pub fn get(&self, game_version: &str) -> Option<&MatrixEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.game_version == game_version)
+    }
+}
+
+// the defaults to offer for a loader when creating an instance
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LoaderDefaults {
+    pub kind: LoaderKind,
+    #[serde(flatten)]
+    pub entry: MatrixEntry,
+}
+
+// Fabric's and Quilt's /versions, every loader works with every game version listed
+#[derive(Deserialize)]
+struct MetaVersions {
+    game: Vec<MetaGame>,
+    loader: Vec<LoaderVersion>,
+}
+
+#[derive(Deserialize)]
+struct MetaGame {
+    version: String,
+}
+
+// "<game version>-recommended" and "<game version>-latest" to a Forge version
+#[derive(Deserialize)]
+struct Promotions {
+    promos: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct MavenVersions {
+    versions: Vec<String>,
+}
+
+fn source_url(kind: LoaderKind) -> anyhow::Result<String> {
+    Ok(match kind {
+        LoaderKind::Fabric | LoaderKind::Quilt => {
+            format!("{}/versions", fabric::meta_url(kind)?)
+        }
+        LoaderKind::Forge => FORGE_PROMOTIONS_URL.to_string(),
+        LoaderKind::NeoForge => NEOFORGE_VERSIONS_URL.to_string(),
+    })
+}
+
+// fetched again while online, offline the matrix cached the last time
+pub async fn matrix(
+    client: &reqwest::Client,
+    cache_dir: &Path,
+    kind: LoaderKind,
+    network: NetworkStatus,
+) -> anyhow::Result<LoaderMatrix> {
+    let path = cache_dir.join(MATRIX_DIR).join(format!("{}.json", kind));
+    let json = match network {
+        NetworkStatus::Online => {
+            let json = client
+                .get(source_url(kind)?)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            write_atomic(&path, &json).await?;
+            json
+        }
+        NetworkStatus::Offline => std::fs::read_to_string(&path).map_err(|_| {
+            anyhow!(
+                "Offline, and the {} version matrix was never fetched while online",
+                kind
+            )
+        })?,
+    };
+
+    Ok(LoaderMatrix {
+        kind,
+        entries: parse(kind, &json)?,
+    })
+}
+
+// every loader's defaults for a game version, leaving out the loaders that don't support it or
+// couldn't be fetched
+pub async fn defaults(
+    client: &reqwest::Client,
+    cache_dir: &Path,
+    game_version: &str,
+    network: NetworkStatus,
+) -> anyhow::Result<Vec<LoaderDefaults>> {
+    let mut defaults = Vec::new();
+    for kind in [
+        LoaderKind::Fabric,
+        LoaderKind::Quilt,
+        LoaderKind::Forge,
+        LoaderKind::NeoForge,
+    ] {
+        let matrix = match matrix(client, cache_dir, kind, network).await {
+            Ok(matrix) => matrix,
+            Err(e) => {
+                debug!(%kind, "no loader matrix: {}", e);
+                continue;
+            }
+        };
+        if let Some(entry) = matrix.get(game_version) {
+            defaults.push(LoaderDefaults {
+                kind,
+                entry: entry.clone(),
+            });
+        }
+    }
+    Ok(defaults)
+}
+
+fn parse(kind: LoaderKind, json: &str) -> anyhow::Result<Vec<MatrixEntry>> {
+    match kind {
+        LoaderKind::Fabric | LoaderKind::Quilt => {
+            let versions = serde_json::from_str::<MetaVersions>(json)?;
+            let Some(latest) = versions.loader.first() else {
+                return Ok(vec![]);
+            };
+            let recommended = versions
+                .loader
+                .iter()
+                .find(|loader| loader.is_stable())
+                .map(|loader| loader.version.clone());
+            Ok(versions
+                .game
+                .into_iter()
+                .map(|game| MatrixEntry {
+                    game_version: game.version,
+                    recommended: recommended.clone(),
+                    latest: latest.version.clone(),
+                })
+                .collect())
+        }
+        LoaderKind::Forge => {
+            let promotions = serde_json::from_str::<Promotions>(json)?;
+            let promoted = |game_version: &str, promotion: &str| {
+                promotions
+                    .promos
+                    .get(&format!("{}-{}", game_version, promotion))
+                    .cloned()
+            };
+            let mut game_versions = promotions
+                .promos
+                .keys()
+                .filter_map(|key| key.rsplit_once('-'))
+                .map(|(game_version, _)| game_version)
+                .collect::<Vec<_>>();
+            game_versions.sort_by(|a, b| compare_versions(b, a));
+            game_versions.dedup();
+            Ok(game_versions
+                .into_iter()
+                .filter_map(|game_version| {
+                    let recommended = promoted(game_version, "recommended");
+                    // a version only ever recommended is the latest too
+                    let latest = promoted(game_version, "latest").or(recommended.clone())?;
+                    Some(MatrixEntry {
+                        game_version: game_version.to_string(),
+                        recommended,
+                        latest,
+                    })
+                })
+                .collect())
+        }
+        LoaderKind::NeoForge => {
+            let versions = serde_json::from_str::<MavenVersions>(json)?;
+            let mut entries = Vec::<MatrixEntry>::new();
+            for version in versions.versions {
+                let Some(game_version) = neoforge_game_version(&version) else {
+                    continue;
+                };
+                let stable = !version.contains('-');
+                let newer =
+                    |current: &str| compare_versions(&version, current) == Ordering::Greater;
+                match entries
+                    .iter_mut()
+                    .find(|entry| entry.game_version == game_version)
+                {
+                    Some(entry) => {
+                        if newer(&entry.latest) {
+                            entry.latest = version.clone();
+                        }
+                        if stable && entry.recommended.as_deref().is_none_or(newer) {
+                            entry.recommended = Some(version);
+                        }
+                    }
+                    None => entries.push(MatrixEntry {
+                        game_version,
+                        recommended: stable.then(|| version.clone()),
+                        latest: version,
+                    }),
+                }
+            }
+            entries.sort_by(|a, b| compare_versions(&b.game_version, &a.game_version));
+            Ok(entries)
+        }
+    }
+}
+
+// NeoForge numbers its builds after the game version: 20.4.80 is for 1.20.4, 21.0.10 for 1.21
+// and, with the year-based game versions, 26.1.0.5 for 26.1. Its 1.20.1 builds are published
+// under Forge's old coordinates and its april fools builds under 0.x, neither shows up here
+fn neoforge_game_version(version: &str) -> Option<String> {
+    let numbers = version.split('-').next()?;
+    let parts = numbers.split('.').collect::<Vec<_>>();
+    if parts.iter().any(|part| part.parse::<u32>().is_err()) {
+        return None;
+    }
+    match parts[..] {
+        [year, drop, "0", _] => Some(format!("{}.{}", year, drop)),
+        [year, drop, patch, _] => Some(format!("{}.{}.{}", year, drop, patch)),
+        [major, "0", _] if major.parse::<u32>().ok()? >= 20 => Some(format!("1.{}", major)),
+        [major, minor, _] if major.parse::<u32>().ok()? >= 20 => {
+            Some(format!("1.{}.{}", major, minor))
+        }
+        _ => None,
+    }
+}
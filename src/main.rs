@@ -1,6 +1,2577 @@
-use mod_launcher::launch_minecraft;
+use std::{
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Context};
+use clap::{Parser, Subcommand};
+use mod_launcher::{
+    adopt,
+    args::QuickPlay,
+    assets::AssetCheck,
+    auth::{self, AccountStore},
+    build_info,
+    bundle::{self, BundleSource},
+    cache,
+    components::{self, Component},
+    config::Config,
+    curseforge,
+    daemon, dedup, default_java_path, disk, download, default_work_dir, fabric,
+    forge::{self, ProcessorEnv},
+    freeze,
+    install_version, instance, java, known_issues, language, launch_minecraft,
+    loader::{LoaderBuild, LoaderKind, Promotion},
+    loader_matrix, mirror,
+    net::{self, MetaProvider, NetworkStatus},
+    portable, saves, search, sessions,
+    shortcuts::{Shortcut, ShortcutKind},
+    signing::{self, SigningKey},
+    skins::{self, SkinVariant},
+    steam,
+    tasks::{self, TaskContext, TaskId, TaskKind, TaskQueue, TaskStatus},
+    update_channel::{self, Rollout},
+    vanilla, verify_version, versions, VersionType,
+};
+use serde::Serialize;
+
+#[derive(Parser)]
+#[command(name = "mod_launcher", version = build_info::VERSION, about = "A Minecraft launcher")]
+struct Cli {
+    /// Print machine-readable JSON instead of human-readable output
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Log level or filter, e.g. debug or mod_launcher=trace,minecraft=off
+    #[arg(long, global = true, env = "MOD_LAUNCHER_LOG", default_value = "info")]
+    log: String,
+
+    /// Write logs to stderr as JSON lines
+    #[arg(long, global = true)]
+    log_json: bool,
+
+    /// Plain output for screen readers and CI logs: no colors, and progress as one line per update
+    /// instead of redrawing. On by default when TERM=dumb or stderr isn't a terminal
+    #[arg(long, global = true, env = "MOD_LAUNCHER_PLAIN")]
+    plain: bool,
+
+    /// Directory holding libraries, assets, instances and accounts
+    #[arg(long, global = true, env = "MOD_LAUNCHER_WORK_DIR")]
+    work_dir: Option<PathBuf>,
+
+    /// Shared libraries, assets and versions, overrides launcher.toml
+    #[arg(long, global = true, env = "MOD_LAUNCHER_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Java binary used to launch the game, overrides launcher.toml
+    #[arg(long, global = true)]
+    java: Option<PathBuf>,
+
+    /// Default max heap in MiB, overrides launcher.toml
+    #[arg(long, global = true)]
+    max_memory: Option<u64>,
+
+    /// Number of parallel downloads, overrides launcher.toml
+    #[arg(long, global = true)]
+    download_concurrency: Option<usize>,
+
+    /// Bytes per second for all downloads together, overrides launcher.toml
+    #[arg(long, global = true)]
+    download_limit: Option<u64>,
+
+    /// Use less memory for constrained devices: fewer parallel downloads and less game output kept
+    #[arg(long, global = true)]
+    low_memory: bool,
+
+    /// Base URL of a download mirror, overrides launcher.toml
+    #[arg(long, global = true)]
+    mirror_url: Option<String>,
+
+    /// Version manifest to list versions from instead of Mojang's, overrides launcher.toml
+    #[arg(long, global = true)]
+    manifest_url: Option<String>,
+
+    /// How downloaded assets are checked: exists, size or hash, overrides launcher.toml
+    #[arg(long, global = true)]
+    asset_check: Option<AssetCheck>,
+
+    /// Hash every installed file instead of trusting the sizes and mtimes the last install recorded
+    #[arg(long, global = true)]
+    verify_all: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+impl Cli {
+    fn output_style(&self) -> OutputStyle {
+        let dumb = std::env::var("TERM").is_ok_and(|term| term == "dumb");
+        if self.plain || dumb || !std::io::stderr().is_terminal() {
+            OutputStyle::Plain
+        } else {
+            OutputStyle::Terminal
+        }
+    }
+
+    fn load_config(&self) -> anyhow::Result<Config> {
+        let work_dir = match &self.work_dir {
+            Some(work_dir) => work_dir.clone(),
+            None => default_work_dir()?,
+        };
+
+        let mut config = Config::load(&work_dir)?;
+        if let Some(cache_dir) = &self.cache_dir {
+            // relative to where the command runs, not to the work dir like launcher.toml values
+            config.cache_dir = Some(std::path::absolute(cache_dir)?);
+        }
+        if let Some(java) = &self.java {
+            config.java_path = Some(java.clone());
+        }
+        if let Some(max_memory) = self.max_memory {
+            config.max_memory_mb = Some(max_memory);
+        }
+        // before the explicit concurrency, which still wins
+        if self.low_memory {
+            config.limit_memory();
+        }
+        if let Some(concurrency) = self.download_concurrency {
+            config.download_concurrency = concurrency;
+        }
+        if let Some(limit) = self.download_limit {
+            config.download_limit = Some(limit);
+        }
+        if let Some(mirror_url) = &self.mirror_url {
+            config.mirror_url = Some(mirror_url.clone());
+        }
+        if let Some(manifest_url) = &self.manifest_url {
+            config.manifest_url = Some(manifest_url.clone());
+        }
+        if let Some(asset_check) = self.asset_check {
+            config.asset_check = asset_check;
+        }
+        if self.verify_all {
+            config.trust_file_stamps = false;
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Launch a version or an instance
+    Launch {
+        /// Version id, defaults to the latest snapshot
+        #[arg(long, conflicts_with = "instance")]
+        version: Option<String>,
+        #[arg(long)]
+        instance: Option<String>,
+        /// Join this server (host[:port]) once the game starts
+        #[arg(long, conflicts_with = "world")]
+        server: Option<String>,
+        /// Open this world (its folder under saves/) once the game starts
+        #[arg(long)]
+        world: Option<String>,
+        /// Play as this logged in account (username or uuid) instead of the selected one
+        #[arg(long)]
+        account: Option<String>,
+        /// Print what the launch would download and run instead of starting the game
+        #[arg(long)]
+        dry_run: bool,
+        /// Start with only essential mods, the default heap and no custom JVM arguments
+        #[arg(long)]
+        safe_mode: bool,
+    },
+    /// List the games this launcher is running
+    Sessions,
+    /// Look at a running or ended game's session
+    Session {
+        #[command(subcommand)]
+        command: SessionCommand,
+    },
+    /// Serve the launcher over local JSON-RPC for GUI frontends
+    Daemon {
+        /// Port on 127.0.0.1, any free one by default
+        #[arg(long, default_value_t = 0)]
+        port: u16,
+    },
+    /// Query available Minecraft versions
+    Versions {
+        #[command(subcommand)]
+        command: VersionsCommand,
+    },
+    /// Query mod loader versions
+    Loaders {
+        #[command(subcommand)]
+        command: LoadersCommand,
+    },
+    /// Manage instances
+    Instance {
+        #[command(subcommand)]
+        command: InstanceCommand,
+    },
+    /// Log in with a Microsoft account
+    Login {
+        /// Azure application (client) id used for the device code flow, overrides launcher.toml
+        #[arg(long)]
+        client_id: Option<String>,
+    },
+    /// Check the installed files of a version against their hashes
+    Verify {
+        #[arg(long)]
+        version: String,
+    },
+    /// Install the Fabric loader for a Minecraft version
+    InstallFabric {
+        #[arg(long)]
+        game_version: String,
+        /// Defaults to the latest stable loader, offline to the latest one cached
+        #[arg(long)]
+        loader_version: Option<String>,
+        /// fabric or quilt, both install the same way
+        #[arg(long, default_value = "fabric")]
+        loader: LoaderKind,
+        /// Switch this instance over to the installed loader
+        #[arg(long)]
+        instance: Option<String>,
+    },
+    /// Install Forge or NeoForge for a Minecraft version by running its installer
+    InstallForge {
+        #[arg(long)]
+        game_version: String,
+        /// Defaults to the recommended build, or the latest one when none is recommended
+        #[arg(long)]
+        build: Option<String>,
+        /// forge or neoforge
+        #[arg(long, default_value = "forge")]
+        loader: LoaderKind,
+        /// Switch this instance over to the installed loader and lock the build it got
+        #[arg(long)]
+        instance: Option<String>,
+    },
+    /// Search instances, installed mods and versions without touching the network
+    Search {
+        query: String,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Queue long running operations and run them in the background
+    Tasks {
+        #[command(subcommand)]
+        command: TasksCommand,
+    },
+    /// Manage the shared library, asset and version cache
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Find libraries and assets duplicated by other launchers' installs
+    Dedup {
+        #[command(subcommand)]
+        command: DedupCommand,
+    },
+    /// Show what is using disk space and clean it up
+    Disk {
+        #[command(subcommand)]
+        command: DiskCommand,
+    },
+    /// Carry a version or instance over to a machine without network access
+    Bundle {
+        #[command(subcommand)]
+        command: BundleCommand,
+    },
+    /// Sign bundles and instance archives, and check their signatures
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommand,
+    },
+    /// Manage the logged in player's skin and cape
+    Skin {
+        #[command(subcommand)]
+        command: SkinCommand,
+    },
+    /// Find Java installs on this machine
+    Java {
+        #[command(subcommand)]
+        command: JavaCommand,
+    },
+    /// Look up known causes of failed launches
+    Issues {
+        #[command(subcommand)]
+        command: IssuesCommand,
+    },
+    /// Back up and restore worlds
+    Saves {
+        /// Defaults to the work dir's .minecraft
+        #[arg(long, global = true)]
+        instance: Option<String>,
+        #[command(subcommand)]
+        command: SavesCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum SkinCommand {
+    /// Show the current skin and the capes owned
+    Show,
+    /// Upload a skin from a PNG file or an image URL
+    Set {
+        source: String,
+        /// classic or slim
+        #[arg(long, default_value = "classic")]
+        variant: SkinVariant,
+    },
+    /// Go back to the default skin
+    Reset,
+    /// Wear one of the owned capes
+    Cape {
+        /// Cape id, as shown by `skin show`
+        #[arg(required_unless_present = "hide")]
+        id: Option<String>,
+        /// Stop wearing a cape
+        #[arg(long, conflicts_with = "id")]
+        hide: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SavesCommand {
+    /// List worlds, most recently played first
+    List,
+    /// Zip a world into the backups folder
+    Backup {
+        /// World folder under saves/
+        world: String,
+    },
+    /// List backups, newest first
+    Backups { world: Option<String> },
+    /// Replace a world with a backup of it
+    Restore { backup: PathBuf },
+    /// Delete old backups
+    Prune {
+        world: Option<String>,
+        /// Backups kept per world
+        #[arg(long)]
+        keep: Option<usize>,
+        #[arg(long)]
+        max_age_days: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TasksCommand {
+    List,
+    /// Queue downloading everything a version needs
+    Install { version: String },
+    /// Queue installing the Fabric loader and its files
+    InstallFabric {
+        #[arg(long)]
+        game_version: String,
+        #[arg(long)]
+        loader_version: Option<String>,
+        #[arg(long)]
+        instance: Option<String>,
+    },
+    /// Queue a cache garbage collection
+    Gc {
+        #[arg(long)]
+        prune_versions: bool,
+    },
+    Pause { id: TaskId },
+    /// Resume a paused task or retry a failed one
+    Resume { id: TaskId },
+    Cancel { id: TaskId },
+    /// Run queued tasks until none are left
+    Run {
+        /// Tasks running at once, overrides launcher.toml
+        #[arg(long)]
+        parallelism: Option<usize>,
+    },
+    /// Drop finished tasks from the log
+    Compact,
+}
+
+#[derive(Subcommand)]
+enum JavaCommand {
+    /// List the Java installs launches pick from when no --java is given
+    List {
+        /// Probe every install again instead of trusting earlier results
+        #[arg(long)]
+        refresh: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Print where the cache lives
+    Path,
+    /// Delete cached files no installed version uses
+    Gc {
+        /// Also uninstall versions no instance uses
+        #[arg(long)]
+        prune_versions: bool,
+        /// Report what would be removed without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write the cached manifest and version metadata out as a static mirror
+    Export {
+        /// Directory to serve, laid out as <host>/<path>
+        out: PathBuf,
+        /// Also export libraries, client jars and asset objects
+        #[arg(long)]
+        files: bool,
+    },
+    /// Show how fast each configured mirror answered, probing those not measured lately
+    Mirrors {
+        /// Probe every mirror again
+        #[arg(long)]
+        reprobe: bool,
+    },
+    /// Show what each download host served against the sizes its metadata declared
+    Sources,
+}
+
+#[derive(Subcommand)]
+enum DedupCommand {
+    /// Report duplicated files without changing anything
+    Scan,
+    /// Move duplicated files into this launcher's stores and link them back
+    Migrate {
+        /// Use hard links instead of symlinks
+        #[arg(long)]
+        hardlink: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SessionCommand {
+    /// Write out the end of a session's game output, kept even for games that crashed
+    DumpLog {
+        id: String,
+        /// Defaults to printing it
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum IssuesCommand {
+    /// Download the ruleset from known_issues_url now
+    Update,
+    /// Match a log or crash report against the known issues
+    Check {
+        log: PathBuf,
+        /// The exit code the game had
+        #[arg(long, allow_hyphen_values = true)]
+        exit_code: Option<i32>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DiskCommand {
+    /// Break down disk usage by version, loader, instance, backups and cache, and what cleaning would free
+    Usage {
+        #[command(flatten)]
+        cleanup: CleanupArgs,
+    },
+    /// Free the space `disk usage` reports as reclaimable
+    Clean {
+        #[command(flatten)]
+        cleanup: CleanupArgs,
+    },
+}
+
+#[derive(clap::Args)]
+struct CleanupArgs {
+    /// Also uninstall versions no instance uses
+    #[arg(long)]
+    prune_versions: bool,
+    /// World backups to keep per world, overrides each instance's backup_keep
+    #[arg(long)]
+    keep_backups: Option<usize>,
+}
+
+impl CleanupArgs {
+    fn options(&self) -> disk::CleanupOptions {
+        disk::CleanupOptions {
+            prune_versions: self.prune_versions,
+            keep_backups: self.keep_backups,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum BundleCommand {
+    /// Package a version, or an instance with its mods and settings, and everything it needs
+    Create {
+        /// Version id, or the instance's name with --instance
+        target: String,
+        #[arg(long)]
+        instance: bool,
+        /// Where to write the bundle
+        #[arg(long)]
+        out: PathBuf,
+        /// Also bundle a Temurin Java runtime for the version, Linux only
+        #[arg(long)]
+        java: bool,
+        /// Sign the bundle with this key file, see `keys generate`
+        #[arg(long)]
+        sign: Option<PathBuf>,
+    },
+    /// Install a bundle without touching the network, checking every file's hash
+    Install {
+        bundle: PathBuf,
+        /// Name for a bundled instance, the name it was bundled with by default
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeysCommand {
+    /// Create a signing key and print the public key to add to trusted_keys
+    Generate {
+        /// Where to keep the key, anyone with this file can sign as you
+        out: PathBuf,
+    },
+    /// Check a bundle or instance archive against its .sig file
+    Verify { archive: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum VersionsCommand {
+    List {
+        /// Include snapshots and old alpha/beta versions
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum LoadersCommand {
+    List {
+        /// Required unless listing cached loaders
+        #[arg(long)]
+        game_version: Option<String>,
+        /// fabric or quilt, both for cached loaders when unset and fabric otherwise
+        #[arg(long)]
+        loader: Option<LoaderKind>,
+        /// Only loaders in the cache, without touching the network
+        #[arg(long)]
+        cached: bool,
+    },
+    /// Recommended and latest loader versions per game version, cached for offline use
+    Matrix {
+        /// Every game version when unset
+        #[arg(long)]
+        game_version: Option<String>,
+        /// Every loader when unset
+        #[arg(long)]
+        loader: Option<LoaderKind>,
+    },
+}
+
+#[derive(Subcommand)]
+enum InstanceCommand {
+    Create {
+        name: String,
+        #[arg(long)]
+        version: String,
+    },
+    List {
+        #[arg(long, conflicts_with = "ungrouped")]
+        group: Option<String>,
+        /// Only list instances outside of any group
+        #[arg(long)]
+        ungrouped: bool,
+        /// Only list instances with this tag, repeatable
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        #[arg(long)]
+        loader: Option<LoaderKind>,
+        /// name, last-played or version
+        #[arg(long, default_value = "name")]
+        sort: instance::SortKey,
+        #[arg(long)]
+        reverse: bool,
+        /// Print instances under their group headings
+        #[arg(long)]
+        by_group: bool,
+    },
+    Delete {
+        name: String,
+    },
+    /// Show an instance's settings and metadata
+    Info {
+        name: String,
+    },
+    /// Update an instance's metadata
+    Edit {
+        name: String,
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long)]
+        group: Option<String>,
+        /// Remove the instance from its group
+        #[arg(long, conflicts_with = "group")]
+        no_group: bool,
+        #[arg(long = "add-tag")]
+        add_tags: Vec<String>,
+        #[arg(long = "remove-tag")]
+        remove_tags: Vec<String>,
+        /// Image copied into the instance as its icon
+        #[arg(long)]
+        icon: Option<PathBuf>,
+        #[arg(long, conflicts_with = "icon")]
+        clear_icon: bool,
+        /// Back up every world before each launch
+        #[arg(long)]
+        backup_worlds: Option<bool>,
+        /// Backups kept per world, 0 keeps all of them
+        #[arg(long)]
+        backup_keep: Option<usize>,
+        /// Always play as this account (username or uuid), empty for the selected one
+        #[arg(long)]
+        account: Option<String>,
+        /// Set an environment variable for the game, KEY=VALUE, repeatable
+        #[arg(long = "env", value_parser = parse_env_var)]
+        set_env: Vec<(String, String)>,
+        #[arg(long = "unset-env")]
+        unset_env: Vec<String>,
+        /// Trust this CA certificate (PEM or DER) in the game, e.g. a school or company proxy's
+        #[arg(long = "add-ca-cert")]
+        add_ca_certs: Vec<PathBuf>,
+        #[arg(long = "remove-ca-cert")]
+        remove_ca_certs: Vec<PathBuf>,
+        /// Update to the latest release or snapshot before each launch
+        #[arg(long)]
+        channel: Option<instance::Channel>,
+        /// Stop following a channel and stay on the current version
+        #[arg(long, conflicts_with = "channel")]
+        pin: bool,
+        /// Leave identifying arguments out and turn the game's optional telemetry off, overrides launcher.toml
+        #[arg(long)]
+        telemetry_opt_out: Option<bool>,
+        /// The game's language, e.g. de_de, set before the next launch. Empty to leave it to the game
+        #[arg(long)]
+        language: Option<String>,
+    },
+    /// Show what an instance launches from the vanilla version up, or add agents and jar mods
+    Components {
+        name: String,
+        /// Load a Java agent into the game, PATH or PATH=OPTIONS, repeatable
+        #[arg(long = "add-agent", value_parser = parse_agent)]
+        add_agents: Vec<(PathBuf, Option<String>)>,
+        /// Patch a zip of classes over the client jar, applied in the order added
+        #[arg(long = "add-jar-mod")]
+        add_jar_mods: Vec<PathBuf>,
+        /// Remove an agent or jar mod by its path
+        #[arg(long)]
+        remove: Vec<PathBuf>,
+        /// Save even when the change leaves the instance unable to launch
+        #[arg(long)]
+        force: bool,
+    },
+    /// Create a desktop shortcut that launches the instance
+    Shortcut {
+        name: String,
+        /// Defaults to the platform's desktop or applications folder
+        #[arg(long)]
+        dest: Option<PathBuf>,
+        /// .ico on Windows, any image on macOS and Linux
+        #[arg(long)]
+        icon: Option<PathBuf>,
+    },
+    /// Add the instance to Steam as a non-Steam game (close Steam first)
+    Steam {
+        name: String,
+        /// Steam account id folder under userdata, defaults to every account found
+        #[arg(long)]
+        steam_user: Option<String>,
+        #[arg(long)]
+        icon: Option<PathBuf>,
+        /// 600x900 library capsule image
+        #[arg(long)]
+        grid_portrait: Option<PathBuf>,
+        /// 920x430 header image
+        #[arg(long)]
+        grid_wide: Option<PathBuf>,
+        #[arg(long)]
+        grid_hero: Option<PathBuf>,
+        #[arg(long)]
+        grid_logo: Option<PathBuf>,
+    },
+    /// Import the official launcher's profiles as instances
+    ImportVanilla {
+        /// The official launcher's .minecraft, detected by default
+        #[arg(long)]
+        minecraft_dir: Option<PathBuf>,
+    },
+    /// Add the instance to the official launcher's profiles (close that launcher first)
+    ExportVanilla {
+        name: String,
+        /// The official launcher's .minecraft, detected by default
+        #[arg(long)]
+        minecraft_dir: Option<PathBuf>,
+    },
+    /// Zip the instance's settings, mod list and config to share it
+    Export {
+        name: String,
+        /// Defaults to <name>.zip in the current directory
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Include mod jars that aren't on Modrinth, only if you may share them
+        #[arg(long)]
+        bundle_unknown_mods: bool,
+        /// Sign the archive with this key file, see `keys generate`
+        #[arg(long)]
+        sign: Option<PathBuf>,
+    },
+    /// Copy the instance with its libraries, assets and Java into a folder that runs on its own
+    Freeze {
+        name: String,
+        /// An empty or missing directory
+        out: PathBuf,
+        /// Leave Java out, the start script uses the java on the PATH
+        #[arg(long)]
+        no_java: bool,
+    },
+    /// Identify mods and resource packs added by hand on Modrinth and CurseForge, launches do this too
+    Adopt { name: String },
+    /// Check mods and resource packs added by hand for newer versions, see `instance adopt`
+    Updates { name: String },
+    /// Recreate an exported instance, downloading its mods and version
+    Import {
+        archive: PathBuf,
+        /// Defaults to the exported instance's name
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Create an instance from a CurseForge modpack zip, installing its mods and loader
+    ImportCurseforge {
+        pack: PathBuf,
+        /// Defaults to the pack's name
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Add the instance as the next release of an update channel, a directory to upload as-is
+    Publish {
+        name: String,
+        /// The channel's directory, created on the first release
+        #[arg(long)]
+        out: PathBuf,
+        /// Include mod jars that aren't on Modrinth, only if you may share them
+        #[arg(long)]
+        bundle_unknown_mods: bool,
+        /// Stage the release to this percentage of followers, widen it later with `instance rollout`
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        rollout: Option<u8>,
+        /// Stage the release to followers in this cohort, repeatable
+        #[arg(long = "cohort")]
+        cohorts: Vec<String>,
+    },
+    /// Stage, widen or halt a published release of an update channel
+    Rollout {
+        /// The channel's directory
+        channel: PathBuf,
+        release: u32,
+        /// Percentage of followers that get the release, 0 halts it for everyone else
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        percent: Option<u8>,
+        /// Followers in this cohort get the release whatever the percentage, repeatable
+        #[arg(long = "cohort")]
+        cohorts: Vec<String>,
+        /// Release to every follower
+        #[arg(long, conflicts_with_all = ["percent", "cohorts"])]
+        everyone: bool,
+    },
+    /// Create an instance from a published update channel and keep it up to date
+    Follow {
+        /// URL of the channel, or of its channel.json
+        url: String,
+        /// Defaults to the published instance's name
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Move an instance to the latest release of the update channel it follows
+    Update { name: String },
+}
+
+fn vanilla_dir(minecraft_dir: Option<PathBuf>) -> anyhow::Result<PathBuf> {
+    minecraft_dir
+        .or_else(vanilla::find_minecraft_dir)
+        .ok_or_else(|| anyhow!("Could not find the official launcher's .minecraft, pass --minecraft-dir"))
+}
+
+// logs go to stderr so they never mix with --json output
+fn init_logging(filter: &str, json: bool, style: OutputStyle) -> anyhow::Result<()> {
+    // https://no-color.org
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let logs = tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_new(filter)
+                .map_err(|e| anyhow!("Invalid log filter {}: {}", filter, e))?,
+        )
+        .with_ansi(style == OutputStyle::Terminal && !no_color)
+        .with_writer(std::io::stderr);
+    if json {
+        logs.json().init();
+    } else {
+        logs.without_time().with_target(false).init();
+    }
+    Ok(())
+}
+
+fn parse_agent(agent: &str) -> anyhow::Result<(PathBuf, Option<String>)> {
+    let (path, options) = match agent.split_once('=') {
+        Some((path, options)) => (path, Some(options.to_string())),
+        None => (agent, None),
+    };
+    // launches run from other directories
+    Ok((dunce::canonicalize(path)?, options))
+}
+
+fn parse_env_var(var: &str) -> anyhow::Result<(String, String)> {
+    let (name, value) = var
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected KEY=VALUE, got {}", var))?;
+    mod_launcher::args::validate_env_var(name)?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+async fn logged_in_account(
+    client: &reqwest::Client,
+    config: &Config,
+    work_dir: &std::path::Path,
+) -> anyhow::Result<auth::Account> {
+    let client_id = config.client_id.as_deref();
+    auth::launch_account(client, client_id, work_dir, None, NetworkStatus::Online)
+        .await?
+        .ok_or_else(|| anyhow!("Not logged in, run `login` first"))
+}
+
+#[derive(Serialize)]
+struct ComponentsReport {
+    components: Vec<Component>,
+    conflicts: Vec<components::ComponentConflict>,
+}
+
+#[derive(Serialize)]
+struct InstalledBuild {
+    id: String,
+    build: LoaderBuild,
+}
+
+// refuses a loader version that can't work with the rest of the instance's components
+fn check_loader_switch(
+    cache_dir: &Path,
+    instances_dir: &Path,
+    name: &str,
+    id: &str,
+    loader: LoaderKind,
+) -> anyhow::Result<()> {
+    let mut proposed = instance::load(instances_dir, name)?;
+    proposed.version = id.to_string();
+    proposed.loader = Some(loader);
+    for conflict in components::check_instance(cache_dir, &proposed)? {
+        match conflict.fatal {
+            true => return Err(anyhow!("{}", conflict.message)),
+            false => eprintln!("Warning: {}", conflict.message),
+        }
+    }
+    Ok(())
+}
+
+fn print_installer_progress(progress: &forge::InstallerProgress) {
+    match (progress.fraction, &progress.message) {
+        (_, None) => eprintln!("[{}/{}] {}", progress.step, progress.steps, progress.phase),
+        (Some(fraction), Some(_)) => eprintln!(
+            "[{}/{}] {} {:.0}%",
+            progress.step,
+            progress.steps,
+            progress.phase,
+            fraction * 100.0
+        ),
+        _ => {}
+    }
+}
+
+// a loader version from the meta, and whether it would install offline
+#[derive(Serialize)]
+struct LoaderListing {
+    #[serde(flatten)]
+    version: fabric::LoaderVersion,
+    cached: bool,
+}
+
+fn print_matrix_entry(kind: LoaderKind, entry: &loader_matrix::MatrixEntry) {
+    match &entry.recommended {
+        Some(recommended) if *recommended != entry.latest => println!(
+            "{} {}\t{} (latest {})",
+            kind, entry.game_version, recommended, entry.latest
+        ),
+        Some(_) => println!("{} {}\t{}", kind, entry.game_version, entry.latest),
+        None => println!(
+            "{} {}\t{} (unstable)",
+            kind, entry.game_version, entry.latest
+        ),
+    }
+}
+
+// how long operations report progress on stderr
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OutputStyle {
+    // one progress line redrawn in place, colored logs
+    Terminal,
+    // a line per update and no escape codes, screen readers read every redraw out again
+    Plain,
+}
+
+struct Progress {
+    style: OutputStyle,
+    label: String,
+    last_percent: Mutex<Option<u64>>,
+}
+
+impl Progress {
+    fn new(style: OutputStyle, label: impl Into<String>) -> Progress {
+        Progress {
+            style,
+            label: label.into(),
+            last_percent: Mutex::new(None),
+        }
+    }
+
+    fn update(&self, done: u64, total: u64) {
+        if total == 0 {
+            return;
+        }
+        let percent = done * 100 / total;
+        // plain output only every 10%, a line per percent would bury everything else
+        let percent = match self.style {
+            OutputStyle::Terminal => percent,
+            OutputStyle::Plain => percent / 10 * 10,
+        };
+        let mut last_percent = self.last_percent.lock().unwrap();
+        if last_percent.replace(percent) == Some(percent) {
+            return;
+        }
+        let line = format!(
+            "{}: {}% ({}/{} MiB)",
+            self.label,
+            percent,
+            done / (1024 * 1024),
+            total.div_ceil(1024 * 1024)
+        );
+        match self.style {
+            OutputStyle::Terminal if done < total => eprint!("\r\x1b[2K{}", line),
+            OutputStyle::Terminal => eprintln!("\r\x1b[2K{}", line),
+            OutputStyle::Plain => eprintln!("{}", line),
+        }
+    }
+}
+
+fn print_output<T: Serialize>(json: bool, value: &T, human: impl FnOnce(&T)) -> anyhow::Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(value)?);
+    } else {
+        human(value);
+    }
+    Ok(())
+}
+
+fn describe_cleanup(action: &disk::CleanupAction) -> String {
+    match action {
+        disk::CleanupAction::RemoveOrphans { files, .. } => format!("remove {} cached files no version uses", files),
+        disk::CleanupAction::PruneVersions { versions, .. } => {
+            format!("uninstall {}, no instance uses them", versions.join(", "))
+        }
+        disk::CleanupAction::PruneBackups {
+            instance, keep, backups, ..
+        } => format!("remove {} world backups of {}, keeping {} per world", backups, instance, keep),
+        disk::CleanupAction::RemoveRuntime { name, .. } => format!("remove Java runtime {}, nothing needs it", name),
+        action => format!("{:?}", action),
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    launch_minecraft().await
+    let cli = Cli::parse();
+    let style = cli.output_style();
+    init_logging(&cli.log, cli.log_json, style)?;
+    let config = cli.load_config()?;
+    let work_dir = config.work_dir.clone();
+    let instances_dir = config.instances_dir();
+    let cache_dir = config.cache_dir();
+    let mirror = config.mirror_url.as_deref();
+    let client = net::client();
+
+    match cli.command {
+        Command::Launch {
+            version,
+            instance,
+            server,
+            world,
+            account,
+            dry_run,
+            safe_mode,
+        } => {
+            let http = config.http_provider(client.clone());
+            let network = http.probe().await;
+            let (mut options, instance_account) = match instance {
+                Some(name) if dry_run => {
+                    let instance = instance::load(&instances_dir, &name)?;
+                    (instance.launch_options(&config), instance.account)
+                }
+                Some(name) => {
+                    instance::track_latest(&instances_dir, &name, &http, &cache_dir, network).await?;
+                    if network == NetworkStatus::Online {
+                        let api = config.curseforge_api();
+                        if let Err(e) = adopt::adopt(&client, api.as_ref(), &instances_dir, &name).await {
+                            eprintln!("Warning: could not identify the files added to {} by hand: {:#}", name, e);
+                        }
+                    }
+                    let instance = instance::mark_played(&instances_dir, &name)?;
+                    (instance.launch_options(&config), instance.account)
+                }
+                None => {
+                    let mut options = config.launch_options();
+                    options.version = version;
+                    (options, None)
+                }
+            };
+            let account = account.or(instance_account);
+            options.account = auth::launch_account(
+                &client,
+                config.client_id.as_deref(),
+                &work_dir,
+                account.as_deref(),
+                network,
+            )
+            .await?;
+            options.offline = network == NetworkStatus::Offline;
+            options.quick_play = server
+                .map(QuickPlay::Multiplayer)
+                .or(world.map(QuickPlay::Singleplayer));
+            options.safe_mode = safe_mode;
+            if dry_run {
+                let plan = options.dry_run().await?;
+                return print_output(cli.json, &plan, |plan| {
+                    println!("Version: {}", plan.version);
+                    println!("Launcher: {}", plan.launcher_version);
+                    let components = plan.components.iter().map(ToString::to_string);
+                    println!("Components: {}", components.collect::<Vec<_>>().join(", "));
+                    match plan.java_major {
+                        Some(major) => println!("Java {}: {}", major, plan.java_path.display()),
+                        None => println!("Java: {}", plan.java_path.display()),
+                    }
+                    println!("Game dir: {}", plan.game_dir.display());
+                    println!("Natives: {}", plan.natives_dir.display());
+                    println!("Classpath:");
+                    for entry in &plan.classpath {
+                        println!("  {}", entry.display());
+                    }
+                    println!("JVM arguments: {}", plan.jvm_args.join(" "));
+                    println!("Main class: {}", plan.main_class);
+                    println!("Game arguments: {}", plan.game_args.join(" "));
+                    for (key, value) in &plan.env {
+                        println!("Environment: {}={}", key, value);
+                    }
+                    if plan.downloads.is_empty() {
+                        println!("Nothing to download");
+                    } else {
+                        println!(
+                            "Downloads {} files ({:.1} MiB):",
+                            plan.downloads.len(),
+                            plan.download_bytes as f64 / (1024.0 * 1024.0)
+                        );
+                        for download in &plan.downloads {
+                            println!("  {} -> {}", download.url, download.path.display());
+                        }
+                    }
+                });
+            }
+            if let (Some(url), NetworkStatus::Online) = (&config.known_issues_url, network) {
+                if let Err(e) = known_issues::refresh(&client, &cache_dir, url).await {
+                    eprintln!("Warning: could not update the known issues: {:#}", e);
+                }
+            }
+            match launch_minecraft(options).await? {
+                Some(crash) => {
+                    print_output(cli.json, &crash, |crash| {
+                        match crash.exit_code {
+                            Some(code) => println!("Minecraft crashed with exit code {}", code),
+                            None => println!("Minecraft was killed"),
+                        }
+                        if let Some(description) = &crash.description {
+                            println!("{}", description);
+                        }
+                        if let Some(report) = &crash.crash_report {
+                            println!("Crash report: {}", report.display());
+                        }
+                        if !crash.suspected_mods.is_empty() {
+                            println!("Suspected mods: {}", crash.suspected_mods.join(", "));
+                        }
+                        for issue in &crash.known_issues {
+                            println!("{}", issue.guidance);
+                        }
+                        if let Some(session) = &crash.session {
+                            println!("The game's output is kept, see `session dump-log {}`", session);
+                        }
+                    })?;
+                    Err(anyhow!("Minecraft crashed"))
+                }
+                None => Ok(()),
+            }
+        }
+        Command::Versions {
+            command: VersionsCommand::List { all },
+        } => {
+            let http = config.http_provider(client.clone());
+            let manifest = versions::manifest(&http, &cache_dir, http.probe().await).await?;
+            let versions = manifest
+                .versions
+                .iter()
+                .filter(|version| all || version.vtype == VersionType::Release)
+                .collect::<Vec<_>>();
+
+            print_output(cli.json, &versions, |versions| {
+                for version in versions {
+                    println!("{}", version.id);
+                }
+            })
+        }
+        Command::Loaders {
+            command:
+                LoadersCommand::List {
+                    game_version,
+                    loader,
+                    cached: true,
+                },
+        } => {
+            let loaders = fabric::cached(&cache_dir, loader, game_version.as_deref())?;
+            print_output(cli.json, &loaders, |loaders| {
+                for loader in loaders {
+                    let incomplete = if loader.complete { "" } else { " (incomplete)" };
+                    println!("{}{}", loader.id, incomplete);
+                }
+            })
+        }
+        Command::Loaders {
+            command:
+                LoadersCommand::List {
+                    game_version,
+                    loader,
+                    cached: false,
+                },
+        } => {
+            let game_version =
+                game_version.ok_or_else(|| anyhow!("Pass --game-version, or --cached"))?;
+            let loader = loader.unwrap_or(LoaderKind::Fabric);
+            let network = config.http_provider(client.clone()).probe().await;
+            let cached = fabric::cached(&cache_dir, Some(loader), Some(&game_version))?;
+            let loaders =
+                fabric::loader_versions(&client, &cache_dir, loader, &game_version, network)
+                    .await?;
+            let loaders = loaders
+                .into_iter()
+                .map(|version| {
+                    let cached = cached
+                        .iter()
+                        .any(|cached| cached.complete && cached.loader_version == version.version);
+                    LoaderListing { version, cached }
+                })
+                .collect::<Vec<_>>();
+            print_output(cli.json, &loaders, |loaders| {
+                for loader in loaders {
+                    let stable = if loader.version.is_stable() { "" } else { " (unstable)" };
+                    let cached = if loader.cached { " (cached)" } else { "" };
+                    println!("{}{}{}", loader.version.version, stable, cached);
+                }
+            })
+        }
+        Command::Loaders {
+            command:
+                LoadersCommand::Matrix {
+                    game_version,
+                    loader,
+                },
+        } => {
+            let network = config.http_provider(client.clone()).probe().await;
+            let mut matrices = Vec::new();
+            for kind in [
+                LoaderKind::Fabric,
+                LoaderKind::Quilt,
+                LoaderKind::Forge,
+                LoaderKind::NeoForge,
+            ] {
+                if loader.is_some_and(|loader| loader != kind) {
+                    continue;
+                }
+                let matrix = loader_matrix::matrix(&client, &cache_dir, kind, network).await;
+                let mut matrix = match matrix {
+                    Ok(matrix) => matrix,
+                    // one source being down shouldn't hide the others
+                    Err(e) if loader.is_none() => {
+                        eprintln!("Warning: no {} version matrix: {}", kind, e);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                if let Some(game_version) = &game_version {
+                    matrix.entries.retain(|entry| &entry.game_version == game_version);
+                }
+                matrices.push(matrix);
+            }
+            print_output(cli.json, &matrices, |matrices| {
+                for matrix in matrices {
+                    for entry in &matrix.entries {
+                        print_matrix_entry(matrix.kind, entry);
+                    }
+                }
+            })
+        }
+        Command::Instance { command } => match command {
+            InstanceCommand::Create { name, version } => {
+                let instance = instance::create(&instances_dir, &name, &version)?;
+                print_output(cli.json, &instance, |instance| {
+                    println!("Created instance {} ({})", instance.name, instance.version)
+                })
+            }
+            InstanceCommand::List {
+                group,
+                ungrouped,
+                tags,
+                loader,
+                sort,
+                reverse,
+                by_group,
+            } => {
+                let query = instance::InstanceQuery {
+                    group,
+                    ungrouped,
+                    tags,
+                    loader,
+                    sort,
+                    reverse,
+                };
+                if by_group {
+                    let groups = instance::groups(&instances_dir, &query)?;
+                    print_output(cli.json, &groups, |groups| {
+                        for group in groups {
+                            println!("{}", group.name.as_deref().unwrap_or("(ungrouped)"));
+                            for instance in &group.instances {
+                                println!("  {}\t{}", instance.name, instance.version);
+                            }
+                        }
+                    })
+                } else {
+                    let instances = instance::query(&instances_dir, &query)?;
+                    print_output(cli.json, &instances, |instances| {
+                        for instance in instances {
+                            println!("{}\t{}", instance.name, instance.version);
+                        }
+                    })
+                }
+            }
+            InstanceCommand::Delete { name } => {
+                instance::delete(&instances_dir, &name)?;
+                print_output(cli.json, &name, |name| {
+                    println!("Deleted instance {}", name)
+                })
+            }
+            InstanceCommand::Components {
+                name,
+                add_agents,
+                add_jar_mods,
+                remove,
+                force,
+            } => {
+                let add = add_agents
+                    .into_iter()
+                    .map(|(path, options)| Ok(Component::Agent { path, options }))
+                    .chain(add_jar_mods.iter().map(|path| {
+                        Ok(Component::JarMod {
+                            path: dunce::canonicalize(path)?,
+                        })
+                    }))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let remove = remove
+                    .iter()
+                    .map(|path| dunce::canonicalize(path).unwrap_or_else(|_| path.clone()))
+                    .collect::<Vec<_>>();
+                let edit = |instance: &mut instance::Instance| {
+                    instance.components.retain(|component| {
+                        component.path().is_none_or(|path| !remove.iter().any(|p| p == path))
+                    });
+                    for component in &add {
+                        let path = component.path();
+                        if !instance.components.iter().any(|c| c.path() == path) {
+                            instance.components.push(component.clone());
+                        }
+                    }
+                };
+
+                // checked as the edit would leave it, before anything is saved
+                let mut instance = instance::load(&instances_dir, &name)?;
+                edit(&mut instance);
+                let conflicts = components::check_instance(&cache_dir, &instance)?;
+                if !add.is_empty() || !remove.is_empty() {
+                    if let Some(conflict) = conflicts.iter().find(|c| c.fatal).filter(|_| !force) {
+                        return Err(anyhow!("{}, pass --force to save anyway", conflict.message));
+                    }
+                    instance = instance::update(&instances_dir, &name, edit)?;
+                }
+                let report = ComponentsReport {
+                    components: components::stack(
+                        &cache_dir,
+                        &instance.version,
+                        instance.loader,
+                        &instance.components,
+                    )?,
+                    conflicts,
+                };
+                print_output(cli.json, &report, |report| {
+                    for component in &report.components {
+                        println!("{}", component);
+                    }
+                    for conflict in &report.conflicts {
+                        let severity = if conflict.fatal { "Conflict" } else { "Warning" };
+                        println!("{}: {}", severity, conflict.message);
+                    }
+                })
+            }
+            InstanceCommand::Info { name } => {
+                let instance = instance::load(&instances_dir, &name)?;
+                print_output(cli.json, &instance, |instance| {
+                    println!("{} ({})", instance.name, instance.version);
+                    if let Some(description) = &instance.description {
+                        println!("{}", description);
+                    }
+                    if let Some(group) = &instance.group {
+                        println!("group: {}", group);
+                    }
+                    if !instance.tags.is_empty() {
+                        println!("tags: {}", instance.tags.join(", "));
+                    }
+                    if let Some(icon) = instance.icon_path(&instances_dir) {
+                        println!("icon: {}", icon.display());
+                    }
+                    if let Some(language) = &instance.language {
+                        println!("language: {}", language);
+                    }
+                })
+            }
+            InstanceCommand::Edit {
+                name,
+                description,
+                group,
+                no_group,
+                add_tags,
+                remove_tags,
+                icon,
+                clear_icon,
+                backup_worlds,
+                backup_keep,
+                account,
+                set_env,
+                unset_env,
+                add_ca_certs,
+                remove_ca_certs,
+                channel,
+                pin,
+                telemetry_opt_out,
+                language,
+            } => {
+                let language = match language.as_deref().map(str::trim) {
+                    Some("") => Some(None),
+                    Some(code) => {
+                        let code = language::normalize(code)?;
+                        let version = instance::load(&instances_dir, &name)?.version;
+                        language::check_installed(&cache_dir, &version, &code).await?;
+                        Some(Some(code))
+                    }
+                    None => None,
+                };
+                // launches run from other directories, keep the certificates findable
+                let add_ca_certs = add_ca_certs
+                    .iter()
+                    .map(dunce::canonicalize)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let remove_ca_certs = remove_ca_certs
+                    .iter()
+                    .map(|cert| dunce::canonicalize(cert).unwrap_or_else(|_| cert.clone()))
+                    .collect::<Vec<_>>();
+                if icon.is_some() || clear_icon {
+                    instance::set_icon(&instances_dir, &name, icon.as_deref())?;
+                }
+                let instance = instance::update(&instances_dir, &name, |instance| {
+                    if let Some(description) = description {
+                        instance.description = Some(description).filter(|d| !d.is_empty());
+                    }
+                    if group.is_some() || no_group {
+                        instance.group = group;
+                    }
+                    for tag in &add_tags {
+                        instance.add_tag(tag);
+                    }
+                    for tag in &remove_tags {
+                        instance.remove_tag(tag);
+                    }
+                    if let Some(backup_worlds) = backup_worlds {
+                        instance.backup_worlds = backup_worlds;
+                    }
+                    if let Some(keep) = backup_keep {
+                        instance.backup_keep = Some(keep).filter(|keep| *keep > 0);
+                    }
+                    if let Some(account) = account {
+                        instance.account = Some(account).filter(|account| !account.is_empty());
+                    }
+                    for name in &unset_env {
+                        instance.env.remove(name);
+                    }
+                    instance.env.extend(set_env);
+                    instance
+                        .ca_certs
+                        .retain(|cert| !remove_ca_certs.contains(cert));
+                    if channel.is_some() || pin {
+                        instance.channel = channel;
+                    }
+                    if let Some(opt_out) = telemetry_opt_out {
+                        instance.telemetry_opt_out = Some(opt_out);
+                    }
+                    for cert in add_ca_certs {
+                        if !instance.ca_certs.contains(&cert) {
+                            instance.ca_certs.push(cert);
+                        }
+                    }
+                    if let Some(language) = &language {
+                        instance.language = language.clone();
+                    }
+                })?;
+                if matches!(language, Some(Some(_))) {
+                    language::reset(&instance.game_dir(&instances_dir)).await?;
+                }
+                print_output(cli.json, &instance, |instance| {
+                    println!("Updated instance {}", instance.name)
+                })
+            }
+            InstanceCommand::Shortcut { name, dest, icon } => {
+                let instance = instance::load(&instances_dir, &name)?;
+                let icon = icon.or_else(|| instance.icon_path(&instances_dir));
+                let kind = ShortcutKind::native();
+                let dest = dest
+                    .or_else(|| kind.default_dir())
+                    .ok_or_else(|| anyhow!("Could not determine where to put the shortcut, pass --dest"))?;
+                let executable = std::env::current_exe()?;
+                // shortcuts outlive the shell they were created from, so pin absolute paths
+                let work_dir = dunce::canonicalize(&work_dir)?;
+
+                let shortcut = Shortcut {
+                    instance: &instance,
+                    work_dir: &work_dir,
+                    executable: &executable,
+                    icon: icon.as_deref(),
+                    limits: config.timeouts.helper(),
+                };
+                let path = shortcut.create(kind, &dest).await?;
+                print_output(cli.json, &path, |path| {
+                    println!("Created shortcut {}", path.display())
+                })
+            }
+            InstanceCommand::Steam {
+                name,
+                steam_user,
+                icon,
+                grid_portrait,
+                grid_wide,
+                grid_hero,
+                grid_logo,
+            } => {
+                let instance = instance::load(&instances_dir, &name)?;
+                let steam_dir = steam::find_steam_dir()
+                    .ok_or_else(|| anyhow!("Could not find a Steam installation"))?;
+                let user_dirs = match steam_user {
+                    Some(user) => vec![steam_dir.join("userdata").join(user)],
+                    None => steam::user_dirs(&steam_dir)?,
+                };
+                if user_dirs.is_empty() {
+                    return Err(anyhow!("No Steam accounts found in {}", steam_dir.display()));
+                }
+
+                let mut shortcut = steam::SteamShortcut::for_instance(
+                    &instance,
+                    &dunce::canonicalize(&work_dir)?,
+                    &std::env::current_exe()?,
+                );
+                shortcut.icon = icon.or_else(|| instance.icon_path(&instances_dir));
+                let artwork = steam::GridArtwork {
+                    portrait: grid_portrait,
+                    wide: grid_wide,
+                    hero: grid_hero,
+                    logo: grid_logo,
+                };
+
+                let mut app_id = 0;
+                for user_dir in &user_dirs {
+                    app_id = steam::add_shortcut(user_dir, &shortcut)?;
+                    steam::install_artwork(user_dir, app_id, &artwork)?;
+                }
+                print_output(cli.json, &app_id, |app_id| {
+                    println!("Added {} to Steam (app id {}), restart Steam to see it", name, app_id)
+                })
+            }
+            InstanceCommand::ImportVanilla { minecraft_dir } => {
+                let minecraft_dir = vanilla_dir(minecraft_dir)?;
+                // only needed for the latest-release/latest-snapshot profiles
+                let latest = config
+                    .http_provider(client.clone())
+                    .version_manifest()
+                    .await
+                    .ok()
+                    .map(|manifest| manifest.latest);
+                let report = vanilla::import_profiles(
+                    &minecraft_dir,
+                    &cache_dir,
+                    &instances_dir,
+                    latest.as_ref(),
+                )?;
+                print_output(cli.json, &report, |report| {
+                    for instance in &report.imported {
+                        println!("Imported {} ({})", instance.name, instance.version);
+                    }
+                    for (name, reason) in &report.skipped {
+                        println!("Skipped {}: {}", name, reason);
+                    }
+                })
+            }
+            InstanceCommand::Freeze { name, out, no_java } => {
+                let instance = instance::load(&instances_dir, &name)?;
+                let options = instance.launch_options(&config);
+                let report = freeze::freeze(&instance, options, &out, !no_java).await?;
+                print_output(cli.json, &report, |report| {
+                    for external in &report.external {
+                        println!("still refers to {}, it is outside the instance", external);
+                    }
+                    println!(
+                        "Froze {} into {} ({} files, {} MiB), start it with {}",
+                        name,
+                        report.dir.display(),
+                        report.files,
+                        report.bytes / 1024 / 1024,
+                        report.script.display()
+                    );
+                })
+            }
+            InstanceCommand::Adopt { name } => {
+                let api = config.curseforge_api();
+                let report = adopt::adopt(&client, api.as_ref(), &instances_dir, &name).await?;
+                print_output(cli.json, &report, |report| {
+                    for adopted in &report.adopted {
+                        match &adopted.source {
+                            Some(adopt::ContentSource::Modrinth { project_id, version_number, .. }) => {
+                                println!("{}: Modrinth project {} {}", adopted.path, project_id, version_number)
+                            }
+                            Some(adopt::ContentSource::CurseForge { project_id, display_name, .. }) => {
+                                println!("{}: CurseForge project {} {}", adopted.path, project_id, display_name)
+                            }
+                            Some(source) => println!("{}: {}", adopted.path, source.version()),
+                            None => println!("{}: unknown", adopted.path),
+                        }
+                    }
+                    for removed in &report.removed {
+                        println!("{}: removed", removed);
+                    }
+                    if report.adopted.is_empty() && report.removed.is_empty() {
+                        println!("Nothing new in {}", name);
+                    }
+                    if api.is_none() {
+                        println!("Set curseforge_api_key to also look files up on CurseForge");
+                    }
+                })
+            }
+            InstanceCommand::Updates { name } => {
+                let api = config.curseforge_api();
+                let updates = adopt::check_updates(&client, api.as_ref(), &instances_dir, &name).await?;
+                print_output(cli.json, &updates, |updates| {
+                    for update in updates {
+                        println!("{}: {} -> {}", update.path, update.current.version(), update.latest.version());
+                    }
+                    if updates.is_empty() {
+                        println!("Everything added to {} by hand is up to date", name);
+                    }
+                })
+            }
+            InstanceCommand::ExportVanilla {
+                name,
+                minecraft_dir,
+            } => {
+                let minecraft_dir = vanilla_dir(minecraft_dir)?;
+                let instance = instance::load(&instances_dir, &name)?;
+                let key =
+                    vanilla::export_instance(&minecraft_dir, &cache_dir, &instances_dir, &instance)?;
+                print_output(cli.json, &key, |_| {
+                    println!("Added {} to {}", name, minecraft_dir.join(vanilla::PROFILES_FILE).display())
+                })
+            }
+            InstanceCommand::Export {
+                name,
+                output,
+                bundle_unknown_mods,
+                sign,
+            } => {
+                // a missing or broken key should fail before anything is exported
+                let key = sign.as_deref().map(SigningKey::load).transpose()?;
+                let output = output.unwrap_or_else(|| PathBuf::from(format!("{}.zip", name)));
+                let report = portable::export(
+                    &client,
+                    &instances_dir,
+                    &cache_dir,
+                    &name,
+                    &output,
+                    bundle_unknown_mods,
+                )
+                .await?;
+                if let Some(key) = &key {
+                    signing::sign_archive(key, &report.path).await?;
+                }
+                print_output(cli.json, &report, |report| {
+                    for file_name in &report.skipped {
+                        println!("Left out {}, it is not on Modrinth", file_name);
+                    }
+                    println!(
+                        "Exported {} to {} ({} mods referenced, {} bundled)",
+                        name,
+                        report.path.display(),
+                        report.referenced.len(),
+                        report.bundled.len()
+                    );
+                    if !report.skipped.is_empty() {
+                        println!("Pass --bundle-unknown-mods to include the mods left out");
+                    }
+                })
+            }
+            InstanceCommand::Publish {
+                name,
+                out,
+                bundle_unknown_mods,
+                rollout,
+                cohorts,
+            } => {
+                let rollout = (rollout.is_some() || !cohorts.is_empty()).then(|| Rollout {
+                    percent: rollout.unwrap_or(0),
+                    cohorts,
+                });
+                let report = update_channel::publish(
+                    &client,
+                    &instances_dir,
+                    &cache_dir,
+                    &name,
+                    &out,
+                    bundle_unknown_mods,
+                    rollout,
+                )
+                .await?;
+                print_output(cli.json, &report, |report| {
+                    for file_name in &report.skipped {
+                        println!("Left out {}, it is not on Modrinth", file_name);
+                    }
+                    match report.published {
+                        true => println!(
+                            "Published release {} of {} to {} ({} new files), upload the directory as-is",
+                            report.release,
+                            name,
+                            out.display(),
+                            report.objects_written
+                        ),
+                        false => println!("Nothing changed since release {}", report.release),
+                    }
+                })
+            }
+            InstanceCommand::Rollout {
+                channel,
+                release,
+                percent,
+                cohorts,
+                everyone,
+            } => {
+                if !everyone && percent.is_none() && cohorts.is_empty() {
+                    return Err(anyhow!("Pass --percent, --cohort or --everyone"));
+                }
+                let rollout = (!everyone).then(|| Rollout {
+                    percent: percent.unwrap_or(0),
+                    cohorts,
+                });
+                let entry = update_channel::set_rollout(&channel, release, rollout).await?;
+                print_output(cli.json, &entry, |entry| match &entry.rollout {
+                    Some(rollout) if rollout.cohorts.is_empty() => {
+                        println!("Release {} goes to {}% of followers", entry.release, rollout.percent)
+                    }
+                    Some(rollout) => println!(
+                        "Release {} goes to {}% of followers and the {} cohorts",
+                        entry.release,
+                        rollout.percent,
+                        rollout.cohorts.join(", ")
+                    ),
+                    None => println!("Release {} goes to every follower", entry.release),
+                })
+            }
+            InstanceCommand::Follow { url, name } => {
+                let http = config.http_provider(client.clone());
+                let instance = update_channel::follow(
+                    &http,
+                    &instances_dir,
+                    &cache_dir,
+                    &url,
+                    name.as_deref(),
+                    &config.update_cohorts,
+                    config.download_concurrency,
+                )
+                .await?;
+                let progress = Progress::new(style, format!("Installing {}", instance.version));
+                install_version(
+                    &http,
+                    &cache_dir,
+                    &instance.version,
+                    config.download_concurrency,
+                    config.verify_policy(),
+                    &|done, total| progress.update(done, total),
+                )
+                .await
+                .with_context(|| format!("Created {}, but could not install {}", instance.name, instance.version))?;
+                print_output(cli.json, &instance, |instance| {
+                    println!("Created {} ({}) from {}", instance.name, instance.version, url)
+                })
+            }
+            InstanceCommand::Update { name } => {
+                let http = config.http_provider(client.clone());
+                let update = update_channel::update(
+                    &http,
+                    &instances_dir,
+                    &cache_dir,
+                    &name,
+                    &config.update_cohorts,
+                    config.download_concurrency,
+                )
+                .await?;
+                if let Some(update) = &update {
+                    let progress = Progress::new(style, format!("Installing {}", update.game_version));
+                    install_version(
+                        &http,
+                        &cache_dir,
+                        &update.game_version,
+                        config.download_concurrency,
+                        config.verify_policy(),
+                        &|done, total| progress.update(done, total),
+                    )
+                    .await?;
+                }
+                print_output(cli.json, &update, |update| match update {
+                    Some(update) => println!(
+                        "Updated {} to release {} ({})",
+                        update.instance, update.to, update.game_version
+                    ),
+                    None => println!("{} is on the latest release", name),
+                })
+            }
+            InstanceCommand::Import { archive, name } => {
+                signing::check_archive(&archive, &config.trust_policy())?;
+                let http = config.http_provider(client.clone());
+                let instance = portable::import(
+                    &client,
+                    &instances_dir,
+                    &cache_dir,
+                    &archive,
+                    name.as_deref(),
+                    config.download_concurrency,
+                    config.batched_writes,
+                )
+                .await?;
+                let progress = Progress::new(style, format!("Installing {}", instance.version));
+                install_version(
+                    &http,
+                    &cache_dir,
+                    &instance.version,
+                    config.download_concurrency,
+                    config.verify_policy(),
+                    &|done, total| progress.update(done, total),
+                )
+                .await
+                .with_context(|| format!("Imported {}, but could not install {}", instance.name, instance.version))?;
+                print_output(cli.json, &instance, |instance| {
+                    println!("Imported {} ({})", instance.name, instance.version)
+                })
+            }
+            InstanceCommand::ImportCurseforge { pack, name } => {
+                let api = config.curseforge_api().ok_or_else(|| {
+                    anyhow!("No CurseForge API key configured, set curseforge_api_key in launcher.toml")
+                })?;
+                let (instance, imported) = curseforge::import_instance(
+                    &instances_dir,
+                    &pack,
+                    name.as_deref(),
+                    &api,
+                    &client,
+                    config.batched_writes,
+                )
+                .await?;
+
+                let http = config.http_provider(client.clone());
+                let game_version = &imported.minecraft_version;
+                let (id, build) = async {
+                    let network = http.probe().await;
+                    let (id, build) = match &imported.loader {
+                        None => (game_version.clone(), None),
+                        Some(loader) if matches!(loader.kind, LoaderKind::Fabric | LoaderKind::Quilt) => {
+                            let id = fabric::install(
+                                &client,
+                                &cache_dir,
+                                loader.kind,
+                                game_version,
+                                Some(&loader.version),
+                                network,
+                            )
+                            .await?;
+                            (id, None)
+                        }
+                        Some(loader) => {
+                            let build = forge::select_build(
+                                &client,
+                                &cache_dir,
+                                loader.kind,
+                                game_version,
+                                Some(&loader.version),
+                                network,
+                            )
+                            .await?;
+                            let java_path = config.java_path.clone().unwrap_or_else(default_java_path);
+                            let id = forge::install(
+                                &http,
+                                &http,
+                                &cache_dir,
+                                &build,
+                                &ProcessorEnv {
+                                    java_path: &java_path,
+                                    external: &dedup::detect_installs(),
+                                    limits: config.timeouts.helper(),
+                                    on_progress: &print_installer_progress,
+                                },
+                                config.download_concurrency,
+                                config.verify_policy(),
+                            )
+                            .await?;
+                            (id, Some(build))
+                        }
+                    };
+                    let progress = Progress::new(style, format!("Installing {}", id));
+                    install_version(
+                        &http,
+                        &cache_dir,
+                        &id,
+                        config.download_concurrency,
+                        config.verify_policy(),
+                        &|done, total| progress.update(done, total),
+                    )
+                    .await?;
+                    anyhow::Ok((id, build))
+                }
+                .await
+                .with_context(|| {
+                    let version = imported.loader.as_ref().map_or(game_version.clone(), ToString::to_string);
+                    format!("Imported {}, but could not install {}", instance.name, version)
+                })?;
+
+                let (instance, _) = instance::update_locked(&instances_dir, &instance.name, |instance, lockfile| {
+                    instance.version = id;
+                    instance.loader = imported.loader.as_ref().map(|loader| loader.kind);
+                    lockfile.loader = build;
+                })?;
+                print_output(cli.json, &instance, |instance| {
+                    println!(
+                        "Imported {} ({}) from {} {}",
+                        instance.name, instance.version, imported.name, imported.version
+                    )
+                })
+            }
+        },
+        Command::Login { client_id } => {
+            let client_id = client_id
+                .or_else(|| config.client_id.clone())
+                .ok_or_else(|| anyhow!("No client id configured, pass --client-id or set client_id in launcher.toml"))?;
+            let json = cli.json;
+            let account = auth::login(&client, &client_id, |code| {
+                if json {
+                    eprintln!("{}", code.message);
+                } else {
+                    println!("{}", code.message);
+                }
+            })
+            .await?;
+
+            let mut store = AccountStore::load(&work_dir)?;
+            store.upsert(account.clone());
+            store.save(&work_dir)?;
+
+            print_output(cli.json, &account.username, |username| {
+                println!("Logged in as {}", username);
+                for restriction in account.restrictions() {
+                    println!("{}", restriction);
+                }
+            })
+        }
+        Command::Verify { version } => {
+            let report = verify_version(&cache_dir, &version, mirror).await?;
+            print_output(cli.json, &report, |report| {
+                for path in &report.missing {
+                    println!("missing: {}", path.display());
+                }
+                for path in &report.corrupt {
+                    println!("corrupt: {}", path.display());
+                }
+                println!(
+                    "Checked {} files, {} missing, {} corrupt",
+                    report.checked,
+                    report.missing.len(),
+                    report.corrupt.len()
+                );
+            })?;
+
+            if report.is_ok() {
+                Ok(())
+            } else {
+                Err(anyhow!("Verification of {} failed", version))
+            }
+        }
+        Command::InstallFabric {
+            game_version,
+            loader_version,
+            loader,
+            instance,
+        } => {
+            let http = config.http_provider(client.clone());
+            let network = http.probe().await;
+            let id = fabric::install(
+                &client,
+                &cache_dir,
+                loader,
+                &game_version,
+                loader_version.as_deref(),
+                network,
+            )
+            .await?;
+            if let Some(name) = &instance {
+                check_loader_switch(&cache_dir, &instances_dir, name, &id, loader)?;
+            }
+            // the loader's libraries, intermediary included, so the next install works offline
+            let progress = Progress::new(style, format!("Installing {}", id));
+            install_version(
+                &http,
+                &cache_dir,
+                &id,
+                config.download_concurrency,
+                config.verify_policy(),
+                &|done, total| progress.update(done, total),
+            )
+            .await?;
+
+            if let Some(name) = instance {
+                instance::update(&instances_dir, &name, |instance| {
+                    instance.version = id.clone();
+                    instance.loader = Some(loader);
+                })?;
+            }
+
+            print_output(cli.json, &id, |id| println!("Installed {}", id))
+        }
+        Command::InstallForge {
+            game_version,
+            build,
+            loader,
+            instance,
+        } => {
+            let http = config.http_provider(client.clone());
+            let network = http.probe().await;
+            let build = forge::select_build(
+                &client,
+                &cache_dir,
+                loader,
+                &game_version,
+                build.as_deref(),
+                network,
+            )
+            .await?;
+            let java_path = config.java_path.clone().unwrap_or_else(default_java_path);
+            let id = forge::install(
+                &http,
+                &http,
+                &cache_dir,
+                &build,
+                &ProcessorEnv {
+                    java_path: &java_path,
+                    external: &dedup::detect_installs(),
+                    limits: config.timeouts.helper(),
+                    on_progress: &print_installer_progress,
+                },
+                config.download_concurrency,
+                config.verify_policy(),
+            )
+            .await?;
+
+            if let Some(name) = instance {
+                check_loader_switch(&cache_dir, &instances_dir, &name, &id, loader)?;
+                instance::update_locked(&instances_dir, &name, |instance, lockfile| {
+                    instance.version = id.clone();
+                    instance.loader = Some(loader);
+                    lockfile.loader = Some(build.clone());
+                })?;
+            }
+
+            let installed = InstalledBuild { id, build };
+            print_output(cli.json, &installed, |installed| {
+                let promotion = match installed.build.promotion {
+                    Promotion::Requested => "",
+                    Promotion::Recommended => " (recommended build)",
+                    Promotion::Latest => " (latest build, none is recommended)",
+                };
+                println!("Installed {}{}", installed.id, promotion)
+            })
+        }
+        Command::Search { query, limit } => {
+            let hits = search::search(&cache_dir, &instances_dir, &query, limit)?;
+            print_output(cli.json, &hits, |hits| {
+                for hit in hits {
+                    let entry = &hit.entry;
+                    match (&entry.instance, entry.kind) {
+                        (Some(instance), _) if entry.id == entry.name => {
+                            println!("mod\t{} in {}", entry.name, instance)
+                        }
+                        (Some(instance), _) => {
+                            println!("mod\t{} ({}) in {}", entry.name, entry.id, instance)
+                        }
+                        (None, search::SearchKind::Version) if !entry.installed => {
+                            println!("version\t{} (not installed)", entry.id)
+                        }
+                        (None, search::SearchKind::Version) => println!("version\t{}", entry.id),
+                        (None, _) => println!("instance\t{}", entry.name),
+                    }
+                }
+            })
+        }
+        Command::Tasks { command } => {
+            let mut queue = TaskQueue::open(&work_dir)?;
+            let queued = match command {
+                TasksCommand::List => None,
+                TasksCommand::Install { version } => Some(TaskKind::InstallVersion { version }),
+                TasksCommand::InstallFabric {
+                    game_version,
+                    loader_version,
+                    instance,
+                } => Some(TaskKind::InstallFabric {
+                    game_version,
+                    loader_version,
+                    instance,
+                }),
+                TasksCommand::Gc { prune_versions } => Some(TaskKind::CacheGc { prune_versions }),
+                TasksCommand::Pause { id } => {
+                    queue.pause(id)?;
+                    None
+                }
+                TasksCommand::Resume { id } => {
+                    queue.resume(id)?;
+                    None
+                }
+                TasksCommand::Cancel { id } => {
+                    queue.cancel(id)?;
+                    None
+                }
+                TasksCommand::Run { parallelism } => {
+                    let context = TaskContext {
+                        client: client.clone(),
+                        cache_dir: cache_dir.clone(),
+                        instances_dir: instances_dir.clone(),
+                        http: config.http_provider(client.clone()),
+                        download_concurrency: config.download_concurrency,
+                        verify_policy: config.verify_policy(),
+                    };
+                    tasks::run(
+                        &mut queue,
+                        context,
+                        parallelism.unwrap_or(config.task_parallelism),
+                    )
+                    .await?;
+                    None
+                }
+                TasksCommand::Compact => {
+                    let removed = queue.compact()?;
+                    if !cli.json {
+                        println!("Removed {} finished tasks", removed);
+                    }
+                    None
+                }
+            };
+            if let Some(kind) = queued {
+                queue.enqueue(kind)?;
+            }
+
+            let tasks = queue.tasks().collect::<Vec<_>>();
+            print_output(cli.json, &tasks, |tasks| {
+                for task in tasks {
+                    let mut progress = if task.total > 0 {
+                        format!(
+                            " {}/{} MiB",
+                            task.done / (1024 * 1024),
+                            task.total.div_ceil(1024 * 1024)
+                        )
+                    } else {
+                        String::new()
+                    };
+                    if task.status == TaskStatus::Running {
+                        if let Some(eta) = task.eta_seconds {
+                            progress.push_str(&format!(
+                                " at {:.1} MiB/s, {}:{:02} left",
+                                task.bytes_per_second as f64 / (1024.0 * 1024.0),
+                                eta / 60,
+                                eta % 60
+                            ));
+                        }
+                    }
+                    println!("{}\t{:?}{}\t{:?}", task.id, task.status, progress, task.kind);
+                    if let Some(error) = &task.error {
+                        println!("\t{}", error);
+                    }
+                }
+            })
+        }
+        Command::Cache { command } => match command {
+            CacheCommand::Path => print_output(cli.json, &cache_dir, |cache_dir| {
+                println!("{}", cache_dir.display())
+            }),
+            CacheCommand::Gc {
+                prune_versions,
+                dry_run,
+            } => {
+                let keep = if prune_versions {
+                    Some(cache::versions_in_use(&instances_dir)?)
+                } else {
+                    None
+                };
+                let report = cache::gc(&cache_dir, keep.as_deref(), dry_run).await?;
+                print_output(cli.json, &report, |report| {
+                    for id in &report.removed_versions {
+                        println!("removed version {}", id);
+                    }
+                    println!(
+                        "{} {} files ({} MiB)",
+                        if dry_run { "Would remove" } else { "Removed" },
+                        report.removed_files.len(),
+                        report.freed_bytes / (1024 * 1024)
+                    );
+                })
+            }
+            CacheCommand::Export { out, files } => {
+                let report = mirror::export(&cache_dir, &out, files).await?;
+                print_output(cli.json, &report, |report| {
+                    for id in &report.skipped {
+                        println!("skipped {}, it is not in the version manifest", id);
+                    }
+                    for url in &report.missing {
+                        println!("missing {}", url);
+                    }
+                    println!(
+                        "Exported {} versions, {} files ({} MiB) to {}",
+                        report.versions.len(),
+                        report.files,
+                        report.bytes / (1024 * 1024),
+                        out.display()
+                    );
+                })
+            }
+            CacheCommand::Sources => {
+                let sources = download::source_stats(&cache_dir);
+                print_output(cli.json, &sources, |sources| {
+                    for (host, source) in sources {
+                        println!(
+                            "{}: {} files, {} MiB declared, {} MiB served, {} with the wrong size",
+                            host,
+                            source.files,
+                            source.declared_bytes / 1024 / 1024,
+                            source.actual_bytes / 1024 / 1024,
+                            source.misdeclared_files
+                        );
+                    }
+                })
+            }
+            CacheCommand::Mirrors { reprobe } => {
+                let Some(selector) = mirror::selector(mirror, &config.mirrors, &cache_dir) else {
+                    return Err(anyhow!("No mirrors are configured, add them to mirrors in launcher.toml"));
+                };
+                if reprobe {
+                    mirror::forget_stats(&cache_dir)?;
+                }
+                let stats = selector.stats(&client).await;
+                print_output(cli.json, &stats, |stats| {
+                    for (mirror, stats) in stats {
+                        match (stats.rtt_ms, stats.bytes_per_sec) {
+                            (Some(rtt_ms), Some(bytes_per_sec)) => println!(
+                                "{}: {} ms, {} KiB/s",
+                                mirror,
+                                rtt_ms,
+                                bytes_per_sec / 1024
+                            ),
+                            _ => println!("{}: unreachable", mirror),
+                        }
+                    }
+                })
+            }
+        },
+        Command::Dedup { command } => {
+            let report = dedup::scan(&cache_dir, dedup::detect_installs())?;
+            match command {
+                DedupCommand::Scan => print_output(cli.json, &report, |report| {
+                    for install in &report.installs {
+                        println!("found {:?} install at {}", install.launcher, install.root.display());
+                    }
+                    println!(
+                        "{} duplicated files, {} MiB reclaimable",
+                        report.files.len(),
+                        report.reclaimable_bytes / 1024 / 1024
+                    );
+                    for conflict in &report.conflicts {
+                        println!("skipping {}, the installs have different files there", conflict.display());
+                    }
+                }),
+                DedupCommand::Migrate { hardlink } => {
+                    let mode = if hardlink {
+                        dedup::LinkMode::Hardlink
+                    } else {
+                        dedup::LinkMode::Symlink
+                    };
+                    let reclaimed = dedup::migrate(&report, mode)?;
+                    print_output(cli.json, &reclaimed, |reclaimed| {
+                        println!("Reclaimed {} MiB", reclaimed / 1024 / 1024)
+                    })
+                }
+            }
+        }
+        Command::Session {
+            command: SessionCommand::DumpLog { id, out },
+        } => {
+            let output = sessions::read_output(&work_dir, &id)?;
+            match out {
+                Some(out) => {
+                    std::fs::write(&out, &output).with_context(|| format!("Could not write {}", out.display()))?;
+                    print_output(cli.json, &out, |out| println!("Wrote the output of {} to {}", id, out.display()))
+                }
+                None => print_output(cli.json, &output, |output| print!("{}", output)),
+            }
+        }
+        Command::Issues { command } => match command {
+            IssuesCommand::Update => {
+                let url = config
+                    .known_issues_url
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("Set known_issues_url in launcher.toml to download known issues"))?;
+                let ruleset = known_issues::update(&client, &cache_dir, url).await?;
+                print_output(cli.json, &ruleset, |ruleset| {
+                    println!("Known issues version {}, {} rules", ruleset.version, ruleset.rules.len())
+                })
+            }
+            IssuesCommand::Check { log, exit_code } => {
+                let text = std::fs::read_to_string(&log).with_context(|| format!("Could not read {}", log.display()))?;
+                let ruleset = known_issues::Ruleset::load(&cache_dir);
+                let issues = ruleset.matches(exit_code, &text, &known_issues::system_language());
+                print_output(cli.json, &issues, |issues| {
+                    for issue in issues {
+                        println!("{}: {}", issue.id, issue.guidance);
+                    }
+                    if issues.is_empty() {
+                        println!("No known issue matches {}", log.display());
+                    }
+                })
+            }
+        },
+        Command::Disk { command } => match command {
+            DiskCommand::Usage { cleanup } => {
+                let report = disk::analyze(&work_dir, &cache_dir, &instances_dir, &cleanup.options()).await?;
+                print_output(cli.json, &report, |report| {
+                    for (category, bytes) in &report.totals {
+                        println!("{:?}\t{} MiB", category, bytes / 1024 / 1024);
+                    }
+                    println!("Total\t{} MiB", report.total_bytes / 1024 / 1024);
+                    println!();
+                    for item in report.items.iter().take(20) {
+                        println!("{} MiB\t{:?}\t{}", item.bytes / 1024 / 1024, item.category, item.name);
+                    }
+                    for action in &report.plan {
+                        println!("{} MiB reclaimable: {}", action.bytes() / 1024 / 1024, describe_cleanup(action));
+                    }
+                    if report.plan.is_empty() {
+                        println!("Nothing to clean up");
+                    }
+                })
+            }
+            DiskCommand::Clean { cleanup } => {
+                let report = disk::analyze(&work_dir, &cache_dir, &instances_dir, &cleanup.options()).await?;
+                let cleaned = disk::clean(&cache_dir, &instances_dir, &report.plan).await?;
+                print_output(cli.json, &cleaned, |cleaned| {
+                    for action in &cleaned.done {
+                        println!("{}", describe_cleanup(action));
+                    }
+                    println!("Freed {} MiB", cleaned.freed_bytes / 1024 / 1024);
+                })
+            }
+        },
+        Command::Bundle { command } => match command {
+            BundleCommand::Create {
+                target,
+                instance,
+                out,
+                java,
+                sign,
+            } => {
+                let key = sign.as_deref().map(SigningKey::load).transpose()?;
+                let http = config.http_provider(client.clone());
+                let (source, version) = match instance {
+                    true => {
+                        let version = instance::load(&instances_dir, &target)?.version;
+                        let source = BundleSource::Instance {
+                            instances_dir: &instances_dir,
+                            name: &target,
+                        };
+                        (source, version)
+                    }
+                    false => (BundleSource::Version(&target), target.clone()),
+                };
+                let progress = Progress::new(style, format!("Installing {}", version));
+                install_version(
+                    &http,
+                    &cache_dir,
+                    &version,
+                    config.download_concurrency,
+                    config.verify_policy(),
+                    &|done, total| progress.update(done, total),
+                )
+                .await?;
+                let report = bundle::create(&http, &cache_dir, source, &out, java).await?;
+                if let Some(key) = &key {
+                    signing::sign_archive(key, &report.path).await?;
+                }
+                print_output(cli.json, &report, |report| {
+                    println!(
+                        "Bundled {} into {}: {} files, {} MiB",
+                        report.version,
+                        report.path.display(),
+                        report.files,
+                        report.bytes / 1024 / 1024
+                    );
+                    for runtime in &report.runtimes {
+                        println!("With Java runtime {}", runtime);
+                    }
+                })
+            }
+            BundleCommand::Install { bundle, name } => {
+                signing::check_archive(&bundle, &config.trust_policy())?;
+                let installed =
+                    bundle::install(&cache_dir, &instances_dir, &bundle, name.as_deref()).await?;
+                print_output(cli.json, &installed, |installed| {
+                    match &installed.instance {
+                        Some(name) => {
+                            println!("Installed instance {} ({})", name, installed.version)
+                        }
+                        None => println!("Installed {}", installed.version),
+                    }
+                    println!(
+                        "{} files written, {} verified",
+                        installed.written, installed.verified
+                    );
+                    if let Some(java_path) = &installed.java_path {
+                        println!("Java: {}", java_path.display());
+                    }
+                })
+            }
+        },
+        Command::Keys { command } => match command {
+            KeysCommand::Generate { out } => {
+                if out.exists() {
+                    return Err(anyhow!("{} already exists", out.display()));
+                }
+                let key = SigningKey::generate()?;
+                key.save(&out).await?;
+                print_output(cli.json, &key.public_key(), |public_key| {
+                    println!("Saved the key to {}", out.display());
+                    println!("Public key for trusted_keys: {}", public_key);
+                })
+            }
+            KeysCommand::Verify { archive } => {
+                // every key counts as trusted, this only says who signed it
+                let signature = std::fs::read_to_string(signing::signature_path(&archive))
+                    .ok()
+                    .and_then(|json| serde_json::from_str::<signing::Signature>(&json).ok())
+                    .ok_or_else(|| anyhow!("{} has no signature", archive.display()))?;
+                let policy = signing::TrustPolicy {
+                    trusted_keys: vec![signature.key],
+                    required: true,
+                };
+                let key = signing::check_archive(&archive, &policy)?;
+                let trusted = config.trusted_keys.iter().any(|trusted| Some(trusted) == key.as_ref());
+                print_output(cli.json, &key, |key| {
+                    let key = key.as_deref().unwrap_or_default();
+                    match trusted {
+                        true => println!("Signed by {}, a trusted key", key),
+                        false => println!("Signed by {}, which is not in trusted_keys", key),
+                    }
+                })
+            }
+        },
+        Command::Skin { command } => {
+            let account = logged_in_account(&client, &config, &work_dir).await?;
+            let profile = match command {
+                SkinCommand::Show => skins::profile(&client, &account).await?,
+                SkinCommand::Set { source, variant } => {
+                    if source.starts_with("http://") || source.starts_with("https://") {
+                        skins::set_skin_url(&client, &account, &source, variant).await?
+                    } else {
+                        let png = std::fs::read(&source)?;
+                        skins::upload_skin(&client, &account, png, variant).await?
+                    }
+                }
+                SkinCommand::Reset => skins::reset_skin(&client, &account).await?,
+                SkinCommand::Cape { id: Some(id), .. } => {
+                    skins::show_cape(&client, &account, &id).await?
+                }
+                SkinCommand::Cape { id: None, .. } => skins::hide_cape(&client, &account).await?,
+            };
+
+            print_output(cli.json, &profile, |profile| {
+                match profile.active_skin() {
+                    Some(skin) => println!(
+                        "{} is wearing {} ({})",
+                        profile.name,
+                        skin.alias.as_deref().unwrap_or(&skin.url),
+                        skin.variant
+                    ),
+                    None => println!("{} is wearing the default skin", profile.name),
+                }
+                for cape in &profile.capes {
+                    println!(
+                        "cape {} ({}){}",
+                        cape.id,
+                        cape.alias.as_deref().unwrap_or("unnamed"),
+                        if cape.state == "ACTIVE" { ", worn" } else { "" }
+                    );
+                }
+            })
+        }
+        Command::Sessions => {
+            let sessions = sessions::list(&work_dir)?;
+            print_output(cli.json, &sessions, |sessions| {
+                for session in sessions {
+                    let account = session
+                        .account
+                        .as_ref()
+                        .map_or("offline", |account| account.username.as_str());
+                    let game_pid = session
+                        .game_pid
+                        .map_or(String::from("-"), |pid| pid.to_string());
+                    println!(
+                        "{}\t{}\t{}\t{}\t{}",
+                        session.id,
+                        session.instance.as_deref().unwrap_or(&session.version),
+                        account,
+                        game_pid,
+                        session.game_dir.display()
+                    );
+                }
+                if sessions.is_empty() {
+                    println!("No games running");
+                }
+            })
+        }
+        Command::Daemon { port } => {
+            let (listener, info) = daemon::bind(port).await?;
+            // the token only goes to stdout for a frontend that started the daemon itself
+            print_output(cli.json, &info, |info| {
+                println!("Listening on 127.0.0.1:{}", info.port);
+                println!("Connection details in {}", daemon::DaemonInfo::path(&work_dir).display());
+            })?;
+            daemon::serve(listener, info, config).await
+        }
+        Command::Java { command } => match command {
+            JavaCommand::List { refresh } => {
+                let installs = java::discover(&cache_dir, refresh, config.timeouts.probe()).await?;
+                print_output(cli.json, &installs, |installs| {
+                    for install in installs {
+                        println!("Java {}\t{}\t{}", install.major, install.arch, install.path.display());
+                    }
+                    if installs.is_empty() {
+                        println!("No Java installs found, install one or pass --java");
+                    }
+                })
+            }
+        },
+        Command::Saves { instance, command } => {
+            let game_dir = match instance {
+                Some(name) => instance::load(&instances_dir, &name)?.game_dir(&instances_dir),
+                None => work_dir.join(".minecraft"),
+            };
+            match command {
+                SavesCommand::List => {
+                    let worlds = saves::list_worlds(&game_dir)?;
+                    print_output(cli.json, &worlds, |worlds| {
+                        for world in worlds {
+                            println!(
+                                "{} ({}){}",
+                                world.folder,
+                                world.name.as_deref().unwrap_or("unreadable level.dat"),
+                                world
+                                    .version
+                                    .as_ref()
+                                    .map(|version| format!(", last saved in {}", version))
+                                    .unwrap_or_default()
+                            );
+                        }
+                    })
+                }
+                SavesCommand::Backup { world } => {
+                    let backup = saves::backup(&game_dir, &world)?;
+                    print_output(cli.json, &backup, |backup| {
+                        println!("Backed up {} to {}", backup.world, backup.path.display())
+                    })
+                }
+                SavesCommand::Backups { world } => {
+                    let backups = saves::list_backups(&game_dir, world.as_deref())?;
+                    print_output(cli.json, &backups, |backups| {
+                        for backup in backups {
+                            println!(
+                                "{} ({} KiB)",
+                                backup.path.display(),
+                                backup.size / 1024
+                            );
+                        }
+                    })
+                }
+                SavesCommand::Restore { backup } => {
+                    let world = saves::restore(&game_dir, &backup)?;
+                    print_output(cli.json, &world, |world| {
+                        println!("Restored {} from {}", world, backup.display())
+                    })
+                }
+                SavesCommand::Prune {
+                    world,
+                    keep,
+                    max_age_days,
+                } => {
+                    if keep.is_none() && max_age_days.is_none() {
+                        return Err(anyhow!("Pass --keep and/or --max-age-days"));
+                    }
+                    let max_age =
+                        max_age_days.map(|days| std::time::Duration::from_secs(days * 24 * 60 * 60));
+                    let removed = saves::prune(&game_dir, world.as_deref(), keep, max_age)?;
+                    print_output(cli.json, &removed, |removed| {
+                        println!("Removed {} backups", removed.len())
+                    })
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,384 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::sync::OnceCell;
+use tracing::{debug, info, warn};
+
+use crate::{
+    cache, mirrored_url,
+    net::{self, DirectoryProvider},
+    versions, write_atomic, AssetIndex, LatestVersion, VersionManifest, VersionType,
+};
+
+const STATS_FILE: &str = "mirror_stats.json";
+// what the stats file calls Mojang's own servers
+pub const UPSTREAM: &str = "upstream";
+// measurements older than this are taken again on the next download
+const REPROBE_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+// what mirrors are compared on, between a tiny asset object and a library jar
+const TYPICAL_FILE_BYTES: f64 = 256.0 * 1024.0;
+
+#[derive(Serialize, Debug, Default)]
+pub struct ExportReport {
+    pub versions: Vec<String>,
+    // installed but not in the manifest, modded versions mostly
+    pub skipped: Vec<String>,
+    pub files: usize,
+    pub bytes: u64,
+    // referenced by an exported version but not in the cache
+    pub missing: Vec<String>,
+}
+
+// lays the cached metadata out the way mirrors serve it, <out>/<host>/<path>, so any static web
+// server can host it and `mirror_url`/`manifest_url` can point at it. Only installed vanilla
+// versions are exported, the manifest is trimmed to them. With `files` the libraries, client
+// jars and asset objects come along too and the result is a complete mirror
+pub async fn export(cache_dir: &Path, out: &Path, files: bool) -> anyhow::Result<ExportReport> {
+    let manifest = versions::cached_manifest(cache_dir)
+        .ok_or_else(|| anyhow!("No version manifest is cached yet, run `versions list` first"))?;
+    let layout = DirectoryProvider::new(out);
+    let mut report = ExportReport::default();
+
+    let installed = versions::installed(cache_dir)?;
+    let mut exported = manifest
+        .versions
+        .into_iter()
+        .filter(|version| installed.contains(&version.id))
+        .collect::<Vec<_>>();
+    report.skipped = installed
+        .iter()
+        .filter(|id| !exported.iter().any(|version| &version.id == *id))
+        .cloned()
+        .collect();
+    if exported.is_empty() {
+        return Err(anyhow!("No installed version is in the version manifest"));
+    }
+    exported.sort_by_key(|version| std::cmp::Reverse(version.release_time));
+
+    // versions share most libraries and objects
+    let mut placed = HashSet::new();
+    for version in &exported {
+        report.versions.push(version.id.clone());
+        let mut wanted = vec![(
+            versions::json_path(cache_dir, &version.id),
+            version.url.clone(),
+        )];
+
+        let info = versions::resolve_installed(cache_dir, &version.id).await?;
+        let assets_dir = cache::assets_dir(cache_dir);
+        let index_file = assets_dir
+            .join("indexes")
+            .join(format!("{}.json", info.asset_index.id));
+        wanted.push((index_file.clone(), info.asset_index.info.url.clone()));
+
+        if files {
+            wanted.push((
+                versions::jar_path(cache_dir, &info.jar),
+                info.downloads.client.url.clone(),
+            ));
+            // every platform's natives, the mirror serves more than this machine
+            let libraries_dir = cache::libraries_dir(cache_dir);
+            for artifact in info.libraries.iter().flat_map(|lib| lib.all_artifacts()) {
+                wanted.push((
+                    libraries_dir.join(&artifact.path),
+                    artifact.info.url.clone(),
+                ));
+            }
+            if let Ok(index) = std::fs::read_to_string(&index_file) {
+                let index: AssetIndex = serde_json::from_str(&index)?;
+                for object in index.objects.values() {
+                    wanted.push((
+                        assets_dir.join("objects").join(object.path()),
+                        format!("{}/{}", net::RESOURCES_URL, object.path()),
+                    ));
+                }
+            }
+        }
+
+        for (source, url) in wanted {
+            if !placed.insert(url.clone()) {
+                continue;
+            }
+            if !source.is_file() {
+                report.missing.push(url);
+                continue;
+            }
+            report.bytes += place(&source, &layout.path_for(&url)?)?;
+            report.files += 1;
+        }
+    }
+
+    // keep `latest` pointing at something the mirror actually has
+    let newest = |release_only: bool| {
+        exported
+            .iter()
+            .find(|version| !release_only || version.vtype == VersionType::Release)
+            .or(exported.first())
+            .map(|version| version.id.clone())
+            .unwrap_or_default()
+    };
+    let manifest = VersionManifest {
+        latest: LatestVersion {
+            release: newest(true),
+            snapshot: newest(false),
+        },
+        versions: exported,
+    };
+    let manifest_path = layout.path_for(net::VERSION_MANIFEST_URL)?;
+    std::fs::create_dir_all(manifest_path.parent().unwrap())?;
+    std::fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+    report.files += 1;
+
+    report.missing.sort();
+    report.missing.dedup();
+    Ok(report)
+}
+
+// hard links where the filesystem allows, the cache and the export are often on the same disk
+fn place(source: &Path, target: &Path) -> anyhow::Result<u64> {
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if target.exists() {
+        std::fs::remove_file(target)?;
+    }
+    if std::fs::hard_link(source, target).is_err() {
+        std::fs::copy(source, target)?;
+    }
+    Ok(std::fs::metadata(target)?.len())
+}
+
+// how a mirror answered the last probe, fetching the version manifest from it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MirrorStats {
+    // None when it couldn't be reached
+    pub rtt_ms: Option<u64>,
+    pub bytes_per_sec: Option<u64>,
+    #[serde(with = "time::serde::iso8601")]
+    pub probed: OffsetDateTime,
+}
+
+impl MirrorStats {
+    // the inverse of how long a typical file would take, 0 for a mirror that is down
+    fn weight(&self) -> f64 {
+        match (self.rtt_ms, self.bytes_per_sec) {
+            (Some(rtt_ms), Some(bytes_per_sec)) => {
+                let seconds =
+                    rtt_ms as f64 / 1000.0 + TYPICAL_FILE_BYTES / bytes_per_sec.max(1) as f64;
+                1.0 / seconds.max(0.001)
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        let age = OffsetDateTime::now_utc() - self.probed;
+        age.is_negative() || age.unsigned_abs() > REPROBE_AFTER
+    }
+}
+
+// spreads downloads over several mirrors, more of them going to the ones that answered faster
+// from here. Each is probed on first use and again once its numbers are a day old, the results
+// are kept in the cache dir. A mirror that fails a download is left out for the rest of the
+// session
+#[derive(Debug)]
+pub struct MirrorSelector {
+    // None for Mojang's own servers
+    candidates: Vec<Option<String>>,
+    stats_path: PathBuf,
+    stats: OnceCell<Vec<MirrorStats>>,
+    failed: Mutex<HashSet<usize>>,
+}
+
+// with `mirrors` configured, picks between them and `mirror_url`, or Mojang's servers when that
+// is unset. None keeps downloads on `mirror_url` alone
+pub fn selector(
+    mirror_url: Option<&str>,
+    mirrors: &[String],
+    cache_dir: &Path,
+) -> Option<MirrorSelector> {
+    if mirrors.is_empty() {
+        return None;
+    }
+    let mut candidates = vec![mirror_url.map(|url| url.trim_end_matches('/').to_string())];
+    for mirror in mirrors {
+        let mirror = Some(mirror.trim_end_matches('/').to_string());
+        if !candidates.contains(&mirror) {
+            candidates.push(mirror);
+        }
+    }
+    Some(MirrorSelector {
+        candidates,
+        stats_path: cache_dir.join(STATS_FILE),
+        stats: OnceCell::new(),
+        failed: Mutex::new(HashSet::new()),
+    })
+}
+
+// so every mirror is probed again on the next download
+pub fn forget_stats(cache_dir: &Path) -> anyhow::Result<()> {
+    match std::fs::remove_file(cache_dir.join(STATS_FILE)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+impl MirrorSelector {
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    pub fn mirror(&self, index: usize) -> Option<&str> {
+        self.candidates[index].as_deref()
+    }
+
+    fn name(&self, index: usize) -> &str {
+        self.mirror(index).unwrap_or(UPSTREAM)
+    }
+
+    // every candidate's numbers, probing the ones that have none or stale ones
+    pub async fn stats(&self, client: &reqwest::Client) -> BTreeMap<String, MirrorStats> {
+        let stats = self.ready(client).await;
+        (0..self.len())
+            .map(|index| (self.name(index).to_string(), stats[index]))
+            .collect()
+    }
+
+    pub(crate) async fn ready(&self, client: &reqwest::Client) -> &[MirrorStats] {
+        self.stats
+            .get_or_init(|| async {
+                let saved = std::fs::read_to_string(&self.stats_path)
+                    .ok()
+                    .and_then(|json| {
+                        serde_json::from_str::<BTreeMap<String, MirrorStats>>(&json).ok()
+                    })
+                    .unwrap_or_default();
+                let stats = futures::future::join_all((0..self.len()).map(|index| {
+                    let saved = saved
+                        .get(self.name(index))
+                        .filter(|stats| !stats.is_stale());
+                    async move {
+                        match saved {
+                            Some(stats) => *stats,
+                            None => probe(client, self.mirror(index)).await,
+                        }
+                    }
+                }))
+                .await;
+
+                let mut all = saved;
+                for (index, stats) in stats.iter().enumerate() {
+                    all.insert(self.name(index).to_string(), *stats);
+                }
+                match serde_json::to_string_pretty(&all) {
+                    Ok(json) => {
+                        if let Err(e) = write_atomic(&self.stats_path, json).await {
+                            warn!("Could not save mirror measurements: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("Could not save mirror measurements: {}", e),
+                }
+                stats
+            })
+            .await
+    }
+
+    // a mirror for the next download, picked at random by weight among those not in `tried`.
+    // Mirrors that failed this session or were down at their probe only come up once nothing
+    // else is left. Call `ready` first
+    pub(crate) fn choose(&self, tried: &[usize]) -> Option<usize> {
+        let stats = self.stats.get()?;
+        let failed = self.failed.lock().unwrap();
+        let left = (0..self.len())
+            .filter(|index| !tried.contains(index))
+            .collect::<Vec<_>>();
+        let weighted = left
+            .iter()
+            .filter(|index| !failed.contains(index))
+            .map(|index| (*index, stats[*index].weight()))
+            .filter(|(_, weight)| *weight > 0.0)
+            .collect::<Vec<_>>();
+        let total = weighted.iter().map(|(_, weight)| weight).sum::<f64>();
+        if weighted.is_empty() {
+            return left.first().copied();
+        }
+
+        let mut random = [0; 8];
+        let roll = match getrandom::getrandom(&mut random) {
+            Ok(()) => u64::from_le_bytes(random) as f64 / u64::MAX as f64 * total,
+            Err(_) => 0.0,
+        };
+        let mut sum = 0.0;
+        for (index, weight) in &weighted {
+            sum += weight;
+            if roll < sum {
+                return Some(*index);
+            }
+        }
+        weighted.last().map(|(index, _)| *index)
+    }
+
+    pub(crate) fn mark_failed(&self, index: usize) {
+        if self.failed.lock().unwrap().insert(index) {
+            info!(
+                "Leaving mirror {} out after a failed download",
+                self.name(index)
+            );
+        }
+    }
+}
+
+// time to the manifest's first byte and the speed of the rest of it
+async fn probe(client: &reqwest::Client, mirror: Option<&str>) -> MirrorStats {
+    let url = mirrored_url(net::VERSION_MANIFEST_URL, mirror);
+    let start = Instant::now();
+    let measured = async {
+        let response = client
+            .get(&url)
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?;
+        let rtt = start.elapsed();
+        let body = response.bytes().await?;
+        let transfer = (start.elapsed() - rtt).max(Duration::from_millis(1));
+        anyhow::Ok((rtt, (body.len() as f64 / transfer.as_secs_f64()) as u64))
+    }
+    .await;
+    let (rtt_ms, bytes_per_sec) = match measured {
+        Ok((rtt, bytes_per_sec)) => {
+            debug!(
+                url,
+                rtt_ms = rtt.as_millis() as u64,
+                bytes_per_sec,
+                "probed mirror"
+            );
+            (Some(rtt.as_millis() as u64), Some(bytes_per_sec))
+        }
+        Err(e) => {
+            warn!(
+                "Mirror {} is unreachable: {}",
+                mirror.unwrap_or(UPSTREAM),
+                e
+            );
+            (None, None)
+        }
+    };
+    MirrorStats {
+        rtt_ms,
+        bytes_per_sec,
+        probed: OffsetDateTime::now_utc(),
+    }
+}
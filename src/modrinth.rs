@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use serde::Deserialize;
+
+use crate::{build_info::USER_AGENT, FileInfo};
+
+// Modrinth asks every client to identify itself, net::client() does but callers may bring their
+// own client, so each request sets USER_AGENT again
+const API_URL: &str = "https://api.modrinth.com/v2";
+
+#[derive(Deserialize, Debug)]
+pub struct Project {
+    pub id: String,
+    pub slug: String,
+    pub title: String,
+    // "mod", "modpack", "resourcepack" or "shader"
+    pub project_type: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ProjectVersion {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    pub version_number: String,
+    pub game_versions: Vec<String>,
+    pub loaders: Vec<String>,
+    pub files: Vec<VersionFile>,
+}
+
+impl ProjectVersion {
+    // the file to install when a version has several
+    pub fn primary_file(&self) -> Option<&VersionFile> {
+        self.files
+            .iter()
+            .find(|file| file.primary)
+            .or(self.files.first())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VersionFile {
+    pub url: String,
+    pub filename: String,
+    #[serde(default)]
+    pub primary: bool,
+    pub size: u64,
+    pub hashes: FileHashes,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FileHashes {
+    pub sha1: String,
+}
+
+impl VersionFile {
+    pub(crate) fn file_info(&self) -> FileInfo {
+        FileInfo {
+            sha1: self.hashes.sha1.clone(),
+            size: self.size,
+            url: self.url.clone(),
+        }
+    }
+}
+
+// accepts either the project id or its slug
+pub async fn project(client: &reqwest::Client, id: &str) -> anyhow::Result<Project> {
+    Ok(client
+        .get(format!("{}/project/{}", API_URL, id))
+        .header(reqwest::header::USER_AGENT, USER_AGENT)
+        .send()
+        .await?
+        .error_for_status()
+        .map_err(|_| anyhow!("No Modrinth project {}", id))?
+        .json()
+        .await?)
+}
+
+// newest first, only versions that list `game_version` when given
+pub async fn versions(
+    client: &reqwest::Client,
+    id: &str,
+    game_version: Option<&str>,
+) -> anyhow::Result<Vec<ProjectVersion>> {
+    let mut request = client
+        .get(format!("{}/project/{}/version", API_URL, id))
+        .header(reqwest::header::USER_AGENT, USER_AGENT);
+    if let Some(game_version) = game_version {
+        request = request.query(&[("game_versions", serde_json::to_string(&[game_version])?)]);
+    }
+
+    Ok(request.send().await?.error_for_status()?.json().await?)
+}
+
+// the versions that published files with these sha1 hashes, keyed by hash. Hashes Modrinth
+// doesn't know are left out
+pub async fn versions_by_hash(
+    client: &reqwest::Client,
+    hashes: &[String],
+) -> anyhow::Result<HashMap<String, ProjectVersion>> {
+    Ok(client
+        .post(format!("{}/version_files", API_URL))
+        .header(reqwest::header::USER_AGENT, USER_AGENT)
+        .json(&serde_json::json!({ "hashes": hashes, "algorithm": "sha1" }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+// the newest version for `loaders` and `game_versions` of each project that published a file
+// with one of these sha1 hashes, keyed by hash. Hashes Modrinth doesn't know are left out
+pub async fn latest_versions_by_hash(
+    client: &reqwest::Client,
+    hashes: &[String],
+    loaders: &[&str],
+    game_versions: &[&str],
+) -> anyhow::Result<HashMap<String, ProjectVersion>> {
+    Ok(client
+        .post(format!("{}/version_files/update", API_URL))
+        .header(reqwest::header::USER_AGENT, USER_AGENT)
+        .json(&serde_json::json!({
+            "hashes": hashes,
+            "algorithm": "sha1",
+            "loaders": loaders,
+            "game_versions": game_versions,
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
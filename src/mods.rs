@@ -0,0 +1,512 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::loader::LoaderKind;
+
+const DISABLED_SUFFIX: &str = ".disabled";
+
+// dependencies provided by the game or loader itself rather than another mod jar
+const BUILTIN_DEPENDENCIES: &[&str] = &[
+    "minecraft",
+    "java",
+    "fabricloader",
+    "quilt_loader",
+    "forge",
+    "neoforge",
+];
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ModInfo {
+    pub file_name: String,
+    pub path: PathBuf,
+    pub enabled: bool,
+    pub metadata: Option<ModMetadata>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ModMetadata {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    pub loader: LoaderKind,
+    pub dependencies: Vec<ModDependency>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ModDependency {
+    pub id: String,
+    pub version_range: String,
+    pub required: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ModIssue {
+    // mods are installed but the instance has no loader to run them
+    NoLoader {
+        mods: usize,
+    },
+    WrongLoader {
+        file_name: String,
+        mod_loader: LoaderKind,
+    },
+    MissingDependency {
+        file_name: String,
+        dependency: String,
+    },
+}
+
+impl std::fmt::Display for ModIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModIssue::NoLoader { mods } => {
+                write!(
+                    f,
+                    "{} mods are installed but no mod loader is, none of them will load",
+                    mods
+                )
+            }
+            ModIssue::WrongLoader {
+                file_name,
+                mod_loader,
+            } => {
+                write!(f, "{} is a {} mod and will not load", file_name, mod_loader)
+            }
+            ModIssue::MissingDependency {
+                file_name,
+                dependency,
+            } => {
+                write!(
+                    f,
+                    "{} requires {} which is not installed",
+                    file_name, dependency
+                )
+            }
+        }
+    }
+}
+
+pub fn mods_dir(game_dir: &Path) -> PathBuf {
+    game_dir.join("mods")
+}
+
+pub fn list_mods(game_dir: &Path) -> anyhow::Result<Vec<ModInfo>> {
+    let mods_dir = mods_dir(game_dir);
+    if !mods_dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut mods = Vec::new();
+    for entry in std::fs::read_dir(&mods_dir)? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+
+        let enabled = file_name.ends_with(".jar");
+        if !enabled && !file_name.ends_with(&format!(".jar{}", DISABLED_SUFFIX)) {
+            continue;
+        }
+
+        mods.push(ModInfo {
+            file_name: file_name.to_string(),
+            metadata: read_metadata(&path).ok().flatten(),
+            path,
+            enabled,
+        });
+    }
+
+    mods.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    Ok(mods)
+}
+
+pub fn set_enabled(game_dir: &Path, file_name: &str, enabled: bool) -> anyhow::Result<PathBuf> {
+    let mods_dir = mods_dir(game_dir);
+    let jar_name = file_name.trim_end_matches(DISABLED_SUFFIX);
+    let enabled_path = mods_dir.join(jar_name);
+    let disabled_path = mods_dir.join(format!("{}{}", jar_name, DISABLED_SUFFIX));
+
+    let (from, to) = if enabled {
+        (disabled_path, enabled_path)
+    } else {
+        (enabled_path, disabled_path)
+    };
+
+    if to.exists() {
+        return Ok(to);
+    }
+    if !from.exists() {
+        return Err(anyhow!("Mod {} is not installed", jar_name));
+    }
+
+    std::fs::rename(&from, &to)?;
+    Ok(to)
+}
+
+pub fn add_mod(game_dir: &Path, source: &Path) -> anyhow::Result<ModInfo> {
+    let file_name = source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| name.ends_with(".jar"))
+        .ok_or_else(|| anyhow!("{} is not a mod jar", source.display()))?;
+
+    let metadata = read_metadata(source)?;
+    let mods_dir = mods_dir(game_dir);
+    std::fs::create_dir_all(&mods_dir)?;
+
+    let path = mods_dir.join(file_name);
+    std::fs::copy(source, &path)?;
+
+    Ok(ModInfo {
+        file_name: file_name.to_string(),
+        path,
+        enabled: true,
+        metadata,
+    })
+}
+
+pub fn remove_mod(game_dir: &Path, file_name: &str) -> anyhow::Result<()> {
+    let mods_dir = mods_dir(game_dir);
+    let jar_name = file_name.trim_end_matches(DISABLED_SUFFIX);
+
+    let mut removed = false;
+    for path in [
+        mods_dir.join(jar_name),
+        mods_dir.join(format!("{}{}", jar_name, DISABLED_SUFFIX)),
+    ] {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+            removed = true;
+        }
+    }
+
+    if !removed {
+        return Err(anyhow!("Mod {} is not installed", jar_name));
+    }
+    Ok(())
+}
+
+pub fn check_compatibility(mods: &[ModInfo], loader: Option<LoaderKind>) -> Vec<ModIssue> {
+    let enabled = mods
+        .iter()
+        .filter(|m| m.enabled)
+        .filter_map(|m| m.metadata.as_ref().map(|metadata| (m, metadata)))
+        .collect::<Vec<_>>();
+    let installed_ids = enabled
+        .iter()
+        .map(|(_, metadata)| metadata.id.as_str())
+        .collect::<HashSet<_>>();
+
+    let mut issues = Vec::new();
+    if loader.is_none() && !enabled.is_empty() {
+        issues.push(ModIssue::NoLoader {
+            mods: enabled.len(),
+        });
+    }
+    for (info, metadata) in enabled {
+        // without a loader there is nothing to check against, NoLoader covers them all
+        if loader.is_some_and(|loader| !runs_on(metadata.loader, loader)) {
+            issues.push(ModIssue::WrongLoader {
+                file_name: info.file_name.clone(),
+                mod_loader: metadata.loader,
+            });
+            continue;
+        }
+
+        for dependency in metadata.dependencies.iter().filter(|dep| dep.required) {
+            if !BUILTIN_DEPENDENCIES.contains(&dependency.id.as_str())
+                && !installed_ids.contains(dependency.id.as_str())
+            {
+                issues.push(ModIssue::MissingDependency {
+                    file_name: info.file_name.clone(),
+                    dependency: dependency.id.clone(),
+                });
+            }
+        }
+    }
+
+    issues
+}
+
+fn runs_on(mod_loader: LoaderKind, loader: LoaderKind) -> bool {
+    // quilt can load fabric mods, nothing else is cross-compatible
+    mod_loader == loader || (mod_loader == LoaderKind::Fabric && loader == LoaderKind::Quilt)
+}
+
+pub fn read_metadata(jar: &Path) -> anyhow::Result<Option<ModMetadata>> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(jar)?)?;
+
+    if let Some(json) = read_entry(&mut archive, "quilt.mod.json")? {
+        return Ok(Some(parse_quilt_metadata(&json)?));
+    }
+    if let Some(json) = read_entry(&mut archive, "fabric.mod.json")? {
+        return Ok(Some(parse_fabric_metadata(&json)?));
+    }
+    if let Some(toml) = read_entry(&mut archive, "META-INF/neoforge.mods.toml")? {
+        return parse_mods_toml(&toml, LoaderKind::NeoForge, &mut archive);
+    }
+    if let Some(toml) = read_entry(&mut archive, "META-INF/mods.toml")? {
+        return parse_mods_toml(&toml, LoaderKind::Forge, &mut archive);
+    }
+
+    Ok(None)
+}
+
+fn read_entry(
+    archive: &mut zip::ZipArchive<std::fs::File>,
+    name: &str,
+) -> anyhow::Result<Option<String>> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut contents = String::new();
+    entry.read_to_string(&mut contents)?;
+    Ok(Some(contents))
+}
+
+#[derive(Deserialize)]
+struct FabricModJson {
+    id: String,
+    version: String,
+    name: Option<String>,
+    #[serde(default)]
+    depends: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    recommends: HashMap<String, serde_json::Value>,
+}
+
+fn parse_fabric_metadata(json: &str) -> anyhow::Result<ModMetadata> {
+    let fabric: FabricModJson = serde_json::from_str(json)?;
+
+    // version predicates are either a single string or any-of array
+    let range = |value: &serde_json::Value| match value {
+        serde_json::Value::String(range) => range.clone(),
+        serde_json::Value::Array(ranges) => ranges
+            .iter()
+            .filter_map(|range| range.as_str())
+            .collect::<Vec<_>>()
+            .join(" || "),
+        _ => String::from("*"),
+    };
+
+    let dependencies = fabric
+        .depends
+        .iter()
+        .map(|(id, value)| (id, value, true))
+        .chain(
+            fabric
+                .recommends
+                .iter()
+                .map(|(id, value)| (id, value, false)),
+        )
+        .map(|(id, value, required)| ModDependency {
+            id: id.clone(),
+            version_range: range(value),
+            required,
+        })
+        .collect();
+
+    Ok(ModMetadata {
+        name: fabric.name.unwrap_or_else(|| fabric.id.clone()),
+        id: fabric.id,
+        version: fabric.version,
+        loader: LoaderKind::Fabric,
+        dependencies,
+    })
+}
+
+#[derive(Deserialize)]
+struct QuiltModJson {
+    quilt_loader: QuiltLoaderSection,
+}
+
+#[derive(Deserialize)]
+struct QuiltLoaderSection {
+    id: String,
+    version: String,
+    metadata: Option<QuiltMetadataSection>,
+    #[serde(default)]
+    depends: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct QuiltMetadataSection {
+    name: Option<String>,
+}
+
+fn parse_quilt_metadata(json: &str) -> anyhow::Result<ModMetadata> {
+    let quilt: QuiltModJson = serde_json::from_str(json)?;
+    let section = quilt.quilt_loader;
+
+    // dependencies are either a bare id or an object with id/versions/optional
+    let dependencies = section
+        .depends
+        .iter()
+        .filter_map(|value| match value {
+            serde_json::Value::String(id) => Some(ModDependency {
+                id: id.clone(),
+                version_range: String::from("*"),
+                required: true,
+            }),
+            serde_json::Value::Object(object) => Some(ModDependency {
+                id: object.get("id")?.as_str()?.to_string(),
+                version_range: object
+                    .get("versions")
+                    .map(|versions| versions.to_string().trim_matches('"').to_string())
+                    .unwrap_or_else(|| String::from("*")),
+                required: !object
+                    .get("optional")
+                    .and_then(|optional| optional.as_bool())
+                    .unwrap_or(false),
+            }),
+            _ => None,
+        })
+        .collect();
+
+    Ok(ModMetadata {
+        name: section
+            .metadata
+            .and_then(|metadata| metadata.name)
+            .unwrap_or_else(|| section.id.clone()),
+        id: section.id,
+        version: section.version,
+        loader: LoaderKind::Quilt,
+        dependencies,
+    })
+}
+
+#[derive(Deserialize)]
+struct ModsToml {
+    mods: Vec<ModsTomlMod>,
+    #[serde(default)]
+    dependencies: HashMap<String, Vec<ModsTomlDependency>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModsTomlMod {
+    mod_id: String,
+    version: Option<String>,
+    display_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModsTomlDependency {
+    mod_id: String,
+    // forge
+    mandatory: Option<bool>,
+    // neoforge
+    #[serde(rename = "type")]
+    dependency_type: Option<String>,
+    version_range: Option<String>,
+}
+
+fn parse_mods_toml(
+    toml: &str,
+    loader: LoaderKind,
+    archive: &mut zip::ZipArchive<std::fs::File>,
+) -> anyhow::Result<Option<ModMetadata>> {
+    let mods_toml: ModsToml = toml::from_str(toml)?;
+    // jars can declare several mods, the first is treated as the primary one
+    let Some(primary) = mods_toml.mods.into_iter().next() else {
+        return Ok(None);
+    };
+
+    let mut version = primary
+        .version
+        .unwrap_or_else(|| String::from("${file.jarVersion}"));
+    if version == "${file.jarVersion}" {
+        if let Some(jar_version) = read_implementation_version(archive)? {
+            version = jar_version;
+        }
+    }
+
+    let dependencies = mods_toml
+        .dependencies
+        .get(&primary.mod_id)
+        .map(|dependencies| {
+            dependencies
+                .iter()
+                .map(|dep| ModDependency {
+                    id: dep.mod_id.clone(),
+                    version_range: dep
+                        .version_range
+                        .clone()
+                        .unwrap_or_else(|| String::from("*")),
+                    required: dep.mandatory.unwrap_or_else(|| {
+                        dep.dependency_type
+                            .as_deref()
+                            .is_none_or(|dependency_type| {
+                                dependency_type.eq_ignore_ascii_case("required")
+                            })
+                    }),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(Some(ModMetadata {
+        name: primary
+            .display_name
+            .unwrap_or_else(|| primary.mod_id.clone()),
+        id: primary.mod_id,
+        version,
+        loader,
+        dependencies,
+    }))
+}
+
+fn read_implementation_version(
+    archive: &mut zip::ZipArchive<std::fs::File>,
+) -> anyhow::Result<Option<String>> {
+    Ok(
+        read_entry(archive, "META-INF/MANIFEST.MF")?.and_then(|manifest| {
+            manifest
+                .lines()
+                .find_map(|line| line.strip_prefix("Implementation-Version:"))
+                .map(|version| version.trim().to_string())
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fabric_mod(id: &str) -> ModInfo {
+        ModInfo {
+            file_name: format!("{}.jar", id),
+            path: PathBuf::from(format!("mods/{}.jar", id)),
+            enabled: true,
+            metadata: Some(ModMetadata {
+                id: id.to_string(),
+                name: id.to_string(),
+                version: String::from("1.0.0"),
+                loader: LoaderKind::Fabric,
+                dependencies: Vec::new(),
+            }),
+        }
+    }
+
+    #[test]
+    fn mods_without_a_loader_are_one_issue() {
+        let mods = [fabric_mod("sodium"), fabric_mod("lithium")];
+        assert_eq!(
+            check_compatibility(&mods, None),
+            [ModIssue::NoLoader { mods: 2 }]
+        );
+        assert!(check_compatibility(&mods, Some(LoaderKind::Quilt)).is_empty());
+        assert_eq!(check_compatibility(&mods, Some(LoaderKind::Forge)).len(), 2);
+        assert!(check_compatibility(&[], None).is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use sha1::{Digest, Sha1};
+use tracing::debug;
+
+use crate::{rules::Environment, Artifact, FileInfo, Library};
+
+// upstream LWJGL publishes the platforms Mojang leaves out
+const MAVEN_CENTRAL: &str = "https://repo1.maven.org/maven2";
+
+// natives Mojang doesn't ship for a platform, swapped for upstream builds of the same version
+pub struct NativeOverride {
+    pub os_name: &'static str,
+    pub os_arch: &'static str,
+    // only libraries whose name starts with this, not every project publishes the classifier
+    pub group: &'static str,
+    pub replaces: &'static str,
+    pub with: &'static str,
+}
+
+pub const OVERRIDES: &[NativeOverride] = &[
+    NativeOverride {
+        os_name: "linux",
+        os_arch: "aarch64",
+        group: "org.lwjgl:",
+        replaces: "natives-linux",
+        with: "natives-linux-arm64",
+    },
+    NativeOverride {
+        os_name: "linux",
+        os_arch: "arm",
+        group: "org.lwjgl:",
+        replaces: "natives-linux",
+        with: "natives-linux-arm32",
+    },
+];
+
+// points natives at the platform's own classifier. Handles both the pre-1.19 `natives` map
+// and 1.19+ per-platform libraries selected by rules
+pub(crate) fn apply_overrides(libraries: &mut [Library], env: &Environment) {
+    for swap in OVERRIDES
+        .iter()
+        .filter(|swap| swap.os_name == env.os_name && swap.os_arch == env.os_arch)
+    {
+        // Mojang may start shipping the classifier itself, theirs wins then
+        let suffix = format!(":{}", swap.with);
+        if libraries.iter().any(|lib| lib.name.ends_with(&suffix)) {
+            continue;
+        }
+
+        for lib in libraries
+            .iter_mut()
+            .filter(|lib| lib.name.starts_with(swap.group))
+        {
+            if let Some(classifier) = lib.native_classifier(env).filter(|c| c == swap.replaces) {
+                let Some(replaced) = lib
+                    .downloads
+                    .classifiers
+                    .get(&classifier)
+                    .and_then(|artifact| swap_classifier(artifact, swap.replaces, swap.with))
+                else {
+                    continue;
+                };
+                lib.downloads
+                    .classifiers
+                    .insert(swap.with.to_string(), replaced);
+                lib.natives
+                    .as_mut()
+                    .unwrap()
+                    .insert(env.os_name.clone(), swap.with.to_string());
+            } else if let Some(name) = lib.name.strip_suffix(&format!(":{}", swap.replaces)) {
+                let Some(replaced) = lib
+                    .downloads
+                    .artifact
+                    .as_ref()
+                    .and_then(|artifact| swap_classifier(artifact, swap.replaces, swap.with))
+                else {
+                    continue;
+                };
+                lib.name = format!("{}:{}", name, swap.with);
+                lib.downloads.artifact = Some(replaced);
+            }
+        }
+    }
+}
+
+fn swap_classifier(artifact: &Artifact, replaces: &str, with: &str) -> Option<Artifact> {
+    let base = artifact.path.strip_suffix(&format!("-{}.jar", replaces))?;
+    let path = format!("{}-{}.jar", base, with);
+
+    Some(Artifact {
+        info: FileInfo {
+            // Mojang's hash is for the other platform, upstream ones go unverified
+            sha1: String::new(),
+            size: 0,
+            url: format!("{}/{}", MAVEN_CENTRAL, path),
+        },
+        path,
+    })
+}
+
+pub fn natives_root(cache_dir: &Path) -> PathBuf {
+    cache_dir.join("natives")
+}
+
+// named after the native jars that go into it, so every version and instance with the same
+// LWJGL build shares one directory
+pub(crate) fn dir_for(cache_dir: &Path, libraries: &[&Library], env: &Environment) -> PathBuf {
+    let mut hasher = Sha1::new();
+    for lib in libraries {
+        let Some(artifact) = lib.native_artifact(env) else {
+            continue;
+        };
+        hasher.update(artifact.path.as_bytes());
+        hasher.update(artifact.info.sha1.as_bytes());
+        for exclude in &lib.extract_exclude {
+            hasher.update(exclude.as_bytes());
+        }
+        hasher.update(b"\n");
+    }
+    let key = format!("{:x}", hasher.finalize());
+    natives_root(cache_dir).join(&key[..16])
+}
+
+// unpacks the natives jars of pre-1.19 versions where java.library.path will find them. A
+// directory that is already there came from the same jars and is used as it is
+pub(crate) fn extract(
+    libraries_dir: &Path,
+    natives_dir: &Path,
+    libraries: &[&Library],
+    env: &Environment,
+) -> anyhow::Result<()> {
+    if natives_dir.is_dir() {
+        debug!(natives = %natives_dir.display(), "reusing extracted natives");
+        return Ok(());
+    }
+    // a launch of another instance may be extracting the same set right now
+    let mut partial = natives_dir.as_os_str().to_owned();
+    partial.push(format!(".part-{}", std::process::id()));
+    let partial = PathBuf::from(partial);
+    if partial.exists() {
+        std::fs::remove_dir_all(&partial)?;
+    }
+    std::fs::create_dir_all(&partial)?;
+    extract_into(libraries_dir, &partial, libraries, env)?;
+    if std::fs::rename(&partial, natives_dir).is_err() {
+        // the other launch won, its copy is just as good
+        std::fs::remove_dir_all(&partial)?;
+        if !natives_dir.is_dir() {
+            return Err(anyhow!(
+                "Could not move natives into {}",
+                natives_dir.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn extract_into(
+    libraries_dir: &Path,
+    natives_dir: &Path,
+    libraries: &[&Library],
+    env: &Environment,
+) -> anyhow::Result<()> {
+    for lib in libraries {
+        let Some(artifact) = lib.native_artifact(env) else {
+            continue;
+        };
+
+        let jar = libraries_dir.join(&artifact.path);
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&jar)?)
+            .map_err(|err| anyhow!("Failed to open natives {}: {}", jar.display(), err))?;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            let Some(relative) = entry.enclosed_name().map(|name| name.to_path_buf()) else {
+                continue;
+            };
+            let excluded = lib
+                .extract_exclude
+                .iter()
+                .any(|exclude| entry.name().starts_with(exclude.as_str()));
+            if entry.is_dir() || excluded {
+                continue;
+            }
+
+            let out = natives_dir.join(relative);
+            if let Some(parent) = out.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::io::copy(&mut entry, &mut std::fs::File::create(out)?)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,402 @@
+use std::{
+    fmt,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use reqwest::{redirect, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, warn};
+
+use crate::{
+    build_info, cert_pins::CertPins, mirror::MirrorSelector, mirrored_url, Version, VersionManifest,
+};
+
+pub const VERSION_MANIFEST_URL: &str =
+    "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
+// the same list without version hashes
+pub const VERSION_MANIFEST_V1_URL: &str =
+    "https://piston-meta.mojang.com/mc/game/version_manifest.json";
+pub const RESOURCES_URL: &str = "https://resources.download.minecraft.net";
+// long enough for a slow connection, short enough not to hold up an offline launch
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkStatus {
+    Online,
+    // nothing is fetched, versions, logins and files come from what is cached
+    Offline,
+}
+
+// every request names the launcher build that sent it
+pub fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .user_agent(build_info::USER_AGENT)
+        .build()
+        .expect("the TLS backend initializes")
+}
+
+// a client that refuses pinned hosts presenting other certificates before anything is sent to
+// them, and redirects from them to hosts without pins
+pub fn pinned_client(pins: &CertPins) -> reqwest::Client {
+    let redirects = pins.clone();
+    let policy = redirect::Policy::custom(move |attempt| {
+        match redirects.check_url(attempt.previous().last(), attempt.url()) {
+            Err(e) => attempt.error(e),
+            // reqwest's own limit
+            Ok(()) if attempt.previous().len() > 10 => attempt.error("too many redirects"),
+            Ok(()) => attempt.follow(),
+        }
+    });
+    reqwest::Client::builder()
+        .user_agent(build_info::USER_AGENT)
+        .use_preconfigured_tls(pins.tls_config())
+        .redirect(policy)
+        .build()
+        .expect("the TLS backend initializes")
+}
+
+// fetches library jars, client jars and asset objects. Callers check hashes themselves
+#[async_trait]
+pub trait Downloader: Send + Sync {
+    async fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>>;
+
+    // writes the file to `part`, continuing after what an interrupted download left there when
+    // the source supports it
+    async fn fetch_to(&self, url: &str, part: &Path) -> anyhow::Result<()> {
+        let bytes = self.fetch(url).await?;
+        tokio::fs::write(part, bytes).await?;
+        Ok(())
+    }
+}
+
+// where the version manifest and vanilla version JSONs come from
+#[async_trait]
+pub trait MetaProvider: Send + Sync {
+    async fn version_manifest(&self) -> anyhow::Result<VersionManifest>;
+
+    // the raw JSON, it is cached as-is and checked against `version.sha1`
+    async fn version_json(&self, version: &Version) -> anyhow::Result<Vec<u8>>;
+}
+
+// where the list of versions comes from. Version JSONs are then fetched from the URLs it lists
+#[async_trait]
+pub trait ManifestSource: fmt::Debug + Send + Sync {
+    async fn manifest(&self, downloader: &dyn Downloader) -> anyhow::Result<VersionManifest>;
+}
+
+// a manifest in Mojang's v1 or v2 format, from Mojang or a private meta server
+#[derive(Debug, Clone)]
+pub struct UrlManifest {
+    pub url: String,
+}
+
+impl UrlManifest {
+    pub fn new(url: impl Into<String>) -> UrlManifest {
+        UrlManifest { url: url.into() }
+    }
+
+    pub fn mojang() -> UrlManifest {
+        UrlManifest::new(VERSION_MANIFEST_URL)
+    }
+
+    // v1 lists no hashes, so version JSONs from it go unverified
+    pub fn mojang_v1() -> UrlManifest {
+        UrlManifest::new(VERSION_MANIFEST_V1_URL)
+    }
+}
+
+impl Default for UrlManifest {
+    fn default() -> Self {
+        UrlManifest::mojang()
+    }
+}
+
+#[async_trait]
+impl ManifestSource for UrlManifest {
+    async fn manifest(&self, downloader: &dyn Downloader) -> anyhow::Result<VersionManifest> {
+        let manifest = downloader.fetch(&self.url).await?;
+        serde_json::from_slice(&manifest)
+            .with_context(|| format!("{} is not a version manifest", self.url))
+    }
+}
+
+#[async_trait]
+impl Downloader for reqwest::Client {
+    async fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        fetch_throttled(self, url, None, None).await
+    }
+
+    async fn fetch_to(&self, url: &str, part: &Path) -> anyhow::Result<()> {
+        fetch_to_throttled(self, url, part, None, None).await
+    }
+}
+
+// pinned hosts only over TLS, the certificates themselves are checked by pinned_client
+fn check_pins(pins: Option<&CertPins>, url: &str) -> anyhow::Result<()> {
+    match pins.filter(|pins| !pins.is_empty()) {
+        Some(pins) => pins.check_url(None, &reqwest::Url::parse(url)?),
+        None => Ok(()),
+    }
+}
+
+async fn fetch_throttled(
+    client: &reqwest::Client,
+    url: &str,
+    throttle: Option<&Throttle>,
+    pins: Option<&CertPins>,
+) -> anyhow::Result<Vec<u8>> {
+    check_pins(pins, url)?;
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if let Some(throttle) = throttle {
+            throttle.consume(chunk.len()).await;
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+async fn fetch_to_throttled(
+    client: &reqwest::Client,
+    url: &str,
+    part: &Path,
+    throttle: Option<&Throttle>,
+    pins: Option<&CertPins>,
+) -> anyhow::Result<()> {
+    check_pins(pins, url)?;
+    let offset = tokio::fs::metadata(part).await.map_or(0, |part| part.len());
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+    }
+    let response = request.send().await?;
+    // the part already holds the whole file
+    if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        return Ok(());
+    }
+    let mut response = response.error_for_status()?;
+    // servers without range support send everything again
+    let resumed = response.status() == StatusCode::PARTIAL_CONTENT;
+    if offset > 0 {
+        debug!(url, offset, resumed, "continuing download");
+    }
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(part)
+        .await?;
+    while let Some(chunk) = response.chunk().await? {
+        if let Some(throttle) = throttle {
+            throttle.consume(chunk.len()).await;
+        }
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+// a token bucket shared by every download going through one provider, so the limit holds for
+// all of them together. Up to a second's worth of bytes can go out in a burst
+#[derive(Debug)]
+pub struct Throttle {
+    bytes_per_second: u64,
+    // bytes that may go out right now, negative while a download waits for its turn
+    bucket: tokio::sync::Mutex<(f64, Instant)>,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_second: u64) -> Throttle {
+        let bytes_per_second = bytes_per_second.max(1);
+        Throttle {
+            bytes_per_second,
+            bucket: tokio::sync::Mutex::new((bytes_per_second as f64, Instant::now())),
+        }
+    }
+
+    async fn consume(&self, bytes: usize) {
+        let rate = self.bytes_per_second as f64;
+        // held while sleeping, downloads take turns instead of all waking up at once
+        let mut bucket = self.bucket.lock().await;
+        let (available, refilled) = &mut *bucket;
+        let now = Instant::now();
+        *available = (*available + now.duration_since(*refilled).as_secs_f64() * rate).min(rate);
+        *refilled = now;
+        *available -= bytes as f64;
+        if *available < 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(-*available / rate)).await;
+        }
+    }
+}
+
+// talks to Mojang, or to a mirror of it when one is configured
+#[derive(Debug, Clone)]
+pub struct HttpProvider {
+    pub client: reqwest::Client,
+    pub mirror: Option<String>,
+    // fetched through the mirror like everything else
+    pub manifest: Arc<dyn ManifestSource>,
+    pub throttle: Option<Arc<Throttle>>,
+    // set with `pin`, which swaps in a client that enforces them
+    pins: Arc<CertPins>,
+    // picks a mirror per download in place of `mirror`, see mirror.rs
+    pub mirrors: Option<Arc<MirrorSelector>>,
+}
+
+impl HttpProvider {
+    pub fn new(client: reqwest::Client, mirror: Option<&str>) -> HttpProvider {
+        HttpProvider {
+            client,
+            mirror: mirror.map(str::to_string),
+            manifest: Arc::new(UrlManifest::mojang()),
+            throttle: None,
+            pins: Arc::new(CertPins::default()),
+            mirrors: None,
+        }
+    }
+
+    pub fn pin(&mut self, pins: &CertPins) {
+        if !pins.is_empty() {
+            self.client = pinned_client(pins);
+            self.pins = Arc::new(pins.clone());
+        }
+    }
+
+    // runs `fetch` on the URL as a mirror serves it. With several mirrors, one that fails is
+    // left out and the next one tried, until none is left
+    async fn through_mirror<T, F>(
+        &self,
+        url: &str,
+        fetch: impl Fn(String) -> F,
+    ) -> anyhow::Result<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        let Some(mirrors) = &self.mirrors else {
+            return fetch(mirrored_url(url, self.mirror.as_deref())).await;
+        };
+        mirrors.ready(&self.client).await;
+        let mut tried = Vec::new();
+        loop {
+            let Some(index) = mirrors.choose(&tried) else {
+                return Err(anyhow!("No mirror is left to download {} from", url));
+            };
+            match fetch(mirrored_url(url, mirrors.mirror(index))).await {
+                Ok(fetched) => return Ok(fetched),
+                Err(e) if tried.len() + 1 < mirrors.len() => {
+                    debug!(url, error = %e, "mirror failed, trying another");
+                    mirrors.mark_failed(index);
+                    tried.push(index);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // a HEAD to where the manifest comes from, any answer at all means online
+    pub async fn probe(&self) -> NetworkStatus {
+        let url = mirrored_url(VERSION_MANIFEST_URL, self.mirror.as_deref());
+        match self.client.head(&url).timeout(PROBE_TIMEOUT).send().await {
+            Ok(_) => NetworkStatus::Online,
+            Err(e) => {
+                debug!("network probe failed: {}", e);
+                warn!("No network connection, working from the cache");
+                NetworkStatus::Offline
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Downloader for HttpProvider {
+    async fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        self.through_mirror(url, |url| async move {
+            fetch_throttled(
+                &self.client,
+                &url,
+                self.throttle.as_deref(),
+                Some(&self.pins),
+            )
+            .await
+        })
+        .await
+    }
+
+    async fn fetch_to(&self, url: &str, part: &Path) -> anyhow::Result<()> {
+        let throttle = self.throttle.as_deref();
+        self.through_mirror(url, |url| async move {
+            fetch_to_throttled(&self.client, &url, part, throttle, Some(&self.pins)).await
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl MetaProvider for HttpProvider {
+    async fn version_manifest(&self) -> anyhow::Result<VersionManifest> {
+        self.manifest.manifest(self).await
+    }
+
+    async fn version_json(&self, version: &Version) -> anyhow::Result<Vec<u8>> {
+        self.fetch(&version.url).await
+    }
+}
+
+// serves files from a directory laid out like a mirror, <root>/<host>/<path>. Nothing is
+// downloaded, which is what tests and offline installs want
+#[derive(Debug, Clone)]
+pub struct DirectoryProvider {
+    pub root: PathBuf,
+    pub manifest: Arc<dyn ManifestSource>,
+}
+
+impl DirectoryProvider {
+    pub fn new(root: impl AsRef<Path>) -> DirectoryProvider {
+        DirectoryProvider {
+            root: root.as_ref().to_path_buf(),
+            manifest: Arc::new(UrlManifest::mojang()),
+        }
+    }
+
+    pub fn path_for(&self, url: &str) -> anyhow::Result<PathBuf> {
+        let (_, host_and_path) = url
+            .split_once("://")
+            .ok_or_else(|| anyhow!("{} is not a URL", url))?;
+        // never serve anything outside the root
+        if host_and_path.split('/').any(|part| part == "..") {
+            return Err(anyhow!("Refusing to serve {}", url));
+        }
+        Ok(self.root.join(host_and_path))
+    }
+}
+
+#[async_trait]
+impl Downloader for DirectoryProvider {
+    async fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        let path = self.path_for(url)?;
+        tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("{} is not available at {}", url, path.display()))
+    }
+}
+
+#[async_trait]
+impl MetaProvider for DirectoryProvider {
+    async fn version_manifest(&self) -> anyhow::Result<VersionManifest> {
+        self.manifest.manifest(self).await
+    }
+
+    async fn version_json(&self, version: &Version) -> anyhow::Result<Vec<u8>> {
+        self.fetch(&version.url).await
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    io::Read,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::anyhow;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{download_artifact, instance::compare_versions, modrinth};
+
+// first release using each resource pack format, oldest first
+const PACK_FORMATS: &[(&str, u32)] = &[
+    ("1.6.1", 1),
+    ("1.9", 2),
+    ("1.11", 3),
+    ("1.13", 4),
+    ("1.15", 5),
+    ("1.16.2", 6),
+    ("1.17", 7),
+    ("1.18", 8),
+    ("1.19", 9),
+    ("1.19.3", 12),
+    ("1.19.4", 13),
+    ("1.20", 15),
+    ("1.20.2", 18),
+    ("1.20.3", 22),
+    ("1.20.5", 32),
+    ("1.21", 34),
+    ("1.21.2", 42),
+    ("1.21.4", 46),
+    ("1.21.5", 55),
+    ("1.21.6", 63),
+    ("1.21.7", 64),
+    ("1.21.9", 69),
+];
+// newer releases may have bumped the format, so they go unchecked rather than misjudged
+const LATEST_KNOWN_RELEASE: &str = "1.21.10";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PackKind {
+    Resource,
+    Shader,
+}
+
+impl PackKind {
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            PackKind::Resource => "resourcepacks",
+            PackKind::Shader => "shaderpacks",
+        }
+    }
+
+    // Modrinth's project_type
+    fn project_type(&self) -> &'static str {
+        match self {
+            PackKind::Resource => "resourcepack",
+            PackKind::Shader => "shader",
+        }
+    }
+}
+
+impl fmt::Display for PackKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PackKind::Resource => "resource pack",
+            PackKind::Shader => "shader pack",
+        })
+    }
+}
+
+impl FromStr for PackKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "resource" | "resourcepack" => Ok(PackKind::Resource),
+            "shader" | "shaderpack" => Ok(PackKind::Shader),
+            _ => Err(anyhow!("Unknown pack kind {}, use resource or shader", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum PackCompatibility {
+    Compatible,
+    // made for older versions, the game still offers to load it
+    TooOld,
+    TooNew,
+    // a shader pack, a snapshot or a release newer than we know about
+    Unknown,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PackInfo {
+    pub kind: PackKind,
+    pub file_name: String,
+    pub path: PathBuf,
+    pub description: Option<String>,
+    pub pack_format: Option<u32>,
+    pub compatibility: PackCompatibility,
+}
+
+#[derive(Deserialize, Debug)]
+struct PackMcmeta {
+    pack: PackSection,
+}
+
+#[derive(Deserialize, Debug)]
+struct PackSection {
+    pack_format: Option<u32>,
+    #[serde(default)]
+    description: Value,
+    // 1.20.2+: a single format, [min, max] or {"min_inclusive", "max_inclusive"}
+    supported_formats: Option<Value>,
+    // 1.21.9+: a major format or [major, minor]
+    min_format: Option<Value>,
+    max_format: Option<Value>,
+}
+
+impl PackSection {
+    fn format_range(&self) -> Option<(u32, u32)> {
+        if let (Some(min), Some(max)) = (&self.min_format, &self.max_format) {
+            return Some((major_format(min)?, major_format(max)?));
+        }
+
+        let range = match &self.supported_formats {
+            Some(Value::Number(format)) => {
+                let format = format.as_u64()? as u32;
+                (format, format)
+            }
+            Some(Value::Array(range)) => (
+                range.first()?.as_u64()? as u32,
+                range.get(1)?.as_u64()? as u32,
+            ),
+            Some(Value::Object(range)) => (
+                range.get("min_inclusive")?.as_u64()? as u32,
+                range.get("max_inclusive")?.as_u64()? as u32,
+            ),
+            _ => {
+                let format = self.pack_format?;
+                (format, format)
+            }
+        };
+        Some(range)
+    }
+}
+
+fn major_format(format: &Value) -> Option<u32> {
+    match format {
+        Value::Array(parts) => parts.first()?.as_u64().map(|major| major as u32),
+        format => format.as_u64().map(|major| major as u32),
+    }
+}
+
+// descriptions are either plain strings or text components
+fn plain_text(component: &Value) -> String {
+    match component {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().map(plain_text).collect(),
+        Value::Object(object) => {
+            let mut text = object
+                .get("text")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            if let Some(extra) = object.get("extra") {
+                text.push_str(&plain_text(extra));
+            }
+            text
+        }
+        _ => String::new(),
+    }
+}
+
+// None for snapshots and releases after LATEST_KNOWN_RELEASE
+pub fn expected_pack_format(game_version: &str) -> Option<u32> {
+    let release = Regex::new(r"^1\.\d+(\.\d+)?$").unwrap();
+    if !release.is_match(game_version)
+        || compare_versions(game_version, LATEST_KNOWN_RELEASE) == Ordering::Greater
+    {
+        return None;
+    }
+
+    PACK_FORMATS
+        .iter()
+        .rev()
+        .find(|(first, _)| compare_versions(game_version, first) != Ordering::Less)
+        .map(|(_, format)| *format)
+}
+
+pub fn packs_dir(game_dir: &Path, kind: PackKind) -> PathBuf {
+    game_dir.join(kind.dir_name())
+}
+
+// zipped or unpacked, both of which the game loads
+pub fn list_packs(
+    game_dir: &Path,
+    kind: PackKind,
+    game_version: Option<&str>,
+) -> anyhow::Result<Vec<PackInfo>> {
+    let dir = packs_dir(game_dir, kind);
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut packs = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_zip = path.extension().is_some_and(|ext| ext == "zip");
+        if !is_zip && !path.is_dir() {
+            continue;
+        }
+
+        // unreadable packs are still listed so they can be removed
+        packs.push(
+            read_pack(&path, kind, game_version).unwrap_or_else(|_| PackInfo {
+                kind,
+                file_name: path.file_name().unwrap().to_string_lossy().to_string(),
+                path,
+                description: None,
+                pack_format: None,
+                compatibility: PackCompatibility::Unknown,
+            }),
+        );
+    }
+
+    packs.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    Ok(packs)
+}
+
+// checks the pack without installing it
+pub fn read_pack(
+    path: &Path,
+    kind: PackKind,
+    game_version: Option<&str>,
+) -> anyhow::Result<PackInfo> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow!("{} is not a {}", path.display(), kind))?;
+
+    let mut info = PackInfo {
+        kind,
+        file_name,
+        path: path.to_path_buf(),
+        description: None,
+        pack_format: None,
+        compatibility: PackCompatibility::Unknown,
+    };
+
+    match kind {
+        // Iris and OptiFine only look for a shaders/ folder
+        PackKind::Shader => {
+            if !has_entry(path, "shaders/")? {
+                return Err(anyhow!(
+                    "{} is not a shader pack, it has no shaders folder",
+                    path.display()
+                ));
+            }
+        }
+        PackKind::Resource => {
+            let mcmeta = read_entry(path, "pack.mcmeta")?.ok_or_else(|| {
+                anyhow!(
+                    "{} is not a resource pack, it has no pack.mcmeta",
+                    path.display()
+                )
+            })?;
+            // the game tolerates a BOM, serde_json doesn't
+            let mcmeta: PackMcmeta = serde_json::from_str(mcmeta.trim_start_matches('\u{feff}'))?;
+
+            let description = plain_text(&mcmeta.pack.description);
+            info.description = Some(description).filter(|description| !description.is_empty());
+            info.pack_format = mcmeta
+                .pack
+                .pack_format
+                .or_else(|| mcmeta.pack.format_range().map(|(min, _)| min));
+            info.compatibility = match (
+                game_version.and_then(expected_pack_format),
+                mcmeta.pack.format_range(),
+            ) {
+                (Some(expected), Some((_, max))) if expected > max => PackCompatibility::TooOld,
+                (Some(expected), Some((min, _))) if expected < min => PackCompatibility::TooNew,
+                (Some(_), Some(_)) => PackCompatibility::Compatible,
+                _ => PackCompatibility::Unknown,
+            };
+        }
+    }
+    Ok(info)
+}
+
+fn read_entry(path: &Path, name: &str) -> anyhow::Result<Option<String>> {
+    if path.is_dir() {
+        let file = path.join(name);
+        return Ok(file
+            .exists()
+            .then(|| std::fs::read_to_string(file))
+            .transpose()?);
+    }
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut contents = String::new();
+    entry.read_to_string(&mut contents)?;
+    Ok(Some(contents))
+}
+
+fn has_entry(path: &Path, prefix: &str) -> anyhow::Result<bool> {
+    if path.is_dir() {
+        return Ok(path.join(prefix).is_dir());
+    }
+
+    let archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    let has_entry = archive.file_names().any(|name| name.starts_with(prefix));
+    Ok(has_entry)
+}
+
+// copies a zipped pack into the instance. Incompatible packs are installed anyway, callers
+// decide what to do about `compatibility`
+pub fn install_pack(
+    game_dir: &Path,
+    kind: PackKind,
+    source: &Path,
+    game_version: Option<&str>,
+) -> anyhow::Result<PackInfo> {
+    if source.extension().is_none_or(|ext| ext != "zip") {
+        return Err(anyhow!("{} is not a zipped {}", source.display(), kind));
+    }
+    let info = read_pack(source, kind, game_version)?;
+
+    let dir = packs_dir(game_dir, kind);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(&info.file_name);
+    std::fs::copy(source, &path)?;
+
+    Ok(PackInfo { path, ..info })
+}
+
+// installs the newest version of a Modrinth project that supports `game_version`
+pub async fn install_modrinth_pack(
+    client: &reqwest::Client,
+    game_dir: &Path,
+    kind: PackKind,
+    project_id: &str,
+    game_version: &str,
+) -> anyhow::Result<PackInfo> {
+    let project = modrinth::project(client, project_id).await?;
+    if project.project_type != kind.project_type() {
+        return Err(anyhow!(
+            "{} is a {} project, not a {}",
+            project.title,
+            project.project_type,
+            kind
+        ));
+    }
+
+    let versions = modrinth::versions(client, &project.id, Some(game_version)).await?;
+    let file = versions
+        .first()
+        .and_then(|version| version.primary_file())
+        .ok_or_else(|| {
+            anyhow!(
+                "{} has no version for Minecraft {}",
+                project.title,
+                game_version
+            )
+        })?;
+    if file.filename.contains(['/', '\\']) {
+        return Err(anyhow!(
+            "Refusing to install {} as {}",
+            project.title,
+            file.filename
+        ));
+    }
+
+    let path = packs_dir(game_dir, kind).join(&file.filename);
+    download_artifact(&path, &file.file_info(), client, None).await?;
+    read_pack(&path, kind, Some(game_version))
+}
+
+pub fn remove_pack(game_dir: &Path, kind: PackKind, file_name: &str) -> anyhow::Result<()> {
+    let path = packs_dir(game_dir, kind).join(file_name);
+    if matches!(file_name, "" | "." | "..") || file_name.contains(['/', '\\']) || !path.exists() {
+        return Err(anyhow!("No {} named {} is installed", kind, file_name));
+    }
+
+    if path.is_dir() {
+        std::fs::remove_dir_all(path)?;
+    } else {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
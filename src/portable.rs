@@ -0,0 +1,366 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    curseforge, dedup,
+    download::{DownloadPlan, DownloadTask},
+    instance::{self, Instance},
+    modrinth, mods,
+    net::Downloader,
+    staging::{Staging, WriteStats},
+    versions,
+};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const FORMAT_VERSION: u32 = 1;
+// settings only, worlds and packs stay with the original instance
+pub(crate) const OVERRIDES: &[&str] = &["config", "defaultconfigs", "options.txt", "servers.dat"];
+
+// what an instance archive holds besides the override files, kept as manifest.json
+#[derive(Serialize, Deserialize, Debug)]
+struct ArchiveManifest {
+    format_version: u32,
+    instance: Instance,
+    mods: Vec<ArchivedMod>,
+    // version JSONs under versions/, the ones the manifest can't provide like loader profiles
+    versions: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ArchivedMod {
+    // with the .disabled suffix when the mod is turned off
+    file_name: String,
+    sha1: String,
+    size: u64,
+    // where the jar is published, bundled under mods/ when unset
+    url: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ExportReport {
+    pub path: PathBuf,
+    // mods the importer downloads again
+    pub referenced: Vec<String>,
+    pub bundled: Vec<String>,
+    // mods not published anywhere we know of, left out
+    pub skipped: Vec<String>,
+}
+
+// an installed mod, with its download on Modrinth when it is published there
+pub(crate) struct LocatedMod {
+    pub info: mods::ModInfo,
+    pub sha1: String,
+    pub size: u64,
+    pub url: Option<String>,
+}
+
+pub(crate) async fn locate_mods(
+    client: &reqwest::Client,
+    game_dir: &Path,
+) -> anyhow::Result<Vec<LocatedMod>> {
+    let mut installed = Vec::new();
+    for info in mods::list_mods(game_dir)? {
+        let bytes = std::fs::read(&info.path)?;
+        let sha1 = format!("{:x}", Sha1::digest(&bytes));
+        installed.push((info, sha1, bytes.len() as u64));
+    }
+    let hashes = installed
+        .iter()
+        .map(|(_, sha1, _)| sha1.clone())
+        .collect::<Vec<_>>();
+    let published = match hashes.is_empty() {
+        true => HashMap::new(),
+        false => modrinth::versions_by_hash(client, &hashes)
+            .await
+            .context("Could not look up the instance's mods on Modrinth")?,
+    };
+    Ok(installed
+        .into_iter()
+        .map(|(info, sha1, size)| {
+            let url = published
+                .get(&sha1)
+                .and_then(|version| version.files.iter().find(|file| file.hashes.sha1 == sha1))
+                .map(|file| file.url.clone());
+            LocatedMod {
+                info,
+                sha1,
+                size,
+                url,
+            }
+        })
+        .collect())
+}
+
+// the instance without anything that only makes sense on this machine
+pub(crate) fn shareable(instance: Instance) -> Instance {
+    Instance {
+        java_path: None,
+        custom_game_dir: None,
+        account: None,
+        ca_certs: vec![],
+        components: vec![],
+        last_played: None,
+        // the player's, not the instance's
+        language: None,
+        ..instance
+    }
+}
+
+// zips the instance's settings, its mod list and config files into `dest`. Mods published on
+// Modrinth are referenced by hash rather than copied, whether their license allows sharing the
+// jar or not. Anything else is only bundled with `bundle_unknown`
+pub async fn export(
+    client: &reqwest::Client,
+    instances_dir: &Path,
+    cache_dir: &Path,
+    name: &str,
+    dest: &Path,
+    bundle_unknown: bool,
+) -> anyhow::Result<ExportReport> {
+    let instance = instance::load(instances_dir, name)?;
+    let game_dir = instance.game_dir(instances_dir);
+
+    let mut report = ExportReport {
+        path: dest.to_path_buf(),
+        ..Default::default()
+    };
+    let mut archived = Vec::new();
+    let mut bundled = Vec::new();
+    for LocatedMod {
+        info,
+        sha1,
+        size,
+        url,
+    } in locate_mods(client, &game_dir).await?
+    {
+        match url {
+            Some(_) => report.referenced.push(info.file_name.clone()),
+            None if bundle_unknown => {
+                report.bundled.push(info.file_name.clone());
+                bundled.push(info.path);
+            }
+            None => {
+                report.skipped.push(info.file_name);
+                continue;
+            }
+        }
+        archived.push(ArchivedMod {
+            file_name: info.file_name,
+            sha1,
+            size,
+            url,
+        });
+    }
+
+    // everything the manifest lists is fetched on the other end anyway
+    let manifest = versions::cached_manifest(cache_dir);
+    let version_ids = versions::lineage(cache_dir, &instance.version)?
+        .into_iter()
+        .filter(|id| {
+            manifest
+                .as_ref()
+                .is_none_or(|manifest| manifest.find_version_by_id(id).is_none())
+        })
+        .collect::<Vec<_>>();
+    let version_files = version_ids
+        .iter()
+        .map(|id| (id.clone(), versions::json_path(cache_dir, id)))
+        .collect();
+
+    let icon = instance
+        .icon_path(instances_dir)
+        .filter(|icon| icon.is_file());
+    let manifest = ArchiveManifest {
+        format_version: FORMAT_VERSION,
+        instance: shareable(instance),
+        mods: archived,
+        versions: version_ids,
+    };
+    let manifest = serde_json::to_string_pretty(&manifest)?;
+    let dest = dest.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        write_archive(&dest, &manifest, &game_dir, icon, version_files, bundled)
+    })
+    .await??;
+
+    Ok(report)
+}
+
+fn write_archive(
+    dest: &Path,
+    manifest: &str,
+    game_dir: &Path,
+    icon: Option<PathBuf>,
+    version_files: Vec<(String, PathBuf)>,
+    bundled: Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    let mut files = Vec::new();
+    for entry in OVERRIDES {
+        for file in dedup::walk_files(&game_dir.join(entry))?
+            .into_iter()
+            .chain(Some(game_dir.join(entry)).filter(|file| file.is_file()))
+        {
+            let relative = file
+                .strip_prefix(game_dir)?
+                .to_string_lossy()
+                .replace('\\', "/");
+            files.push((format!("overrides/{}", relative), file));
+        }
+    }
+    for (id, file) in version_files {
+        files.push((format!("versions/{}.json", id), file));
+    }
+    for file in bundled {
+        let name = file.file_name().unwrap().to_string_lossy().to_string();
+        files.push((format!("mods/{}", name), file));
+    }
+    if let Some(icon) = icon {
+        let name = icon.file_name().unwrap().to_string_lossy().to_string();
+        files.push((format!("icon/{}", name), icon));
+    }
+
+    let mut partial = dest.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let mut zip = ZipWriter::new(std::fs::File::create(&partial)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(MANIFEST_FILE, options)?;
+    zip.write_all(manifest.as_bytes())?;
+    for (name, file) in files {
+        zip.start_file(name, options)?;
+        std::io::copy(&mut std::fs::File::open(&file)?, &mut zip)?;
+    }
+    zip.finish()?.flush()?;
+    std::fs::rename(&partial, dest)?;
+    Ok(())
+}
+
+fn read_manifest(archive_path: &Path) -> anyhow::Result<ArchiveManifest> {
+    let mut archive = ZipArchive::new(std::fs::File::open(archive_path)?)?;
+    let mut manifest = String::new();
+    archive
+        .by_name(MANIFEST_FILE)
+        .with_context(|| format!("{} is not an instance archive", archive_path.display()))?
+        .read_to_string(&mut manifest)?;
+    let manifest: ArchiveManifest = serde_json::from_str(&manifest)?;
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(anyhow!(
+            "{} was exported by a newer launcher, update to import it",
+            archive_path.display()
+        ));
+    }
+
+    // both end up in paths
+    let unsafe_name =
+        |name: &str| name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']);
+    if let Some(name) = manifest
+        .mods
+        .iter()
+        .map(|archived| archived.file_name.as_str())
+        .chain(manifest.versions.iter().map(String::as_str))
+        .find(|name| unsafe_name(name))
+    {
+        return Err(anyhow!("Refusing to import {:?} from the archive", name));
+    }
+    Ok(manifest)
+}
+
+// recreates an exported instance as `name`, the exported name by default. Mods are downloaded
+// again and checked against the hashes they were exported with, the game version itself is
+// left for the caller to install. `batched` writes the game dir through a Staging dir
+pub async fn import(
+    downloader: &dyn Downloader,
+    instances_dir: &Path,
+    cache_dir: &Path,
+    archive_path: &Path,
+    name: Option<&str>,
+    concurrency: usize,
+    batched: bool,
+) -> anyhow::Result<Instance> {
+    let manifest = read_manifest(archive_path)?;
+    let mut instance = manifest.instance;
+    if let Some(name) = name {
+        instance.name = name.to_string();
+    }
+    if instance::load(instances_dir, &instance.name).is_ok() {
+        return Err(anyhow!(
+            "An instance named {} already exists, import it under another name",
+            instance.name
+        ));
+    }
+    instance::save(instances_dir, &instance)?;
+
+    let result = async {
+        let game_dir = instance.game_dir(instances_dir);
+        let staging = batched.then(|| Staging::new(&game_dir)).transpose()?;
+        let write_dir = staging
+            .as_ref()
+            .map_or(game_dir.clone(), |staging| staging.dir().to_path_buf());
+        let instance_dir = instance.dir(instances_dir);
+        let archive_path = archive_path.to_path_buf();
+        let version_ids = manifest.versions;
+        let versions_cache = cache_dir.to_path_buf();
+        let extract_dir = write_dir.clone();
+        let stats = tokio::task::spawn_blocking(move || {
+            let mut archive = ZipArchive::new(std::fs::File::open(&archive_path)?)?;
+            for id in version_ids {
+                let path = versions::json_path(&versions_cache, &id);
+                // an installed version with this id is the same one
+                if path.exists() {
+                    continue;
+                }
+                std::fs::create_dir_all(versions::version_dir(&versions_cache, &id))?;
+                let mut entry = archive
+                    .by_name(&format!("versions/{}.json", id))
+                    .with_context(|| format!("The archive is missing version {}", id))?;
+                std::io::copy(&mut entry, &mut std::fs::File::create(&path)?)?;
+            }
+            let stats = WriteStats::default();
+            curseforge::extract_overrides(&archive_path, "overrides", &extract_dir, &stats)?;
+            curseforge::extract_overrides(
+                &archive_path,
+                "mods",
+                &mods::mods_dir(&extract_dir),
+                &stats,
+            )?;
+            curseforge::extract_overrides(&archive_path, "icon", &instance_dir, &stats)?;
+            anyhow::Ok(stats)
+        })
+        .await??;
+        stats.warn_if_slow(&[instances_dir, cache_dir]);
+
+        let mods_dir = mods::mods_dir(&write_dir);
+        let mut plan = DownloadPlan::default();
+        for archived in manifest.mods {
+            if let Some(url) = archived.url {
+                plan.push(DownloadTask {
+                    url,
+                    path: mods_dir.join(&archived.file_name),
+                    sha1: archived.sha1,
+                    size: archived.size,
+                });
+            }
+        }
+        plan.run(downloader, concurrency, None, &|_, _| {}).await?;
+        if let Some(staging) = staging {
+            staging.commit()?;
+        }
+        anyhow::Ok(())
+    }
+    .await;
+
+    // a half imported instance would only be in the way of trying again
+    if let Err(e) = result {
+        let _ = std::fs::remove_dir_all(instance.dir(instances_dir));
+        return Err(e);
+    }
+    Ok(instance)
+}
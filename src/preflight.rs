@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use tracing::debug;
+
+use crate::{cache, versions, Artifact, AssetIndex, VersionInfo};
+
+// room for `.part` files that exist next to their target while being renamed, and for whatever
+// the game writes on first start
+const HEADROOM_BYTES: u64 = 64 * 1024 * 1024;
+
+// fails before the first download instead of halfway through a few hundred MB of assets
+pub(crate) fn check(
+    info: &VersionInfo,
+    artifacts: &[&Artifact],
+    cache_dir: &Path,
+) -> anyhow::Result<()> {
+    check_writable(cache_dir)?;
+    let needed = download_size(info, artifacts, cache_dir);
+    debug!(needed, "download size");
+    if needed > 0 {
+        check_space(cache_dir, needed + HEADROOM_BYTES)?;
+    }
+    Ok(())
+}
+
+// bytes still missing from the cache, so a warm cache on a nearly full disk still launches.
+// Asset objects are only known once the index is, until then all of them count
+fn download_size(info: &VersionInfo, artifacts: &[&Artifact], cache_dir: &Path) -> u64 {
+    let missing = |path: &Path, size: u64| if path.exists() { 0 } else { size };
+
+    let libraries_dir = cache::libraries_dir(cache_dir);
+    let libraries: u64 = artifacts
+        .iter()
+        .map(|artifact| missing(&libraries_dir.join(&artifact.path), artifact.info.size))
+        .sum();
+    let client = missing(
+        &versions::jar_path(cache_dir, &info.jar),
+        info.downloads.client.size,
+    );
+
+    let assets_dir = cache::assets_dir(cache_dir);
+    let index_file = assets_dir
+        .join("indexes")
+        .join(format!("{}.json", info.asset_index.id));
+    let index = std::fs::read_to_string(&index_file)
+        .ok()
+        .and_then(|index| serde_json::from_str::<AssetIndex>(&index).ok());
+    let assets = match index {
+        Some(index) => index
+            .objects
+            .values()
+            .map(|object| missing(&assets_dir.join("objects").join(object.path()), object.size))
+            .sum(),
+        None => info.asset_index.total_size + info.asset_index.info.size,
+    };
+
+    libraries + client + assets
+}
+
+pub fn check_writable(dir: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    let probe = dir.join(".write_test");
+    std::fs::write(&probe, b"")
+        .with_context(|| format!("{} is not writable, check its permissions", dir.display()))?;
+    let _ = std::fs::remove_file(probe);
+    Ok(())
+}
+
+pub fn check_space(dir: &Path, needed: u64) -> anyhow::Result<()> {
+    // some platforms can't tell, that shouldn't block a launch
+    let Ok(available) = fs2::available_space(dir) else {
+        return Ok(());
+    };
+    debug!(needed, available, dir = %dir.display(), "disk space");
+    if available < needed {
+        return Err(anyhow!(
+            "Not enough disk space for {}: {} MiB needed, {} MiB free",
+            dir.display(),
+            needed.div_ceil(1024 * 1024),
+            available / (1024 * 1024)
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,24 @@
+// the part of the API frontends can build on, `use mod_launcher::prelude::*`. What it promises
+// and how things leave it is in the crate docs, lib.rs
+
+#[cfg(feature = "client")]
+pub use crate::protocol::DaemonClient;
+pub use crate::{
+    args::{QuickPlay, Resolution},
+    assets::{AssetCheck, VerifyPolicy},
+    auth::{Account, AccountStore, AuthError},
+    components::{Component, Conflict, Fix},
+    config::Config,
+    crash::CrashInfo,
+    install_state::InstallStep,
+    install_version, install_version_with,
+    instance::{ChangeKind, Instance, InstanceChange, InstanceQuery, InstanceWatcher},
+    known_issues::KnownIssue,
+    launch_minecraft,
+    loader::LoaderKind,
+    net::{HttpProvider, NetworkStatus},
+    protocol::Notification,
+    sessions::Session,
+    tasks::{TaskEvent, TaskKind},
+    verify_version, LaunchOptions, LaunchPlan, VerifyReport,
+};
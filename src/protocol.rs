@@ -0,0 +1,402 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    crash::CrashInfo,
+    instance::{Instance, InstanceChange, VersionUpdate},
+    net::NetworkStatus,
+    state::AccountSummary,
+    tasks::{Task, TaskId},
+};
+
+// the daemon's JSON-RPC API as types. The daemon reads its requests and writes its
+// notifications with them, Rust frontends get DaemonClient with the `client` feature
+
+// JSON-RPC 2.0 error codes, the last two are ours
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const APP_ERROR: i64 = -32000;
+pub const UNAUTHORIZED: i64 = -32001;
+
+// launches run in the background, their notifications carry this. Installs are tasks, see
+// tasks.rs
+pub type JobId = u64;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> RpcError {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AuthParams {
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct VersionsParams {
+    // snapshots and old versions too, releases only otherwise
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct InstallParams {
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GcParams {
+    // also versions no instance uses
+    #[serde(default)]
+    pub prune_versions: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TaskParams {
+    pub id: TaskId,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LaunchParams {
+    // the latest snapshot when neither this nor an instance is given
+    pub version: Option<String>,
+    pub instance: Option<String>,
+    // username or uuid, the selected account otherwise
+    pub account: Option<String>,
+    // quick play, at most one of them
+    pub server: Option<String>,
+    pub world: Option<String>,
+    // see LaunchOptions::safe_mode
+    #[serde(default)]
+    pub safe_mode: bool,
+}
+
+// an instance as a frontend is about to save it, new or edited
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CheckParams {
+    pub instance: Option<Instance>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SessionOutputParams {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LoaderDefaultsParams {
+    pub game_version: String,
+}
+
+// the result of `launch`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct JobStarted {
+    pub job: JobId,
+}
+
+// the result of `install` and `cache.gc`. The task is in the daemon's queue, it carries on
+// after a restart and `tasks.*` pause, resume and cancel it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct TaskQueued {
+    pub task: TaskId,
+}
+
+// sent without an id, on the connection that started the job. instance.changed, task.changed
+// and account.changed belong to no job and go to every authenticated connection
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "method", content = "params")]
+#[non_exhaustive]
+pub enum Notification {
+    #[serde(rename = "network.status")]
+    NetworkStatus { job: JobId, status: NetworkStatus },
+    // a launched instance followed its channel to a newer version
+    #[serde(rename = "instance.updated")]
+    InstanceUpdated { job: JobId, update: VersionUpdate },
+    // by anyone, this daemon, the CLI or another frontend
+    #[serde(rename = "instance.changed")]
+    InstanceChanged(Box<InstanceChange>),
+    // the task as it is after an event, replaces the one with its id in LauncherState::tasks
+    #[serde(rename = "task.changed")]
+    TaskChanged(Box<Task>),
+    // every account after a login, logout or switch, replaces LauncherState::accounts
+    #[serde(rename = "account.changed")]
+    AccountsChanged { accounts: Vec<AccountSummary> },
+    #[serde(rename = "launch.output")]
+    LaunchOutput { job: JobId, line: String },
+    // `crash` is None for a clean exit, `error` is set when the game never started
+    #[serde(rename = "launch.finished")]
+    LaunchFinished {
+        job: JobId,
+        #[serde(default)]
+        crash: Option<CrashInfo>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+#[cfg(feature = "client")]
+pub use client::DaemonClient;
+
+#[cfg(feature = "client")]
+mod client {
+    use std::{collections::VecDeque, path::Path};
+
+    use anyhow::anyhow;
+    use serde::{de::DeserializeOwned, Deserialize, Serialize};
+    use serde_json::{json, Value};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+        net::{
+            tcp::{OwnedReadHalf, OwnedWriteHalf},
+            TcpStream,
+        },
+    };
+    use tracing::debug;
+
+    use super::{
+        AuthParams, CheckParams, GcParams, InstallParams, JobId, JobStarted, LaunchParams,
+        LoaderDefaultsParams, Notification, RpcError, SessionOutputParams, TaskParams, TaskQueued,
+        VersionsParams,
+    };
+    use crate::{
+        components::ComponentConflict,
+        daemon::DaemonInfo,
+        instance::Instance,
+        loader_matrix::LoaderDefaults,
+        sessions::Session,
+        state::{AccountSummary, LauncherState},
+        tasks::{Task, TaskId},
+        Version,
+    };
+
+    #[derive(Deserialize)]
+    struct Response {
+        id: Option<Value>,
+        #[serde(default)]
+        result: Value,
+        error: Option<RpcError>,
+    }
+
+    // one authenticated connection to a daemon. Calls wait for their response, notifications
+    // that arrive meanwhile are kept for next_notification
+    pub struct DaemonClient {
+        lines: Lines<BufReader<OwnedReadHalf>>,
+        write: OwnedWriteHalf,
+        next_id: u64,
+        notifications: VecDeque<Notification>,
+    }
+
+    impl DaemonClient {
+        // the daemon serving `work_dir`, as found in its daemon.json
+        pub async fn connect(work_dir: &Path) -> anyhow::Result<DaemonClient> {
+            Self::connect_to(&DaemonInfo::load(work_dir)?).await
+        }
+
+        pub async fn connect_to(info: &DaemonInfo) -> anyhow::Result<DaemonClient> {
+            let (read, write) = TcpStream::connect(("127.0.0.1", info.port))
+                .await?
+                .into_split();
+            let mut client = DaemonClient {
+                lines: BufReader::new(read).lines(),
+                write,
+                next_id: 1,
+                notifications: VecDeque::new(),
+            };
+            client
+                .call::<_, Value>(
+                    "auth",
+                    AuthParams {
+                        token: info.token.clone(),
+                    },
+                )
+                .await?;
+            Ok(client)
+        }
+
+        // any method, for what the typed helpers don't cover. Errors from the daemon are
+        // RpcErrors
+        pub async fn call<P: Serialize, R: DeserializeOwned>(
+            &mut self,
+            method: &str,
+            params: P,
+        ) -> anyhow::Result<R> {
+            let id = self.next_id;
+            self.next_id += 1;
+            let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+            self.write
+                .write_all(format!("{}\n", request).as_bytes())
+                .await?;
+
+            loop {
+                let message = self
+                    .read()
+                    .await?
+                    .ok_or_else(|| anyhow!("The daemon closed the connection"))?;
+                if message.get("method").is_some() {
+                    self.queue(message);
+                    continue;
+                }
+                let response = serde_json::from_value::<Response>(message)?;
+                if response.id != Some(json!(id)) {
+                    debug!(id = ?response.id, "daemon response for another request");
+                    continue;
+                }
+                return match response.error {
+                    Some(error) => Err(error.into()),
+                    None => Ok(serde_json::from_value(response.result)?),
+                };
+            }
+        }
+
+        // the next notification, None once the daemon closed the connection
+        pub async fn next_notification(&mut self) -> anyhow::Result<Option<Notification>> {
+            loop {
+                if let Some(notification) = self.notifications.pop_front() {
+                    return Ok(Some(notification));
+                }
+                match self.read().await? {
+                    Some(message) => self.queue(message),
+                    None => return Ok(None),
+                }
+            }
+        }
+
+        pub async fn versions(&mut self, all: bool) -> anyhow::Result<Vec<Version>> {
+            self.call("versions.list", VersionsParams { all }).await
+        }
+
+        // queues the install, see tasks()
+        pub async fn install(&mut self, version: &str) -> anyhow::Result<TaskId> {
+            let queued: TaskQueued = self
+                .call(
+                    "install",
+                    InstallParams {
+                        version: version.to_string(),
+                    },
+                )
+                .await?;
+            Ok(queued.task)
+        }
+
+        pub async fn gc(&mut self, prune_versions: bool) -> anyhow::Result<TaskId> {
+            let queued: TaskQueued = self.call("cache.gc", GcParams { prune_versions }).await?;
+            Ok(queued.task)
+        }
+
+        // finished ones too, until the queue is compacted
+        pub async fn tasks(&mut self) -> anyhow::Result<Vec<Task>> {
+            self.call("tasks.list", Value::Null).await
+        }
+
+        pub async fn pause_task(&mut self, id: TaskId) -> anyhow::Result<()> {
+            self.call::<_, Value>("tasks.pause", TaskParams { id })
+                .await?;
+            Ok(())
+        }
+
+        // also retries a failed task
+        pub async fn resume_task(&mut self, id: TaskId) -> anyhow::Result<()> {
+            self.call::<_, Value>("tasks.resume", TaskParams { id })
+                .await?;
+            Ok(())
+        }
+
+        pub async fn cancel_task(&mut self, id: TaskId) -> anyhow::Result<()> {
+            self.call::<_, Value>("tasks.cancel", TaskParams { id })
+                .await?;
+            Ok(())
+        }
+
+        // the game's output and how it exited arrive as notifications
+        pub async fn launch(&mut self, params: LaunchParams) -> anyhow::Result<JobId> {
+            let started: JobStarted = self.call("launch", params).await?;
+            Ok(started.job)
+        }
+
+        pub async fn sessions(&mut self) -> anyhow::Result<Vec<Session>> {
+            self.call("sessions.list", Value::Null).await
+        }
+
+        // the end of a running or ended game's output, see sessions::OutputRing
+        pub async fn session_output(&mut self, id: &str) -> anyhow::Result<String> {
+            let params = SessionOutputParams { id: id.to_string() };
+            self.call("sessions.output", params).await
+        }
+
+        pub async fn accounts(&mut self) -> anyhow::Result<Vec<AccountSummary>> {
+            self.call("accounts.list", Value::Null).await
+        }
+
+        pub async fn instances(&mut self) -> anyhow::Result<Vec<Instance>> {
+            self.call("instances.list", Value::Null).await
+        }
+
+        // what's wrong with an instance before saving it, see components::check
+        pub async fn check_instance(
+            &mut self,
+            instance: Instance,
+        ) -> anyhow::Result<Vec<ComponentConflict>> {
+            let instance = Some(instance);
+            self.call("instances.check", CheckParams { instance }).await
+        }
+
+        // what to offer for each loader when creating an instance, from the cache when offline
+        pub async fn loader_defaults(
+            &mut self,
+            game_version: &str,
+        ) -> anyhow::Result<Vec<LoaderDefaults>> {
+            let game_version = game_version.to_string();
+            self.call("loaders.defaults", LoaderDefaultsParams { game_version })
+                .await
+        }
+
+        pub async fn snapshot(&mut self) -> anyhow::Result<LauncherState> {
+            self.call("state.snapshot", Value::Null).await
+        }
+
+        // refused while games are still being launched, queued tasks carry on after a restart
+        pub async fn shutdown(&mut self) -> anyhow::Result<()> {
+            self.call::<_, Value>("shutdown", Value::Null).await?;
+            Ok(())
+        }
+
+        async fn read(&mut self) -> anyhow::Result<Option<Value>> {
+            loop {
+                let Some(line) = self.lines.next_line().await? else {
+                    return Ok(None);
+                };
+                if !line.trim().is_empty() {
+                    return Ok(Some(serde_json::from_str(&line)?));
+                }
+            }
+        }
+
+        // methods newer than this client are skipped
+        fn queue(&mut self, message: Value) {
+            match serde_json::from_value::<Notification>(message) {
+                Ok(notification) => self.notifications.push_back(notification),
+                Err(e) => debug!("skipped daemon notification: {}", e),
+            }
+        }
+    }
+}
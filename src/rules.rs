@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone)]
+pub struct Environment {
+    // Mojang's names: "windows", "osx" or "linux"
+    pub os_name: String,
+    pub os_arch: String,
+    pub features: Vec<String>,
+}
+
+impl Environment {
+    pub fn current() -> Environment {
+        let os_name = match std::env::consts::OS {
+            "macos" => "osx",
+            os => os,
+        };
+
+        Environment {
+            os_name: os_name.to_string(),
+            os_arch: std::env::consts::ARCH.to_string(),
+            features: vec![],
+        }
+    }
+
+    // what Mojang's "${arch}" in native classifiers stands for
+    pub fn arch_bits(&self) -> &'static str {
+        match self.os_arch.as_str() {
+            "x86" | "i686" | "arm" => "32",
+            _ => "64",
+        }
+    }
+
+    pub fn classpath_separator(&self) -> &'static str {
+        if self.os_name == "windows" {
+            ";"
+        } else {
+            ":"
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    Allow,
+    Disallow,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Rule {
+    pub action: RuleAction,
+    pub features: Option<HashMap<String, bool>>,
+    pub os: Option<OsConstraint>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OsConstraint {
+    pub name: Option<String>,
+    pub arch: Option<String>,
+}
+
+impl Rule {
+    pub fn matches(&self, env: &Environment) -> bool {
+        let matches_features = self.features.as_ref().is_none_or(|features| {
+            features
+                .iter()
+                .all(|(feature, state)| env.features.contains(feature) == *state)
+        });
+
+        let matches_os = self.os.as_ref().is_none_or(|os| {
+            let matches_name = os.name.as_ref().is_none_or(|name| env.os_name == *name);
+            let matches_arch = os.arch.as_ref().is_none_or(|arch| arch_matches(arch, &env.os_arch));
+            matches_name && matches_arch
+        });
+
+        matches_features && matches_os
+    }
+}
+
+// Mojang's "x86" means a 32-bit JVM, not the x86 family
+fn arch_matches(rule_arch: &str, arch: &str) -> bool {
+    rule_arch == arch || (rule_arch == "x86" && arch == "i686")
+}
+
+// no rules means allowed, otherwise everything starts disallowed and the last matching rule wins
+pub fn is_allowed(rules: Option<&[Rule]>, env: &Environment) -> bool {
+    let Some(rules) = rules else {
+        return true;
+    };
+
+    rules
+        .iter()
+        .rfind(|rule| rule.matches(env))
+        .is_some_and(|rule| rule.action == RuleAction::Allow)
+}
@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use tracing::debug;
+
+use crate::mods::{self, ModInfo};
+
+// safe mode runs the game without the player's customizations: only essential mods, the
+// default heap and no custom JVM arguments. It plays in an overlay of the game dir that links
+// back to everything but the mods, so the instance itself never changes. Worlds and settings
+// that exist are shared, anything the game creates fresh stays in the overlay and is gone on
+// the next safe mode launch
+const OVERLAY_DIR: &str = "safe-mode";
+// libraries other mods build on, they change nothing on their own
+const ESSENTIAL_MOD_IDS: &[&str] = &[
+    "fabric-api",
+    // Fabric API before 0.42
+    "fabric",
+    "quilted_fabric_api",
+    "fabric-language-kotlin",
+];
+
+pub fn overlay_dir(work_dir: &Path, name: &str) -> PathBuf {
+    work_dir.join(OVERLAY_DIR).join(name)
+}
+
+pub fn is_essential(info: &ModInfo) -> bool {
+    info.enabled
+        && info
+            .metadata
+            .as_ref()
+            .is_some_and(|metadata| ESSENTIAL_MOD_IDS.contains(&metadata.id.as_str()))
+}
+
+// rebuilds the overlay from the game dir as it is now, returns how many mods were left out
+pub fn prepare_overlay(game_dir: &Path, overlay: &Path) -> anyhow::Result<usize> {
+    // only the links go, not what they point at
+    if overlay.exists() {
+        std::fs::remove_dir_all(overlay)?;
+    }
+    std::fs::create_dir_all(overlay)?;
+    for entry in std::fs::read_dir(game_dir)? {
+        let entry = entry?;
+        if entry.file_name() == "mods" {
+            continue;
+        }
+        link(
+            &entry.path(),
+            &overlay.join(entry.file_name()),
+            entry.file_type()?.is_dir(),
+        )?;
+    }
+
+    let overlay_mods = mods::mods_dir(overlay);
+    std::fs::create_dir_all(&overlay_mods)?;
+    let mut disabled = 0;
+    for info in mods::list_mods(game_dir)? {
+        if is_essential(&info) {
+            link(&info.path, &overlay_mods.join(&info.file_name), false)?;
+        } else if info.enabled {
+            debug!(file = info.file_name, "left out in safe mode");
+            disabled += 1;
+        }
+    }
+    Ok(disabled)
+}
+
+#[cfg(unix)]
+fn link(original: &Path, link: &Path, _is_dir: bool) -> anyhow::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+        .with_context(|| format!("Could not link {} into safe mode", original.display()))
+}
+
+// symlinks need developer mode or an elevated launcher on Windows
+#[cfg(windows)]
+fn link(original: &Path, link: &Path, is_dir: bool) -> anyhow::Result<()> {
+    let linked = match is_dir {
+        true => std::os::windows::fs::symlink_dir(original, link),
+        false => std::os::windows::fs::symlink_file(original, link),
+    };
+    linked.with_context(|| {
+        format!(
+            "Could not link {} into safe mode, enable developer mode to allow symlinks",
+            original.display()
+        )
+    })
+}
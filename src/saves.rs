@@ -0,0 +1,387 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::anyhow;
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use time::{macros::format_description, OffsetDateTime};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{args, dedup};
+
+const SAVES_DIR: &str = "saves";
+const BACKUPS_DIR: &str = "backups";
+// held open by a running game, and meaningless in a backup anyway
+const SKIPPED_FILES: &[&str] = &["session.lock"];
+// corrupt files shouldn't be able to blow the stack
+const MAX_NBT_DEPTH: usize = 512;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct World {
+    // the folder under saves/, which is what every other function takes
+    pub folder: String,
+    // the name shown in game, None when level.dat is missing or unreadable
+    pub name: Option<String>,
+    // the version that last saved it, only recorded since 1.9
+    pub version: Option<String>,
+    #[serde(with = "time::serde::iso8601::option")]
+    pub last_played: Option<OffsetDateTime>,
+    pub path: PathBuf,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Backup {
+    pub world: String,
+    pub path: PathBuf,
+    #[serde(with = "time::serde::iso8601")]
+    pub created: OffsetDateTime,
+    pub size: u64,
+}
+
+pub fn saves_dir(game_dir: &Path) -> PathBuf {
+    game_dir.join(SAVES_DIR)
+}
+
+pub fn backups_dir(game_dir: &Path) -> PathBuf {
+    game_dir.join(BACKUPS_DIR)
+}
+
+pub fn list_worlds(game_dir: &Path) -> anyhow::Result<Vec<World>> {
+    let saves_dir = saves_dir(game_dir);
+    if !saves_dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut worlds = Vec::new();
+    for entry in std::fs::read_dir(saves_dir)? {
+        let path = entry?.path();
+        // leftovers of an interrupted restore
+        if !path.is_dir() || path.extension().is_some_and(|ext| ext == "restoring") {
+            continue;
+        }
+
+        let level = read_level(&path.join("level.dat")).ok();
+        let data = level.as_ref().and_then(|level| level.get("Data"));
+        worlds.push(World {
+            folder: path.file_name().unwrap().to_string_lossy().to_string(),
+            name: data
+                .and_then(|data| data.get("LevelName"))
+                .and_then(Nbt::as_str)
+                .map(|name| name.to_string()),
+            version: data
+                .and_then(|data| data.get("Version"))
+                .and_then(|version| version.get("Name"))
+                .and_then(Nbt::as_str)
+                .map(|name| name.to_string()),
+            last_played: data
+                .and_then(|data| data.get("LastPlayed"))
+                .and_then(Nbt::as_i64)
+                .and_then(|millis| {
+                    OffsetDateTime::from_unix_timestamp_nanos(millis as i128 * 1_000_000).ok()
+                }),
+            path,
+        });
+    }
+
+    worlds.sort_by_key(|world| std::cmp::Reverse(world.last_played));
+    Ok(worlds)
+}
+
+fn world_dir(game_dir: &Path, world: &str) -> anyhow::Result<PathBuf> {
+    args::validate_world_name(world)?;
+    let path = saves_dir(game_dir).join(world);
+    if !path.is_dir() {
+        return Err(anyhow!("No world named {}", world));
+    }
+    Ok(path)
+}
+
+// zips saves/<world> into backups/<world>/<world>_<timestamp>.zip
+pub fn backup(game_dir: &Path, world: &str) -> anyhow::Result<Backup> {
+    let world_dir = world_dir(game_dir, world)?;
+    let created = OffsetDateTime::now_utc();
+    let timestamp = created.format(format_description!(
+        "[year]-[month]-[day]_[hour]-[minute]-[second]"
+    ))?;
+
+    let dir = backups_dir(game_dir).join(world);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}_{}.zip", world, timestamp));
+    // written under a temporary name so an interrupted backup is never mistaken for a good one
+    let partial = dir.join(format!("{}_{}.zip.part", world, timestamp));
+
+    let mut zip = ZipWriter::new(std::fs::File::create(&partial)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for file in dedup::walk_files(&world_dir)? {
+        let relative = file.strip_prefix(&world_dir)?;
+        if SKIPPED_FILES
+            .iter()
+            .any(|skipped| relative == Path::new(skipped))
+        {
+            continue;
+        }
+
+        // zip64 headers only where needed, some unzip tools misread them
+        let large_file = std::fs::metadata(&file)?.len() >= u32::MAX as u64;
+        zip.start_file(
+            relative.to_string_lossy().replace('\\', "/"),
+            options.large_file(large_file),
+        )?;
+        std::io::copy(&mut std::fs::File::open(&file)?, &mut zip)?;
+    }
+    zip.finish()?.flush()?;
+    std::fs::rename(&partial, &path)?;
+
+    Ok(Backup {
+        world: world.to_string(),
+        size: std::fs::metadata(&path)?.len(),
+        path,
+        created,
+    })
+}
+
+// newest first. Every world's backups when `world` is None
+pub fn list_backups(game_dir: &Path, world: Option<&str>) -> anyhow::Result<Vec<Backup>> {
+    let backups_dir = backups_dir(game_dir);
+    if !backups_dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(&backups_dir)? {
+        let dir = entry?.path();
+        let folder = dir.file_name().unwrap().to_string_lossy().to_string();
+        if !dir.is_dir() || world.is_some_and(|world| world != folder) {
+            continue;
+        }
+
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "zip") {
+                continue;
+            }
+
+            let metadata = entry.metadata()?;
+            backups.push(Backup {
+                world: folder.clone(),
+                path,
+                created: metadata.modified()?.into(),
+                size: metadata.len(),
+            });
+        }
+    }
+
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created));
+    Ok(backups)
+}
+
+// replaces the world with the backup's contents. The current world is only removed once the
+// backup extracted cleanly
+pub fn restore(game_dir: &Path, backup: &Path) -> anyhow::Result<String> {
+    let world = backup
+        .parent()
+        .and_then(|dir| dir.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow!("{} is not a world backup", backup.display()))?;
+
+    let saves_dir = saves_dir(game_dir);
+    let staging = saves_dir.join(format!("{}.restoring", world));
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    std::fs::create_dir_all(&staging)?;
+
+    let mut archive = ZipArchive::new(std::fs::File::open(backup)?)?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        // ignore entries that would escape the world folder
+        let Some(relative) = file.enclosed_name().map(|name| name.to_path_buf()) else {
+            continue;
+        };
+
+        let out = staging.join(relative);
+        if file.is_dir() {
+            std::fs::create_dir_all(&out)?;
+            continue;
+        }
+        if let Some(parent) = out.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::io::copy(&mut file, &mut std::fs::File::create(&out)?)?;
+    }
+
+    let world_dir = saves_dir.join(&world);
+    if world_dir.exists() {
+        std::fs::remove_dir_all(&world_dir)?;
+    }
+    std::fs::rename(&staging, &world_dir)?;
+    Ok(world)
+}
+
+// keeps the newest `keep` backups of each world and drops any older than `max_age`
+pub fn prune(
+    game_dir: &Path,
+    world: Option<&str>,
+    keep: Option<usize>,
+    max_age: Option<Duration>,
+) -> anyhow::Result<Vec<Backup>> {
+    let now = SystemTime::now();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut removed = Vec::new();
+    for backup in list_backups(game_dir, world)? {
+        let index = seen.entry(backup.world.clone()).or_default();
+        *index += 1;
+
+        let too_many = keep.is_some_and(|keep| *index > keep);
+        let too_old = max_age.is_some_and(|max_age| {
+            now.duration_since(backup.created.into())
+                .is_ok_and(|age| age > max_age)
+        });
+        if too_many || too_old {
+            std::fs::remove_file(&backup.path)?;
+            removed.push(backup);
+        }
+    }
+    Ok(removed)
+}
+
+// backs up every world before a launch, then prunes down to `keep` per world
+pub fn backup_all(game_dir: &Path, keep: Option<usize>) -> anyhow::Result<Vec<Backup>> {
+    let mut backups = Vec::new();
+    for world in list_worlds(game_dir)? {
+        backups.push(backup(game_dir, &world.folder)?);
+    }
+    prune(game_dir, None, keep, None)?;
+    Ok(backups)
+}
+
+// just enough NBT to read level.dat, payloads we never look at are skipped
+#[derive(Debug)]
+enum Nbt {
+    // every integer width
+    Int(i64),
+    String(String),
+    Compound(HashMap<String, Nbt>),
+    Other,
+}
+
+impl Nbt {
+    fn get(&self, key: &str) -> Option<&Nbt> {
+        match self {
+            Nbt::Compound(entries) => entries.get(key),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Nbt::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn as_i64(&self) -> Option<i64> {
+        match self {
+            Nbt::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+fn read_level(path: &Path) -> anyhow::Result<Nbt> {
+    let mut bytes = Vec::new();
+    GzDecoder::new(std::fs::File::open(path)?).read_to_end(&mut bytes)?;
+    let mut reader = bytes.as_slice();
+
+    let tag = read_u8(&mut reader)?;
+    read_string(&mut reader)?;
+    read_payload(&mut reader, tag, 0)
+}
+
+fn read_payload(reader: &mut &[u8], tag: u8, depth: usize) -> anyhow::Result<Nbt> {
+    if depth > MAX_NBT_DEPTH {
+        return Err(anyhow!("NBT nested too deeply"));
+    }
+
+    Ok(match tag {
+        1 => Nbt::Int(read_u8(reader)? as i8 as i64),
+        2 => Nbt::Int(i16::from_be_bytes(read_array(reader)?) as i64),
+        3 => Nbt::Int(i32::from_be_bytes(read_array(reader)?) as i64),
+        4 => Nbt::Int(i64::from_be_bytes(read_array(reader)?)),
+        // float and double
+        5 => skip(reader, 4)?,
+        6 => skip(reader, 8)?,
+        // byte, int and long arrays
+        7 => {
+            let len = read_len(reader)?;
+            skip(reader, len)?
+        }
+        11 => {
+            let len = read_len(reader)? * 4;
+            skip(reader, len)?
+        }
+        12 => {
+            let len = read_len(reader)? * 8;
+            skip(reader, len)?
+        }
+        8 => Nbt::String(read_string(reader)?),
+        9 => {
+            let item_tag = read_u8(reader)?;
+            for _ in 0..read_len(reader)? {
+                read_payload(reader, item_tag, depth + 1)?;
+            }
+            Nbt::Other
+        }
+        10 => {
+            let mut entries = HashMap::new();
+            loop {
+                let tag = read_u8(reader)?;
+                if tag == 0 {
+                    break;
+                }
+                let name = read_string(reader)?;
+                entries.insert(name, read_payload(reader, tag, depth + 1)?);
+            }
+            Nbt::Compound(entries)
+        }
+        _ => return Err(anyhow!("Unknown NBT tag {}", tag)),
+    })
+}
+
+fn skip(reader: &mut &[u8], len: usize) -> anyhow::Result<Nbt> {
+    take(reader, len)?;
+    Ok(Nbt::Other)
+}
+
+fn take<'a>(reader: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    if reader.len() < len {
+        return Err(anyhow!("Unexpected end of NBT"));
+    }
+    let (taken, rest) = reader.split_at(len);
+    *reader = rest;
+    Ok(taken)
+}
+
+fn read_array<const N: usize>(reader: &mut &[u8]) -> anyhow::Result<[u8; N]> {
+    Ok(take(reader, N)?.try_into()?)
+}
+
+fn read_u8(reader: &mut &[u8]) -> anyhow::Result<u8> {
+    Ok(take(reader, 1)?[0])
+}
+
+// negative lengths only show up in corrupt files
+fn read_len(reader: &mut &[u8]) -> anyhow::Result<usize> {
+    Ok(usize::try_from(i32::from_be_bytes(read_array(reader)?)).unwrap_or(0))
+}
+
+// strings are modified UTF-8, which only differs from UTF-8 for NUL and surrogate pairs
+fn read_string(reader: &mut &[u8]) -> anyhow::Result<String> {
+    let len = u16::from_be_bytes(read_array(reader)?) as usize;
+    Ok(String::from_utf8_lossy(take(reader, len)?).to_string())
+}
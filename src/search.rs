@@ -0,0 +1,161 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::{instance, mods, versions};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchKind {
+    Instance,
+    Mod,
+    Version,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchEntry {
+    pub kind: SearchKind,
+    // instance name, mod id (or file name without metadata) or version id
+    pub id: String,
+    pub name: String,
+    // the instance a mod is installed in
+    pub instance: Option<String>,
+    // whether a version is installed locally rather than only listed in the manifest
+    pub installed: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub entry: SearchEntry,
+    pub score: u32,
+}
+
+// everything searchable, built from local files only
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    entries: Vec<SearchEntry>,
+}
+
+impl SearchIndex {
+    pub fn build(cache_dir: &Path, instances_dir: &Path) -> anyhow::Result<SearchIndex> {
+        let mut entries = Vec::new();
+
+        for instance in instance::list(instances_dir)? {
+            for installed_mod in mods::list_mods(&instance.game_dir(instances_dir))? {
+                let (id, name) = match installed_mod.metadata {
+                    Some(metadata) => (metadata.id, metadata.name),
+                    None => (installed_mod.file_name.clone(), installed_mod.file_name),
+                };
+                entries.push(SearchEntry {
+                    kind: SearchKind::Mod,
+                    id,
+                    name,
+                    instance: Some(instance.name.clone()),
+                    installed: true,
+                });
+            }
+
+            entries.push(SearchEntry {
+                kind: SearchKind::Instance,
+                id: instance.name.clone(),
+                name: instance.name,
+                instance: None,
+                installed: true,
+            });
+        }
+
+        let installed = versions::installed(cache_dir)?;
+        let manifest_ids = versions::cached_manifest(cache_dir)
+            .map(|manifest| manifest.versions.into_iter().map(|version| version.id))
+            .into_iter()
+            .flatten()
+            .filter(|id| !installed.contains(id))
+            .collect::<Vec<_>>();
+        for (id, installed) in installed
+            .into_iter()
+            .map(|id| (id, true))
+            .chain(manifest_ids.into_iter().map(|id| (id, false)))
+        {
+            entries.push(SearchEntry {
+                kind: SearchKind::Version,
+                name: id.clone(),
+                id,
+                instance: None,
+                installed,
+            });
+        }
+
+        Ok(SearchIndex { entries })
+    }
+
+    pub fn entries(&self) -> &[SearchEntry] {
+        &self.entries
+    }
+
+    // best matches first, an empty query matches nothing
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return vec![];
+        }
+
+        let mut hits = self
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let score = match_score(&entry.name, &query).max(match_score(&entry.id, &query));
+                (score > 0).then(|| SearchHit {
+                    entry: entry.clone(),
+                    score,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        hits.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then(a.entry.kind.cmp(&b.entry.kind))
+                .then(b.entry.installed.cmp(&a.entry.installed))
+                .then(a.entry.name.len().cmp(&b.entry.name.len()))
+                .then_with(|| a.entry.name.cmp(&b.entry.name))
+        });
+        hits.truncate(limit);
+        hits
+    }
+}
+
+// exact > prefix > word prefix > substring > in-order characters, 0 for no match
+fn match_score(text: &str, query: &str) -> u32 {
+    let text = text.to_lowercase();
+    if text == query {
+        100
+    } else if text.starts_with(query) {
+        80
+    } else if text
+        .match_indices(query)
+        .any(|(index, _)| text[..index].ends_with([' ', '-', '_', '.', ':']))
+    {
+        60
+    } else if text.contains(query) {
+        40
+    } else if is_subsequence(query, &text) {
+        10
+    } else {
+        0
+    }
+}
+
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut haystack = haystack.chars();
+    needle.chars().all(|c| haystack.any(|h| h == c))
+}
+
+pub fn search(
+    cache_dir: &Path,
+    instances_dir: &Path,
+    query: &str,
+    limit: usize,
+) -> anyhow::Result<Vec<SearchHit>> {
+    Ok(SearchIndex::build(cache_dir, instances_dir)?.search(query, limit))
+}
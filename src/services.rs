@@ -0,0 +1,66 @@
+use anyhow::anyhow;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ServiceOverrides {
+    pub auth_host: Option<String>,
+    pub account_host: Option<String>,
+    pub session_host: Option<String>,
+    pub services_host: Option<String>,
+    // a Yggdrasil-compatible auth server, hooked up through authlib-injector
+    pub authlib_injector: Option<String>,
+}
+
+impl ServiceOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.authlib_injector.is_none() && !self.has_hosts()
+    }
+
+    fn has_hosts(&self) -> bool {
+        self.hosts().iter().any(|(_, host)| host.is_some())
+    }
+
+    fn hosts(&self) -> [(&'static str, &Option<String>); 4] {
+        [
+            ("auth", &self.auth_host),
+            ("account", &self.account_host),
+            ("session", &self.session_host),
+            ("services", &self.services_host),
+        ]
+    }
+
+    pub fn jvm_args(&self) -> anyhow::Result<Vec<String>> {
+        if !self.has_hosts() {
+            return Ok(vec![]);
+        }
+        // the injector redirects every service itself
+        if self.authlib_injector.is_some() {
+            return Err(anyhow!(
+                "authlib_injector can't be combined with host overrides, set one or the other"
+            ));
+        }
+
+        // the game only honours the host properties in the custom environment
+        let mut args = vec![String::from("-Dminecraft.api.env=custom")];
+        for (service, host) in self.hosts() {
+            let Some(host) = host else {
+                continue;
+            };
+
+            let url = Url::parse(host)
+                .map_err(|e| anyhow!("Invalid {} host override {}: {}", service, host, e))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(anyhow!("{} host override {} must be http(s)", service, host));
+            }
+
+            args.push(format!(
+                "-Dminecraft.api.{}.host={}",
+                service,
+                host.trim_end_matches('/')
+            ));
+        }
+
+        Ok(args)
+    }
+}
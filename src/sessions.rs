@@ -0,0 +1,282 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{auth::Account, build_info};
+
+const SESSIONS_DIR: &str = "sessions";
+// the output of this many of the latest sessions is kept, the others' is deleted
+const KEPT_OUTPUTS: usize = 10;
+// the count of bytes ever written in front of the ring
+const OUTPUT_HEADER: u64 = 8;
+pub const DEFAULT_OUTPUT_BUFFER_MB: u64 = 4;
+
+// a running game, one file per launch under <work_dir>/sessions. Each launch holds a lock on its
+// own `.lock` file until the game exits, so entries whose lock is free were left behind by a
+// launcher that died and are cleaned up when listing
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    pub id: String,
+    pub launcher_pid: u32,
+    pub game_pid: Option<u32>,
+    pub version: String,
+    pub instance: Option<String>,
+    pub game_dir: PathBuf,
+    pub account: Option<SessionAccount>,
+    #[serde(with = "time::serde::iso8601")]
+    pub started_at: OffsetDateTime,
+    // build_info::VERSION of the launcher that started it, empty for older launchers
+    #[serde(default)]
+    pub launcher_version: String,
+}
+
+// who a session plays as, never the tokens
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SessionAccount {
+    pub username: String,
+    pub uuid: String,
+}
+
+impl From<&Account> for SessionAccount {
+    fn from(account: &Account) -> Self {
+        SessionAccount {
+            username: account.username.clone(),
+            uuid: account.uuid.clone(),
+        }
+    }
+}
+
+// removes the session when the launch ends, however it ends
+#[derive(Debug)]
+pub struct SessionGuard {
+    json: PathBuf,
+    lock_path: PathBuf,
+    _lock: File,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.json);
+        let _ = std::fs::remove_file(&self.lock_path);
+    }
+}
+
+fn sessions_dir(work_dir: &Path) -> PathBuf {
+    work_dir.join(SESSIONS_DIR)
+}
+
+impl Session {
+    pub fn new(
+        version: &str,
+        instance: Option<&str>,
+        game_dir: &Path,
+        account: Option<&Account>,
+        game_pid: Option<u32>,
+    ) -> Session {
+        let launcher_pid = std::process::id();
+        let started_at = OffsetDateTime::now_utc();
+        Session {
+            id: format!("{}-{}", launcher_pid, started_at.unix_timestamp_nanos()),
+            launcher_pid,
+            game_pid,
+            version: version.to_string(),
+            instance: instance.map(str::to_string),
+            game_dir: game_dir.to_path_buf(),
+            account: account.map(SessionAccount::from),
+            started_at,
+            launcher_version: build_info::VERSION.to_string(),
+        }
+    }
+
+    pub fn register(&self, work_dir: &Path) -> anyhow::Result<SessionGuard> {
+        let dir = sessions_dir(work_dir);
+        std::fs::create_dir_all(&dir)?;
+        // locked before the JSON appears, so listing never takes a fresh session for a stale one
+        let lock_path = dir.join(format!("{}.lock", self.id));
+        let lock = File::create(&lock_path)?;
+        lock.try_lock_exclusive()
+            .with_context(|| format!("Could not lock {}", lock_path.display()))?;
+
+        let json = dir.join(format!("{}.json", self.id));
+        std::fs::write(&json, serde_json::to_string_pretty(self)?)?;
+        Ok(SessionGuard {
+            json,
+            lock_path,
+            _lock: lock,
+        })
+    }
+}
+
+// the end of a session's output, <id>.output next to the session: the number of bytes ever
+// written, then a ring of `capacity` bytes. It stays after the game exits, so the output of a
+// crash can be looked at afterwards even when nothing else logged it
+pub struct OutputRing {
+    file: File,
+    capacity: u64,
+    written: u64,
+}
+
+impl OutputRing {
+    pub fn push_line(&mut self, line: &str) -> std::io::Result<()> {
+        let mut bytes = format!("{}\n", line).into_bytes();
+        // only the end of a line longer than the whole ring survives anyway
+        if bytes.len() as u64 > self.capacity {
+            bytes.drain(..bytes.len() - self.capacity as usize);
+        }
+        let mut rest = bytes.as_slice();
+        while !rest.is_empty() {
+            let position = self.written % self.capacity;
+            let chunk = rest.len().min((self.capacity - position) as usize);
+            self.file.seek(SeekFrom::Start(OUTPUT_HEADER + position))?;
+            self.file.write_all(&rest[..chunk])?;
+            self.written += chunk as u64;
+            rest = &rest[chunk..];
+        }
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&self.written.to_le_bytes())
+    }
+}
+
+fn output_path(work_dir: &Path, id: &str) -> anyhow::Result<PathBuf> {
+    if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+        return Err(anyhow!("Invalid session id {}", id));
+    }
+    Ok(sessions_dir(work_dir).join(format!("{}.output", id)))
+}
+
+impl Session {
+    // starts keeping the last `capacity` bytes of the game's output, removing the output of all
+    // but the latest sessions
+    pub fn output_ring(&self, work_dir: &Path, capacity: u64) -> anyhow::Result<OutputRing> {
+        let path = output_path(work_dir, &self.id)?;
+        let capacity = capacity.max(1);
+        let mut outputs = std::fs::read_dir(sessions_dir(work_dir))?
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "output"))
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .collect::<Vec<_>>();
+        outputs.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+        // this session's is the newest
+        for (_, old) in outputs.iter().skip(KEPT_OUTPUTS.saturating_sub(1)) {
+            let _ = std::fs::remove_file(old);
+        }
+
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| format!("Could not create {}", path.display()))?;
+        file.set_len(OUTPUT_HEADER + capacity)?;
+        Ok(OutputRing {
+            file,
+            capacity,
+            written: 0,
+        })
+    }
+}
+
+// what the ring of a running or ended session holds, oldest line first
+pub fn read_output(work_dir: &Path, id: &str) -> anyhow::Result<String> {
+    let path = output_path(work_dir, id)?;
+    let mut bytes = Vec::new();
+    File::open(&path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|_| anyhow!("No output kept for session {}", id))?;
+    if (bytes.len() as u64) < OUTPUT_HEADER {
+        return Err(anyhow!("{} is not a session output", path.display()));
+    }
+    let (header, ring) = bytes.split_at(OUTPUT_HEADER as usize);
+    let written = u64::from_le_bytes(header.try_into()?);
+    let capacity = ring.len() as u64;
+    if written <= capacity {
+        return Ok(String::from_utf8_lossy(&ring[..written as usize]).into_owned());
+    }
+    let start = (written % capacity) as usize;
+    let ordered = [&ring[start..], &ring[..start]].concat();
+    // the oldest line was partly overwritten
+    let first_line = ordered
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    Ok(String::from_utf8_lossy(&ordered[first_line..]).into_owned())
+}
+
+// running sessions, oldest first
+pub fn list(work_dir: &Path) -> anyhow::Result<Vec<Session>> {
+    let dir = sessions_dir(work_dir);
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Ok(vec![]);
+    };
+
+    let mut sessions = Vec::new();
+    for entry in entries.flatten() {
+        let json = entry.path();
+        if json.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let lock_path = json.with_extension("lock");
+        let alive = File::open(&lock_path).is_ok_and(|lock| lock.try_lock_exclusive().is_err());
+        if !alive {
+            let _ = std::fs::remove_file(&json);
+            let _ = std::fs::remove_file(&lock_path);
+            continue;
+        }
+        // a session still being written, it shows up next time
+        let Ok(session) = std::fs::read_to_string(&json) else {
+            continue;
+        };
+        if let Ok(session) = serde_json::from_str::<Session>(&session) {
+            sessions.push(session);
+        }
+    }
+    sessions.sort_by_key(|session| session.started_at);
+    Ok(sessions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_output_outlives_the_session() {
+        let work_dir = std::env::temp_dir().join(format!(
+            "mod_launcher_session_output_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&work_dir);
+        let session = Session::new("fixture-1.0", None, &work_dir, None, None);
+        let guard = session.register(&work_dir).unwrap();
+        let mut ring = session.output_ring(&work_dir, 64).unwrap();
+
+        ring.push_line("starting").unwrap();
+        assert_eq!(read_output(&work_dir, &session.id).unwrap(), "starting\n");
+        for i in 0..20 {
+            ring.push_line(&format!("line {}", i)).unwrap();
+        }
+        drop((ring, guard));
+
+        // the end of it, in order and without the partly overwritten line
+        let output = read_output(&work_dir, &session.id).unwrap();
+        assert!(output.len() <= 64);
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.last(), Some(&"line 19"));
+        for (line, i) in lines.iter().rev().zip((0..20).rev()) {
+            assert_eq!(*line, format!("line {}", i));
+        }
+        assert!(list(&work_dir).unwrap().is_empty());
+
+        assert!(read_output(&work_dir, "../launcher").is_err());
+        assert!(read_output(&work_dir, "unknown").is_err());
+
+        std::fs::remove_dir_all(work_dir).unwrap();
+    }
+}
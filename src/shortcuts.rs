@@ -0,0 +1,231 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+
+use crate::{
+    instance::Instance,
+    watchdog::{self, Limits},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShortcutKind {
+    // Windows .lnk
+    Lnk,
+    // freedesktop .desktop entry
+    DesktopEntry,
+    // Finder alias to a launch script kept in the work dir, aliases can't carry arguments
+    Alias,
+}
+
+impl ShortcutKind {
+    pub fn native() -> ShortcutKind {
+        match std::env::consts::OS {
+            "windows" => ShortcutKind::Lnk,
+            "macos" => ShortcutKind::Alias,
+            _ => ShortcutKind::DesktopEntry,
+        }
+    }
+
+    pub fn default_dir(&self) -> Option<PathBuf> {
+        let home = std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(PathBuf::from)?;
+
+        Some(match self {
+            ShortcutKind::Lnk => home.join("Desktop"),
+            ShortcutKind::Alias => home.join("Applications"),
+            ShortcutKind::DesktopEntry => std::env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".local").join("share"))
+                .join("applications"),
+        })
+    }
+}
+
+pub struct Shortcut<'a> {
+    pub instance: &'a Instance,
+    pub work_dir: &'a Path,
+    // the launcher binary the shortcut invokes
+    pub executable: &'a Path,
+    pub icon: Option<&'a Path>,
+    // for PowerShell writing .lnk files and Finder making aliases
+    pub limits: Limits,
+}
+
+// the CLI invocation every external entry point uses to start an instance
+pub fn launch_args(work_dir: &Path, instance: &Instance) -> Vec<String> {
+    vec![
+        String::from("--work-dir"),
+        work_dir.to_string_lossy().into_owned(),
+        String::from("launch"),
+        String::from("--instance"),
+        instance.name.clone(),
+    ]
+}
+
+impl Shortcut<'_> {
+    fn launch_args(&self) -> Vec<String> {
+        launch_args(self.work_dir, self.instance)
+    }
+
+    pub async fn create(&self, kind: ShortcutKind, dest_dir: &Path) -> anyhow::Result<PathBuf> {
+        tokio::fs::create_dir_all(dest_dir).await?;
+        match kind {
+            ShortcutKind::DesktopEntry => self.create_desktop_entry(dest_dir).await,
+            ShortcutKind::Lnk => self.create_lnk(dest_dir).await,
+            ShortcutKind::Alias => self.create_alias(dest_dir).await,
+        }
+    }
+
+    async fn create_desktop_entry(&self, dest_dir: &Path) -> anyhow::Result<PathBuf> {
+        let exec = std::iter::once(self.executable.to_string_lossy().into_owned())
+            .chain(self.launch_args())
+            .map(|arg| desktop_quote(&arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut entry = format!(
+            "[Desktop Entry]\nType=Application\nName={}\nComment=Launch the {} instance\nExec={}\nTerminal=false\nCategories=Game;\n",
+            self.instance.name, self.instance.name, exec
+        );
+        if let Some(icon) = self.icon {
+            entry.push_str(&format!("Icon={}\n", icon.display()));
+        }
+
+        let path = dest_dir.join(format!("mod_launcher-{}.desktop", file_stem(&self.instance.name)));
+        tokio::fs::write(&path, entry).await?;
+        set_executable(&path).await?;
+        Ok(path)
+    }
+
+    async fn create_lnk(&self, dest_dir: &Path) -> anyhow::Result<PathBuf> {
+        let path = dest_dir.join(format!("{}.lnk", file_stem(&self.instance.name)));
+        let arguments = self
+            .launch_args()
+            .iter()
+            .map(|arg| format!("\"{}\"", arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        // the .lnk format is undocumented enough that letting the shell write it is the safest option
+        let mut script = format!(
+            "$s = (New-Object -ComObject WScript.Shell).CreateShortcut({});$s.TargetPath = {};$s.Arguments = {};$s.WorkingDirectory = {};",
+            ps_quote(&path.to_string_lossy()),
+            ps_quote(&self.executable.to_string_lossy()),
+            ps_quote(&arguments),
+            ps_quote(&self.work_dir.to_string_lossy()),
+        );
+        if let Some(icon) = self.icon {
+            script.push_str(&format!("$s.IconLocation = {};", ps_quote(&icon.to_string_lossy())));
+        }
+        script.push_str("$s.Save()");
+
+        let mut command = tokio::process::Command::new("powershell");
+        command.args(["-NoProfile", "-NonInteractive", "-Command", &script]);
+        let output = watchdog::output(&mut command, self.limits).await?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to create shortcut {}: {}",
+                path.display(),
+                output.stderr.trim()
+            ));
+        }
+        Ok(path)
+    }
+
+    async fn create_alias(&self, dest_dir: &Path) -> anyhow::Result<PathBuf> {
+        let name = file_stem(&self.instance.name);
+        let scripts_dir = self.work_dir.join("shortcuts");
+        tokio::fs::create_dir_all(&scripts_dir).await?;
+        let command = std::iter::once(self.executable.to_string_lossy().into_owned())
+            .chain(self.launch_args())
+            .map(|arg| sh_quote(&arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let script_path = scripts_dir.join(format!("{}.command", name));
+        tokio::fs::write(&script_path, format!("#!/bin/sh\nexec {}\n", command)).await?;
+        set_executable(&script_path).await?;
+
+        // Finder would name a second one "<name> alias 2"
+        let path = dest_dir.join(&name);
+        if tokio::fs::symlink_metadata(&path).await.is_ok() {
+            tokio::fs::remove_file(&path).await?;
+        }
+        // alias files are Finder's own format, it is the one to write them
+        let mut lines = vec![
+            String::from("use framework \"AppKit\""),
+            String::from("use scripting additions"),
+            String::from("tell application \"Finder\""),
+            format!(
+                "set theAlias to make new alias file at (POSIX file {} as alias) to (POSIX file {} as alias)",
+                as_quote(&dest_dir.to_string_lossy()),
+                as_quote(&script_path.to_string_lossy())
+            ),
+            format!("set name of theAlias to {}", as_quote(&name)),
+            String::from("end tell"),
+        ];
+        if let Some(icon) = self.icon {
+            lines.push(format!(
+                "set theIcon to current application's NSImage's alloc()'s initWithContentsOfFile:{}",
+                as_quote(&icon.to_string_lossy())
+            ));
+            lines.push(format!(
+                "current application's NSWorkspace's sharedWorkspace()'s setIcon:theIcon forFile:{} options:0",
+                as_quote(&path.to_string_lossy())
+            ));
+        }
+
+        let mut command = tokio::process::Command::new("osascript");
+        for line in &lines {
+            command.args(["-e", line]);
+        }
+        let output = watchdog::output(&mut command, self.limits).await?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to create shortcut {}: {}",
+                path.display(),
+                output.stderr.trim()
+            ));
+        }
+        Ok(path)
+    }
+}
+
+fn file_stem(name: &str) -> String {
+    name.replace(' ', "_")
+}
+
+fn desktop_quote(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn ps_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn as_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(unix)]
+async fn set_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).await
+}
+
+#[cfg(not(unix))]
+async fn set_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use ed25519_dalek::Signer;
+
+use crate::write_atomic;
+
+// what is signed is this followed by the archive's SHA-256, so a signature over anything else
+// can't be passed off as one over an archive
+const CONTEXT: &[u8] = b"mod_launcher archive signature v1\0";
+
+// an Ed25519 key for signing bundles and instance archives, kept as its base64 seed
+pub struct SigningKey {
+    key: ed25519_dalek::SigningKey,
+}
+
+impl SigningKey {
+    pub fn generate() -> anyhow::Result<SigningKey> {
+        let mut seed = [0; 32];
+        getrandom::getrandom(&mut seed).map_err(|e| anyhow!("Could not generate a key: {}", e))?;
+        Ok(SigningKey::from_seed(seed))
+    }
+
+    pub fn from_seed(seed: [u8; 32]) -> SigningKey {
+        SigningKey {
+            key: ed25519_dalek::SigningKey::from_bytes(&seed),
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<SigningKey> {
+        let encoded = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read signing key {}", path.display()))?;
+        let seed = STANDARD
+            .decode(encoded.trim())
+            .ok()
+            .and_then(|seed| <[u8; 32]>::try_from(seed).ok())
+            .ok_or_else(|| anyhow!("{} is not a signing key", path.display()))?;
+        Ok(SigningKey::from_seed(seed))
+    }
+
+    // readable by the owner only where the platform allows it
+    pub async fn save(&self, path: &Path) -> anyhow::Result<()> {
+        write_atomic(path, STANDARD.encode(self.key.to_bytes())).await?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    // base64, what trusted_keys in launcher.toml lists
+    pub fn public_key(&self) -> String {
+        STANDARD.encode(self.key.verifying_key().as_bytes())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> [u8; 64] {
+        self.key.sign(message).to_bytes()
+    }
+}
+
+pub fn verify(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    // strict, a signature only verifies in its one canonical encoding and not under weak keys
+    ed25519_dalek::VerifyingKey::from_bytes(public_key).is_ok_and(|key| {
+        key.verify_strict(message, &ed25519_dalek::Signature::from_bytes(signature))
+            .is_ok()
+    })
+}
+
+// kept next to the archive as <archive>.sig
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    // base64 public key
+    pub key: String,
+    // base64
+    pub signature: String,
+}
+
+// which archives are accepted, from launcher.toml
+#[derive(Debug, Clone, Default)]
+pub struct TrustPolicy {
+    // base64 public keys
+    pub trusted_keys: Vec<String>,
+    // refuse archives without a signature from a trusted key
+    pub required: bool,
+}
+
+pub fn signature_path(archive: &Path) -> PathBuf {
+    let mut path = archive.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+fn signed_message(archive: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut file =
+        std::fs::File::open(archive).with_context(|| format!("Missing {}", archive.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok([CONTEXT, &hasher.finalize()].concat())
+}
+
+// writes <archive>.sig, returns its path
+pub async fn sign_archive(key: &SigningKey, archive: &Path) -> anyhow::Result<PathBuf> {
+    let message = signed_message(archive)?;
+    let signature = Signature {
+        key: key.public_key(),
+        signature: STANDARD.encode(key.sign(&message)),
+    };
+    let path = signature_path(archive);
+    write_atomic(&path, serde_json::to_string_pretty(&signature)?).await?;
+    Ok(path)
+}
+
+// checks <archive>.sig against the archive before it is installed or imported. A signature that
+// doesn't match always fails, a missing or untrusted one only when the policy requires
+// signatures. Returns the key the archive is signed with when that key is trusted
+pub fn check_archive(archive: &Path, policy: &TrustPolicy) -> anyhow::Result<Option<String>> {
+    let path = signature_path(archive);
+    let signature = match std::fs::read_to_string(&path) {
+        Ok(json) => serde_json::from_str::<Signature>(&json)
+            .with_context(|| format!("{} is not a signature", path.display()))?,
+        Err(_) if policy.required => {
+            return Err(anyhow!(
+                "{} is not signed, and launcher.toml requires signatures",
+                archive.display()
+            ));
+        }
+        Err(_) => return Ok(None),
+    };
+
+    let key = STANDARD
+        .decode(&signature.key)
+        .ok()
+        .and_then(|key| <[u8; 32]>::try_from(key).ok())
+        .ok_or_else(|| anyhow!("{} has an invalid key", path.display()))?;
+    let bytes = STANDARD
+        .decode(&signature.signature)
+        .ok()
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .ok_or_else(|| anyhow!("{} has an invalid signature", path.display()))?;
+    if !verify(&key, &signed_message(archive)?, &bytes) {
+        return Err(anyhow!(
+            "The signature of {} does not match, it was changed after being signed",
+            archive.display()
+        ));
+    }
+
+    if !policy.trusted_keys.contains(&signature.key) {
+        if policy.required {
+            return Err(anyhow!(
+                "{} is signed by {}, which is not one of the trusted keys",
+                archive.display(),
+                signature.key
+            ));
+        }
+        warn!(
+            "{} is signed by {}, which is not one of the trusted keys",
+            archive.display(),
+            signature.key
+        );
+        return Ok(None);
+    }
+    info!("{} is signed by {}", archive.display(), signature.key);
+    Ok(Some(signature.key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn signatures_match_rfc_8032() {
+        // RFC 8032 section 7.1, tests 1 and 2
+        let vectors = [
+            (
+                "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+                "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+                "",
+                "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+            ),
+            (
+                "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+                "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+                "72",
+                "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+            ),
+        ];
+        for (seed, public, message, signature) in vectors {
+            let key = SigningKey::from_seed(hex(seed).try_into().unwrap());
+            assert_eq!(STANDARD.decode(key.public_key()).unwrap(), hex(public));
+            let message = hex(message);
+            let signed = key.sign(&message);
+            assert_eq!(signed.to_vec(), hex(signature));
+
+            let public: [u8; 32] = hex(public).try_into().unwrap();
+            assert!(verify(&public, &message, &signed));
+            assert!(!verify(&public, b"something else", &signed));
+            let mut flipped = signed;
+            flipped[10] ^= 1;
+            assert!(!verify(&public, &message, &flipped));
+            // S + L, the same signature in a second encoding
+            let mut malleable = signed;
+            let mut carry = 0;
+            let order = hex("edd3f55c1a631258d69cf7a2def9de1400000000000000000000000000000010");
+            for (byte, l) in malleable[32..].iter_mut().zip(order) {
+                let sum = *byte as u16 + l as u16 + carry;
+                *byte = sum as u8;
+                carry = sum >> 8;
+            }
+            assert!(!verify(&public, &message, &malleable));
+        }
+    }
+}
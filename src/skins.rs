@@ -0,0 +1,219 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::auth::Account;
+
+const PROFILE_URL: &str = "https://api.minecraftservices.com/minecraft/profile";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SkinVariant {
+    // Steve's 4px arms
+    #[default]
+    Classic,
+    // Alex's 3px arms
+    Slim,
+}
+
+impl SkinVariant {
+    // uploads want it lowercase, responses give it uppercase
+    fn id(&self) -> &'static str {
+        match self {
+            SkinVariant::Classic => "classic",
+            SkinVariant::Slim => "slim",
+        }
+    }
+}
+
+impl fmt::Display for SkinVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+impl FromStr for SkinVariant {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "classic" | "wide" => Ok(SkinVariant::Classic),
+            "slim" => Ok(SkinVariant::Slim),
+            _ => Err(anyhow!("Unknown skin variant {}, use classic or slim", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Skin {
+    pub id: String,
+    // "ACTIVE" or "INACTIVE"
+    pub state: String,
+    pub url: String,
+    pub variant: SkinVariant,
+    // set for the default skins, e.g. "STEVE"
+    pub alias: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Cape {
+    pub id: String,
+    pub state: String,
+    pub url: String,
+    pub alias: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Profile {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub skins: Vec<Skin>,
+    // every cape the player owns, at most one of them active
+    #[serde(default)]
+    pub capes: Vec<Cape>,
+}
+
+impl Profile {
+    pub fn active_skin(&self) -> Option<&Skin> {
+        self.skins.iter().find(|skin| skin.state == "ACTIVE")
+    }
+
+    pub fn active_cape(&self) -> Option<&Cape> {
+        self.capes.iter().find(|cape| cape.state == "ACTIVE")
+    }
+}
+
+// every profile endpoint answers with the updated profile
+async fn send(request: reqwest::RequestBuilder) -> anyhow::Result<Profile> {
+    let response = request.send().await?;
+    match response.status() {
+        reqwest::StatusCode::UNAUTHORIZED => {
+            Err(anyhow!("Your login has expired, run `login` again"))
+        }
+        reqwest::StatusCode::NOT_FOUND => Err(anyhow!("This account does not own Minecraft")),
+        status if !status.is_success() => Err(anyhow!(
+            "Minecraft services returned {}: {}",
+            status,
+            response.text().await.unwrap_or_default()
+        )),
+        _ => Ok(response.json().await?),
+    }
+}
+
+pub async fn profile(client: &reqwest::Client, account: &Account) -> anyhow::Result<Profile> {
+    send(client.get(PROFILE_URL).bearer_auth(&account.access_token)).await
+}
+
+// the game only accepts 64x64 and legacy 64x32 skins
+pub fn validate_skin(png: &[u8]) -> anyhow::Result<()> {
+    // the IHDR chunk comes first, width and height are its first two fields
+    if png.len() < 24 || !png.starts_with(PNG_SIGNATURE) || &png[12..16] != b"IHDR" {
+        return Err(anyhow!("Skins must be PNG images"));
+    }
+    let width = u32::from_be_bytes(png[16..20].try_into()?);
+    let height = u32::from_be_bytes(png[20..24].try_into()?);
+    if width != 64 || (height != 64 && height != 32) {
+        return Err(anyhow!(
+            "Skins must be 64x64 or 64x32, this one is {}x{}",
+            width,
+            height
+        ));
+    }
+    Ok(())
+}
+
+pub async fn upload_skin(
+    client: &reqwest::Client,
+    account: &Account,
+    png: Vec<u8>,
+    variant: SkinVariant,
+) -> anyhow::Result<Profile> {
+    validate_skin(&png)?;
+
+    // built by hand, reqwest's multipart support drags in mime guessing we don't need.
+    // the boundary only has to be unlikely to show up inside the PNG
+    let nonce = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    let boundary = format!("mod_launcher{:x}", nonce);
+    let mut body = Vec::new();
+    body.extend(
+        format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"variant\"\r\n\r\n{}\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"skin.png\"\r\n\
+             Content-Type: image/png\r\n\r\n",
+            variant.id(),
+            b = boundary
+        )
+        .into_bytes(),
+    );
+    body.extend(png);
+    body.extend(format!("\r\n--{}--\r\n", boundary).into_bytes());
+
+    send(
+        client
+            .post(format!("{}/skins", PROFILE_URL))
+            .bearer_auth(&account.access_token)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body),
+    )
+    .await
+}
+
+// points the skin at an image Mojang downloads itself
+pub async fn set_skin_url(
+    client: &reqwest::Client,
+    account: &Account,
+    url: &str,
+    variant: SkinVariant,
+) -> anyhow::Result<Profile> {
+    send(
+        client
+            .post(format!("{}/skins", PROFILE_URL))
+            .bearer_auth(&account.access_token)
+            .json(&json!({ "variant": variant.id(), "url": url })),
+    )
+    .await
+}
+
+// back to the default skin
+pub async fn reset_skin(client: &reqwest::Client, account: &Account) -> anyhow::Result<Profile> {
+    send(
+        client
+            .delete(format!("{}/skins/active", PROFILE_URL))
+            .bearer_auth(&account.access_token),
+    )
+    .await
+}
+
+pub async fn show_cape(
+    client: &reqwest::Client,
+    account: &Account,
+    cape_id: &str,
+) -> anyhow::Result<Profile> {
+    send(
+        client
+            .put(format!("{}/capes/active", PROFILE_URL))
+            .bearer_auth(&account.access_token)
+            .json(&json!({ "capeId": cape_id })),
+    )
+    .await
+}
+
+pub async fn hide_cape(client: &reqwest::Client, account: &Account) -> anyhow::Result<Profile> {
+    send(
+        client
+            .delete(format!("{}/capes/active", PROFILE_URL))
+            .bearer_auth(&account.access_token),
+    )
+    .await
+}
@@ -0,0 +1,116 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+
+// writes slower than this on average point at a real-time scanner inspecting every file
+const SLOW_WRITE: Duration = Duration::from_millis(10);
+// a handful of files says little about the disk
+const MIN_SAMPLE_FILES: u64 = 50;
+
+// how long writing files one by one took, to tell a slow disk or a scanner from a slow network
+#[derive(Debug, Default)]
+pub struct WriteStats {
+    files: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl WriteStats {
+    // timed from creating the file until it's closed, scanners on Windows look at it on close
+    pub(crate) fn write(&self, path: &Path, contents: &mut impl Read) -> anyhow::Result<u64> {
+        let start = Instant::now();
+        let mut file = File::create(path)?;
+        let bytes = std::io::copy(contents, &mut file)?;
+        drop(file);
+        self.files.fetch_add(1, Ordering::Relaxed);
+        self.nanos
+            .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        Ok(bytes)
+    }
+
+    fn average(&self) -> Option<Duration> {
+        let files = self.files.load(Ordering::Relaxed);
+        (files >= MIN_SAMPLE_FILES)
+            .then(|| Duration::from_nanos(self.nanos.load(Ordering::Relaxed) / files))
+    }
+
+    // only ever a suggestion, excluding directories from scanning is up to the user
+    pub fn warn_if_slow(&self, exclude: &[&Path]) {
+        let Some(average) = self.average().filter(|average| *average >= SLOW_WRITE) else {
+            return;
+        };
+        let exclude = exclude
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect::<Vec<_>>();
+        warn!(
+            event = "slow_io",
+            files = self.files.load(Ordering::Relaxed),
+            average_write_ms = average.as_millis() as u64,
+            exclude = ?exclude,
+            "Writing files took {} ms each, real-time antivirus scanning may be slowing installs down. Consider excluding {} from scanning",
+            average.as_millis(),
+            exclude.join(" and ")
+        );
+    }
+}
+
+// collects an install's files next to their target and moves them in by whole directories once
+// everything is written, so a scanner watching the target sees a few renames instead of every
+// file landing. Removed again when dropped, committed or not
+#[derive(Debug)]
+pub struct Staging {
+    dir: PathBuf,
+    target: PathBuf,
+}
+
+impl Staging {
+    // a sibling, renames don't work across filesystems
+    pub fn new(target: &Path) -> anyhow::Result<Staging> {
+        let name = target
+            .file_name()
+            .map_or_else(Default::default, |name| name.to_string_lossy());
+        let dir = target.with_file_name(format!(".{}.staging-{}", name, std::process::id()));
+        // left by a crashed install
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        Ok(Staging {
+            dir,
+            target: target.to_path_buf(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn commit(self) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.target)?;
+        move_into(&self.dir, &self.target)
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+// only descends where the target already has a directory of the same name
+fn move_into(from: &Path, to: &Path) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() && dest.is_dir() {
+            move_into(&entry.path(), &dest)?;
+        } else {
+            std::fs::rename(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
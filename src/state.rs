@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::AccountStore,
+    config::Config,
+    instance::{self, Instance},
+    sessions::{self, Session},
+    tasks::{Task, TaskQueue},
+    versions, Version,
+};
+
+// what a frontend may know about a login, never the tokens
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AccountSummary {
+    pub username: String,
+    pub uuid: String,
+    pub selected: bool,
+}
+
+impl AccountSummary {
+    pub fn list(store: &AccountStore) -> Vec<AccountSummary> {
+        store
+            .accounts
+            .iter()
+            .map(|account| AccountSummary {
+                username: account.username.clone(),
+                uuid: account.uuid.clone(),
+                selected: store.selected.as_ref() == Some(&account.uuid),
+            })
+            .collect()
+    }
+}
+
+// everything a frontend renders its first view from. What changes afterwards arrives as the
+// daemon's instance.changed, task.changed and account.changed notifications
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LauncherState {
+    pub accounts: Vec<AccountSummary>,
+    pub instances: Vec<Instance>,
+    // games running right now, from this and other launcher processes
+    pub sessions: Vec<Session>,
+    // queued, running and paused
+    pub tasks: Vec<Task>,
+    // the version list as last fetched online, empty before the first time
+    pub versions: Vec<Version>,
+    // ids with a JSON in the cache, vanilla and modded
+    pub installed_versions: Vec<String>,
+}
+
+impl LauncherState {
+    // reads what is on disk, nothing goes to the network
+    pub fn snapshot(config: &Config) -> anyhow::Result<LauncherState> {
+        let work_dir = &config.work_dir;
+        let cache_dir = config.cache_dir();
+
+        let tasks = TaskQueue::open(work_dir)?
+            .tasks()
+            .filter(|task| !task.status.is_finished())
+            .cloned()
+            .collect();
+
+        Ok(LauncherState {
+            accounts: AccountSummary::list(&AccountStore::load(work_dir)?),
+            instances: instance::list(&config.instances_dir())?,
+            sessions: sessions::list(work_dir)?,
+            tasks,
+            versions: versions::cached_manifest(&cache_dir)
+                .map(|manifest| manifest.versions)
+                .unwrap_or_default(),
+            installed_versions: versions::installed(&cache_dir)?,
+        })
+    }
+}
@@ -0,0 +1,294 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+
+use crate::{instance::Instance, shortcuts};
+
+// binary VDF type tags
+const TYPE_MAP: u8 = 0x00;
+const TYPE_STRING: u8 = 0x01;
+const TYPE_INT: u8 = 0x02;
+const TYPE_UINT64: u8 = 0x07;
+const TYPE_MAP_END: u8 = 0x08;
+
+#[derive(Debug, Clone, PartialEq)]
+enum VdfValue {
+    Map(Vec<(String, VdfValue)>),
+    String(String),
+    Int(u32),
+    Uint64(u64),
+}
+
+impl VdfValue {
+    fn get(&self, key: &str) -> Option<&VdfValue> {
+        match self {
+            VdfValue::Map(entries) => entries
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+fn parse_vdf(bytes: &[u8]) -> anyhow::Result<Vec<(String, VdfValue)>> {
+    let mut pos = 0;
+    let entries = parse_map(bytes, &mut pos)?;
+    Ok(entries)
+}
+
+fn parse_map(bytes: &[u8], pos: &mut usize) -> anyhow::Result<Vec<(String, VdfValue)>> {
+    let mut entries = Vec::new();
+    loop {
+        let Some(&tag) = bytes.get(*pos) else {
+            // the top level map isn't always terminated
+            return Ok(entries);
+        };
+        *pos += 1;
+
+        if tag == TYPE_MAP_END {
+            return Ok(entries);
+        }
+
+        let key = read_cstring(bytes, pos)?;
+        let value = match tag {
+            TYPE_MAP => VdfValue::Map(parse_map(bytes, pos)?),
+            TYPE_STRING => VdfValue::String(read_cstring(bytes, pos)?),
+            TYPE_INT => VdfValue::Int(u32::from_le_bytes(read_array(bytes, pos)?)),
+            TYPE_UINT64 => VdfValue::Uint64(u64::from_le_bytes(read_array(bytes, pos)?)),
+            _ => return Err(anyhow!("Unknown VDF type {:#x} at offset {}", tag, *pos - 1)),
+        };
+        entries.push((key, value));
+    }
+}
+
+fn read_cstring(bytes: &[u8], pos: &mut usize) -> anyhow::Result<String> {
+    let end = bytes[*pos..]
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| anyhow!("Unterminated VDF string"))?;
+    let value = String::from_utf8_lossy(&bytes[*pos..*pos + end]).into_owned();
+    *pos += end + 1;
+    Ok(value)
+}
+
+fn read_array<const N: usize>(bytes: &[u8], pos: &mut usize) -> anyhow::Result<[u8; N]> {
+    let array = bytes
+        .get(*pos..*pos + N)
+        .ok_or_else(|| anyhow!("Truncated VDF value"))?
+        .try_into()?;
+    *pos += N;
+    Ok(array)
+}
+
+fn write_map(out: &mut Vec<u8>, entries: &[(String, VdfValue)]) {
+    for (key, value) in entries {
+        let tag = match value {
+            VdfValue::Map(_) => TYPE_MAP,
+            VdfValue::String(_) => TYPE_STRING,
+            VdfValue::Int(_) => TYPE_INT,
+            VdfValue::Uint64(_) => TYPE_UINT64,
+        };
+        out.push(tag);
+        out.extend_from_slice(key.as_bytes());
+        out.push(0);
+
+        match value {
+            VdfValue::Map(entries) => write_map(out, entries),
+            VdfValue::String(value) => {
+                out.extend_from_slice(value.as_bytes());
+                out.push(0);
+            }
+            VdfValue::Int(value) => out.extend_from_slice(&value.to_le_bytes()),
+            VdfValue::Uint64(value) => out.extend_from_slice(&value.to_le_bytes()),
+        }
+    }
+    out.push(TYPE_MAP_END);
+}
+
+#[derive(Debug, Clone)]
+pub struct SteamShortcut {
+    pub app_name: String,
+    pub exe: PathBuf,
+    pub start_dir: PathBuf,
+    pub launch_options: String,
+    pub icon: Option<PathBuf>,
+}
+
+impl SteamShortcut {
+    pub fn for_instance(instance: &Instance, work_dir: &Path, exe: &Path) -> SteamShortcut {
+        let launch_options = shortcuts::launch_args(work_dir, instance)
+            .iter()
+            .map(|arg| format!("\"{}\"", arg.replace('"', "\\\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        SteamShortcut {
+            app_name: instance.name.clone(),
+            exe: exe.to_path_buf(),
+            start_dir: work_dir.to_path_buf(),
+            launch_options,
+            icon: None,
+        }
+    }
+
+    fn quoted_exe(&self) -> String {
+        format!("\"{}\"", self.exe.display())
+    }
+
+    // the id Steam derives for non-Steam games, used for grid artwork file names
+    pub fn app_id(&self) -> u32 {
+        let key = format!("{}{}", self.quoted_exe(), self.app_name);
+        crc32(key.as_bytes()) | 0x8000_0000
+    }
+
+    fn to_vdf(&self) -> VdfValue {
+        let string = |key: &str, value: String| (key.to_string(), VdfValue::String(value));
+        let int = |key: &str, value: u32| (key.to_string(), VdfValue::Int(value));
+
+        VdfValue::Map(vec![
+            int("appid", self.app_id()),
+            string("AppName", self.app_name.clone()),
+            string("Exe", self.quoted_exe()),
+            string("StartDir", format!("\"{}\"", self.start_dir.display())),
+            string(
+                "icon",
+                self.icon
+                    .as_ref()
+                    .map(|icon| icon.display().to_string())
+                    .unwrap_or_default(),
+            ),
+            string("ShortcutPath", String::new()),
+            string("LaunchOptions", self.launch_options.clone()),
+            int("IsHidden", 0),
+            int("AllowDesktopConfig", 1),
+            int("AllowOverlay", 1),
+            int("OpenVR", 0),
+            int("Devkit", 0),
+            string("DevkitGameID", String::new()),
+            int("DevkitOverrideAppID", 0),
+            int("LastPlayTime", 0),
+            string("FlatpakAppID", String::new()),
+            ("tags".to_string(), VdfValue::Map(vec![])),
+        ])
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct GridArtwork {
+    // 600x900 library capsule
+    pub portrait: Option<PathBuf>,
+    // 920x430 header
+    pub wide: Option<PathBuf>,
+    pub hero: Option<PathBuf>,
+    pub logo: Option<PathBuf>,
+}
+
+pub fn find_steam_dir() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+
+    let mut candidates = vec![
+        PathBuf::from(r"C:\Program Files (x86)\Steam"),
+        PathBuf::from(r"C:\Program Files\Steam"),
+    ];
+    if let Some(home) = home {
+        candidates.extend([
+            home.join(".steam").join("steam"),
+            home.join(".local").join("share").join("Steam"),
+            home.join(".var/app/com.valvesoftware.Steam/data/Steam"),
+            home.join("Library").join("Application Support").join("Steam"),
+        ]);
+    }
+
+    candidates
+        .into_iter()
+        .find(|dir| dir.join("userdata").is_dir())
+}
+
+pub fn user_dirs(steam_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut users = Vec::new();
+    for entry in std::fs::read_dir(steam_dir.join("userdata"))? {
+        let path = entry?.path();
+        let is_account_id = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name != "0" && name.chars().all(|c| c.is_ascii_digit()));
+        if path.is_dir() && is_account_id {
+            users.push(path);
+        }
+    }
+    Ok(users)
+}
+
+// Steam rewrites shortcuts.vdf on exit, so it has to be closed for this to stick
+pub fn add_shortcut(user_dir: &Path, shortcut: &SteamShortcut) -> anyhow::Result<u32> {
+    let config_dir = user_dir.join("config");
+    let vdf_path = config_dir.join("shortcuts.vdf");
+
+    let mut root = if vdf_path.exists() {
+        parse_vdf(&std::fs::read(&vdf_path)?)?
+    } else {
+        vec![]
+    };
+    if !root.iter().any(|(key, _)| key == "shortcuts") {
+        root.push((String::from("shortcuts"), VdfValue::Map(vec![])));
+    }
+
+    let app_id = shortcut.app_id();
+    let Some((_, VdfValue::Map(shortcuts))) = root.iter_mut().find(|(key, _)| key == "shortcuts")
+    else {
+        return Err(anyhow!("Malformed shortcuts.vdf"));
+    };
+
+    // re-adding the same instance replaces the old entry instead of duplicating it
+    shortcuts.retain(|(_, entry)| entry.get("appid") != Some(&VdfValue::Int(app_id)));
+    for (index, (key, _)) in shortcuts.iter_mut().enumerate() {
+        *key = index.to_string();
+    }
+    shortcuts.push((shortcuts.len().to_string(), shortcut.to_vdf()));
+
+    let mut out = Vec::new();
+    write_map(&mut out, &root);
+    std::fs::create_dir_all(&config_dir)?;
+    std::fs::write(&vdf_path, out)?;
+
+    Ok(app_id)
+}
+
+pub fn install_artwork(user_dir: &Path, app_id: u32, artwork: &GridArtwork) -> anyhow::Result<()> {
+    let grid_dir = user_dir.join("config").join("grid");
+    std::fs::create_dir_all(&grid_dir)?;
+
+    let targets = [
+        (&artwork.portrait, format!("{}p", app_id)),
+        (&artwork.wide, format!("{}", app_id)),
+        (&artwork.hero, format!("{}_hero", app_id)),
+        (&artwork.logo, format!("{}_logo", app_id)),
+    ];
+    for (source, stem) in targets {
+        let Some(source) = source else {
+            continue;
+        };
+        let extension = source
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("png");
+        std::fs::copy(source, grid_dir.join(format!("{}.{}", stem, extension)))?;
+    }
+
+    Ok(())
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
@@ -0,0 +1,754 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{BufRead, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task::AbortHandle};
+
+use crate::{
+    assets::VerifyPolicy, cache, download::RateEstimator, fabric, install_version, instance,
+    loader::LoaderKind, net::HttpProvider,
+};
+
+// append-only event log, the queue's state is whatever replaying it produces
+const TASKS_FILE: &str = "tasks.jsonl";
+// how often the runner picks up pause/cancel requests made by other processes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub type TaskId = u64;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum TaskKind {
+    InstallVersion {
+        version: String,
+    },
+    InstallFabric {
+        game_version: String,
+        loader_version: Option<String>,
+        // switched over to the installed loader once it's done
+        instance: Option<String>,
+    },
+    CacheGc {
+        prune_versions: bool,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Queued,
+    Running,
+    Paused,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl TaskStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            TaskStatus::Completed | TaskStatus::Failed | TaskStatus::Cancelled
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum TaskEvent {
+    Queued {
+        id: TaskId,
+        kind: TaskKind,
+        #[serde(with = "time::serde::iso8601")]
+        at: time::OffsetDateTime,
+    },
+    Started {
+        id: TaskId,
+    },
+    Progress {
+        id: TaskId,
+        done: u64,
+        total: u64,
+        #[serde(default)]
+        bytes_per_second: u64,
+        #[serde(default)]
+        eta_seconds: Option<u64>,
+    },
+    Paused {
+        id: TaskId,
+    },
+    Resumed {
+        id: TaskId,
+    },
+    Cancelled {
+        id: TaskId,
+    },
+    Completed {
+        id: TaskId,
+    },
+    Failed {
+        id: TaskId,
+        error: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Task {
+    pub id: TaskId,
+    pub kind: TaskKind,
+    pub status: TaskStatus,
+    pub done: u64,
+    pub total: u64,
+    // as of the last recorded progress, meaningless once the task stopped running
+    pub bytes_per_second: u64,
+    pub eta_seconds: Option<u64>,
+    pub error: Option<String>,
+    #[serde(with = "time::serde::iso8601")]
+    pub queued_at: time::OffsetDateTime,
+}
+
+pub struct TaskQueue {
+    path: PathBuf,
+    tasks: BTreeMap<TaskId, Task>,
+    // how far into the log has been replayed
+    offset: u64,
+    // gets every task an event changed, whichever process recorded it
+    observer: Option<mpsc::UnboundedSender<Task>>,
+}
+
+impl TaskQueue {
+    pub fn open(work_dir: &Path) -> anyhow::Result<TaskQueue> {
+        let mut queue = TaskQueue {
+            path: work_dir.join(TASKS_FILE),
+            tasks: BTreeMap::new(),
+            offset: 0,
+            observer: None,
+        };
+        queue.refresh()?;
+        Ok(queue)
+    }
+
+    // replays events appended since the last refresh, including ones written by other processes
+    pub fn refresh(&mut self) -> anyhow::Result<()> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+
+        let mut file = std::fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut reader = std::io::BufReader::new(file);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            // a line without its newline is still being written
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+            self.offset += read as u64;
+
+            if !line.trim().is_empty() {
+                let event = serde_json::from_str(&line)
+                    .with_context(|| format!("Corrupt event in {}", self.path.display()))?;
+                self.apply(event);
+            }
+        }
+        Ok(())
+    }
+
+    // from now on, what was replayed before isn't sent
+    pub fn observe(&mut self, observer: mpsc::UnboundedSender<Task>) {
+        self.observer = Some(observer);
+    }
+
+    fn apply(&mut self, event: TaskEvent) {
+        let changed = self.apply_event(event);
+        if let (Some(observer), Some(task)) = (&self.observer, changed.and_then(|id| self.get(id)))
+        {
+            let _ = observer.send(task.clone());
+        }
+    }
+
+    // the task the event changed, None for a stale one
+    fn apply_event(&mut self, event: TaskEvent) -> Option<TaskId> {
+        if let TaskEvent::Queued { id, kind, at } = event {
+            self.tasks.insert(
+                id,
+                Task {
+                    id,
+                    kind,
+                    status: TaskStatus::Queued,
+                    done: 0,
+                    total: 0,
+                    bytes_per_second: 0,
+                    eta_seconds: None,
+                    error: None,
+                    queued_at: at,
+                },
+            );
+            return Some(id);
+        }
+
+        let (id, status) = match &event {
+            TaskEvent::Queued { .. } => unreachable!(),
+            TaskEvent::Started { id } => (id, TaskStatus::Running),
+            TaskEvent::Progress { id, .. } => (id, TaskStatus::Running),
+            TaskEvent::Paused { id } => (id, TaskStatus::Paused),
+            TaskEvent::Resumed { id } => (id, TaskStatus::Queued),
+            TaskEvent::Cancelled { id } => (id, TaskStatus::Cancelled),
+            TaskEvent::Completed { id } => (id, TaskStatus::Completed),
+            TaskEvent::Failed { id, .. } => (id, TaskStatus::Failed),
+        };
+        let id = *id;
+        let task = self.tasks.get_mut(&id)?;
+        // late events from a task that was already cancelled or finished don't revive it
+        let stale = task.status.is_finished()
+            || (task.status == TaskStatus::Paused
+                && !matches!(status, TaskStatus::Queued | TaskStatus::Cancelled));
+        if stale {
+            return None;
+        }
+
+        task.status = status;
+        match event {
+            TaskEvent::Progress {
+                done,
+                total,
+                bytes_per_second,
+                eta_seconds,
+                ..
+            } => {
+                task.done = done;
+                task.total = total;
+                task.bytes_per_second = bytes_per_second;
+                task.eta_seconds = eta_seconds;
+            }
+            TaskEvent::Failed { error, .. } => task.error = Some(error),
+            _ => {}
+        }
+        Some(id)
+    }
+
+    fn record(&mut self, event: TaskEvent) -> anyhow::Result<()> {
+        // catch up first so the new event lands on the latest state
+        self.refresh()?;
+
+        let mut line = serde_json::to_string(&event)?;
+        line.push('\n');
+        // a single appended line per event keeps concurrent writers from interleaving
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+
+        self.offset += line.len() as u64;
+        self.apply(event);
+        Ok(())
+    }
+
+    pub fn tasks(&self) -> impl Iterator<Item = &Task> {
+        self.tasks.values()
+    }
+
+    pub fn get(&self, id: TaskId) -> Option<&Task> {
+        self.tasks.get(&id)
+    }
+
+    fn get_checked(&self, id: TaskId) -> anyhow::Result<&Task> {
+        self.get(id)
+            .ok_or_else(|| anyhow!("No task with id {}", id))
+    }
+
+    pub fn enqueue(&mut self, kind: TaskKind) -> anyhow::Result<TaskId> {
+        self.refresh()?;
+        let id = self.tasks.keys().next_back().map_or(1, |id| id + 1);
+        self.record(TaskEvent::Queued {
+            id,
+            kind,
+            at: time::OffsetDateTime::now_utc(),
+        })?;
+        Ok(id)
+    }
+
+    // a running task is stopped and starts over when resumed
+    pub fn pause(&mut self, id: TaskId) -> anyhow::Result<()> {
+        let task = self.get_checked(id)?;
+        if !matches!(task.status, TaskStatus::Queued | TaskStatus::Running) {
+            return Err(anyhow!(
+                "Task {} is {:?} and can't be paused",
+                id,
+                task.status
+            ));
+        }
+        self.record(TaskEvent::Paused { id })
+    }
+
+    // also retries failed tasks
+    pub fn resume(&mut self, id: TaskId) -> anyhow::Result<()> {
+        let task = self.get_checked(id)?;
+        match task.status {
+            TaskStatus::Paused => self.record(TaskEvent::Resumed { id }),
+            TaskStatus::Failed => {
+                let kind = task.kind.clone();
+                self.enqueue(kind).map(|_| ())
+            }
+            status => Err(anyhow!("Task {} is {:?} and can't be resumed", id, status)),
+        }
+    }
+
+    pub fn cancel(&mut self, id: TaskId) -> anyhow::Result<()> {
+        let task = self.get_checked(id)?;
+        if task.status.is_finished() {
+            return Err(anyhow!("Task {} already finished", id));
+        }
+        self.record(TaskEvent::Cancelled { id })
+    }
+
+    // rewrites the log without finished tasks, only safe while no runner is active
+    pub fn compact(&mut self) -> anyhow::Result<usize> {
+        self.refresh()?;
+        let before = self.tasks.len();
+        self.tasks.retain(|_, task| !task.status.is_finished());
+
+        let mut log = String::new();
+        for task in self.tasks.values() {
+            let mut events = vec![TaskEvent::Queued {
+                id: task.id,
+                kind: task.kind.clone(),
+                at: task.queued_at,
+            }];
+            if task.status == TaskStatus::Paused {
+                events.push(TaskEvent::Paused { id: task.id });
+            }
+            for event in events {
+                log.push_str(&serde_json::to_string(&event)?);
+                log.push('\n');
+            }
+        }
+
+        let temp = self.path.with_extension("jsonl.tmp");
+        std::fs::write(&temp, &log)?;
+        std::fs::rename(&temp, &self.path)?;
+        self.offset = log.len() as u64;
+        // interrupted tasks restart from the beginning after compaction
+        for task in self.tasks.values_mut() {
+            if task.status == TaskStatus::Running {
+                task.status = TaskStatus::Queued;
+            }
+        }
+        Ok(before - self.tasks.len())
+    }
+
+    fn next_runnable(&self, running: &HashMap<TaskId, AbortHandle>) -> Option<TaskId> {
+        // a task still marked running but not ours was interrupted by a restart
+        self.tasks
+            .values()
+            .find(|task| {
+                !running.contains_key(&task.id)
+                    && matches!(task.status, TaskStatus::Queued | TaskStatus::Running)
+            })
+            .map(|task| task.id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskContext {
+    pub client: reqwest::Client,
+    pub cache_dir: PathBuf,
+    pub instances_dir: PathBuf,
+    pub http: HttpProvider,
+    pub download_concurrency: usize,
+    pub verify_policy: VerifyPolicy,
+}
+
+// runs queued tasks until none are left, only one runner should use a work dir at a time
+pub async fn run(
+    queue: &mut TaskQueue,
+    context: TaskContext,
+    parallelism: usize,
+) -> anyhow::Result<()> {
+    let context = Arc::new(context);
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel::<TaskEvent>();
+    let mut running: HashMap<TaskId, AbortHandle> = HashMap::new();
+    // last persisted percentage per task, progress is only logged when it moves
+    let mut logged_percent: HashMap<TaskId, u64> = HashMap::new();
+
+    loop {
+        queue.refresh()?;
+
+        running.retain(|id, handle| {
+            let still_running = queue
+                .get(*id)
+                .is_some_and(|task| task.status == TaskStatus::Running);
+            if !still_running {
+                handle.abort();
+            }
+            still_running
+        });
+
+        while running.len() < parallelism.max(1) {
+            let Some(id) = queue.next_runnable(&running) else {
+                break;
+            };
+            queue.record(TaskEvent::Started { id })?;
+            let kind = queue.get_checked(id)?.kind.clone();
+            running.insert(id, spawn(id, kind, context.clone(), events_tx.clone()));
+        }
+
+        if running.is_empty() {
+            return Ok(());
+        }
+
+        tokio::select! {
+            Some(event) = events_rx.recv() => {
+                match &event {
+                    TaskEvent::Progress { id, done, total, .. } => {
+                        let percent = done * 100 / (*total).max(1);
+                        if logged_percent.insert(*id, percent) == Some(percent) {
+                            continue;
+                        }
+                    }
+                    TaskEvent::Completed { id } | TaskEvent::Failed { id, .. } => {
+                        running.remove(id);
+                        logged_percent.remove(id);
+                    }
+                    _ => {}
+                }
+                queue.record(event)?;
+            }
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+        }
+    }
+}
+
+fn spawn(
+    id: TaskId,
+    kind: TaskKind,
+    context: Arc<TaskContext>,
+    events: mpsc::UnboundedSender<TaskEvent>,
+) -> AbortHandle {
+    let progress_events = events.clone();
+    let estimator = RateEstimator::default();
+    let on_progress = move |done, total| {
+        let estimate = estimator.update(done, total);
+        let _ = progress_events.send(TaskEvent::Progress {
+            id,
+            done,
+            total,
+            bytes_per_second: estimate.bytes_per_second,
+            eta_seconds: estimate.eta_seconds,
+        });
+    };
+    let task = tokio::spawn(async move { execute(kind, &context, &on_progress).await });
+    let abort = task.abort_handle();
+
+    tokio::spawn(async move {
+        let event = match task.await {
+            Ok(Ok(())) => TaskEvent::Completed { id },
+            Ok(Err(error)) => TaskEvent::Failed {
+                id,
+                error: format!("{:#}", error),
+            },
+            // paused or cancelled, the queue already knows
+            Err(error) if error.is_cancelled() => return,
+            Err(_) => TaskEvent::Failed {
+                id,
+                error: String::from("Task panicked"),
+            },
+        };
+        let _ = events.send(event);
+    });
+
+    abort
+}
+
+async fn execute(
+    kind: TaskKind,
+    context: &TaskContext,
+    on_progress: &(dyn Fn(u64, u64) + Send + Sync),
+) -> anyhow::Result<()> {
+    match kind {
+        TaskKind::InstallVersion { version } => {
+            install_version(
+                &context.http,
+                &context.cache_dir,
+                &version,
+                context.download_concurrency,
+                context.verify_policy,
+                on_progress,
+            )
+            .await
+        }
+        TaskKind::InstallFabric {
+            game_version,
+            loader_version,
+            instance,
+        } => {
+            let id = fabric::install(
+                &context.client,
+                &context.cache_dir,
+                LoaderKind::Fabric,
+                &game_version,
+                loader_version.as_deref(),
+                context.http.probe().await,
+            )
+            .await?;
+            install_version(
+                &context.http,
+                &context.cache_dir,
+                &id,
+                context.download_concurrency,
+                context.verify_policy,
+                on_progress,
+            )
+            .await?;
+
+            if let Some(name) = instance {
+                instance::update(&context.instances_dir, &name, |instance| {
+                    instance.version = id;
+                    instance.loader = Some(LoaderKind::Fabric);
+                })?;
+            }
+            Ok(())
+        }
+        TaskKind::CacheGc { prune_versions } => {
+            let keep = if prune_versions {
+                Some(cache::versions_in_use(&context.instances_dir)?)
+            } else {
+                None
+            };
+            cache::gc(&context.cache_dir, keep.as_deref(), false).await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_work_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("mod_launcher_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn gc_task(id: TaskId) -> TaskEvent {
+        TaskEvent::Queued {
+            id,
+            kind: TaskKind::CacheGc {
+                prune_versions: false,
+            },
+            at: time::OffsetDateTime::now_utc(),
+        }
+    }
+
+    fn progress(id: TaskId) -> TaskEvent {
+        TaskEvent::Progress {
+            id,
+            done: 50,
+            total: 100,
+            bytes_per_second: 10,
+            eta_seconds: Some(5),
+        }
+    }
+
+    fn write_log(work_dir: &Path, events: &[TaskEvent]) {
+        let log = events
+            .iter()
+            .map(|event| serde_json::to_string(event).unwrap() + "\n")
+            .collect::<String>();
+        std::fs::write(work_dir.join(TASKS_FILE), log).unwrap();
+    }
+
+    fn status(queue: &TaskQueue, id: TaskId) -> TaskStatus {
+        queue.get(id).unwrap().status
+    }
+
+    #[test]
+    fn late_events_dont_revive_stopped_tasks() {
+        let work_dir = temp_work_dir("tasks_replay");
+        write_log(
+            &work_dir,
+            &[
+                gc_task(1),
+                gc_task(2),
+                gc_task(3),
+                // the runner was still reporting when another process cancelled or paused
+                TaskEvent::Started { id: 1 },
+                TaskEvent::Cancelled { id: 1 },
+                progress(1),
+                TaskEvent::Completed { id: 1 },
+                TaskEvent::Started { id: 2 },
+                TaskEvent::Paused { id: 2 },
+                progress(2),
+                TaskEvent::Completed { id: 2 },
+                TaskEvent::Started { id: 3 },
+                TaskEvent::Completed { id: 3 },
+                progress(3),
+                TaskEvent::Failed {
+                    id: 3,
+                    error: String::from("late"),
+                },
+                // for a task nobody queued
+                TaskEvent::Started { id: 9 },
+            ],
+        );
+
+        let mut queue = TaskQueue::open(&work_dir).unwrap();
+        assert_eq!(status(&queue, 1), TaskStatus::Cancelled);
+        assert_eq!(status(&queue, 2), TaskStatus::Paused);
+        assert_eq!(queue.get(2).unwrap().done, 0);
+        assert_eq!(status(&queue, 3), TaskStatus::Completed);
+        assert_eq!(queue.get(3).unwrap().error, None);
+        assert!(queue.get(9).is_none());
+
+        // a paused task can still be resumed or cancelled
+        queue.resume(2).unwrap();
+        assert_eq!(status(&queue, 2), TaskStatus::Queued);
+        queue.pause(2).unwrap();
+        queue.cancel(2).unwrap();
+        assert_eq!(status(&queue, 2), TaskStatus::Cancelled);
+
+        std::fs::remove_dir_all(work_dir).unwrap();
+    }
+
+    #[test]
+    fn state_changes_reach_other_processes() {
+        let work_dir = temp_work_dir("tasks_state");
+        let mut queue = TaskQueue::open(&work_dir).unwrap();
+        let kind = TaskKind::InstallVersion {
+            version: String::from("1.20.1"),
+        };
+        let id = queue.enqueue(kind.clone()).unwrap();
+
+        queue.pause(id).unwrap();
+        assert!(queue.pause(id).is_err());
+        queue.resume(id).unwrap();
+        assert!(queue.resume(id).is_err());
+        queue.record(TaskEvent::Started { id }).unwrap();
+        queue
+            .record(TaskEvent::Failed {
+                id,
+                error: String::from("offline"),
+            })
+            .unwrap();
+        assert!(queue.pause(id).is_err());
+        assert!(queue.cancel(id).is_err());
+
+        // a retry is a new task, the failed one stays as it was
+        queue.resume(id).unwrap();
+        let retry = queue.tasks().last().unwrap();
+        assert_ne!(retry.id, id);
+        assert_eq!(retry.kind, kind);
+        assert_eq!(retry.status, TaskStatus::Queued);
+        assert_eq!(queue.get(id).unwrap().error.as_deref(), Some("offline"));
+
+        let mut other = TaskQueue::open(&work_dir).unwrap();
+        assert_eq!(status(&other, id), TaskStatus::Failed);
+        other.cancel(id + 1).unwrap();
+        queue.refresh().unwrap();
+        assert_eq!(status(&queue, id + 1), TaskStatus::Cancelled);
+        assert!(queue.resume(99).is_err());
+
+        std::fs::remove_dir_all(work_dir).unwrap();
+    }
+
+    #[test]
+    fn compact_keeps_unfinished_tasks() {
+        let work_dir = temp_work_dir("tasks_compact");
+        write_log(
+            &work_dir,
+            &[
+                gc_task(1),
+                gc_task(2),
+                gc_task(3),
+                gc_task(4),
+                gc_task(5),
+                TaskEvent::Started { id: 1 },
+                TaskEvent::Completed { id: 1 },
+                TaskEvent::Paused { id: 2 },
+                TaskEvent::Started { id: 3 },
+                progress(3),
+                TaskEvent::Cancelled { id: 4 },
+            ],
+        );
+
+        let mut queue = TaskQueue::open(&work_dir).unwrap();
+        assert_eq!(queue.compact().unwrap(), 2);
+        let statuses = |queue: &TaskQueue| {
+            queue
+                .tasks()
+                .map(|task| (task.id, task.status))
+                .collect::<Vec<_>>()
+        };
+        // the interrupted task starts over
+        let expected = vec![
+            (2, TaskStatus::Paused),
+            (3, TaskStatus::Queued),
+            (5, TaskStatus::Queued),
+        ];
+        assert_eq!(statuses(&queue), expected);
+        assert_eq!(statuses(&TaskQueue::open(&work_dir).unwrap()), expected);
+
+        // new events land after the rewritten log
+        queue.resume(2).unwrap();
+        assert_eq!(
+            status(&TaskQueue::open(&work_dir).unwrap(), 2),
+            TaskStatus::Queued
+        );
+
+        std::fs::remove_dir_all(work_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_stays_within_parallelism() {
+        let work_dir = temp_work_dir("tasks_run");
+        let mut queue = TaskQueue::open(&work_dir).unwrap();
+        for _ in 0..5 {
+            queue
+                .enqueue(TaskKind::CacheGc {
+                    prune_versions: false,
+                })
+                .unwrap();
+        }
+        let context = TaskContext {
+            client: reqwest::Client::new(),
+            cache_dir: work_dir.join("cache"),
+            instances_dir: work_dir.join("instances"),
+            http: HttpProvider::new(reqwest::Client::new(), None),
+            download_concurrency: 1,
+            verify_policy: VerifyPolicy::default(),
+        };
+        run(&mut queue, context, 2).await.unwrap();
+        assert!(queue.tasks().all(|task| task.status.is_finished()));
+
+        // the log is in the order the runner saw things happen
+        let log = std::fs::read_to_string(work_dir.join(TASKS_FILE)).unwrap();
+        let mut running = 0;
+        let mut most = 0;
+        for line in log.lines() {
+            match serde_json::from_str(line).unwrap() {
+                TaskEvent::Started { .. } => running += 1,
+                TaskEvent::Completed { .. } | TaskEvent::Failed { .. } => running -= 1,
+                _ => {}
+            }
+            most = most.max(running);
+        }
+        assert_eq!(most, 2);
+        assert_eq!(running, 0);
+
+        std::fs::remove_dir_all(work_dir).unwrap();
+    }
+}
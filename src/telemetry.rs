@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use tracing::debug;
+
+use crate::write_atomic;
+
+// the game has no switch for the events it always sends. What a launcher can do is leave out the
+// arguments that identify the player and the launcher, and turn off the snooper and the optional
+// events in options.txt before the game reads it
+
+// account ids the game attaches to its events, dropped together with their value
+const IDENTIFYING_GAME_ARGS: &[&str] = &["--clientId", "--xuid"];
+// reported along with every event and crash report
+const IDENTIFYING_JVM_PROPERTIES: &[&str] = &[
+    "-Dminecraft.launcher.brand=",
+    "-Dminecraft.launcher.version=",
+];
+// `snooperEnabled` before 1.18, `telemetryOptInExtra` for the optional events since 1.19
+const OPT_OUT_OPTIONS: &[(&str, &str)] = &[
+    ("snooperEnabled", "false"),
+    ("telemetryOptInExtra", "false"),
+];
+
+pub fn filter_jvm_args(args: &mut Vec<String>) {
+    args.retain(|arg| {
+        !IDENTIFYING_JVM_PROPERTIES
+            .iter()
+            .any(|property| arg.starts_with(property))
+    });
+}
+
+pub fn filter_game_args(args: &mut Vec<String>) {
+    let mut skip_value = false;
+    args.retain(|arg| {
+        if std::mem::take(&mut skip_value) {
+            return false;
+        }
+        skip_value = IDENTIFYING_GAME_ARGS.contains(&arg.as_str());
+        !skip_value
+    });
+}
+
+// keeps every other option and the file's order, a missing options.txt is created with just these
+pub async fn disable_in_options(game_dir: &Path) -> anyhow::Result<()> {
+    let path = game_dir.join("options.txt");
+    let existing = tokio::fs::read_to_string(&path).await.unwrap_or_default();
+    let mut lines = existing.lines().map(str::to_string).collect::<Vec<_>>();
+    let mut changed = false;
+    for (key, value) in OPT_OUT_OPTIONS {
+        let setting = format!("{}:{}", key, value);
+        match lines
+            .iter_mut()
+            .find(|line| line.split_once(':').is_some_and(|(k, _)| k == *key))
+        {
+            Some(line) if *line == setting => {}
+            Some(line) => {
+                *line = setting;
+                changed = true;
+            }
+            None => {
+                lines.push(setting);
+                changed = true;
+            }
+        }
+    }
+    if changed {
+        debug!(path = %path.display(), "turned telemetry off in options");
+        write_atomic(&path, lines.join("\n") + "\n").await?;
+    }
+    Ok(())
+}
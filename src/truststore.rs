@@ -0,0 +1,140 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{
+    install_state::FileStamp,
+    watchdog::{self, Limits},
+};
+
+const STORE_FILE: &str = "cacerts";
+const RECORD_FILE: &str = "truststore.json";
+// the JDK's own default, the store only holds public certificates
+const STORE_PASSWORD: &str = "changeit";
+
+// what a built store was made from, it is rebuilt when any of it changes
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct StoreRecord {
+    java: PathBuf,
+    sources: Vec<(PathBuf, Option<FileStamp>)>,
+}
+
+// the Java install's default CA certificates plus `certs`, for networks that intercept TLS with
+// their own CA. Built with the keytool that ships next to the java binary, in `dir`
+pub async fn build(
+    java_path: &Path,
+    certs: &[PathBuf],
+    dir: &Path,
+    limits: Limits,
+) -> anyhow::Result<PathBuf> {
+    let java_path = dunce::canonicalize(java_path)?;
+    let keytool = java_path.with_file_name(format!("keytool{}", std::env::consts::EXE_SUFFIX));
+    if !keytool.exists() {
+        return Err(anyhow!(
+            "No keytool next to {}, CA certificates need a Java install that has one",
+            java_path.display()
+        ));
+    }
+    let default_store = default_store(&java_path);
+
+    let store = dir.join(STORE_FILE);
+    let record = StoreRecord {
+        java: java_path.clone(),
+        sources: default_store
+            .iter()
+            .chain(certs)
+            .map(|path| (path.clone(), FileStamp::of(path)))
+            .collect(),
+    };
+    if let Some((cert, _)) = record.sources.iter().find(|(_, stamp)| stamp.is_none()) {
+        return Err(anyhow!("CA certificate {} not found", cert.display()));
+    }
+    let recorded = std::fs::read_to_string(dir.join(RECORD_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_str::<StoreRecord>(&json).ok());
+    if store.exists() && recorded.as_ref() == Some(&record) {
+        debug!(store = %store.display(), "truststore up to date");
+        return Ok(store);
+    }
+
+    info!(
+        "Building a truststore with {} extra CA certificates",
+        certs.len()
+    );
+    std::fs::create_dir_all(dir)?;
+    let part = dir.join(format!("{}.part", STORE_FILE));
+    let _ = std::fs::remove_file(&part);
+    match &default_store {
+        Some(default_store) => {
+            std::fs::copy(default_store, &part).with_context(|| {
+                format!("Could not copy the default truststore {}", default_store.display())
+            })?;
+        }
+        // still works behind an intercepting proxy, everything is signed by its CA there
+        None => warn!(
+            "Could not find the default CA certificates of {}, only the configured ones will be trusted",
+            java_path.display()
+        ),
+    }
+    for (index, cert) in certs.iter().enumerate() {
+        import(&keytool, &part, cert, index, limits).await?;
+    }
+    std::fs::rename(&part, &store)?;
+    std::fs::write(
+        dir.join(RECORD_FILE),
+        serde_json::to_string_pretty(&record)?,
+    )?;
+    Ok(store)
+}
+
+pub fn jvm_args(store: &Path) -> anyhow::Result<Vec<String>> {
+    let store = dunce::canonicalize(store)?;
+    Ok(vec![
+        format!("-Djavax.net.ssl.trustStore={}", store.display()),
+        format!("-Djavax.net.ssl.trustStorePassword={}", STORE_PASSWORD),
+    ])
+}
+
+// lib/security/cacerts, under jre/ on Java 8 JDKs
+fn default_store(java_path: &Path) -> Option<PathBuf> {
+    let home = java_path.parent()?.parent()?;
+    [home.join("lib"), home.join("jre").join("lib")]
+        .into_iter()
+        .map(|lib| lib.join("security").join("cacerts"))
+        .find(|store| store.is_file())
+}
+
+async fn import(
+    keytool: &Path,
+    store: &Path,
+    cert: &Path,
+    index: usize,
+    limits: Limits,
+) -> anyhow::Result<()> {
+    let stem = cert
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let mut command = tokio::process::Command::new(keytool);
+    command
+        .arg("-importcert")
+        .arg("-noprompt")
+        .args(["-alias", &format!("mod_launcher_{}_{}", index, stem)])
+        .arg("-file")
+        .arg(cert)
+        .arg("-keystore")
+        .arg(store)
+        .args(["-storepass", STORE_PASSWORD]);
+    let output = watchdog::output(&mut command, limits).await?;
+    if !output.status.success() {
+        // keytool reports problems on stdout
+        return Err(anyhow!(
+            "Could not import CA certificate {}: {}",
+            cert.display(),
+            output.stdout.trim()
+        ));
+    }
+    Ok(())
+}
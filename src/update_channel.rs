@@ -0,0 +1,685 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::{
+    dedup,
+    download::{DownloadPlan, DownloadTask},
+    instance::{self, Instance},
+    loader::LoaderBuild,
+    net::Downloader,
+    portable::{self, LocatedMod, OVERRIDES},
+    sha1_file, versions, write_atomic,
+};
+
+// a published channel is a directory of static files, uploaded as-is to S3, GitHub Pages or
+// any web server:
+//
+//   channel.json              the index followers poll, written last
+//   releases/<release>.json   one per published release
+//   objects/<ab>/<sha1>       mods, settings and version JSONs by hash, shared across releases
+const CHANNEL_FILE: &str = "channel.json";
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelIndex {
+    pub format_version: u32,
+    // the published instance's name, what followers are called by default
+    pub name: String,
+    // the newest release, followers outside its rollout stay on an older one
+    pub latest: u32,
+    // oldest first
+    pub releases: Vec<ReleaseEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReleaseEntry {
+    pub release: u32,
+    #[serde(with = "time::serde::iso8601")]
+    pub published: OffsetDateTime,
+    pub game_version: String,
+    // relative to the channel
+    pub manifest: String,
+    // of the manifest, which has the hashes of everything else
+    pub sha256: String,
+    // everyone gets the release when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollout: Option<Rollout>,
+}
+
+// a release staged to part of the followers, so a bad update doesn't reach everyone at once.
+// Widened by editing channel.json, see `set_rollout`. Followers that got the release keep it when
+// it is narrowed again
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Rollout {
+    // of the followers, 0 to 100, picked by their follower id
+    pub percent: u8,
+    // followers in any of these cohorts, from update_cohorts in launcher.toml, get the release
+    // whatever the percentage
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cohorts: Vec<String>,
+}
+
+impl Rollout {
+    fn check(&self) -> anyhow::Result<()> {
+        if self.percent > 100 {
+            return Err(anyhow!("A rollout can't go past 100%"));
+        }
+        Ok(())
+    }
+
+    // each release picks its share anew, so the same followers aren't always first
+    fn includes(&self, follower_id: &str, release: u32, cohorts: &[String]) -> bool {
+        if self.cohorts.iter().any(|cohort| cohorts.contains(cohort)) {
+            return true;
+        }
+        let hash = Sha256::digest(format!("{}:{}", follower_id, release));
+        let bucket = u64::from_be_bytes(hash[..8].try_into().unwrap()) % 100;
+        bucket < self.percent as u64
+    }
+}
+
+impl ChannelIndex {
+    // the newest release that has rolled out to this follower
+    fn pick(&self, follower_id: &str, cohorts: &[String]) -> Option<&ReleaseEntry> {
+        self.releases.iter().rev().find(|entry| {
+            entry
+                .rollout
+                .as_ref()
+                .is_none_or(|rollout| rollout.includes(follower_id, entry.release, cohorts))
+        })
+    }
+}
+
+// releases/<release>.json
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ReleaseManifest {
+    format_version: u32,
+    release: u32,
+    // its version, loader and settings, without anything only the publisher's machine has
+    instance: Instance,
+    // the loader build the publisher's lockfile has
+    loader: Option<LoaderBuild>,
+    // under the game dir, mods and settings
+    files: Vec<PublishedFile>,
+    // version JSONs the version manifest can't provide, like loader profiles
+    versions: Vec<PublishedVersion>,
+    // in the instance dir
+    icon: Option<PublishedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct PublishedFile {
+    // relative, with / separators
+    path: String,
+    sha1: String,
+    size: u64,
+    // mods published on Modrinth are downloaded from there, anything else from objects/
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct PublishedVersion {
+    id: String,
+    sha1: String,
+    size: u64,
+}
+
+// kept in the follower's lockfile, so the next update knows which files the channel put there
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InstalledRelease {
+    pub release: u32,
+    // path under the game dir to its sha1
+    pub files: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PublishReport {
+    pub channel: PathBuf,
+    pub release: u32,
+    // false when nothing changed since the latest release, which is then left as it was
+    pub published: bool,
+    pub objects_written: usize,
+    // mods followers download from Modrinth
+    pub referenced: Vec<String>,
+    pub bundled: Vec<String>,
+    // mods not on Modrinth and not bundled, left out
+    pub skipped: Vec<String>,
+}
+
+// an instance moved to a newer release of its channel
+#[derive(Serialize, Debug, Clone)]
+pub struct ReleaseUpdate {
+    pub instance: String,
+    pub from: Option<u32>,
+    pub to: u32,
+    pub game_version: String,
+}
+
+fn object_path(sha1: &str) -> String {
+    format!("objects/{}/{}", &sha1[..2], sha1)
+}
+
+fn read_index(out_dir: &Path) -> anyhow::Result<Option<ChannelIndex>> {
+    let path = out_dir.join(CHANNEL_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(json) => {
+            Ok(Some(serde_json::from_str(&json).with_context(|| {
+                format!("Failed to parse {}", path.display())
+            })?))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// renders the instance, as its lockfile pins it, into the next release of the channel in
+// `out_dir`, adding to the releases already there. Mods on Modrinth are referenced rather than
+// copied, anything else is only bundled with `bundle_unknown`, like `portable::export`
+pub async fn publish(
+    client: &reqwest::Client,
+    instances_dir: &Path,
+    cache_dir: &Path,
+    name: &str,
+    out_dir: &Path,
+    bundle_unknown: bool,
+    rollout: Option<Rollout>,
+) -> anyhow::Result<PublishReport> {
+    if let Some(rollout) = &rollout {
+        rollout.check()?;
+    }
+    let instance = instance::load(instances_dir, name)?;
+    let lockfile = instance::lockfile(instances_dir, name)?;
+    let game_dir = instance.game_dir(instances_dir);
+    let index = read_index(out_dir)?;
+    if let Some(index) = index.as_ref().filter(|index| index.name != instance.name) {
+        return Err(anyhow!(
+            "{} publishes {}, not {}",
+            out_dir.display(),
+            index.name,
+            instance.name
+        ));
+    }
+
+    let mut report = PublishReport {
+        channel: out_dir.join(CHANNEL_FILE),
+        release: index.as_ref().map_or(0, |index| index.latest) + 1,
+        published: false,
+        objects_written: 0,
+        referenced: vec![],
+        bundled: vec![],
+        skipped: vec![],
+    };
+    // objects to copy out, by hash
+    let mut objects = BTreeMap::new();
+    let mut publish_file = |path: &Path, relative: String| -> anyhow::Result<PublishedFile> {
+        let sha1 = sha1_file(path)?;
+        let size = std::fs::metadata(path)?.len();
+        objects.insert(sha1.clone(), path.to_path_buf());
+        Ok(PublishedFile {
+            path: relative,
+            sha1,
+            size,
+            url: None,
+        })
+    };
+
+    let mut files = Vec::new();
+    for LocatedMod {
+        info,
+        sha1,
+        size,
+        url,
+    } in portable::locate_mods(client, &game_dir).await?
+    {
+        let relative = format!("mods/{}", info.file_name);
+        match url {
+            Some(url) => {
+                report.referenced.push(info.file_name);
+                files.push(PublishedFile {
+                    path: relative,
+                    sha1,
+                    size,
+                    url: Some(url),
+                });
+            }
+            None if bundle_unknown => {
+                report.bundled.push(info.file_name);
+                files.push(publish_file(&info.path, relative)?);
+            }
+            None => report.skipped.push(info.file_name),
+        }
+    }
+    for entry in OVERRIDES {
+        for file in dedup::walk_files(&game_dir.join(entry))?
+            .into_iter()
+            .chain(Some(game_dir.join(entry)).filter(|file| file.is_file()))
+        {
+            let relative = file
+                .strip_prefix(&game_dir)?
+                .to_string_lossy()
+                .replace('\\', "/");
+            files.push(publish_file(&file, relative)?);
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let manifest = versions::cached_manifest(cache_dir);
+    let mut published_versions = Vec::new();
+    for id in versions::lineage(cache_dir, &instance.version)? {
+        if manifest
+            .as_ref()
+            .is_some_and(|manifest| manifest.find_version_by_id(&id).is_some())
+        {
+            continue;
+        }
+        let file = publish_file(&versions::json_path(cache_dir, &id), id)?;
+        published_versions.push(PublishedVersion {
+            id: file.path,
+            sha1: file.sha1,
+            size: file.size,
+        });
+    }
+    let icon = match instance
+        .icon_path(instances_dir)
+        .filter(|icon| icon.is_file())
+    {
+        Some(icon) => {
+            let file_name = icon.file_name().unwrap().to_string_lossy().to_string();
+            Some(publish_file(&icon, file_name)?)
+        }
+        None => None,
+    };
+
+    let release = ReleaseManifest {
+        format_version: FORMAT_VERSION,
+        release: report.release,
+        instance: Instance {
+            update_url: None,
+            ..portable::shareable(instance)
+        },
+        loader: lockfile.loader,
+        files,
+        versions: published_versions,
+        icon,
+    };
+
+    // publishing again without changes shouldn't make followers update
+    if let Some(index) = &index {
+        let latest = out_dir.join(format!("releases/{}.json", index.latest));
+        let previous = std::fs::read_to_string(&latest)
+            .ok()
+            .and_then(|json| serde_json::from_str::<ReleaseManifest>(&json).ok());
+        let same = previous.is_some_and(|previous| {
+            let previous = ReleaseManifest {
+                release: release.release,
+                ..previous
+            };
+            serde_json::to_value(previous).ok() == serde_json::to_value(&release).ok()
+        });
+        if same {
+            report.release = index.latest;
+            return Ok(report);
+        }
+    }
+
+    for (sha1, source) in objects {
+        let dest = out_dir.join(object_path(&sha1));
+        if dest.exists() {
+            continue;
+        }
+        write_atomic(&dest, tokio::fs::read(&source).await?).await?;
+        report.objects_written += 1;
+    }
+    let manifest_path = format!("releases/{}.json", release.release);
+    let json = serde_json::to_string_pretty(&release)?;
+    write_atomic(&out_dir.join(&manifest_path), &json).await?;
+
+    let mut index = index.unwrap_or_else(|| ChannelIndex {
+        format_version: FORMAT_VERSION,
+        name: release.instance.name.clone(),
+        latest: 0,
+        releases: vec![],
+    });
+    index.latest = release.release;
+    index.releases.push(ReleaseEntry {
+        release: release.release,
+        published: OffsetDateTime::now_utc(),
+        game_version: release.instance.version.clone(),
+        manifest: manifest_path,
+        sha256: format!("{:x}", Sha256::digest(json.as_bytes())),
+        rollout,
+    });
+    // last, so followers never see a release whose files aren't there yet
+    write_atomic(&report.channel, serde_json::to_string_pretty(&index)?).await?;
+    info!(
+        "Published release {} of {} to {}",
+        release.release,
+        index.name,
+        out_dir.display()
+    );
+    report.published = true;
+    Ok(report)
+}
+
+// stages, widens or halts a published release, None rolls it out to everyone
+pub async fn set_rollout(
+    out_dir: &Path,
+    release: u32,
+    rollout: Option<Rollout>,
+) -> anyhow::Result<ReleaseEntry> {
+    if let Some(rollout) = &rollout {
+        rollout.check()?;
+    }
+    let mut index = read_index(out_dir)?
+        .ok_or_else(|| anyhow!("{} is not an update channel", out_dir.display()))?;
+    let entry = index
+        .releases
+        .iter_mut()
+        .find(|entry| entry.release == release)
+        .ok_or_else(|| anyhow!("{} has no release {}", out_dir.display(), release))?;
+    entry.rollout = rollout;
+    let entry = entry.clone();
+    write_atomic(
+        &out_dir.join(CHANNEL_FILE),
+        serde_json::to_string_pretty(&index)?,
+    )
+    .await?;
+    Ok(entry)
+}
+
+fn new_follower_id() -> anyhow::Result<String> {
+    let mut id = [0u8; 16];
+    getrandom::getrandom(&mut id).map_err(|e| anyhow!("Could not generate an id: {}", e))?;
+    Ok(id.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// the URL of channel.json, given it or the directory it is in
+fn index_url(url: &str) -> String {
+    match url.ends_with(".json") {
+        true => url.to_string(),
+        false => format!("{}/{}", url.trim_end_matches('/'), CHANNEL_FILE),
+    }
+}
+
+async fn fetch_index(downloader: &dyn Downloader, url: &str) -> anyhow::Result<ChannelIndex> {
+    let bytes = downloader
+        .fetch(url)
+        .await
+        .with_context(|| format!("Could not fetch the update channel {}", url))?;
+    let index: ChannelIndex = serde_json::from_slice(&bytes)
+        .with_context(|| format!("{} is not an update channel", url))?;
+    if index.format_version > FORMAT_VERSION {
+        return Err(anyhow!(
+            "{} was published by a newer launcher, update to follow it",
+            url
+        ));
+    }
+    Ok(index)
+}
+
+async fn fetch_release(
+    downloader: &dyn Downloader,
+    index_url: &str,
+    entry: &ReleaseEntry,
+) -> anyhow::Result<ReleaseManifest> {
+    let url = resolve(index_url, &entry.manifest);
+    let bytes = downloader.fetch(&url).await?;
+    if format!("{:x}", Sha256::digest(&bytes)) != entry.sha256 {
+        return Err(anyhow!("Incorrect hash for {}", url));
+    }
+    let release: ReleaseManifest = serde_json::from_slice(&bytes)
+        .with_context(|| format!("{} is not a release manifest", url))?;
+    if release.format_version > FORMAT_VERSION {
+        return Err(anyhow!(
+            "{} was published by a newer launcher, update to install it",
+            url
+        ));
+    }
+
+    // all of these end up in paths
+    let unsafe_path = |path: &str| {
+        path.is_empty()
+            || path.starts_with('/')
+            || path.contains('\\')
+            || path.contains(':')
+            || path.split('/').any(|part| part.is_empty() || part == "..")
+    };
+    if let Some(path) = release
+        .files
+        .iter()
+        .chain(&release.icon)
+        .map(|file| file.path.as_str())
+        .chain(release.versions.iter().map(|version| version.id.as_str()))
+        .find(|path| unsafe_path(path))
+    {
+        return Err(anyhow!("Refusing to install {:?} from {}", path, url));
+    }
+    if let Some(icon) = release.icon.as_ref().filter(|icon| icon.path.contains('/')) {
+        return Err(anyhow!("Refusing to install {:?} from {}", icon.path, url));
+    }
+    Ok(release)
+}
+
+// `path` relative to the directory channel.json is in
+fn resolve(index_url: &str, path: &str) -> String {
+    let base = index_url
+        .rsplit_once('/')
+        .map_or(index_url, |(base, _)| base);
+    format!("{}/{}", base, path)
+}
+
+fn task(index_url: &str, file: &PublishedFile, path: PathBuf) -> DownloadTask {
+    DownloadTask {
+        url: file
+            .url
+            .clone()
+            .unwrap_or_else(|| resolve(index_url, &object_path(&file.sha1))),
+        path,
+        sha1: file.sha1.clone(),
+        size: file.size,
+    }
+}
+
+// already there as published
+fn unchanged(path: &Path, sha1: &str) -> bool {
+    path.is_file() && sha1_file(path).is_ok_and(|existing| existing == sha1)
+}
+
+// puts the release's files in place and records it in the lockfile. Files an earlier release
+// put there and this one dropped are removed, unless they were edited since
+async fn apply(
+    downloader: &dyn Downloader,
+    instances_dir: &Path,
+    cache_dir: &Path,
+    instance: &Instance,
+    index_url: &str,
+    release: ReleaseManifest,
+    concurrency: usize,
+) -> anyhow::Result<Instance> {
+    let game_dir = instance.game_dir(instances_dir);
+    let previous = instance::lockfile(instances_dir, &instance.name)?.release;
+
+    let mut plan = DownloadPlan::default();
+    for version in &release.versions {
+        let path = versions::json_path(cache_dir, &version.id);
+        // an installed version with this id is the same one
+        if !path.exists() {
+            let file = PublishedFile {
+                path: version.id.clone(),
+                sha1: version.sha1.clone(),
+                size: version.size,
+                url: None,
+            };
+            plan.push(task(index_url, &file, path));
+        }
+    }
+    for file in &release.files {
+        let path = game_dir.join(&file.path);
+        if !unchanged(&path, &file.sha1) {
+            plan.push(task(index_url, file, path));
+        }
+    }
+    if let Some(icon) = &release.icon {
+        let path = instance.dir(instances_dir).join(&icon.path);
+        if !unchanged(&path, &icon.sha1) {
+            plan.push(task(index_url, icon, path));
+        }
+    }
+    plan.run(downloader, concurrency, None, &|_, _| {}).await?;
+
+    let files = release
+        .files
+        .iter()
+        .map(|file| (file.path.clone(), file.sha1.clone()))
+        .collect::<BTreeMap<_, _>>();
+    for (path, sha1) in previous.iter().flat_map(|previous| &previous.files) {
+        if files.contains_key(path) {
+            continue;
+        }
+        let file = game_dir.join(path);
+        match unchanged(&file, sha1) {
+            true => std::fs::remove_file(&file)?,
+            false if file.exists() => {
+                warn!(
+                    "Kept {}, the channel dropped it but it was edited since",
+                    file.display()
+                );
+            }
+            false => {}
+        }
+    }
+
+    let installed = InstalledRelease {
+        release: release.release,
+        files,
+    };
+    // local settings stay as they are, only what the pack is built on follows the channel
+    let (instance, _) =
+        instance::update_locked(instances_dir, &instance.name, |instance, lock| {
+            instance.version = release.instance.version.clone();
+            instance.loader = release.instance.loader;
+            if let Some(icon) = &release.icon {
+                instance.icon = Some(icon.path.clone());
+            }
+            lock.loader = release.loader.clone();
+            lock.release = Some(installed);
+        })?;
+    Ok(instance)
+}
+
+// creates an instance from the latest release of the channel at `url` that rolled out to it,
+// which it then keeps following. The game version is left for the caller to install
+pub async fn follow(
+    downloader: &dyn Downloader,
+    instances_dir: &Path,
+    cache_dir: &Path,
+    url: &str,
+    name: Option<&str>,
+    cohorts: &[String],
+    concurrency: usize,
+) -> anyhow::Result<Instance> {
+    let url = index_url(url);
+    let index = fetch_index(downloader, &url).await?;
+    let follower_id = new_follower_id()?;
+    let entry = index
+        .pick(&follower_id, cohorts)
+        .ok_or_else(|| anyhow!("No release of {} has rolled out to this machine yet", url))?;
+    let release = fetch_release(downloader, &url, entry).await?;
+
+    let instance = Instance {
+        name: name.unwrap_or(&index.name).to_string(),
+        update_url: Some(url.clone()),
+        icon: None,
+        ..release.instance.clone()
+    };
+    if instance::load(instances_dir, &instance.name).is_ok() {
+        return Err(anyhow!(
+            "An instance named {} already exists, follow the channel under another name",
+            instance.name
+        ));
+    }
+    instance::save(instances_dir, &instance)?;
+    instance::update_locked(instances_dir, &instance.name, |_, lockfile| {
+        lockfile.follower_id = Some(follower_id)
+    })?;
+
+    let applied = apply(
+        downloader,
+        instances_dir,
+        cache_dir,
+        &instance,
+        &url,
+        release,
+        concurrency,
+    )
+    .await;
+    // a half installed instance would only be in the way of trying again
+    if applied.is_err() {
+        let _ = std::fs::remove_dir_all(instance.dir(instances_dir));
+    }
+    applied
+}
+
+// moves an instance that follows a channel to the latest release that rolled out to it, None
+// when it is on that one or a newer one already
+pub async fn update(
+    downloader: &dyn Downloader,
+    instances_dir: &Path,
+    cache_dir: &Path,
+    name: &str,
+    cohorts: &[String],
+    concurrency: usize,
+) -> anyhow::Result<Option<ReleaseUpdate>> {
+    let instance = instance::load(instances_dir, name)?;
+    let url = instance
+        .update_url
+        .clone()
+        .ok_or_else(|| anyhow!("{} does not follow an update channel", name))?;
+    let lockfile = instance::lockfile(instances_dir, name)?;
+    let from = lockfile.release.map(|release| release.release);
+    // kept, or every check would be another roll of the dice
+    let follower_id = match lockfile.follower_id {
+        Some(id) => id,
+        None => {
+            let id = new_follower_id()?;
+            instance::update_locked(instances_dir, name, |_, lockfile| {
+                lockfile.follower_id = Some(id.clone())
+            })?;
+            id
+        }
+    };
+    let index = fetch_index(downloader, &url).await?;
+    let Some(entry) = index
+        .pick(&follower_id, cohorts)
+        .filter(|entry| from.is_none_or(|from| entry.release > from))
+    else {
+        return Ok(None);
+    };
+    let release = fetch_release(downloader, &url, entry).await?;
+    let instance = apply(
+        downloader,
+        instances_dir,
+        cache_dir,
+        &instance,
+        &url,
+        release,
+        concurrency,
+    )
+    .await?;
+    info!("Updated {} to release {}", name, entry.release);
+    Ok(Some(ReleaseUpdate {
+        instance: instance.name,
+        from,
+        to: entry.release,
+        game_version: instance.version,
+    }))
+}
@@ -0,0 +1,292 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dedup::{self, ExternalLauncher},
+    instance::{self, Instance},
+    loader::LoaderKind,
+    versions, LatestVersion,
+};
+
+pub const PROFILES_FILE: &str = "launcher_profiles.json";
+// profiles we write are keyed by this prefix so exporting again updates them in place
+const PROFILE_KEY_PREFIX: &str = "mod_launcher-";
+const DEFAULT_ICON: &str = "Furnace";
+
+// only the fields we understand, everything else is kept as-is so the official launcher doesn't lose data
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LauncherProfile {
+    #[serde(default)]
+    pub name: String,
+    // "custom", "latest-release" or "latest-snapshot"
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub ptype: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_version_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game_dir: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub java_dir: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub java_args: Option<String>,
+    // a built in block name or a data:image/png;base64 URI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl LauncherProfile {
+    fn version_id(&self, latest: Option<&LatestVersion>) -> Option<String> {
+        match (self.ptype.as_deref(), latest) {
+            (Some("latest-release"), Some(latest)) => Some(latest.release.clone()),
+            (Some("latest-snapshot"), Some(latest)) => Some(latest.snapshot.clone()),
+            _ => self
+                .last_version_id
+                .clone()
+                .filter(|id| !id.starts_with("latest-")),
+        }
+    }
+
+    fn display_name(&self) -> String {
+        match (self.name.trim(), self.ptype.as_deref()) {
+            ("", Some("latest-release")) => String::from("Latest release"),
+            ("", Some("latest-snapshot")) => String::from("Latest snapshot"),
+            (name, _) => name.to_string(),
+        }
+    }
+}
+
+pub struct LauncherProfiles {
+    path: PathBuf,
+    root: serde_json::Value,
+}
+
+impl LauncherProfiles {
+    pub fn load(minecraft_dir: &Path) -> anyhow::Result<LauncherProfiles> {
+        let path = minecraft_dir.join(PROFILES_FILE);
+        let root = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&path)?)
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+        } else {
+            serde_json::json!({ "profiles": {}, "version": 3 })
+        };
+
+        Ok(LauncherProfiles { path, root })
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.root)?)?;
+        Ok(())
+    }
+
+    pub fn profiles(&self) -> anyhow::Result<Vec<(String, LauncherProfile)>> {
+        let Some(profiles) = self.root.get("profiles").and_then(|p| p.as_object()) else {
+            return Ok(vec![]);
+        };
+
+        profiles
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), serde_json::from_value(value.clone())?)))
+            .collect()
+    }
+
+    pub fn get(&self, key: &str) -> anyhow::Result<Option<LauncherProfile>> {
+        match self.root.get("profiles").and_then(|p| p.get(key)) {
+            Some(value) => Ok(Some(serde_json::from_value(value.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn upsert(&mut self, key: &str, profile: &LauncherProfile) -> anyhow::Result<()> {
+        let root = self
+            .root
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("Malformed {}", self.path.display()))?;
+        let profiles = root
+            .entry("profiles")
+            .or_insert_with(|| serde_json::json!({}))
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("Malformed {}", self.path.display()))?;
+        profiles.insert(key.to_string(), serde_json::to_value(profile)?);
+        Ok(())
+    }
+}
+
+// the official launcher's directory, if it has been run on this machine
+pub fn find_minecraft_dir() -> Option<PathBuf> {
+    dedup::detect_installs()
+        .into_iter()
+        .filter(|install| install.launcher == ExternalLauncher::Vanilla)
+        .map(|install| install.root)
+        .find(|root| root.join(PROFILES_FILE).exists())
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ProfileImport {
+    pub imported: Vec<Instance>,
+    // profile name and why it was left out
+    pub skipped: Vec<(String, String)>,
+}
+
+// `latest` resolves the "latest-release"/"latest-snapshot" profiles, they are skipped without it
+pub fn import_profiles(
+    minecraft_dir: &Path,
+    cache_dir: &Path,
+    instances_dir: &Path,
+    latest: Option<&LatestVersion>,
+) -> anyhow::Result<ProfileImport> {
+    let profiles = LauncherProfiles::load(minecraft_dir)?;
+    let mut report = ProfileImport::default();
+
+    for (key, profile) in profiles.profiles()? {
+        // our own exports would come back as duplicates
+        if key.starts_with(PROFILE_KEY_PREFIX) {
+            continue;
+        }
+
+        let name = instance_name(&profile.display_name());
+        let Some(version) = profile.version_id(latest) else {
+            report
+                .skipped
+                .push((name, String::from("could not resolve its version")));
+            continue;
+        };
+        if instance::load(instances_dir, &name).is_ok() {
+            report
+                .skipped
+                .push((name, String::from("an instance with this name exists")));
+            continue;
+        }
+
+        copy_version_chain(minecraft_dir, cache_dir, &version)?;
+
+        let mut instance = Instance::new(&name, &version);
+        instance.loader = guess_loader(&version);
+        instance.java_path = profile.java_dir.clone();
+        instance.jvm_args = profile
+            .java_args
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(String::from)
+            .collect();
+        // profiles without a game dir play straight out of .minecraft
+        instance.custom_game_dir = Some(
+            profile
+                .game_dir
+                .clone()
+                .unwrap_or_else(|| minecraft_dir.to_path_buf()),
+        );
+        instance::save(instances_dir, &instance)?;
+
+        if let Some(icon) = profile.icon.as_deref().and_then(decode_icon) {
+            std::fs::write(instance.dir(instances_dir).join("icon.png"), icon)?;
+            instance.icon = Some(String::from("icon.png"));
+            instance::save(instances_dir, &instance)?;
+        }
+
+        report.imported.push(instance);
+    }
+
+    Ok(report)
+}
+
+// adds or updates a profile pointing at the instance's game dir, returns the profile key
+pub fn export_instance(
+    minecraft_dir: &Path,
+    cache_dir: &Path,
+    instances_dir: &Path,
+    instance: &Instance,
+) -> anyhow::Result<String> {
+    let mut profiles = LauncherProfiles::load(minecraft_dir)?;
+    let key = format!("{}{}", PROFILE_KEY_PREFIX, instance.name);
+    let now =
+        time::OffsetDateTime::now_utc().format(&time::format_description::well_known::Rfc3339)?;
+
+    let mut profile = profiles.get(&key)?.unwrap_or_default();
+    profile.name = instance.name.clone();
+    profile.ptype = Some(String::from("custom"));
+    profile.last_version_id = Some(instance.version.clone());
+    profile.game_dir = Some(dunce::canonicalize(instance.game_dir(instances_dir))?);
+    profile.java_dir = instance.java_path.clone();
+    profile.java_args = Some(instance.jvm_args.join(" ")).filter(|args| !args.is_empty());
+    profile.icon = Some(encode_icon(instance, instances_dir)?);
+    profile.created.get_or_insert_with(|| now.clone());
+    profile.last_used = Some(now);
+
+    // the official launcher can't install modded versions on its own
+    copy_version_chain(cache_dir, minecraft_dir, &instance.version)?;
+
+    profiles.upsert(&key, &profile)?;
+    profiles.save()?;
+    Ok(key)
+}
+
+fn instance_name(profile_name: &str) -> String {
+    let name = profile_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ' ') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(64)
+        .collect::<String>();
+    let name = name.trim().trim_start_matches('.');
+
+    if name.is_empty() {
+        String::from("Imported")
+    } else {
+        name.to_string()
+    }
+}
+
+fn guess_loader(version_id: &str) -> Option<LoaderKind> {
+    let id = version_id.to_ascii_lowercase();
+    [
+        LoaderKind::NeoForge,
+        LoaderKind::Forge,
+        LoaderKind::Quilt,
+        LoaderKind::Fabric,
+    ]
+    .into_iter()
+    .find(|kind| id.contains(kind.id()))
+}
+
+fn decode_icon(icon: &str) -> Option<Vec<u8>> {
+    let data = icon.strip_prefix("data:image/png;base64,")?;
+    STANDARD.decode(data.trim()).ok()
+}
+
+fn encode_icon(instance: &Instance, instances_dir: &Path) -> anyhow::Result<String> {
+    match instance.icon_path(instances_dir) {
+        Some(path) if path.extension().is_some_and(|ext| ext == "png") => Ok(format!(
+            "data:image/png;base64,{}",
+            STANDARD.encode(std::fs::read(path)?)
+        )),
+        _ => Ok(String::from(DEFAULT_ICON)),
+    }
+}
+
+// copies versions/<id>/<id>.json and its parents, vanilla versions are left to the manifest
+fn copy_version_chain(from: &Path, to: &Path, id: &str) -> anyhow::Result<()> {
+    for id in versions::lineage(from, id)? {
+        let dest = versions::json_path(to, &id);
+        if !dest.exists() {
+            std::fs::create_dir_all(versions::version_dir(to, &id))?;
+            std::fs::copy(versions::json_path(from, &id), &dest)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,434 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::{
+    check_sha1_matches, instance, legacy, natives,
+    net::{MetaProvider, NetworkStatus},
+    rules::{Environment, Rule},
+    write_atomic, Artifact, AssetIndexFile, FileInfo, JavaVersion, LaunchArguments, Library,
+    LibraryDownloads, VersionDownloads, VersionInfo, VersionManifest,
+};
+
+// Mojang's own repository, used by libraries that give neither `downloads` nor `url`
+const DEFAULT_MAVEN: &str = "https://libraries.minecraft.net/";
+
+// a version JSON as stored on disk, modded ones only carry what differs from `inheritsFrom`
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct VersionJson {
+    id: String,
+    inherits_from: Option<String>,
+    // the version whose client jar is launched
+    jar: Option<String>,
+    arguments: Option<LaunchArguments>,
+    // everything before 1.13, game arguments only
+    minecraft_arguments: Option<String>,
+    #[serde(default)]
+    minimum_launcher_version: u32,
+    asset_index: Option<AssetIndexFile>,
+    downloads: Option<VersionDownloads>,
+    java_version: Option<JavaVersion>,
+    #[serde(default)]
+    libraries: Vec<LibraryJson>,
+    main_class: Option<String>,
+    #[serde(default, with = "time::serde::iso8601::option")]
+    release_time: Option<time::OffsetDateTime>,
+}
+
+// vanilla libraries list their downloads, Fabric and Forge ones give a maven coordinate and repository
+#[derive(Deserialize, Debug)]
+pub(crate) struct LibraryJson {
+    name: String,
+    downloads: Option<LibraryDownloads>,
+    url: Option<String>,
+    sha1: Option<String>,
+    size: Option<u64>,
+    rules: Option<Vec<Rule>>,
+    natives: Option<HashMap<String, String>>,
+    extract: Option<ExtractJson>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ExtractJson {
+    #[serde(default)]
+    exclude: Vec<String>,
+}
+
+impl LibraryJson {
+    pub(crate) fn into_library(self) -> anyhow::Result<Library> {
+        let downloads = match self.downloads {
+            Some(downloads) => downloads,
+            None => {
+                let path = maven_path(&self.name)
+                    .ok_or_else(|| anyhow!("Invalid library name {}", self.name))?;
+                let repository = self.url.as_deref().unwrap_or(DEFAULT_MAVEN);
+                LibraryDownloads {
+                    artifact: Some(Artifact {
+                        info: FileInfo {
+                            // an empty hash skips verification
+                            sha1: self.sha1.unwrap_or_default(),
+                            size: self.size.unwrap_or_default(),
+                            url: format!("{}/{}", repository.trim_end_matches('/'), path),
+                        },
+                        path,
+                    }),
+                    classifiers: HashMap::new(),
+                }
+            }
+        };
+
+        Ok(Library {
+            downloads,
+            name: self.name,
+            rules: self.rules,
+            natives: self.natives,
+            extract_exclude: self
+                .extract
+                .map(|extract| extract.exclude)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+// a library name, group:artifact:version[:classifier][@extension]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Coordinate<'a> {
+    pub group: &'a str,
+    pub artifact: &'a str,
+    pub version: &'a str,
+    pub classifier: Option<&'a str>,
+    pub extension: &'a str,
+}
+
+impl<'a> Coordinate<'a> {
+    pub fn parse(name: &'a str) -> Option<Coordinate<'a>> {
+        let (coordinate, extension) = name.split_once('@').unwrap_or((name, "jar"));
+        let mut parts = coordinate.split(':');
+        Some(Coordinate {
+            group: parts.next()?,
+            artifact: parts.next()?,
+            version: parts.next()?,
+            classifier: parts.next(),
+            extension,
+        })
+    }
+
+    // the same jar in any version: natives for another platform are a different one
+    pub fn key(&self) -> (&'a str, &'a str, Option<&'a str>, &'a str) {
+        (self.group, self.artifact, self.classifier, self.extension)
+    }
+}
+
+// group:artifact:version[:classifier][@extension] -> group/path/artifact/version/artifact-version[-classifier].extension
+pub(crate) fn maven_path(name: &str) -> Option<String> {
+    let Coordinate {
+        group,
+        artifact,
+        version,
+        classifier,
+        extension,
+    } = Coordinate::parse(name)?;
+    let file_name = match classifier {
+        Some(classifier) => format!("{}-{}-{}.{}", artifact, version, classifier, extension),
+        None => format!("{}-{}.{}", artifact, version, extension),
+    };
+
+    Some(format!(
+        "{}/{}/{}/{}",
+        group.replace('.', "/"),
+        artifact,
+        version,
+        file_name
+    ))
+}
+
+// one entry per artifact, in the order they first appear so the classpath is the same on every
+// launch. Of two versions the higher one stays, e.g. when a hand-edited or merged JSON lists
+// Guava twice. Names that aren't maven coordinates are kept as they are
+pub(crate) fn dedup_libraries<'a>(
+    libraries: impl Iterator<Item = &'a Library>,
+) -> Vec<&'a Library> {
+    let mut kept: Vec<&Library> = Vec::new();
+    let mut positions = HashMap::new();
+    for library in libraries {
+        let Some(coordinate) = Coordinate::parse(&library.name) else {
+            kept.push(library);
+            continue;
+        };
+        let Some(&position) = positions.get(&coordinate.key()) else {
+            positions.insert(coordinate.key(), kept.len());
+            kept.push(library);
+            continue;
+        };
+
+        let existing = kept[position];
+        let existing_version = Coordinate::parse(&existing.name).map_or("", |c| c.version);
+        if instance::compare_versions(coordinate.version, existing_version).is_gt() {
+            debug!(kept = %library.name, dropped = %existing.name, "duplicate library");
+            kept[position] = library;
+        } else {
+            debug!(kept = %existing.name, dropped = %library.name, "duplicate library");
+        }
+    }
+    kept
+}
+
+impl VersionJson {
+    // `self` is the child: its values win, and its libraries go first on the classpath
+    // a parent's library the child lists in any version is dropped, the loader knows what it needs
+    fn inherit(self, parent: VersionJson) -> VersionJson {
+        let overridden = self
+            .libraries
+            .iter()
+            .filter_map(|library| Coordinate::parse(&library.name))
+            .map(|coordinate| coordinate.key())
+            .collect::<HashSet<_>>();
+        let parent_libraries = parent
+            .libraries
+            .into_iter()
+            .filter(|library| {
+                let keep = Coordinate::parse(&library.name)
+                    .is_none_or(|coordinate| !overridden.contains(&coordinate.key()));
+                if !keep {
+                    debug!(library = %library.name, child = %self.id, "library overridden");
+                }
+                keep
+            })
+            .collect::<Vec<_>>();
+
+        let arguments = match (parent.arguments, self.arguments) {
+            (Some(mut arguments), Some(child)) => {
+                arguments.game.extend(child.game);
+                arguments.jvm.extend(child.jvm);
+                Some(arguments)
+            }
+            (parent, child) => child.or(parent),
+        };
+
+        VersionJson {
+            id: self.id,
+            inherits_from: parent.inherits_from,
+            jar: self.jar.or(parent.jar),
+            arguments,
+            // legacy Forge repeats the whole string rather than adding to it
+            minecraft_arguments: self.minecraft_arguments.or(parent.minecraft_arguments),
+            minimum_launcher_version: self
+                .minimum_launcher_version
+                .max(parent.minimum_launcher_version),
+            asset_index: self.asset_index.or(parent.asset_index),
+            downloads: self.downloads.or(parent.downloads),
+            java_version: self.java_version.or(parent.java_version),
+            libraries: self.libraries.into_iter().chain(parent_libraries).collect(),
+            main_class: self.main_class.or(parent.main_class),
+            // loaders stamp their own build date here, the game's is what era checks care about
+            release_time: parent.release_time.or(self.release_time),
+        }
+    }
+
+    fn into_info(self) -> anyhow::Result<VersionInfo> {
+        let id = self.id;
+        let missing = |field: &str| anyhow!("Version {} has no {}", id, field);
+
+        let arguments = match (self.arguments, self.minecraft_arguments) {
+            (Some(arguments), _) => arguments,
+            (None, Some(legacy)) => legacy::arguments(&legacy),
+            (None, None) => return Err(missing("arguments")),
+        };
+
+        Ok(VersionInfo {
+            arguments,
+            asset_index: self.asset_index.ok_or_else(|| missing("assetIndex"))?,
+            downloads: self.downloads.ok_or_else(|| missing("downloads"))?,
+            java_version: self.java_version.ok_or_else(|| missing("javaVersion"))?,
+            libraries: self
+                .libraries
+                .into_iter()
+                .map(LibraryJson::into_library)
+                .collect::<anyhow::Result<_>>()?,
+            main_class: self.main_class.ok_or_else(|| missing("mainClass"))?,
+            minimum_launcher_version: self.minimum_launcher_version,
+            release_time: self.release_time.ok_or_else(|| missing("releaseTime"))?,
+            jar: self.jar.unwrap_or_else(|| id.clone()),
+            id,
+        })
+    }
+}
+
+pub fn version_dir(cache_dir: &Path, id: &str) -> PathBuf {
+    cache_dir.join("versions").join(id)
+}
+
+pub fn json_path(cache_dir: &Path, id: &str) -> PathBuf {
+    version_dir(cache_dir, id).join(format!("{}.json", id))
+}
+
+pub fn jar_path(cache_dir: &Path, id: &str) -> PathBuf {
+    version_dir(cache_dir, id).join(format!("{}.jar", id))
+}
+
+fn manifest_cache_path(cache_dir: &Path) -> PathBuf {
+    cache_dir.join("versions").join("version_manifest_v2.json")
+}
+
+// the last manifest seen, for lookups that shouldn't touch the network
+pub fn cached_manifest(cache_dir: &Path) -> Option<VersionManifest> {
+    let json = std::fs::read_to_string(manifest_cache_path(cache_dir)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+pub fn cache_manifest(cache_dir: &Path, manifest: &VersionManifest) -> anyhow::Result<()> {
+    let path = manifest_cache_path(cache_dir);
+    std::fs::create_dir_all(cache_dir.join("versions"))?;
+    std::fs::write(path, serde_json::to_string(manifest)?)?;
+    Ok(())
+}
+
+// the current manifest, or offline the last one seen
+pub async fn manifest(
+    meta: &dyn MetaProvider,
+    cache_dir: &Path,
+    network: NetworkStatus,
+) -> anyhow::Result<VersionManifest> {
+    match network {
+        NetworkStatus::Online => {
+            let manifest = meta.version_manifest().await?;
+            // keeps `search` and offline launches able to list versions
+            cache_manifest(cache_dir, &manifest)?;
+            Ok(manifest)
+        }
+        NetworkStatus::Offline => cached_manifest(cache_dir)
+            .ok_or_else(|| anyhow!("Offline, and no version list was cached while online")),
+    }
+}
+
+// ids of every version with a JSON in versions/, vanilla and modded alike
+pub fn installed(cache_dir: &Path) -> anyhow::Result<Vec<String>> {
+    let versions_dir = cache_dir.join("versions");
+    if !versions_dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut ids = Vec::new();
+    for entry in std::fs::read_dir(versions_dir)? {
+        let entry = entry?;
+        let id = entry.file_name().to_string_lossy().into_owned();
+        if json_path(cache_dir, &id).exists() {
+            ids.push(id);
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+// ids from `id` up through its `inheritsFrom` parents, stopping at the first one not installed
+pub fn lineage(cache_dir: &Path, id: &str) -> anyhow::Result<Vec<String>> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Parent {
+        inherits_from: Option<String>,
+    }
+
+    let mut ids: Vec<String> = Vec::new();
+    let mut next = Some(id.to_string());
+    while let Some(id) = next {
+        let path = json_path(cache_dir, &id);
+        if ids.contains(&id) || !path.exists() {
+            break;
+        }
+        next = serde_json::from_str::<Parent>(&std::fs::read_to_string(&path)?)
+            .with_context(|| format!("Failed to parse {}", path.display()))?
+            .inherits_from;
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+// loads versions/<id>/<id>.json, fetching vanilla versions from the manifest the first time
+// unless there is nothing to fetch from
+async fn load(
+    meta: Option<&dyn MetaProvider>,
+    cache_dir: &Path,
+    id: &str,
+    manifest: &mut Option<VersionManifest>,
+) -> anyhow::Result<VersionJson> {
+    let path = json_path(cache_dir, id);
+    if !path.exists() {
+        let Some(meta) = meta else {
+            return Err(anyhow!("Version {} is not installed", id));
+        };
+        if manifest.is_none() {
+            let fetched = meta.version_manifest().await?;
+            cache_manifest(cache_dir, &fetched)?;
+            *manifest = Some(fetched);
+        }
+        let version = manifest
+            .as_ref()
+            .and_then(|manifest| manifest.find_version_by_id(id))
+            .ok_or_else(|| anyhow!("Unknown version {}", id))?;
+
+        let bytes = meta.version_json(version).await?;
+        // v1 manifests carry no hash to check against
+        if !version.sha1.is_empty() && !check_sha1_matches(&bytes, &version.sha1) {
+            return Err(anyhow!("Version JSON for {} does not match its hash", id));
+        }
+
+        write_atomic(&path, bytes).await?;
+    }
+
+    let mut json: VersionJson = serde_json::from_str(&tokio::fs::read_to_string(&path).await?)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    if json.jar.is_none() && json.downloads.is_some() {
+        json.jar = Some(json.id.clone());
+    }
+    Ok(json)
+}
+
+pub(crate) async fn resolve(
+    meta: &dyn MetaProvider,
+    cache_dir: &Path,
+    id: &str,
+) -> anyhow::Result<VersionInfo> {
+    resolve_with(Some(meta), cache_dir, id).await
+}
+
+// resolves from versions/ alone, never downloads
+pub(crate) async fn resolve_installed(cache_dir: &Path, id: &str) -> anyhow::Result<VersionInfo> {
+    resolve_with(None, cache_dir, id).await
+}
+
+// follows `inheritsFrom` up to the root version and merges the chain back down
+async fn resolve_with(
+    meta: Option<&dyn MetaProvider>,
+    cache_dir: &Path,
+    id: &str,
+) -> anyhow::Result<VersionInfo> {
+    let mut manifest = None;
+    let mut chain: Vec<VersionJson> = Vec::new();
+    let mut next = Some(id.to_string());
+
+    while let Some(id) = next {
+        if chain.iter().any(|version| version.id == id) {
+            return Err(anyhow!("Version {} inherits from itself", id));
+        }
+        let version = load(meta, cache_dir, &id, &mut manifest).await?;
+        next = version.inherits_from.clone();
+        chain.push(version);
+    }
+
+    let mut merged = chain
+        .pop()
+        .expect("chain holds at least the requested version");
+    while let Some(child) = chain.pop() {
+        merged = child.inherit(merged);
+    }
+
+    let mut info = merged.into_info()?;
+    natives::apply_overrides(&mut info.libraries, &Environment::current());
+    Ok(info)
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    process::{ExitStatus, Stdio},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::Command,
+    sync::mpsc,
+    time::Instant,
+};
+use tracing::warn;
+
+// lines of output kept for errors, the rest only goes to whoever asked for it line by line
+pub const TAIL_LINES: usize = 20;
+
+// how long the launcher waits on the helper processes it runs: installers and their
+// processors, keytool, java probes and the shell. The game itself is never timed out
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct Timeouts {
+    // `java -version` and other quick checks
+    pub probe_secs: u64,
+    // anything else, from start to exit. Forge processors remapping the game take minutes on
+    // slow disks
+    pub helper_secs: u64,
+    // a helper printing nothing for this long counts as hung too, never when 0
+    pub idle_secs: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            probe_secs: 30,
+            helper_secs: 30 * 60,
+            idle_secs: 10 * 60,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn probe(&self) -> Limits {
+        Limits {
+            total: Duration::from_secs(self.probe_secs),
+            idle: None,
+        }
+    }
+
+    pub fn helper(&self) -> Limits {
+        Limits {
+            total: Duration::from_secs(self.helper_secs),
+            idle: (self.idle_secs > 0).then(|| Duration::from_secs(self.idle_secs)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub total: Duration,
+    pub idle: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub struct Finished {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+    // the last TAIL_LINES lines of both, in the order they came
+    pub tail: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HangReason {
+    TimedOut(Duration),
+    Silent(Duration),
+}
+
+// returned, through anyhow, when the watchdog killed a helper
+#[derive(Debug)]
+pub struct Hung {
+    pub command: String,
+    pub reason: HangReason,
+    pub tail: Vec<String>,
+}
+
+impl fmt::Display for Hung {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reason {
+            HangReason::TimedOut(limit) => write!(
+                f,
+                "{} was still running after {}s and was killed",
+                self.command,
+                limit.as_secs()
+            )?,
+            HangReason::Silent(limit) => write!(
+                f,
+                "{} printed nothing for {}s and was killed",
+                self.command,
+                limit.as_secs()
+            )?,
+        }
+        match self.tail.is_empty() {
+            true => write!(f, ", it had no output"),
+            false => write!(f, ", its last output:\n{}", self.tail.join("\n")),
+        }
+    }
+}
+
+impl std::error::Error for Hung {}
+
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+async fn forward_lines(
+    stream: impl AsyncRead + Unpin,
+    kind: Stream,
+    sink: mpsc::UnboundedSender<(Stream, String)>,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line).await? > 0 {
+        let text = String::from_utf8_lossy(&line);
+        let _ = sink.send((kind, text.trim_end_matches(['\r', '\n']).to_string()));
+        line.clear();
+    }
+    Ok(())
+}
+
+// runs `command` to the end with its output captured, handing every line to `on_line` as it
+// comes. Killed with a Hung error once it runs past `limits`, or when `on_line` fails
+pub async fn run(
+    command: &mut Command,
+    limits: Limits,
+    on_line: &mut (dyn FnMut(&str) -> anyhow::Result<()> + Send),
+) -> anyhow::Result<Finished> {
+    let name = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Could not run {}", name))?;
+    let (sink, mut lines) = mpsc::unbounded_channel();
+    let readers = tokio::spawn(futures::future::join(
+        forward_lines(
+            child.stdout.take().expect("piped stdout"),
+            Stream::Stdout,
+            sink.clone(),
+        ),
+        forward_lines(
+            child.stderr.take().expect("piped stderr"),
+            Stream::Stderr,
+            sink,
+        ),
+    ));
+
+    let deadline = Instant::now() + limits.total;
+    let mut last_output = Instant::now();
+    let (mut stdout, mut stderr) = (String::new(), String::new());
+    let mut tail = VecDeque::new();
+    let reason = loop {
+        // without an idle limit this never fires before the deadline does
+        let quiet_until = last_output + limits.idle.unwrap_or(limits.total);
+        tokio::select! {
+            line = lines.recv() => {
+                let Some((kind, line)) = line else {
+                    break None;
+                };
+                last_output = Instant::now();
+                on_line(&line)?;
+                let captured = match kind {
+                    Stream::Stdout => &mut stdout,
+                    Stream::Stderr => &mut stderr,
+                };
+                captured.push_str(&line);
+                captured.push('\n');
+                if tail.len() == TAIL_LINES {
+                    tail.pop_front();
+                }
+                tail.push_back(line);
+            }
+            _ = tokio::time::sleep_until(deadline) => break Some(HangReason::TimedOut(limits.total)),
+            _ = tokio::time::sleep_until(quiet_until), if limits.idle.is_some() => {
+                break Some(HangReason::Silent(limits.idle.unwrap_or_default()));
+            }
+        }
+    };
+    // its output ended, it may still be busy exiting
+    let reason = match reason {
+        Some(reason) => reason,
+        None => match tokio::time::timeout_at(deadline, child.wait()).await {
+            Ok(status) => {
+                let _ = readers.await;
+                return Ok(Finished {
+                    status: status?,
+                    stdout,
+                    stderr,
+                    tail: tail.into(),
+                });
+            }
+            Err(_) => HangReason::TimedOut(limits.total),
+        },
+    };
+
+    readers.abort();
+    if let Err(e) = child.kill().await {
+        warn!("Could not kill {}: {}", name, e);
+    }
+    Err(Hung {
+        command: name,
+        reason,
+        tail: tail.into(),
+    }
+    .into())
+}
+
+// `run` for helpers whose output is only looked at once they exit
+pub async fn output(command: &mut Command, limits: Limits) -> anyhow::Result<Finished> {
+    run(command, limits, &mut |_| Ok(())).await
+}
@@ -0,0 +1,246 @@
+use std::path::PathBuf;
+
+use mod_launcher::{config::Config, daemon};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+};
+
+fn temp_work_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("mod_launcher_test_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+struct Client {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    write: OwnedWriteHalf,
+    next_id: u64,
+    // what arrived while waiting for responses
+    notifications: Vec<Value>,
+}
+
+impl Client {
+    async fn connect(port: u16) -> Client {
+        let (read, write) = TcpStream::connect(("127.0.0.1", port))
+            .await
+            .unwrap()
+            .into_split();
+        Client {
+            lines: BufReader::new(read).lines(),
+            write,
+            next_id: 1,
+            notifications: Vec::new(),
+        }
+    }
+
+    async fn call(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        self.write
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .unwrap();
+        loop {
+            let response = self.lines.next_line().await.unwrap().unwrap();
+            let response: Value = serde_json::from_str(&response).unwrap();
+            // notifications arrive in between
+            if response.get("method").is_some() {
+                self.notifications.push(response);
+                continue;
+            }
+            assert_eq!(response["id"], id);
+            return response;
+        }
+    }
+
+    // the params of the first notification of `method` that `matches`
+    async fn wait_for_notification(
+        &mut self,
+        method: &str,
+        matches: impl Fn(&Value) -> bool,
+    ) -> Value {
+        let found = |message: &Value| message["method"] == method && matches(&message["params"]);
+        if let Some(message) = self.notifications.iter().find(|message| found(message)) {
+            return message["params"].clone();
+        }
+        loop {
+            let message = self.lines.next_line().await.unwrap().unwrap();
+            let message: Value = serde_json::from_str(&message).unwrap();
+            if found(&message) {
+                return message["params"].clone();
+            }
+        }
+    }
+
+    // the task once it stopped being queued or running
+    async fn wait_for_task(&mut self, id: u64) -> Value {
+        for _ in 0..100 {
+            let tasks = self.call("tasks.list", Value::Null).await["result"].clone();
+            let task = tasks
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|task| task["id"] == id)
+                .cloned()
+                .unwrap();
+            if !matches!(task["status"].as_str(), Some("queued" | "running")) {
+                return task;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        panic!("task {} never finished", id);
+    }
+}
+
+#[tokio::test]
+async fn daemon_requires_the_token() {
+    let work_dir = temp_work_dir("daemon");
+    let config = Config::load(&work_dir).unwrap();
+    let (listener, info) = daemon::bind(0).await.unwrap();
+    let server = tokio::spawn(daemon::serve(listener, info.clone(), config));
+
+    // serve writes the connection details before accepting anything
+    let mut client = Client::connect(info.port).await;
+    let response = client.call("sessions.list", Value::Null).await;
+    assert_eq!(response["error"]["code"], -32001);
+    assert_eq!(
+        daemon::DaemonInfo::load(&work_dir).unwrap().token,
+        info.token
+    );
+
+    let response = client.call("auth", json!({ "token": "wrong" })).await;
+    assert_eq!(response["error"]["code"], -32001);
+    let response = client.call("auth", json!({ "token": info.token })).await;
+    assert_eq!(response["result"], Value::Null);
+
+    let response = client.call("sessions.list", Value::Null).await;
+    assert_eq!(response["result"], json!([]));
+    let response = client.call("state.snapshot", Value::Null).await;
+    assert_eq!(response["result"]["accounts"], json!([]));
+    assert_eq!(response["result"]["instances"], json!([]));
+    assert_eq!(response["result"]["tasks"], json!([]));
+    let response = client.call("build.info", Value::Null).await;
+    assert_eq!(response["result"]["version"], env!("CARGO_PKG_VERSION"));
+    let response = client.call("versions.delete", Value::Null).await;
+    assert_eq!(response["error"]["code"], -32601);
+    let response = client.call("install", json!({ "version": 1 })).await;
+    assert_eq!(response["error"]["code"], -32602);
+
+    let response = client.call("shutdown", Value::Null).await;
+    assert_eq!(response["result"], Value::Null);
+    server.await.unwrap().unwrap();
+    assert!(!daemon::DaemonInfo::path(&work_dir).exists());
+}
+
+#[cfg(feature = "client")]
+#[tokio::test]
+async fn typed_client_talks_to_the_daemon() {
+    use mod_launcher::{
+        instance::ChangeKind,
+        protocol::{DaemonClient, Notification, RpcError, INVALID_PARAMS},
+    };
+
+    let work_dir = temp_work_dir("daemon_client");
+    let config = Config::load(&work_dir).unwrap();
+    let config_instances_dir = config.instances_dir();
+    let (listener, info) = daemon::bind(0).await.unwrap();
+    let server = tokio::spawn(daemon::serve(listener, info.clone(), config));
+
+    let mut client = DaemonClient::connect_to(&info).await.unwrap();
+    assert!(client.sessions().await.unwrap().is_empty());
+    assert!(client.accounts().await.unwrap().is_empty());
+    let state = client.snapshot().await.unwrap();
+    assert!(state.instances.is_empty());
+    // made outside the daemon, like the CLI would
+    mod_launcher::instance::create(&config_instances_dir, "watched", "1.20.4").unwrap();
+    match client.next_notification().await.unwrap().unwrap() {
+        Notification::InstanceChanged(change) => {
+            assert_eq!(change.name, "watched");
+            assert_eq!(change.kind, ChangeKind::Added);
+        }
+        other => panic!("unexpected notification {:?}", other),
+    }
+    let error = client.install("").await.unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<RpcError>().unwrap().code,
+        INVALID_PARAMS
+    );
+
+    client.shutdown().await.unwrap();
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn daemon_runs_the_task_queue() {
+    use mod_launcher::tasks::{TaskKind, TaskQueue};
+
+    let work_dir = temp_work_dir("daemon_tasks");
+    let config = Config::load(&work_dir).unwrap();
+    // queued by the CLI or an earlier daemon
+    std::fs::create_dir_all(&work_dir).unwrap();
+    let mut queue = TaskQueue::open(&work_dir).unwrap();
+    let gc = || TaskKind::CacheGc {
+        prune_versions: false,
+    };
+    let waiting = queue.enqueue(gc()).unwrap();
+    let paused = queue.enqueue(gc()).unwrap();
+    queue.pause(paused).unwrap();
+
+    let (listener, info) = daemon::bind(0).await.unwrap();
+    let server = tokio::spawn(daemon::serve(listener, info.clone(), config));
+    let mut client = Client::connect(info.port).await;
+    client.call("auth", json!({ "token": info.token })).await;
+
+    let task = client.wait_for_task(waiting).await;
+    assert!(task["status"] == "completed" || task["status"] == "failed");
+    let tasks = client.call("tasks.list", Value::Null).await;
+    assert_eq!(tasks["result"][1]["status"], "paused");
+    let response = client.call("tasks.resume", json!({ "id": paused })).await;
+    assert_eq!(response["result"], Value::Null);
+    client.wait_for_task(paused).await;
+    let response = client.call("tasks.cancel", json!({ "id": paused })).await;
+    assert_eq!(response["error"]["code"], -32000);
+    let response = client.call("tasks.pause", json!({ "id": 99 })).await;
+    assert_eq!(response["error"]["code"], -32000);
+
+    let response = client.call("cache.gc", json!({})).await;
+    let queued = response["result"]["task"].as_u64().unwrap();
+    assert_eq!(queued, paused + 1);
+    client.wait_for_task(queued).await;
+
+    // frontends keep their snapshot current from notifications
+    let task = client
+        .wait_for_notification("task.changed", |task| {
+            task["id"] == queued && task["status"] != "queued" && task["status"] != "running"
+        })
+        .await;
+    assert_eq!(task["kind"]["type"], "cache_gc");
+    let account = json!({
+        "username": "Steve",
+        "uuid": "fa7dae1b-e8ca-4540-9195-356e364db0af",
+        "access_token": "token",
+        "expires_at": "2030-01-01T00:00:00Z",
+        "refresh_token": "refresh",
+        "xuid": null,
+    });
+    let store = json!({ "selected": account["uuid"], "accounts": [account] });
+    std::fs::write(work_dir.join("accounts.json"), store.to_string()).unwrap();
+    let accounts = client
+        .wait_for_notification("account.changed", |_| true)
+        .await;
+    assert_eq!(accounts["accounts"][0]["username"], "Steve");
+    assert_eq!(accounts["accounts"][0]["selected"], true);
+    assert!(!accounts.to_string().contains("token"));
+
+    let response = client.call("shutdown", Value::Null).await;
+    assert_eq!(response["result"], Value::Null);
+    server.await.unwrap().unwrap();
+    std::fs::remove_dir_all(work_dir).unwrap();
+}
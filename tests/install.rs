@@ -0,0 +1,2435 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use mod_launcher::{
+    adopt::{self, AdoptedFile, ContentSource},
+    adoptium,
+    assets::{AssetCheck, VerifyPolicy},
+    cache,
+    components::{self, Component},
+    curseforge,
+    disk::{self, Category, CleanupAction, CleanupOptions},
+    download::{self, DownloadPlan, DownloadTask, Misdeclared, RateEstimator},
+    fabric, freeze, install_version_with, instance, language, launch_minecraft,
+    loader::{Loader, LoaderKind},
+    loader_matrix, mirror,
+    net::{DirectoryProvider, Downloader, MetaProvider, NetworkStatus, UrlManifest},
+    portable,
+    rules::Environment,
+    saves, verify_version, versions, LaunchOptions,
+};
+
+const VERSION: &str = "fixture-1.0";
+// objects from the fixture's asset index
+const SOUNDS_OBJECT: &str = "8a/8adfeb550ed9b52fd6f034d939fa9029fd0de9db";
+const TEXTURE_OBJECT: &str = "de/deb50296b6d6f176c81c54033ba5f740fd366ffe";
+
+fn fixture_mirror() -> DirectoryProvider {
+    DirectoryProvider::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mirror"))
+}
+
+// a fresh cache dir per test, tests run in parallel
+fn temp_cache(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("mod_launcher_test_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn install(
+    downloader: &dyn Downloader,
+    cache_dir: &Path,
+    check: AssetCheck,
+) -> anyhow::Result<()> {
+    let policy = VerifyPolicy {
+        asset_check: check,
+        ..Default::default()
+    };
+    install_version_with(
+        &fixture_mirror(),
+        downloader,
+        cache_dir,
+        VERSION,
+        2,
+        policy,
+        &|_, _| {},
+    )
+    .await
+}
+
+struct CountingDownloader {
+    inner: DirectoryProvider,
+    fetched: AtomicUsize,
+}
+
+#[async_trait]
+impl Downloader for CountingDownloader {
+    async fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        self.fetched.fetch_add(1, Ordering::SeqCst);
+        self.inner.fetch(url).await
+    }
+}
+
+// serves garbage in place of one file
+struct TamperingDownloader {
+    inner: DirectoryProvider,
+    target: &'static str,
+}
+
+#[async_trait]
+impl Downloader for TamperingDownloader {
+    async fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        if url.ends_with(self.target) {
+            return Ok(b"tampered".to_vec());
+        }
+        self.inner.fetch(url).await
+    }
+}
+
+#[tokio::test]
+async fn installs_fixture_version() {
+    let cache_dir = temp_cache("installs");
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+
+    let libraries = cache::libraries_dir(&cache_dir);
+    assert!(libraries
+        .join("com/example/fixture-lib/1.0/fixture-lib-1.0.jar")
+        .is_file());
+    // maven coordinates without `downloads` resolve against the library's repository
+    assert!(libraries
+        .join("com/example/fixture-maven/2.0/fixture-maven-2.0.jar")
+        .is_file());
+    // only allowed on another OS
+    assert!(!libraries.join("com/example/fixture-elsewhere").exists());
+
+    assert!(versions::jar_path(&cache_dir, VERSION).is_file());
+    assert!(versions::json_path(&cache_dir, VERSION).is_file());
+    let objects = cache::assets_dir(&cache_dir).join("objects");
+    assert!(objects.join(SOUNDS_OBJECT).is_file());
+    assert!(objects.join(TEXTURE_OBJECT).is_file());
+
+    let report = verify_version(&cache_dir, VERSION, None).await.unwrap();
+    assert!(report.is_ok(), "{:?}", report);
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+#[tokio::test]
+async fn dry_run_plans_without_touching_the_game_dir() {
+    let cache_dir = temp_cache("dry_run");
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+
+    let game_dir = cache_dir.join("game");
+    let mut options = LaunchOptions::new(VERSION);
+    options.work_dir = Some(cache_dir.clone());
+    options.cache_dir = Some(cache_dir.clone());
+    options.game_dir = Some(game_dir.clone());
+    options.java_path = Some(cache_dir.join("no-java"));
+    options.offline = true;
+    let plan = options.dry_run().await.unwrap();
+    assert_eq!(plan.version, VERSION);
+    assert_eq!(
+        plan.components,
+        [Component::Vanilla {
+            version: VERSION.to_string()
+        }]
+    );
+    assert_eq!(plan.classpath.len(), 3);
+    assert!(plan.downloads.is_empty());
+    assert!(!game_dir.exists());
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+fn write_zip(path: &Path, entries: &[(&str, &str)]) {
+    use std::io::Write;
+
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+    for (name, contents) in entries {
+        zip.start_file(*name, zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(contents.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+#[test]
+fn jar_mods_patch_the_client_jar_in_order() {
+    use std::io::Read;
+
+    let dir = temp_cache("jar_mods");
+    std::fs::create_dir_all(&dir).unwrap();
+    let client_jar = dir.join("client.jar");
+    write_zip(
+        &client_jar,
+        &[
+            ("META-INF/MOJANGCS.SF", "signature"),
+            ("a.class", "vanilla"),
+            ("b.class", "vanilla"),
+        ],
+    );
+    let (first, second, agent) = (
+        dir.join("first.zip"),
+        dir.join("second.zip"),
+        dir.join("agent.jar"),
+    );
+    write_zip(&first, &[("a.class", "first"), ("c.class", "first")]);
+    write_zip(&second, &[("a.class", "second")]);
+    write_zip(&agent, &[]);
+
+    let jar_mod = |path: &Path| Component::JarMod {
+        path: path.to_path_buf(),
+    };
+    let extra = [
+        jar_mod(&first),
+        jar_mod(&second),
+        Component::Agent {
+            path: agent.clone(),
+            options: Some(String::from("verbose")),
+        },
+    ];
+    let layered = components::apply(&dir, &client_jar, &extra, false).unwrap();
+    assert_eq!(layered.jvm_args.len(), 1);
+    assert!(layered.jvm_args[0].starts_with("-javaagent:"));
+    assert!(layered.jvm_args[0].ends_with("agent.jar=verbose"));
+
+    let patched = layered.client_jar.unwrap();
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&patched).unwrap()).unwrap();
+    let mut read = |name: &str| {
+        let mut contents = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    };
+    assert_eq!(read("a.class"), "second");
+    assert_eq!(read("b.class"), "vanilla");
+    assert_eq!(read("c.class"), "first");
+    assert!(archive.by_name("META-INF/MOJANGCS.SF").is_err());
+
+    // same inputs, same jar. Another order is another jar
+    let again = components::apply(&dir, &client_jar, &extra, false).unwrap();
+    assert_eq!(again.client_jar.unwrap(), patched);
+    let swapped = [jar_mod(&second), jar_mod(&first)];
+    let swapped = components::apply(&dir, &client_jar, &swapped, true).unwrap();
+    assert_ne!(swapped.client_jar.unwrap(), patched);
+
+    let vanilla = Component::Vanilla {
+        version: VERSION.to_string(),
+    };
+    assert!(components::validate(&[vanilla]).is_err());
+    assert!(components::validate(&[jar_mod(&first), jar_mod(&first)]).is_err());
+    assert!(components::validate(&[jar_mod(&dir.join("missing.zip"))]).is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn component_conflicts_are_found_before_saving() {
+    use mod_launcher::components::{Conflict, Fix};
+
+    let cache_dir = temp_cache("conflicts");
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+    let profile = |id: &str, parent: &str, release_time: &str| {
+        let path = versions::json_path(&cache_dir, id);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let json = format!(
+            r#"{{ "id": "{}", "inheritsFrom": "{}", "releaseTime": "{}" }}"#,
+            id, parent, release_time
+        );
+        std::fs::write(path, json.replace(r#""inheritsFrom": "", "#, "")).unwrap();
+    };
+    profile(
+        "fabric-loader-0.15.0-fixture-1.0",
+        VERSION,
+        "2024-01-01T00:00:00+00:00",
+    );
+    profile(
+        "fabric-loader-0.15.0-other-1.0",
+        VERSION,
+        "2024-01-01T00:00:00+00:00",
+    );
+    profile(
+        "quilt-loader-0.26.0-fixture-1.0",
+        "fabric-loader-0.15.0-fixture-1.0",
+        "2024-01-01T00:00:00+00:00",
+    );
+    profile("ancient-1.0", "", "2012-01-01T00:00:00+00:00");
+    profile(
+        "fabric-loader-0.15.0-ancient-1.0",
+        "ancient-1.0",
+        "2024-01-01T00:00:00+00:00",
+    );
+
+    let jar_mod = cache_dir.join("jar_mod.zip");
+    write_zip(&jar_mod, &[("a.class", "patched")]);
+    let conflicts = |version: &str, loader, components: Vec<Component>| {
+        let mut instance = instance::Instance::new("checked", version);
+        instance.loader = loader;
+        instance.components = components;
+        components::check_instance(&cache_dir, &instance)
+            .unwrap()
+            .into_iter()
+            .map(|conflict| (conflict.conflict, conflict.fatal, conflict.fixes))
+            .collect::<Vec<_>>()
+    };
+
+    let fabric = "fabric-loader-0.15.0-fixture-1.0";
+    assert!(conflicts(fabric, Some(LoaderKind::Fabric), vec![]).is_empty());
+    assert_eq!(
+        conflicts(fabric, None, vec![]),
+        [(
+            Conflict::LoaderMismatch {
+                expected: None,
+                found: Some(LoaderKind::Fabric)
+            },
+            false,
+            vec![Fix::SetLoader {
+                loader: Some(LoaderKind::Fabric)
+            }]
+        )]
+    );
+    let with_jar_mod = vec![Component::JarMod {
+        path: jar_mod.clone(),
+    }];
+    assert_eq!(
+        conflicts(fabric, Some(LoaderKind::Fabric), with_jar_mod.clone()),
+        [(
+            Conflict::JarModOnLoader {
+                position: 2,
+                loader_position: 1
+            },
+            false,
+            vec![Fix::Remove { position: 2 }]
+        )]
+    );
+    assert!(conflicts(VERSION, None, with_jar_mod).is_empty());
+
+    let fatal = |version: &str, loader| {
+        conflicts(version, loader, vec![])
+            .into_iter()
+            .filter(|(_, fatal, _)| *fatal)
+            .map(|(conflict, _, _)| conflict)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        fatal("fabric-loader-0.15.0-other-1.0", Some(LoaderKind::Fabric)),
+        [Conflict::WrongGameVersion {
+            position: 1,
+            game_version: VERSION.to_string()
+        }]
+    );
+    assert_eq!(
+        fatal("quilt-loader-0.26.0-fixture-1.0", Some(LoaderKind::Quilt)),
+        [Conflict::TwoLoaders {
+            first: 1,
+            second: 2
+        }]
+    );
+    assert!(matches!(
+        conflicts(
+            "fabric-loader-0.15.0-ancient-1.0",
+            Some(LoaderKind::Fabric),
+            vec![]
+        )[..],
+        [(
+            Conflict::UnsupportedGameVersion { position: 1, .. },
+            false,
+            _
+        )]
+    ));
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+#[tokio::test]
+async fn progress_counts_bytes() {
+    let cache_dir = temp_cache("progress");
+    let reports = Mutex::new(Vec::new());
+    let estimator = RateEstimator::default();
+    let last_estimate = Mutex::new(None);
+    install_version_with(
+        &fixture_mirror(),
+        &fixture_mirror(),
+        &cache_dir,
+        VERSION,
+        2,
+        VerifyPolicy::default(),
+        &|done, total| {
+            reports.lock().unwrap().push((done, total));
+            *last_estimate.lock().unwrap() = Some(estimator.update(done, total));
+        },
+    )
+    .await
+    .unwrap();
+
+    // library, client jar and both objects. The maven library has no declared size, it only
+    // counts once it arrived
+    let reports = reports.into_inner().unwrap();
+    assert_eq!(reports.first(), Some(&(0, 77)));
+    assert_eq!(reports.last(), Some(&(103, 103)));
+    assert!(reports.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+    let estimate = last_estimate.into_inner().unwrap().unwrap();
+    assert_eq!((estimate.percent, estimate.eta_seconds), (100, Some(0)));
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+#[tokio::test]
+async fn reinstall_fetches_nothing() {
+    let cache_dir = temp_cache("reinstall");
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+
+    let counting = CountingDownloader {
+        inner: fixture_mirror(),
+        fetched: AtomicUsize::new(0),
+    };
+    install(&counting, &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+    assert_eq!(counting.fetched.load(Ordering::SeqCst), 0);
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+fn part_files(dir: &Path) -> Vec<PathBuf> {
+    let mut parts = Vec::new();
+    for entry in std::fs::read_dir(dir).unwrap().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            parts.extend(part_files(&path));
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "part")
+        {
+            parts.push(path);
+        }
+    }
+    parts
+}
+
+#[tokio::test]
+async fn concurrent_installs_share_the_cache() {
+    let cache_dir = temp_cache("concurrent");
+    let mirror = fixture_mirror();
+    let (first, second) = tokio::join!(
+        install(&mirror, &cache_dir, AssetCheck::Exists),
+        install(&mirror, &cache_dir, AssetCheck::Exists),
+    );
+    first.unwrap();
+    second.unwrap();
+
+    let report = verify_version(&cache_dir, VERSION, None).await.unwrap();
+    assert!(report.is_ok(), "{:?}", report);
+    let leftovers = part_files(&cache_dir);
+    assert!(leftovers.is_empty(), "{:?}", leftovers);
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+#[tokio::test]
+async fn hash_check_repairs_corrupt_assets() {
+    let cache_dir = temp_cache("repairs");
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+
+    // same size, so only hashing notices
+    let object = cache::assets_dir(&cache_dir)
+        .join("objects")
+        .join(TEXTURE_OBJECT);
+    let size = std::fs::metadata(&object).unwrap().len() as usize;
+    std::fs::write(&object, vec![b'x'; size]).unwrap();
+
+    for check in [AssetCheck::Exists, AssetCheck::Size] {
+        install(&fixture_mirror(), &cache_dir, check).await.unwrap();
+        let report = verify_version(&cache_dir, VERSION, None).await.unwrap();
+        assert_eq!(report.corrupt, vec![object.clone()], "{} check", check);
+    }
+
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Hash)
+        .await
+        .unwrap();
+    let report = verify_version(&cache_dir, VERSION, None).await.unwrap();
+    assert!(report.is_ok(), "{:?}", report);
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+#[tokio::test]
+async fn trusted_stamps_skip_hashing() {
+    let cache_dir = temp_cache("stamps");
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+
+    // same size and mtime, so only hashing notices
+    let library =
+        cache::libraries_dir(&cache_dir).join("com/example/fixture-lib/1.0/fixture-lib-1.0.jar");
+    let metadata = std::fs::metadata(&library).unwrap();
+    std::fs::write(&library, vec![b'x'; metadata.len() as usize]).unwrap();
+    std::fs::File::options()
+        .write(true)
+        .open(&library)
+        .unwrap()
+        .set_modified(metadata.modified().unwrap())
+        .unwrap();
+
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+    let report = verify_version(&cache_dir, VERSION, None).await.unwrap();
+    assert_eq!(report.corrupt, vec![library]);
+
+    let policy = VerifyPolicy {
+        trust_stamps: false,
+        ..Default::default()
+    };
+    install_version_with(
+        &fixture_mirror(),
+        &fixture_mirror(),
+        &cache_dir,
+        VERSION,
+        2,
+        policy,
+        &|_, _| {},
+    )
+    .await
+    .unwrap();
+    let report = verify_version(&cache_dir, VERSION, None).await.unwrap();
+    assert!(report.is_ok(), "{:?}", report);
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+#[tokio::test]
+async fn rejects_files_with_wrong_hash() {
+    for target in ["fixture-lib-1.0.jar", "client.jar", TEXTURE_OBJECT] {
+        let cache_dir = temp_cache(&format!("tampered_{}", target.replace('/', "_")));
+        let tampering = TamperingDownloader {
+            inner: fixture_mirror(),
+            target,
+        };
+
+        let err = install(&tampering, &cache_dir, AssetCheck::Exists)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().starts_with("Incorrect hash"),
+            "{}: {}",
+            target,
+            err
+        );
+
+        let _ = std::fs::remove_dir_all(cache_dir);
+    }
+}
+
+#[tokio::test]
+async fn interrupted_install_resumes() {
+    let cache_dir = temp_cache("resumes");
+    // a download cut off by the launcher being killed
+    let library =
+        cache::libraries_dir(&cache_dir).join("com/example/fixture-lib/1.0/fixture-lib-1.0.jar");
+    std::fs::create_dir_all(library.parent().unwrap()).unwrap();
+    std::fs::write(library.with_extension("jar.part"), b"fix").unwrap();
+
+    let tampering = TamperingDownloader {
+        inner: fixture_mirror(),
+        target: TEXTURE_OBJECT,
+    };
+    // one at a time, the texture is the smallest file and goes last
+    install_version_with(
+        &fixture_mirror(),
+        &tampering,
+        &cache_dir,
+        VERSION,
+        1,
+        VerifyPolicy::default(),
+        &|_, _| {},
+    )
+    .await
+    .unwrap_err();
+    let journal = versions::version_dir(&cache_dir, VERSION).join("downloads.jsonl");
+    let journal_lines = std::fs::read_to_string(&journal).unwrap();
+    assert!(journal_lines.contains("fixture-lib-1.0.jar"));
+    assert!(!library.with_extension("jar.part").exists());
+
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Hash)
+        .await
+        .unwrap();
+    assert!(!journal.exists());
+    let report = verify_version(&cache_dir, VERSION, None).await.unwrap();
+    assert!(report.is_ok(), "{:?}", report);
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+#[tokio::test]
+async fn installs_from_v1_manifest() {
+    let cache_dir = temp_cache("v1");
+    let mut meta = fixture_mirror();
+    meta.manifest = Arc::new(UrlManifest::mojang_v1());
+
+    let manifest = meta.version_manifest().await.unwrap();
+    assert!(manifest.versions[0].sha1.is_empty());
+    install_version_with(
+        &meta,
+        &fixture_mirror(),
+        &cache_dir,
+        VERSION,
+        2,
+        VerifyPolicy::default(),
+        &|_, _| {},
+    )
+    .await
+    .unwrap();
+    assert!(versions::json_path(&cache_dir, VERSION).is_file());
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+#[tokio::test]
+async fn exported_mirror_installs() {
+    let cache_dir = temp_cache("export_source");
+    let out = temp_cache("export_out");
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+
+    let report = mirror::export(&cache_dir, &out, true).await.unwrap();
+    assert_eq!(report.versions, vec![VERSION]);
+    // never installed on this OS, so it isn't cached
+    assert_eq!(
+        report.missing,
+        vec!["https://libraries.minecraft.net/com/example/fixture-elsewhere/1.0/fixture-elsewhere-1.0.jar"]
+    );
+
+    // a second cache filled from nothing but the export
+    let exported = DirectoryProvider::new(&out);
+    let second_cache = temp_cache("export_target");
+    install_version_with(
+        &exported,
+        &exported,
+        &second_cache,
+        VERSION,
+        2,
+        VerifyPolicy::default(),
+        &|_, _| {},
+    )
+    .await
+    .unwrap();
+    let report = verify_version(&second_cache, VERSION, None).await.unwrap();
+    assert!(report.is_ok(), "{:?}", report);
+
+    for dir in [cache_dir, out, second_cache] {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn unknown_version_is_an_error() {
+    let cache_dir = temp_cache("unknown");
+    let err = install_version_with(
+        &fixture_mirror(),
+        &fixture_mirror(),
+        &cache_dir,
+        "not-a-version",
+        2,
+        VerifyPolicy::default(),
+        &|_, _| {},
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.to_string(), "Unknown version not-a-version");
+
+    let _ = std::fs::remove_dir_all(cache_dir);
+}
+
+#[tokio::test]
+async fn channel_instances_follow_latest() {
+    let dir = temp_cache("channel");
+    let instances_dir = dir.join("instances");
+    let mut tracking = instance::Instance::new("tracking", "fixture-0.9");
+    tracking.channel = Some(instance::Channel::Release);
+    instance::save(&instances_dir, &tracking).unwrap();
+    instance::create(&instances_dir, "pinned", "fixture-0.9").unwrap();
+
+    let mirror = fixture_mirror();
+    let track = |name: &'static str, network| {
+        instance::track_latest(&instances_dir, name, &mirror, &dir, network)
+    };
+    assert!(track("tracking", NetworkStatus::Offline)
+        .await
+        .unwrap()
+        .is_none());
+    let update = track("tracking", NetworkStatus::Online)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        (update.from.as_str(), update.to.as_str()),
+        ("fixture-0.9", VERSION)
+    );
+    assert_eq!(
+        instance::load(&instances_dir, "tracking").unwrap().version,
+        VERSION
+    );
+    assert!(track("tracking", NetworkStatus::Online)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(track("pinned", NetworkStatus::Online)
+        .await
+        .unwrap()
+        .is_none());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn cached_loader_profiles_install_offline() {
+    let cache_dir = temp_cache("loaders");
+    // as left behind by an earlier online install
+    let profile = cache_dir
+        .join("loaders/fabric")
+        .join(VERSION)
+        .join("0.15.0.json");
+    std::fs::create_dir_all(profile.parent().unwrap()).unwrap();
+    std::fs::write(
+        &profile,
+        r#"{ "id": "fabric-loader-0.15.0-fixture-1.0", "inheritsFrom": "fixture-1.0", "libraries": [] }"#,
+    )
+    .unwrap();
+
+    let client = mod_launcher::net::client();
+    let install_loader = |loader_version| {
+        fabric::install(
+            &client,
+            &cache_dir,
+            LoaderKind::Fabric,
+            VERSION,
+            loader_version,
+            NetworkStatus::Offline,
+        )
+    };
+    let err = install_loader(Some("0.16.0")).await.unwrap_err();
+    assert!(err.to_string().contains("never installed while online"));
+    let id = install_loader(None).await.unwrap();
+    assert_eq!(id, "fabric-loader-0.15.0-fixture-1.0");
+    assert!(versions::json_path(&cache_dir, &id).exists());
+
+    let cached = fabric::cached(&cache_dir, None, None).unwrap();
+    assert_eq!(cached.len(), 1);
+    assert!(!cached[0].complete);
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+    assert!(
+        fabric::cached(&cache_dir, Some(LoaderKind::Fabric), Some(VERSION)).unwrap()[0].complete
+    );
+    assert!(fabric::cached(&cache_dir, Some(LoaderKind::Quilt), None)
+        .unwrap()
+        .is_empty());
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+#[tokio::test]
+async fn loader_matrix_offers_defaults_offline() {
+    let cache_dir = temp_cache("matrix");
+    let matrix_dir = cache_dir.join("loaders/matrix");
+    std::fs::create_dir_all(&matrix_dir).unwrap();
+    // as fetched from each loader's meta, promotions or maven
+    let sources = [
+        (
+            "fabric",
+            r#"{ "game": [{ "version": "1.20.4", "stable": true }, { "version": "24w14a", "stable": false }],
+                 "loader": [{ "version": "0.16.0-beta.1", "stable": false }, { "version": "0.15.11", "stable": true }] }"#,
+        ),
+        (
+            "forge",
+            r#"{ "promos": { "1.9-recommended": "12.16.1.1887", "1.20.4-latest": "49.0.50",
+                             "1.20.4-recommended": "49.0.49", "1.20.6-latest": "50.0.1" } }"#,
+        ),
+        (
+            "neoforge",
+            r#"{ "versions": ["0.25w14craftmine.3-beta", "20.4.80-beta", "20.4.237", "20.4.239-beta",
+                              "21.0.10-beta", "21.1.1"] }"#,
+        ),
+    ];
+    for (kind, json) in sources {
+        std::fs::write(matrix_dir.join(format!("{}.json", kind)), json).unwrap();
+    }
+
+    let client = mod_launcher::net::client();
+    let defaults = loader_matrix::defaults(&client, &cache_dir, "1.20.4", NetworkStatus::Offline)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|defaults| {
+            (
+                defaults.kind,
+                defaults.entry.recommended,
+                defaults.entry.latest,
+            )
+        })
+        .collect::<Vec<_>>();
+    // quilt was never fetched
+    assert_eq!(
+        defaults,
+        [
+            (
+                LoaderKind::Fabric,
+                Some("0.15.11".to_string()),
+                "0.16.0-beta.1".to_string()
+            ),
+            (
+                LoaderKind::Forge,
+                Some("49.0.49".to_string()),
+                "49.0.50".to_string()
+            ),
+            (
+                LoaderKind::NeoForge,
+                Some("20.4.237".to_string()),
+                "20.4.239-beta".to_string()
+            ),
+        ]
+    );
+
+    let (client, cache_dir) = (&client, &cache_dir);
+    let game_versions = |kind| async move {
+        loader_matrix::matrix(client, cache_dir, kind, NetworkStatus::Offline)
+            .await
+            .unwrap()
+            .entries
+            .into_iter()
+            .map(|entry| (entry.game_version, entry.recommended.is_some()))
+            .collect::<Vec<_>>()
+    };
+    let forge = game_versions(LoaderKind::Forge).await;
+    assert_eq!(
+        forge,
+        [
+            ("1.20.6".to_string(), false),
+            ("1.20.4".to_string(), true),
+            ("1.9".to_string(), true)
+        ]
+    );
+    let neoforge = game_versions(LoaderKind::NeoForge).await;
+    assert_eq!(
+        neoforge,
+        [
+            ("1.21.1".to_string(), true),
+            ("1.21".to_string(), false),
+            ("1.20.4".to_string(), true)
+        ]
+    );
+    let err = loader_matrix::matrix(client, cache_dir, LoaderKind::Quilt, NetworkStatus::Offline)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("never fetched while online"));
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+// the installer's processor is a shell script standing in for java
+#[cfg(unix)]
+#[tokio::test]
+async fn forge_installs_the_recommended_build_and_locks_it() {
+    use mod_launcher::{
+        dedup::{ExternalInstall, ExternalLauncher},
+        forge::{self, InstallerProgress, ProcessorEnv},
+        loader::{LoaderBuild, Promotion},
+        watchdog::Timeouts,
+    };
+    use sha1::{Digest, Sha1};
+    use std::os::unix::fs::PermissionsExt;
+
+    let cache_dir = temp_cache("forge");
+    let libraries_dir = cache::libraries_dir(&cache_dir);
+    std::fs::create_dir_all(cache_dir.join("loaders/matrix")).unwrap();
+    std::fs::write(
+        cache_dir.join("loaders/matrix/forge.json"),
+        r#"{ "promos": { "fixture-1.0-latest": "1.0.1", "fixture-1.0-recommended": "1.0.0",
+                         "fixture-2.0-latest": "2.0.0" } }"#,
+    )
+    .unwrap();
+
+    let client = mod_launcher::net::client();
+    let select = |game_version, build| {
+        forge::select_build(
+            &client,
+            &cache_dir,
+            LoaderKind::Forge,
+            game_version,
+            build,
+            NetworkStatus::Offline,
+        )
+    };
+    let build = select(VERSION, None).await.unwrap();
+    assert_eq!(
+        (build.version.as_str(), build.promotion),
+        ("1.0.0", Promotion::Recommended)
+    );
+    let latest = select("fixture-2.0", None).await.unwrap();
+    assert_eq!(
+        (latest.version.as_str(), latest.promotion),
+        ("2.0.0", Promotion::Latest)
+    );
+    let requested = select(VERSION, Some("0.9.0")).await.unwrap();
+    assert_eq!(requested.promotion, Promotion::Requested);
+    assert!(select("fixture-3.0", None).await.is_err());
+
+    let patched_sha1 = format!("{:x}", Sha1::digest(b"patched client"));
+    let forge_dir = libraries_dir.join("net/minecraftforge/forge/fixture-1.0-1.0.0");
+    std::fs::create_dir_all(&forge_dir).unwrap();
+    let install_profile = format!(
+        r#"{{
+            "minecraft": "fixture-1.0",
+            "data": {{
+                "PATCHED": {{ "client": "[net.minecraftforge:forge:fixture-1.0-1.0.0:client]", "server": "" }},
+                "PATCHED_SHA": {{ "client": "'{sha1}'", "server": "" }},
+                "BINPATCH": {{ "client": "/data/client.lzma", "server": "" }}
+            }},
+            "processors": [
+                {{ "jar": "test:processor:1.0",
+                   "args": ["--input", "{{MINECRAFT_JAR}}", "--patch", "{{BINPATCH}}", "--output", "{{PATCHED}}"],
+                   "outputs": {{ "{{PATCHED}}": "{{PATCHED_SHA}}" }} }},
+                {{ "sides": ["server"], "jar": "test:server-only:1.0" }}
+            ],
+            "libraries": [{{ "name": "test:processor:1.0", "url": "https://maven.example.com/" }}]
+        }}"#,
+        sha1 = patched_sha1
+    );
+    let version_json = format!(
+        r#"{{
+            "id": "fixture-1.0-forge-1.0.0",
+            "inheritsFrom": "fixture-1.0",
+            "mainClass": "cpw.mods.bootstraplauncher.BootstrapLauncher",
+            "libraries": [{{
+                "name": "net.minecraftforge:forge:fixture-1.0-1.0.0:client",
+                "downloads": {{ "artifact": {{
+                    "path": "net/minecraftforge/forge/fixture-1.0-1.0.0/forge-fixture-1.0-1.0.0-client.jar",
+                    "url": "", "sha1": "{}", "size": 14 }} }}
+            }}]
+        }}"#,
+        patched_sha1
+    );
+    write_zip(
+        &forge_dir.join("forge-fixture-1.0-1.0.0-installer.jar"),
+        &[
+            ("install_profile.json", &install_profile),
+            ("version.json", &version_json),
+            ("data/client.lzma", "patched client"),
+        ],
+    );
+    let processor = libraries_dir.join("test/processor/1.0/processor-1.0.jar");
+    std::fs::create_dir_all(processor.parent().unwrap()).unwrap();
+    write_zip(
+        &processor,
+        &[("META-INF/MANIFEST.MF", "Main-Class: test.Processor\n")],
+    );
+    // java -cp <jar> test.Processor --input <jar> --patch <file> --output <file>
+    let java = cache_dir.join("java");
+    std::fs::write(
+        &java,
+        "#!/bin/sh\n[ \"$3\" = test.Processor ] && [ -f \"$5\" ] || exit 1\necho 'Patching 1/2'\necho '100%' >&2\nmkdir -p \"$(dirname \"$9\")\" && cp \"$7\" \"$9\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&java, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let instances_dir = cache_dir.join("instances");
+    instance::create(&instances_dir, "forged", VERSION).unwrap();
+    let events = std::sync::Mutex::new(Vec::new());
+    let record = |progress: &InstallerProgress| events.lock().unwrap().push(progress.clone());
+    let id = forge::install(
+        &fixture_mirror(),
+        &fixture_mirror(),
+        &cache_dir,
+        &build,
+        &ProcessorEnv {
+            java_path: &java,
+            external: &[],
+            on_progress: &record,
+            limits: Timeouts::default().helper(),
+        },
+        2,
+        VerifyPolicy::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(id, "fixture-1.0-forge-1.0.0");
+    let mut events = events
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|progress| {
+            (
+                progress.phase,
+                progress.step,
+                progress.steps,
+                progress.fraction,
+            )
+        })
+        .collect::<Vec<_>>();
+    // stdout and stderr arrive in either order
+    events[1..].sort_by(|a, b| a.3.partial_cmp(&b.3).unwrap());
+    assert_eq!(
+        events,
+        [
+            ("processor".to_string(), 1, 1, None),
+            ("processor".to_string(), 1, 1, Some(0.5)),
+            ("processor".to_string(), 1, 1, Some(1.0)),
+        ]
+    );
+    let log = versions::version_dir(&cache_dir, &id).join("installer.log");
+    assert!(std::fs::read_to_string(&log)
+        .unwrap()
+        .contains("Patching 1/2"));
+    assert_eq!(
+        std::fs::read_to_string(forge_dir.join("forge-fixture-1.0-1.0.0-client.jar")).unwrap(),
+        "patched client"
+    );
+    assert!(versions::json_path(&cache_dir, &id).exists());
+    assert!(!versions::version_dir(&cache_dir, &id)
+        .join("installer")
+        .exists());
+
+    // another instance installing the same build gets the stored outputs, the processor never
+    // runs again
+    std::fs::remove_file(forge_dir.join("forge-fixture-1.0-1.0.0-client.jar")).unwrap();
+    std::fs::write(&java, "#!/bin/sh\necho 'Broken patch' >&2\nexit 1\n").unwrap();
+    let reinstalled = forge::install(
+        &fixture_mirror(),
+        &fixture_mirror(),
+        &cache_dir,
+        &build,
+        &ProcessorEnv {
+            java_path: &java,
+            external: &[],
+            on_progress: &|_| {},
+            limits: Timeouts::default().helper(),
+        },
+        2,
+        VerifyPolicy::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(reinstalled, id);
+    assert_eq!(
+        std::fs::read_to_string(forge_dir.join("forge-fixture-1.0-1.0.0-client.jar")).unwrap(),
+        "patched client"
+    );
+
+    // migrating from a launcher that ran the same installer, its outputs are taken once their
+    // hashes match
+    std::fs::remove_dir_all(cache_dir.join("processors")).unwrap();
+    std::fs::remove_file(forge_dir.join("forge-fixture-1.0-1.0.0-client.jar")).unwrap();
+    let external = ExternalInstall {
+        launcher: ExternalLauncher::Vanilla,
+        root: cache_dir.join(".minecraft"),
+    };
+    let external_jar = external.root.join(
+        "libraries/net/minecraftforge/forge/fixture-1.0-1.0.0/forge-fixture-1.0-1.0.0-client.jar",
+    );
+    std::fs::create_dir_all(external_jar.parent().unwrap()).unwrap();
+    let mirror = fixture_mirror();
+    let migrating = ProcessorEnv {
+        java_path: &java,
+        external: std::slice::from_ref(&external),
+        on_progress: &|_| {},
+        limits: Timeouts::default().helper(),
+    };
+    let install_migrating = || {
+        forge::install(
+            &mirror,
+            &mirror,
+            &cache_dir,
+            &build,
+            &migrating,
+            2,
+            VerifyPolicy::default(),
+        )
+    };
+    std::fs::write(&external_jar, "tampered client").unwrap();
+    // the processor runs, and what it printed explains the failure
+    let err = install_migrating().await.unwrap_err();
+    assert!(format!("{:#}", err).contains("failed with exit status: 1:\nBroken patch"));
+    assert!(std::fs::read_to_string(&log)
+        .unwrap()
+        .contains("Broken patch"));
+    std::fs::write(&external_jar, "patched client").unwrap();
+    install_migrating().await.unwrap();
+    assert_eq!(
+        std::fs::read_to_string(forge_dir.join("forge-fixture-1.0-1.0.0-client.jar")).unwrap(),
+        "patched client"
+    );
+
+    assert_eq!(
+        instance::lockfile(&instances_dir, "forged").unwrap().loader,
+        None
+    );
+    instance::update_locked(&instances_dir, "forged", |instance, lockfile| {
+        instance.version = id.clone();
+        lockfile.loader = Some(build.clone());
+    })
+    .unwrap();
+    let locked: Option<LoaderBuild> = instance::lockfile(&instances_dir, "forged").unwrap().loader;
+    assert_eq!(locked, Some(build));
+    assert_eq!(
+        instance::load(&instances_dir, "forged").unwrap().version,
+        id
+    );
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+#[test]
+fn instance_writers_take_turns_and_watchers_see_changes() {
+    let dir = temp_cache("registry");
+    let instances_dir = dir.join("instances");
+    instance::create(&instances_dir, "shared", VERSION).unwrap();
+    let mut watcher = instance::InstanceWatcher::new(&instances_dir).unwrap();
+    assert!(watcher.poll().unwrap().is_empty());
+
+    // a lost update would drop somebody's tag
+    std::thread::scope(|scope| {
+        for writer in 0..8 {
+            let instances_dir = &instances_dir;
+            scope.spawn(move || {
+                instance::update(instances_dir, "shared", |instance| {
+                    instance.add_tag(&format!("writer-{}", writer))
+                })
+                .unwrap()
+            });
+        }
+    });
+    assert_eq!(
+        instance::load(&instances_dir, "shared").unwrap().tags.len(),
+        8
+    );
+
+    instance::create(&instances_dir, "added", VERSION).unwrap();
+    let changes = watcher.poll().unwrap();
+    let changes = changes
+        .iter()
+        .map(|change| (change.name.as_str(), change.kind))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        [
+            ("added", instance::ChangeKind::Added),
+            ("shared", instance::ChangeKind::Modified)
+        ]
+    );
+
+    instance::delete(&instances_dir, "added").unwrap();
+    let changes = watcher.poll().unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].kind, instance::ChangeKind::Removed);
+    assert!(changes[0].instance.is_none());
+    assert!(watcher.poll().unwrap().is_empty());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn exported_instances_import_elsewhere() {
+    let dir = temp_cache("portable");
+    let (cache_dir, instances_dir) = (dir.join("cache"), dir.join("instances"));
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+    // a loader profile, the manifest has no way to provide it
+    let loader = versions::json_path(&cache_dir, "fixture-loader");
+    std::fs::create_dir_all(loader.parent().unwrap()).unwrap();
+    std::fs::write(
+        &loader,
+        r#"{"id": "fixture-loader", "inheritsFrom": "fixture-1.0", "libraries": []}"#,
+    )
+    .unwrap();
+
+    let mut original = instance::Instance::new("original", "fixture-loader");
+    original.java_path = Some(PathBuf::from("/opt/java/bin/java"));
+    original.jvm_args = vec![String::from("-XX:+UseZGC")];
+    instance::save(&instances_dir, &original).unwrap();
+    let game_dir = original.game_dir(&instances_dir);
+    std::fs::create_dir_all(game_dir.join("config")).unwrap();
+    std::fs::write(game_dir.join("config/fixture.toml"), "enabled = true").unwrap();
+    std::fs::write(game_dir.join("options.txt"), "fov:0.5").unwrap();
+    std::fs::create_dir_all(game_dir.join("saves/world")).unwrap();
+
+    let archive = dir.join("original.zip");
+    let report = portable::export(
+        &reqwest::Client::new(),
+        &instances_dir,
+        &cache_dir,
+        "original",
+        &archive,
+        false,
+    )
+    .await
+    .unwrap();
+    assert!(report.referenced.is_empty() && report.skipped.is_empty());
+
+    let (other_cache, other_instances) = (dir.join("other_cache"), dir.join("other_instances"));
+    let imported = portable::import(
+        &fixture_mirror(),
+        &other_instances,
+        &other_cache,
+        &archive,
+        Some("copy"),
+        2,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(imported.version, "fixture-loader");
+    assert_eq!(imported.jvm_args, original.jvm_args);
+    assert_eq!(imported.java_path, None);
+    let imported_game_dir = imported.game_dir(&other_instances);
+    assert_eq!(
+        std::fs::read_to_string(imported_game_dir.join("config/fixture.toml")).unwrap(),
+        "enabled = true"
+    );
+    assert!(imported_game_dir.join("options.txt").is_file());
+    assert!(!imported_game_dir.join("saves").exists());
+    // the staging dir was moved in whole
+    let instance_entries = std::fs::read_dir(imported.dir(&other_instances))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    assert!(
+        instance_entries
+            .iter()
+            .all(|name| !name.contains("staging")),
+        "{:?}",
+        instance_entries
+    );
+    // the vanilla parent comes from the manifest
+    assert!(!versions::json_path(&other_cache, VERSION).exists());
+    install_version_with(
+        &fixture_mirror(),
+        &fixture_mirror(),
+        &other_cache,
+        &imported.version,
+        2,
+        VerifyPolicy::default(),
+        &|_, _| {},
+    )
+    .await
+    .unwrap();
+
+    let err = portable::import(
+        &fixture_mirror(),
+        &other_instances,
+        &other_cache,
+        &archive,
+        Some("copy"),
+        2,
+        false,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("already exists"), "{}", err);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn hung_helpers_are_killed_with_their_output() {
+    use mod_launcher::watchdog::{self, HangReason, Hung, Limits};
+    use std::time::{Duration, Instant};
+
+    let sh = |script: &str| {
+        let mut command = tokio::process::Command::new("sh");
+        command.args(["-c", script]);
+        command
+    };
+    let limits = Limits {
+        total: Duration::from_secs(2),
+        idle: Some(Duration::from_millis(500)),
+    };
+
+    let finished = watchdog::output(&mut sh("echo out; echo err >&2"), limits)
+        .await
+        .unwrap();
+    assert!(finished.status.success());
+    assert_eq!(finished.stdout, "out\n");
+    assert_eq!(finished.stderr, "err\n");
+    assert_eq!(finished.tail, ["out", "err"]);
+
+    // quiet for longer than the idle limit
+    let started = Instant::now();
+    let err = watchdog::output(&mut sh("echo 'Working...'; sleep 30"), limits)
+        .await
+        .unwrap_err();
+    assert!(started.elapsed() < Duration::from_secs(2));
+    let hung = err.downcast_ref::<Hung>().unwrap();
+    assert_eq!(hung.reason, HangReason::Silent(Duration::from_millis(500)));
+    assert_eq!(hung.tail, ["Working..."]);
+    assert!(err.to_string().contains("Working..."), "{}", err);
+
+    // chatty, but never done
+    let err = watchdog::output(&mut sh("while true; do echo tick; sleep 0.1; done"), limits)
+        .await
+        .unwrap_err();
+    let hung = err.downcast_ref::<Hung>().unwrap();
+    assert_eq!(hung.reason, HangReason::TimedOut(Duration::from_secs(2)));
+    assert_eq!(hung.tail.last().map(String::as_str), Some("tick"));
+}
+
+#[tokio::test]
+async fn pinned_hosts_only_accept_their_certificates() {
+    use mod_launcher::{
+        cert_pins::{CertPins, Pin},
+        net::HttpProvider,
+    };
+
+    let certificate = std::fs::read(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/certs/piston-meta.der"),
+    )
+    .unwrap();
+    let host = "piston-meta.mojang.com";
+    let pinned =
+        |pin: &str| CertPins([(host.to_string(), vec![pin.parse::<Pin>().unwrap()])].into());
+
+    let key = pinned("sha256/5iiKVJ9jJzT59Kqgm9d7JxPmaUBmXGujSs5ykGxeezU=");
+    key.check(host, &[&certificate]).unwrap();
+    let fingerprint = "C8:E0:E9:5A:6B:7A:E6:1B:B7:7B:FD:1D:FE:79:0F:F8:38:79:86:B3:C3:D4:19:B4:BE:5C:B4:E1:05:4D:62:75";
+    pinned(fingerprint).check(host, &[&certificate]).unwrap();
+    pinned(&fingerprint.replace(':', "").to_lowercase())
+        .check(host, &[&certificate])
+        .unwrap();
+    let err = pinned("sha256/AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=")
+        .check(host, &[&certificate])
+        .unwrap_err();
+    assert!(err.to_string().contains("intercepted"), "{}", err);
+    // a pinned intermediate covers whatever server certificate it issued
+    key.check(host, &[b"another certificate", &certificate])
+        .unwrap();
+    key.check("libraries.minecraft.net", &[b"any certificate"])
+        .unwrap();
+
+    // unpinned hosts are left alone, pinned ones need TLS and can't redirect elsewhere
+    let url = |url: &str| reqwest::Url::parse(url).unwrap();
+    key.check_url(None, &url("http://libraries.minecraft.net/a.jar"))
+        .unwrap();
+    key.check_url(None, &url("https://piston-meta.mojang.com/a.json"))
+        .unwrap();
+    let err = key
+        .check_url(None, &url("http://piston-meta.mojang.com/a.json"))
+        .unwrap_err();
+    assert!(err.to_string().contains("not reached over TLS"), "{}", err);
+    let err = key
+        .check_url(
+            Some(&url("https://piston-meta.mojang.com/a.json")),
+            &url("https://proxy.example.com/a.json"),
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("redirected"), "{}", err);
+
+    // a pinned provider doesn't even connect over plain HTTP
+    let mut http = HttpProvider::new(reqwest::Client::new(), None);
+    http.pin(&key);
+    let err = http
+        .fetch("http://piston-meta.mojang.com/mc/game/version_manifest_v2.json")
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("not reached over TLS"), "{}", err);
+
+    // as written in launcher.toml
+    let parsed: CertPins = toml::from_str(
+        "\"piston-meta.mojang.com\" = [\"sha256/5iiKVJ9jJzT59Kqgm9d7JxPmaUBmXGujSs5ykGxeezU=\"]",
+    )
+    .unwrap();
+    assert_eq!(parsed, key);
+    assert_eq!(fingerprint.parse::<Pin>().unwrap().to_string(), fingerprint);
+    assert!("sha256/c2hvcnQ=".parse::<Pin>().is_err());
+    assert!("not a pin".parse::<Pin>().is_err());
+}
+
+#[tokio::test]
+async fn bundles_install_offline_with_every_hash_checked() {
+    use mod_launcher::bundle::{self, BundleSource};
+    use std::io::{Read, Write};
+
+    let online = temp_cache("bundle_online");
+    install(&fixture_mirror(), &online, AssetCheck::Exists)
+        .await
+        .unwrap();
+    let instances_dir = online.join("instances");
+    let packed = instance::create(&instances_dir, "packed", VERSION).unwrap();
+    let game_dir = packed.game_dir(&instances_dir);
+    std::fs::create_dir_all(game_dir.join("mods")).unwrap();
+    std::fs::write(game_dir.join("mods/sodium.jar"), "sodium").unwrap();
+    std::fs::write(game_dir.join("options.txt"), "fov:1.0").unwrap();
+    std::fs::create_dir_all(game_dir.join("saves/world")).unwrap();
+    std::fs::write(game_dir.join("saves/world/level.dat"), "world").unwrap();
+
+    let out = online.join("packed.zip");
+    let source = BundleSource::Instance {
+        instances_dir: &instances_dir,
+        name: "packed",
+    };
+    let report = bundle::create(&fixture_mirror(), &online, source, &out, false)
+        .await
+        .unwrap();
+    assert_eq!(report.version, VERSION);
+    assert!(report.runtimes.is_empty());
+
+    let offline = temp_cache("bundle_offline");
+    let offline_instances = offline.join("instances");
+    let installed = bundle::install(&offline, &offline_instances, &out, Some("carried"))
+        .await
+        .unwrap();
+    assert_eq!(installed.instance.as_deref(), Some("carried"));
+    assert_eq!(installed.written, report.files);
+    assert!(installed.verified > 0);
+    assert!(mod_launcher::verify_installed(&offline, VERSION)
+        .await
+        .unwrap()
+        .is_ok());
+    let carried = instance::load(&offline_instances, "carried").unwrap();
+    let carried_dir = carried.game_dir(&offline_instances);
+    assert_eq!(
+        std::fs::read_to_string(carried_dir.join("mods/sodium.jar")).unwrap(),
+        "sodium"
+    );
+    assert!(carried_dir.join("options.txt").is_file());
+    // worlds stay behind
+    assert!(!carried_dir.join("saves").exists());
+
+    // the cache has everything now, only the instance's own files are written
+    let again = bundle::install(&offline, &offline_instances, &out, Some("again"))
+        .await
+        .unwrap();
+    assert_eq!(again.written, 2);
+    let err = bundle::install(&offline, &offline_instances, &out, Some("again"))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("already exists"), "{}", err);
+
+    // a file changed after bundling is caught before it lands
+    let tampered = online.join("tampered.zip");
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&out).unwrap()).unwrap();
+    let mut writer = zip::ZipWriter::new(std::fs::File::create(&tampered).unwrap());
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).unwrap();
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents).unwrap();
+        if entry.name().ends_with(".jar") && entry.name().starts_with("cache/") {
+            contents.extend_from_slice(b"tampered");
+        }
+        writer
+            .start_file(entry.name(), zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(&contents).unwrap();
+    }
+    writer.finish().unwrap();
+    let fresh = temp_cache("bundle_tampered");
+    let err = bundle::install(&fresh, &fresh.join("instances"), &tampered, None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("corrupt"), "{}", err);
+    assert!(instance::load(&fresh.join("instances"), "packed").is_err());
+
+    for dir in [online, offline, fresh] {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn signed_archives_are_checked_against_trusted_keys() {
+    use mod_launcher::signing::{self, SigningKey, TrustPolicy};
+
+    let dir = temp_cache("signing");
+    std::fs::create_dir_all(&dir).unwrap();
+    let archive = dir.join("pack.zip");
+    std::fs::write(&archive, b"not really a zip").unwrap();
+    let key = SigningKey::generate().unwrap();
+    let key_file = dir.join("signing.key");
+    key.save(&key_file).await.unwrap();
+    let key = SigningKey::load(&key_file).unwrap();
+
+    let trusting = |required| TrustPolicy {
+        trusted_keys: vec![key.public_key()],
+        required,
+    };
+    let untrusting = |required| TrustPolicy {
+        trusted_keys: vec![SigningKey::generate().unwrap().public_key()],
+        required,
+    };
+
+    // unsigned archives only pass while signatures are optional
+    assert_eq!(
+        signing::check_archive(&archive, &trusting(false)).unwrap(),
+        None
+    );
+    assert!(signing::check_archive(&archive, &trusting(true)).is_err());
+
+    let signature = signing::sign_archive(&key, &archive).await.unwrap();
+    assert_eq!(signature, dir.join("pack.zip.sig"));
+    assert_eq!(
+        signing::check_archive(&archive, &trusting(true)).unwrap(),
+        Some(key.public_key())
+    );
+    assert_eq!(
+        signing::check_archive(&archive, &untrusting(false)).unwrap(),
+        None
+    );
+    let untrusted = signing::check_archive(&archive, &untrusting(true)).unwrap_err();
+    assert!(untrusted
+        .to_string()
+        .contains("not one of the trusted keys"));
+
+    // a changed archive fails whatever the policy
+    std::fs::write(&archive, b"not really a zip, changed").unwrap();
+    for policy in [trusting(false), untrusting(false)] {
+        let tampered = signing::check_archive(&archive, &policy).unwrap_err();
+        assert!(
+            tampered.to_string().contains("does not match"),
+            "{}",
+            tampered
+        );
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn published_channels_update_their_followers() {
+    use mod_launcher::{net::DirectoryProvider, update_channel};
+
+    let dir = temp_cache("update_channel");
+    let (cache_dir, instances_dir) = (dir.join("cache"), dir.join("instances"));
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+    let loader = versions::json_path(&cache_dir, "fixture-loader");
+    std::fs::create_dir_all(loader.parent().unwrap()).unwrap();
+    std::fs::write(
+        &loader,
+        r#"{"id": "fixture-loader", "inheritsFrom": "fixture-1.0", "libraries": []}"#,
+    )
+    .unwrap();
+    let mut pack = instance::Instance::new("pack", "fixture-loader");
+    pack.jvm_args = vec![String::from("-XX:+UseZGC")];
+    instance::save(&instances_dir, &pack).unwrap();
+    let game_dir = pack.game_dir(&instances_dir);
+    std::fs::create_dir_all(game_dir.join("config")).unwrap();
+    std::fs::write(game_dir.join("config/fixture.toml"), "enabled = true").unwrap();
+    std::fs::write(game_dir.join("options.txt"), "fov:0.5").unwrap();
+
+    let client = reqwest::Client::new();
+    let site = dir.join("site");
+    let out = site.join("packs.example.com/pack");
+    let publish = || {
+        update_channel::publish(
+            &client,
+            &instances_dir,
+            &cache_dir,
+            "pack",
+            &out,
+            false,
+            None,
+        )
+    };
+    let first = publish().await.unwrap();
+    assert!(first.published);
+    assert_eq!(first.release, 1);
+    // the loader profile and both settings files
+    assert_eq!(first.objects_written, 3);
+    let again = publish().await.unwrap();
+    assert!(!again.published);
+    assert_eq!(again.release, 1);
+
+    let host = DirectoryProvider::new(&site);
+    let (other_cache, other_instances) = (dir.join("other_cache"), dir.join("other_instances"));
+    let follower = update_channel::follow(
+        &host,
+        &other_instances,
+        &other_cache,
+        "https://packs.example.com/pack",
+        Some("follower"),
+        &[],
+        2,
+    )
+    .await
+    .unwrap();
+    assert_eq!(follower.version, "fixture-loader");
+    assert_eq!(follower.jvm_args, pack.jvm_args);
+    assert_eq!(
+        follower.update_url.as_deref(),
+        Some("https://packs.example.com/pack/channel.json")
+    );
+    assert!(versions::json_path(&other_cache, "fixture-loader").is_file());
+    let follower_dir = follower.game_dir(&other_instances);
+    assert_eq!(
+        std::fs::read_to_string(follower_dir.join("config/fixture.toml")).unwrap(),
+        "enabled = true"
+    );
+    let lockfile = instance::lockfile(&other_instances, "follower").unwrap();
+    assert_eq!(lockfile.release.unwrap().release, 1);
+
+    // the pack drops a config file and changes another, the follower has one of its own
+    std::fs::remove_file(game_dir.join("config/fixture.toml")).unwrap();
+    std::fs::write(game_dir.join("config/added.toml"), "added = true").unwrap();
+    std::fs::write(game_dir.join("options.txt"), "fov:0.75").unwrap();
+    std::fs::write(follower_dir.join("config/mine.toml"), "mine = true").unwrap();
+    let second = publish().await.unwrap();
+    assert!(second.published);
+    assert_eq!(second.release, 2);
+
+    let update = update_channel::update(&host, &other_instances, &other_cache, "follower", &[], 2)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((update.from, update.to), (Some(1), 2));
+    assert!(!follower_dir.join("config/fixture.toml").exists());
+    assert!(follower_dir.join("config/added.toml").is_file());
+    assert!(follower_dir.join("config/mine.toml").is_file());
+    assert_eq!(
+        std::fs::read_to_string(follower_dir.join("options.txt")).unwrap(),
+        "fov:0.75"
+    );
+    let update = update_channel::update(&host, &other_instances, &other_cache, "follower", &[], 2)
+        .await
+        .unwrap();
+    assert!(update.is_none());
+
+    // a release changed after it was published is refused
+    let manifest = out.join("releases/2.json");
+    let tampered = std::fs::read_to_string(&manifest)
+        .unwrap()
+        .replace("-XX:+UseZGC", "-XX:+UseSerialGC");
+    std::fs::write(&manifest, tampered).unwrap();
+    let err = update_channel::follow(
+        &host,
+        &other_instances,
+        &other_cache,
+        "https://packs.example.com/pack/channel.json",
+        Some("tampered"),
+        &[],
+        2,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("Incorrect hash"), "{}", err);
+    assert!(!other_instances.join("tampered").exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn staged_releases_only_reach_their_rollout() {
+    use mod_launcher::{
+        net::DirectoryProvider,
+        update_channel::{self, Rollout},
+    };
+
+    let dir = temp_cache("rollout");
+    let (cache_dir, instances_dir) = (dir.join("cache"), dir.join("instances"));
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+    let pack = instance::Instance::new("pack", VERSION);
+    instance::save(&instances_dir, &pack).unwrap();
+    let options = pack.game_dir(&instances_dir).join("options.txt");
+    std::fs::create_dir_all(options.parent().unwrap()).unwrap();
+    std::fs::write(&options, "fov:0.5").unwrap();
+
+    let client = reqwest::Client::new();
+    let site = dir.join("site");
+    let out = site.join("packs.example.com/pack");
+    let publish = |rollout| {
+        update_channel::publish(
+            &client,
+            &instances_dir,
+            &cache_dir,
+            "pack",
+            &out,
+            false,
+            rollout,
+        )
+    };
+    publish(None).await.unwrap();
+    std::fs::write(&options, "fov:0.75").unwrap();
+    let staged = Rollout {
+        percent: 0,
+        cohorts: vec![String::from("staff")],
+    };
+    assert_eq!(publish(Some(staged)).await.unwrap().release, 2);
+
+    let host = DirectoryProvider::new(&site);
+    let (other_cache, other_instances) = (dir.join("other_cache"), dir.join("other_instances"));
+    let url = "https://packs.example.com/pack";
+    let staff = [String::from("staff")];
+    let follow = |name, cohorts| {
+        update_channel::follow(
+            &host,
+            &other_instances,
+            &other_cache,
+            url,
+            Some(name),
+            cohorts,
+            2,
+        )
+    };
+    let release = |name| {
+        instance::lockfile(&other_instances, name)
+            .unwrap()
+            .release
+            .unwrap()
+            .release
+    };
+    follow("player", &[]).await.unwrap();
+    follow("tester", &staff).await.unwrap();
+    assert_eq!(release("player"), 1);
+    assert_eq!(release("tester"), 2);
+    let update = |name| update_channel::update(&host, &other_instances, &other_cache, name, &[], 2);
+    assert!(update("player").await.unwrap().is_none());
+
+    let err = update_channel::set_rollout(
+        &out,
+        2,
+        Some(Rollout {
+            percent: 101,
+            cohorts: vec![],
+        }),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("100%"), "{}", err);
+    update_channel::set_rollout(&out, 2, None).await.unwrap();
+    let updated = update("player").await.unwrap().unwrap();
+    assert_eq!((updated.from, updated.to), (Some(1), 2));
+
+    // halting it again leaves those that got it where they are
+    let halted = Rollout {
+        percent: 0,
+        cohorts: vec![],
+    };
+    update_channel::set_rollout(&out, 2, Some(halted))
+        .await
+        .unwrap();
+    assert!(update("tester").await.unwrap().is_none());
+    assert_eq!(release("tester"), 2);
+    follow("newcomer", &[]).await.unwrap();
+    assert_eq!(release("newcomer"), 1);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+// serves `root` laid out as a mirror over plain HTTP, answering after `delay`. POSTs are
+// answered by path too. Returns its base URL and how many requests it got
+async fn serve_mirror(root: PathBuf, delay: std::time::Duration) -> (String, Arc<AtomicUsize>) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .unwrap();
+    let base = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let (root, counter) = (root.clone(), counter.clone());
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                // the headers, then the body they announce
+                let mut length = None;
+                while length.is_none_or(|length| request.len() < length) {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    if read == 0 {
+                        return;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                    let end = request.windows(4).position(|window| window == b"\r\n\r\n");
+                    if let (None, Some(end)) = (length, end) {
+                        let headers = String::from_utf8_lossy(&request[..end]).to_lowercase();
+                        let body = headers
+                            .lines()
+                            .find_map(|line| line.strip_prefix("content-length:"))
+                            .map_or(0, |body| body.trim().parse().unwrap());
+                        length = Some(end + 4 + body);
+                    }
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(delay).await;
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split(' ').nth(1).unwrap().trim_start_matches('/');
+                let response = match std::fs::read(root.join(path)) {
+                    Ok(body) => [
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        )
+                        .into_bytes(),
+                        body,
+                    ]
+                    .concat(),
+                    Err(_) => {
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_vec()
+                    }
+                };
+                let _ = stream.write_all(&response).await;
+            });
+        }
+    });
+    (base, hits)
+}
+
+#[tokio::test]
+async fn downloads_are_spread_over_mirrors_by_speed() {
+    use mod_launcher::{mirror, net::HttpProvider};
+
+    let dir = temp_cache("mirror_selection");
+    let root = dir.join("mirror");
+    let manifest = root.join("piston-meta.mojang.com/mc/game/version_manifest_v2.json");
+    std::fs::create_dir_all(manifest.parent().unwrap()).unwrap();
+    std::fs::write(&manifest, vec![b' '; 64 * 1024]).unwrap();
+    std::fs::create_dir_all(root.join("example.com")).unwrap();
+    std::fs::write(root.join("example.com/file.bin"), b"mirrored").unwrap();
+
+    let (fast, fast_hits) = serve_mirror(root.clone(), std::time::Duration::ZERO).await;
+    let (slow, _) = serve_mirror(root.clone(), std::time::Duration::from_millis(300)).await;
+    // nothing listens there once the listener is dropped
+    let dead = {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port())
+    };
+    let cache_dir = dir.join("cache");
+    let others = [slow.clone(), dead.clone()];
+    let provider = || {
+        let mut http = HttpProvider::new(reqwest::Client::new(), Some(&fast));
+        http.mirrors = mirror::selector(Some(&fast), &others, &cache_dir).map(Arc::new);
+        http
+    };
+
+    // the first download probes every mirror
+    let http = provider();
+    for _ in 0..10 {
+        let fetched = http.fetch("https://example.com/file.bin").await.unwrap();
+        assert_eq!(fetched, b"mirrored");
+    }
+    let stats = http.mirrors.as_ref().unwrap().stats(&http.client).await;
+    assert_eq!(stats[&dead].rtt_ms, None);
+    assert!(
+        stats[&fast].rtt_ms.unwrap() < stats[&slow].rtt_ms.unwrap(),
+        "{:?}",
+        stats
+    );
+    assert!(cache_dir.join("mirror_stats.json").is_file());
+
+    // saved numbers are used as they are, here claiming the dead mirror is the only one up
+    let mut saved: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(cache_dir.join("mirror_stats.json")).unwrap())
+            .unwrap();
+    saved[&dead] = saved[&fast].clone();
+    for up in [&fast, &slow] {
+        saved[up]["rtt_ms"] = serde_json::Value::Null;
+    }
+    std::fs::write(cache_dir.join("mirror_stats.json"), saved.to_string()).unwrap();
+    let hits_before = fast_hits.load(Ordering::SeqCst);
+    let http = provider();
+    let fetched = http.fetch("https://example.com/file.bin").await.unwrap();
+    assert_eq!(fetched, b"mirrored");
+    // no probe, the download failed over to the first mirror left
+    assert_eq!(fast_hits.load(Ordering::SeqCst), hits_before + 1);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn disk_usage_is_attributed_and_cleaned_up() {
+    let work_dir = temp_cache("disk");
+    let (cache_dir, instances_dir) = (work_dir.join("cache"), work_dir.join("instances"));
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+    std::fs::write(work_dir.join("launcher.toml"), "").unwrap();
+    // a library left behind by a version that is gone
+    let stray = cache::libraries_dir(&cache_dir).join("com/example/gone/1.0/gone-1.0.jar");
+    std::fs::create_dir_all(stray.parent().unwrap()).unwrap();
+    std::fs::write(&stray, vec![0; 1000]).unwrap();
+    // the runtime the fixture needs, and one nothing does
+    let arch = Environment::current().os_arch;
+    let needed = adoptium::runtime_dir(&cache_dir, 17, &arch);
+    let unused = adoptium::runtime_dir(&cache_dir, 8, &arch);
+    for runtime in [&needed, &unused] {
+        std::fs::create_dir_all(runtime.join("bin")).unwrap();
+        std::fs::write(runtime.join("bin/java"), vec![0; 2000]).unwrap();
+    }
+
+    let survival = instance::create(&instances_dir, "survival", VERSION).unwrap();
+    let game_dir = survival.game_dir(&instances_dir);
+    std::fs::create_dir_all(game_dir.join("mods")).unwrap();
+    std::fs::write(game_dir.join("mods/fixture.jar"), vec![0; 3000]).unwrap();
+    let backups = saves::backups_dir(&game_dir).join("world");
+    std::fs::create_dir_all(&backups).unwrap();
+    for n in 0..3 {
+        std::fs::write(backups.join(format!("world_{}.zip", n)), vec![0; 500]).unwrap();
+    }
+
+    let options = CleanupOptions {
+        keep_backups: Some(1),
+        ..Default::default()
+    };
+    let report = disk::analyze(&work_dir, &cache_dir, &instances_dir, &options)
+        .await
+        .unwrap();
+    let item = |category, name: &str| {
+        report
+            .items
+            .iter()
+            .find(|item| item.category == category && item.name == name)
+            .unwrap_or_else(|| panic!("no {:?} {}", category, name))
+    };
+    assert_eq!(item(Category::Orphans, "libraries").bytes, 1000);
+    assert_eq!(item(Category::Backups, "survival").bytes, 1500);
+    assert!(item(Category::Instances, "survival").bytes >= 3000);
+    assert!(item(Category::Versions, VERSION).bytes > 0);
+    assert_eq!(
+        item(Category::Runtimes, &format!("temurin-8-{}", arch)).bytes,
+        2000
+    );
+    assert!(report.items.iter().any(|item| item.name == "launcher.toml"));
+    // every file counted once
+    assert_eq!(report.total_bytes, report.totals.values().sum::<u64>());
+    assert_eq!(report.total_bytes, tree_size(&work_dir));
+
+    let plan = &report.plan;
+    assert_eq!(plan.len(), 3, "{:?}", plan);
+    assert!(matches!(
+        plan[0],
+        CleanupAction::RemoveOrphans {
+            files: 1,
+            bytes: 1000
+        }
+    ));
+    assert!(matches!(
+        &plan[1],
+        CleanupAction::PruneBackups { instance, keep: 1, backups: 2, bytes: 1000 } if instance == "survival"
+    ));
+    assert!(
+        matches!(&plan[2], CleanupAction::RemoveRuntime { name, bytes: 2000 } if name.starts_with("temurin-8-"))
+    );
+
+    let cleaned = disk::clean(&cache_dir, &instances_dir, plan).await.unwrap();
+    assert_eq!(cleaned.freed_bytes, 4000);
+    assert!(!stray.exists() && !unused.exists() && needed.exists());
+    assert_eq!(saves::list_backups(&game_dir, None).unwrap().len(), 1);
+    assert!(game_dir.join("mods/fixture.jar").exists());
+
+    // nothing left to clean, and the installed version still verifies
+    let report = disk::analyze(&work_dir, &cache_dir, &instances_dir, &options)
+        .await
+        .unwrap();
+    assert!(report.plan.is_empty(), "{:?}", report.plan);
+    let verified = verify_version(&cache_dir, VERSION, None).await.unwrap();
+    assert!(verified.is_ok(), "{:?}", verified);
+
+    // pruning versions takes the one no instance uses
+    instance::delete(&instances_dir, "survival").unwrap();
+    let options = CleanupOptions {
+        prune_versions: true,
+        ..Default::default()
+    };
+    let report = disk::analyze(&work_dir, &cache_dir, &instances_dir, &options)
+        .await
+        .unwrap();
+    assert!(matches!(
+        &report.plan[..],
+        [CleanupAction::PruneVersions { versions, .. }] if versions == &[VERSION.to_string()]
+    ));
+    disk::clean(&cache_dir, &instances_dir, &report.plan)
+        .await
+        .unwrap();
+    assert!(versions::installed(&cache_dir).unwrap().is_empty());
+}
+
+// what du would count, without following links
+fn tree_size(dir: &Path) -> u64 {
+    let mut size = 0;
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let entry = entry.unwrap();
+        let metadata = std::fs::symlink_metadata(entry.path()).unwrap();
+        size += match metadata.is_dir() {
+            true => tree_size(&entry.path()),
+            false => metadata.len(),
+        };
+    }
+    size
+}
+
+#[tokio::test]
+async fn misdeclared_sizes_are_accounted_per_host() {
+    let cache_dir = temp_cache("misdeclared");
+    let library = "https://libraries.minecraft.net/com/example/fixture-lib/1.0/fixture-lib-1.0.jar";
+    let client =
+        "https://piston-data.mojang.com/v1/objects/67d5738c17a7a547c6bcb4aa3fc9080ea0c008a1/client.jar";
+    let library_size = 20;
+    let client_size = std::fs::metadata(fixture_mirror().path_for(client).unwrap())
+        .unwrap()
+        .len();
+
+    // the library's metadata is wrong twice, then right
+    for (run, declared) in [(0, 1000), (1, 1000), (2, library_size)] {
+        let mut plan = DownloadPlan::in_cache(&cache_dir);
+        for (url, size) in [(library, declared), (client, client_size)] {
+            plan.push(DownloadTask {
+                url: url.to_string(),
+                path: cache_dir.join(format!("{}-{}", run, url.rsplit('/').next().unwrap())),
+                sha1: String::new(),
+                size,
+            });
+        }
+        let reported = Mutex::new(Vec::new());
+        let account = plan
+            .run(&fixture_mirror(), 2, None, &|done, total| {
+                reported.lock().unwrap().push((done, total))
+            })
+            .await
+            .unwrap();
+        let actual = library_size + client_size;
+        assert_eq!(account.files, 2);
+        assert_eq!(account.declared_bytes, declared + client_size);
+        assert_eq!(
+            (account.actual_bytes, account.transferred_bytes),
+            (actual, actual)
+        );
+        let reported = reported.into_inner().unwrap();
+        // the total starts at the declared sizes and ends at what arrived
+        assert_eq!(reported.first(), Some(&(0, declared + client_size)));
+        assert_eq!(reported.last(), Some(&(actual, actual)));
+
+        let sources = download::source_stats(&cache_dir);
+        let libraries = sources["libraries.minecraft.net"];
+        if run < 2 {
+            assert_eq!(
+                account.misdeclared,
+                vec![Misdeclared {
+                    url: library.to_string(),
+                    declared,
+                    actual: library_size,
+                }]
+            );
+            assert_eq!(libraries.misdeclared_streak, run + 1);
+        } else {
+            assert!(account.misdeclared.is_empty());
+            assert_eq!(libraries.misdeclared_streak, 0);
+        }
+        assert_eq!(libraries.misdeclared_files, 2.min(run as u64 + 1));
+        let clients = sources["piston-data.mojang.com"];
+        assert_eq!(clients.files, run as u64 + 1);
+        assert_eq!(clients.misdeclared_files, 0);
+        assert_eq!(clients.actual_bytes, clients.declared_bytes);
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn frozen_instances_run_without_the_launcher() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = temp_cache("freeze");
+    let (cache_dir, instances_dir) = (dir.join("cache"), dir.join("instances"));
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+    // a runtime that writes down how it was started
+    let jdk = dir.join("jdk");
+    std::fs::create_dir_all(jdk.join("bin")).unwrap();
+    std::fs::create_dir_all(jdk.join("lib")).unwrap();
+    std::fs::write(jdk.join("lib/modules"), "modules").unwrap();
+    let java = jdk.join("bin/java");
+    std::fs::write(
+        &java,
+        "#!/bin/sh\n[ \"$1\" = -version ] && echo 'openjdk version \"17.0.8\"' >&2 && exit 0\n\
+         { pwd; printf '%s\\n' \"$@\"; } > \"$(dirname \"$0\")/../../launched.txt\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&java, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut frozen = instance::create(&instances_dir, "frozen", VERSION).unwrap();
+    frozen.java_path = Some(java.clone());
+    let assets_dir = cache::assets_dir(&cache_dir);
+    frozen.jvm_args = vec![format!("-Dfixture.assets={}", assets_dir.display())];
+    instance::save(&instances_dir, &frozen).unwrap();
+    let game_dir = frozen.game_dir(&instances_dir);
+    std::fs::create_dir_all(game_dir.join("mods")).unwrap();
+    std::fs::write(game_dir.join("mods/fixture.jar"), "mod").unwrap();
+    std::fs::write(game_dir.join("options.txt"), "fov:0.5").unwrap();
+    let backups = saves::backups_dir(&game_dir).join("world");
+    std::fs::create_dir_all(&backups).unwrap();
+    std::fs::write(backups.join("world_0.zip"), "backup").unwrap();
+
+    let mut options = LaunchOptions::new(VERSION);
+    options.instance = Some(frozen.name.clone());
+    options.work_dir = Some(dir.clone());
+    options.cache_dir = Some(cache_dir.clone());
+    options.game_dir = Some(game_dir.clone());
+    options.java_path = frozen.java_path.clone();
+    options.extra_jvm_args = frozen.jvm_args.clone();
+    let out = dir.join("out");
+    let report = freeze::freeze(&frozen, options, &out, true).await.unwrap();
+    assert!(report.external.is_empty(), "{:?}", report.external);
+    assert_eq!(report.script, out.canonicalize().unwrap().join("start.sh"));
+    let script = std::fs::read_to_string(&report.script).unwrap();
+    assert!(!script.contains(dir.to_str().unwrap()), "{}", script);
+    assert!(out.join("game/mods/fixture.jar").is_file());
+    assert!(!out.join("game/backups").exists());
+    assert!(out.join("runtime/lib/modules").is_file());
+    assert!(out.join("instance.json").is_file());
+    // this version's assets, nothing else from the cache's assets dir
+    let copied = out.join("cache/assets/objects");
+    assert!(copied.join(SOUNDS_OBJECT).is_file() && copied.join(TEXTURE_OBJECT).is_file());
+
+    // moved somewhere else, with the launcher's directories gone
+    let moved = dir.join("moved");
+    std::fs::rename(&out, &moved).unwrap();
+    for gone in [&cache_dir, &instances_dir, &jdk] {
+        std::fs::remove_dir_all(gone).unwrap();
+    }
+    let status = std::process::Command::new(moved.join("start.sh"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let launched = std::fs::read_to_string(moved.join("launched.txt")).unwrap();
+    let launched = launched.lines().collect::<Vec<_>>();
+    let moved = moved.canonicalize().unwrap();
+    assert_eq!(Path::new(launched[0]), moved.join("game"));
+    let classpath = launched[launched.iter().position(|arg| *arg == "-cp").unwrap() + 1];
+    assert_eq!(classpath.split(':').count(), 3);
+    for entry in classpath.split(':') {
+        assert!(
+            Path::new(entry).starts_with(&moved) && Path::new(entry).is_file(),
+            "{}",
+            entry
+        );
+    }
+    assert!(launched
+        .contains(&format!("-Dfixture.assets={}", moved.join("cache/assets").display()).as_str()));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn files_added_by_hand_keep_their_source_when_renamed() {
+    // MurmurHash2 with seed 1 of nothing, whitespace doesn't count
+    assert_eq!(curseforge::fingerprint(b""), 0x5bd1_5e36);
+    assert_eq!(curseforge::fingerprint(b" \t\r\n"), 0x5bd1_5e36);
+    assert_eq!(
+        curseforge::fingerprint(b"mod\ncontent"),
+        curseforge::fingerprint(b"mod content\r\n")
+    );
+    assert_ne!(
+        curseforge::fingerprint(b"mod content"),
+        curseforge::fingerprint(b"mod contents")
+    );
+
+    let instances_dir = temp_cache("adopt");
+    instance::create(&instances_dir, "manual", VERSION).unwrap();
+    let game_dir = instance::load(&instances_dir, "manual")
+        .unwrap()
+        .game_dir(&instances_dir);
+    std::fs::create_dir_all(game_dir.join("mods")).unwrap();
+    std::fs::write(game_dir.join("mods/sodium.jar"), b"not really a jar").unwrap();
+    let source = ContentSource::Modrinth {
+        project_id: "AANobbMI".to_string(),
+        version_id: "ver1".to_string(),
+        version_number: "0.5.3".to_string(),
+    };
+    instance::update_locked(&instances_dir, "manual", |_, lockfile| {
+        lockfile.content.insert(
+            "mods/sodium.jar".to_string(),
+            AdoptedFile {
+                // sha1 of "not really a jar"
+                sha1: "38eaf03257a4bc319e4d8a8461543c0a041071f1".to_string(),
+                source: Some(source.clone()),
+            },
+        );
+    })
+    .unwrap();
+    // nothing to look up, so nothing is asked of Modrinth
+    let client = reqwest::Client::new();
+    let report = adopt::adopt(&client, None, &instances_dir, "manual")
+        .await
+        .unwrap();
+    assert!(report.adopted.is_empty() && report.removed.is_empty());
+
+    // disabling renames the jar, it is still the same file
+    std::fs::rename(
+        game_dir.join("mods/sodium.jar"),
+        game_dir.join("mods/sodium.jar.disabled"),
+    )
+    .unwrap();
+    let report = adopt::adopt(&client, None, &instances_dir, "manual")
+        .await
+        .unwrap();
+    assert_eq!(report.adopted.len(), 1);
+    assert_eq!(report.adopted[0].path, "mods/sodium.jar.disabled");
+    assert_eq!(report.adopted[0].source, Some(source.clone()));
+    assert_eq!(report.removed, vec!["mods/sodium.jar".to_string()]);
+    let content = instance::lockfile(&instances_dir, "manual")
+        .unwrap()
+        .content;
+    assert_eq!(content.len(), 1);
+    assert_eq!(
+        content["mods/sodium.jar.disabled"].sha1,
+        "38eaf03257a4bc319e4d8a8461543c0a041071f1"
+    );
+
+    std::fs::remove_dir_all(instances_dir).unwrap();
+}
+
+#[tokio::test]
+async fn curseforge_packs_import_as_instances() {
+    let dir = temp_cache("curseforge_import");
+    let (api_root, instances_dir) = (dir.join("api"), dir.join("instances"));
+    std::fs::create_dir_all(api_root.join("mods")).unwrap();
+    std::fs::create_dir_all(api_root.join("files")).unwrap();
+    std::fs::write(api_root.join("files/sodium.jar"), "not really a jar").unwrap();
+    let (base, _) = serve_mirror(api_root.clone(), std::time::Duration::ZERO).await;
+    let api = curseforge::Api {
+        url: base.clone(),
+        key: String::from("test-key"),
+    };
+
+    let pack = dir.join("pack.zip");
+    let manifest = serde_json::json!({
+        "minecraft": {
+            "version": "1.20.1",
+            "modLoaders": [
+                { "id": "forge-47.2.0" },
+                { "id": "fabric-0.15.7", "primary": true },
+            ],
+        },
+        "manifestType": "minecraftModpack",
+        "manifestVersion": 1,
+        "name": "Fixture Pack",
+        "version": "2.1",
+        "files": [
+            { "projectID": 394468, "fileID": 4242, "required": true },
+            { "projectID": 1, "fileID": 1, "required": false },
+        ],
+        "overrides": "overrides",
+    });
+    write_zip(
+        &pack,
+        &[
+            ("manifest.json", &manifest.to_string()),
+            ("overrides/options.txt", "lang:de_de"),
+            ("overrides/config/sodium.json", "{}"),
+            ("overrides/../escape.txt", "outside"),
+        ],
+    );
+
+    // the files API doesn't answer yet, nothing is left behind
+    let client = reqwest::Client::new();
+    curseforge::import_instance(&instances_dir, &pack, None, &api, &client, false)
+        .await
+        .unwrap_err();
+    assert!(!instances_dir.join("Fixture Pack").exists());
+
+    std::fs::write(
+        api_root.join("mods/files"),
+        serde_json::json!({ "data": [{
+            "id": 4242,
+            "modId": 394468,
+            "fileName": "sodium.jar",
+            "fileLength": 16,
+            "downloadUrl": format!("{}/files/sodium.jar", base),
+            // sha1 of "not really a jar"
+            "hashes": [{ "value": "38eaf03257a4bc319e4d8a8461543c0a041071f1", "algo": 1 }],
+        }] })
+        .to_string(),
+    )
+    .unwrap();
+    for batched in [false, true] {
+        let name = format!("Fixture Pack {}", batched);
+        let (instance, imported) =
+            curseforge::import_instance(&instances_dir, &pack, Some(&name), &api, &client, batched)
+                .await
+                .unwrap();
+        assert_eq!(instance.name, name);
+        // the loader is installed afterwards
+        assert_eq!(instance.version, "1.20.1");
+        assert_eq!(
+            imported.loader,
+            Some(Loader {
+                kind: LoaderKind::Fabric,
+                version: String::from("0.15.7"),
+            })
+        );
+        assert_eq!(
+            (imported.name.as_str(), imported.version.as_str()),
+            ("Fixture Pack", "2.1")
+        );
+
+        let game_dir = instance.game_dir(&instances_dir);
+        let read = |path: &str| std::fs::read_to_string(game_dir.join(path)).unwrap();
+        assert_eq!(read("mods/sodium.jar"), "not really a jar");
+        assert_eq!(read("options.txt"), "lang:de_de");
+        assert_eq!(read("config/sodium.json"), "{}");
+        assert!(!game_dir.join("escape.txt").exists());
+        assert!(!game_dir.parent().unwrap().join("escape.txt").exists());
+    }
+
+    let err = curseforge::import_instance(
+        &instances_dir,
+        &pack,
+        Some("Fixture Pack true"),
+        &api,
+        &client,
+        false,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("already exists"), "{}", err);
+    // and the instance that was there is left alone
+    instance::load(&instances_dir, "Fixture Pack true").unwrap();
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[cfg(unix)]
+#[tokio::test]
+async fn gc_logging_follows_the_java_that_runs() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let cache_dir = temp_cache("gc_java");
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+    // an old version that asks for Java 8, run on 17
+    let json_path = versions::json_path(&cache_dir, VERSION);
+    let mut json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+    json["javaVersion"]["majorVersion"] = serde_json::json!(8);
+    std::fs::write(&json_path, json.to_string()).unwrap();
+    let java = cache_dir.join("java");
+    std::fs::write(
+        &java,
+        "#!/bin/sh\n[ \"$1\" = -version ] && echo 'openjdk version \"17.0.8\"' >&2\nexit 0\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&java, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut options = LaunchOptions::new(VERSION);
+    options.work_dir = Some(cache_dir.clone());
+    options.cache_dir = Some(cache_dir.clone());
+    options.game_dir = Some(cache_dir.join("game"));
+    options.java_path = Some(java);
+    options.gc_logging = true;
+    options.offline = true;
+    let plan = options.dry_run().await.unwrap();
+    assert_eq!(plan.java_major, Some(17));
+    // unified logging, what Java 9 and later take
+    assert!(plan.jvm_args.iter().any(|arg| arg.starts_with("-Xlog:gc")));
+    assert!(!plan.jvm_args.iter().any(|arg| arg.starts_with("-Xloggc:")));
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+#[tokio::test]
+async fn instance_language_is_set_before_the_game_picks_one() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = temp_cache("language");
+    let cache_dir = dir.join("cache");
+    install(&fixture_mirror(), &cache_dir, AssetCheck::Exists)
+        .await
+        .unwrap();
+    let java = dir.join("java");
+    std::fs::write(
+        &java,
+        "#!/bin/sh\n[ \"$1\" = -version ] && echo 'openjdk version \"17.0.8\"' >&2\nexit 0\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&java, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    // English is in the client jar, the fixture's assets have no other language
+    language::check_installed(&cache_dir, VERSION, "en_us")
+        .await
+        .unwrap();
+    let error = language::check_installed(&cache_dir, VERSION, "de_de")
+        .await
+        .unwrap_err();
+    assert!(
+        error.to_string().contains("not one of the game's"),
+        "{}",
+        error
+    );
+    let indexes = cache::assets_dir(&cache_dir).join("indexes");
+    let index_path = std::fs::read_dir(&indexes)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let mut index: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&index_path).unwrap()).unwrap();
+    for code in ["de_de", "de_at"] {
+        index["objects"][format!("minecraft/lang/{}.json", code)] =
+            serde_json::json!({ "hash": "0000000000000000000000000000000000000000", "size": 2 });
+    }
+    std::fs::write(&index_path, index.to_string()).unwrap();
+    let error = language::check_installed(&cache_dir, VERSION, "de_ch")
+        .await
+        .unwrap_err();
+    assert!(
+        error.to_string().ends_with("did you mean de_at, de_de?"),
+        "{}",
+        error
+    );
+
+    let game_dir = dir.join("game");
+    std::fs::create_dir_all(&game_dir).unwrap();
+    std::fs::write(game_dir.join("options.txt"), "fov:0.5").unwrap();
+    let launch = |code: &str| {
+        let mut options = LaunchOptions::new(VERSION);
+        options.work_dir = Some(dir.clone());
+        options.cache_dir = Some(cache_dir.clone());
+        options.game_dir = Some(game_dir.clone());
+        options.java_path = Some(java.clone());
+        options.language = Some(code.to_string());
+        options.offline = true;
+        launch_minecraft(options)
+    };
+    assert!(launch("de_de").await.unwrap().is_none());
+    let options = || std::fs::read_to_string(game_dir.join("options.txt")).unwrap();
+    assert_eq!(options(), "fov:0.5\nlang:de_de\n");
+    // the game or the player set one by now, it stays
+    launch("de_at").await.unwrap();
+    assert_eq!(options(), "fov:0.5\nlang:de_de\n");
+    language::reset(&game_dir).await.unwrap();
+    launch("de_at").await.unwrap();
+    assert_eq!(options(), "fov:0.5\nlang:de_at\n");
+    assert!(launch("fr_fr").await.is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}